@@ -0,0 +1,589 @@
+//! `--archives`: list the members of `.zip`, `.tar` and `.tar.gz` files as
+//! if the archive were a directory.
+//!
+//! Only the listing is read, never a member's data: a zip's central
+//! directory, or a tar's headers, skipping the data between them. A
+//! compressed tar has to be decompressed all the way through to reach
+//! every header, with [`crate::inflate`], so it is read into memory whole.
+//! Members are grouped into directories by their paths, whether or not the
+//! archive has entries for those directories itself.
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::gzip;
+use crate::inflate::inflate;
+
+/// A file or directory inside an archive. Children are keyed by name.
+#[derive(Default)]
+pub struct Member {
+    pub is_dir: bool,
+    pub size: u64,
+    pub children: BTreeMap<String, Member>,
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+fn kind(name: &OsStr) -> Option<Kind> {
+    let name = name.to_string_lossy().to_ascii_lowercase();
+    if name.ends_with(".zip") {
+        Some(Kind::Zip)
+    } else if name.ends_with(".tar") {
+        Some(Kind::Tar)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(Kind::TarGz)
+    } else {
+        None
+    }
+}
+
+/// Whether `name` looks like an archive `--archives` can list.
+pub fn is_archive(name: &OsStr) -> bool {
+    kind(name).is_some()
+}
+
+/// Reads the member listing of the archive at `path`.
+pub fn read_members(path: &Path) -> io::Result<BTreeMap<String, Member>> {
+    let kind = path
+        .file_name()
+        .and_then(kind)
+        .ok_or_else(|| invalid("not an archive"))?;
+    let entries = match kind {
+        Kind::Zip => zip_entries(&mut File::open(path)?)?,
+        Kind::Tar => {
+            let mut file = File::open(path)?;
+            let mut tar = TarParser::default();
+            let mut buf = vec![0; 64 * 1024];
+            while !tar.done {
+                let n = file.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                tar.feed(&buf[..n])?;
+            }
+            tar.finish()?
+        }
+        Kind::TarGz => {
+            let data = std::fs::read(path)?;
+            let mut tar = TarParser::default();
+            let mut rest = &data[..];
+            while rest.len() >= 2 && rest[..2] == [0x1f, 0x8b] && !tar.done {
+                let start = gzip::header_len(rest)?;
+                let used = inflate(&rest[start..], |chunk| {
+                    if tar.done { Ok(()) } else { tar.feed(chunk) }
+                })?;
+                // The CRC-32 and length trailer; another member may follow.
+                rest = rest.get(start + used + 8..).unwrap_or_default();
+            }
+            tar.finish()?
+        }
+    };
+
+    let mut root = BTreeMap::new();
+    for (name, is_dir, size) in entries {
+        insert(&mut root, &name, is_dir, size);
+    }
+    Ok(root)
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what.to_string())
+}
+
+/// Files the entry under its path, creating the directories above it.
+/// `./` and empty components are dropped, as is `..`, which can't be placed.
+fn insert(root: &mut BTreeMap<String, Member>, name: &str, is_dir: bool, size: u64) {
+    let parts: Vec<&str> = name
+        .split(['/', '\\'])
+        .filter(|p| !p.is_empty() && *p != "." && *p != "..")
+        .collect();
+    let Some((last, parents)) = parts.split_last() else {
+        return;
+    };
+    let mut level = root;
+    for part in parents {
+        let dir = level.entry(part.to_string()).or_default();
+        dir.is_dir = true;
+        level = &mut dir.children;
+    }
+    let member = level.entry(last.to_string()).or_default();
+    member.is_dir |= is_dir;
+    if !is_dir {
+        member.size = size;
+    }
+}
+
+fn u16_at(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(buf[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(buf: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(buf[at..at + 8].try_into().unwrap())
+}
+
+const EOCD_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+const ZIP64_EOCD_SIGNATURE: u32 = 0x0606_4b50;
+const CENTRAL_SIGNATURE: u32 = 0x0201_4b50;
+
+/// Name, directory flag and uncompressed size of every central directory
+/// entry.
+fn zip_entries(file: &mut File) -> io::Result<Vec<(String, bool, u64)>> {
+    // The end record is 22 bytes plus a comment of up to 64 KiB.
+    let len = file.seek(SeekFrom::End(0))?;
+    let tail_len = len.min(22 + 0xffff);
+    file.seek(SeekFrom::Start(len - tail_len))?;
+    let mut tail = vec![0; tail_len as usize];
+    file.read_exact(&mut tail)?;
+    let eocd = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&at| u32_at(&tail, at) == EOCD_SIGNATURE)
+        .ok_or_else(|| invalid("not a zip archive"))?;
+    let mut count = u64::from(u16_at(&tail, eocd + 10));
+    let mut size = u64::from(u32_at(&tail, eocd + 12));
+    let mut offset = u64::from(u32_at(&tail, eocd + 16));
+
+    if eocd >= 20 && u32_at(&tail, eocd - 20) == ZIP64_LOCATOR_SIGNATURE {
+        file.seek(SeekFrom::Start(u64_at(&tail, eocd - 20 + 8)))?;
+        let mut record = [0; 56];
+        file.read_exact(&mut record)?;
+        if u32_at(&record, 0) != ZIP64_EOCD_SIGNATURE {
+            return Err(invalid("bad zip64 end of central directory"));
+        }
+        count = u64_at(&record, 32);
+        size = u64_at(&record, 40);
+        offset = u64_at(&record, 48);
+    }
+    if offset.checked_add(size).is_none_or(|end| end > len) {
+        return Err(invalid("central directory out of bounds"));
+    }
+
+    file.seek(SeekFrom::Start(offset))?;
+    let mut central = vec![0; size as usize];
+    file.read_exact(&mut central)?;
+    let mut entries = Vec::new();
+    let mut at = 0;
+    while entries.len() as u64 != count {
+        if at + 46 > central.len() || u32_at(&central, at) != CENTRAL_SIGNATURE {
+            return Err(invalid("bad central directory entry"));
+        }
+        let name_len = u16_at(&central, at + 28) as usize;
+        let extra_len = u16_at(&central, at + 30) as usize;
+        let comment_len = u16_at(&central, at + 32) as usize;
+        let name_end = at + 46 + name_len;
+        let extra_end = name_end + extra_len;
+        if extra_end + comment_len > central.len() {
+            return Err(invalid("bad central directory entry"));
+        }
+        let name = String::from_utf8_lossy(&central[at + 46..name_end]).into_owned();
+        let mut size = u64::from(u32_at(&central, at + 24));
+        if size == 0xffff_ffff {
+            size = zip64_size(&central[name_end..extra_end]).unwrap_or(size);
+        }
+        let is_dir = name.ends_with('/');
+        entries.push((name, is_dir, size));
+        at = extra_end + comment_len;
+    }
+    Ok(entries)
+}
+
+/// The uncompressed size from a zip64 extra field, which comes first in it.
+fn zip64_size(mut extra: &[u8]) -> Option<u64> {
+    while extra.len() >= 4 {
+        let id = u16_at(extra, 0);
+        let len = u16_at(extra, 2) as usize;
+        let data = extra.get(4..4 + len)?;
+        if id == 0x0001 && data.len() >= 8 {
+            return Some(u64_at(data, 0));
+        }
+        extra = &extra[4 + len..];
+    }
+    None
+}
+
+const BLOCK: usize = 512;
+
+/// A tar reader fed in arbitrary pieces, so it can sit behind inflate.
+#[derive(Default)]
+struct TarParser {
+    /// A header being filled in.
+    header: Vec<u8>,
+    /// Data bytes, padding included, still to pass over.
+    skip: u64,
+    /// A GNU long name (`L`) or pax header (`x`) being read: the type, the
+    /// bytes still to come and those read so far.
+    extension: Option<(u8, u64, Vec<u8>)>,
+    /// Name for the next entry, from an extension header.
+    next_name: Option<String>,
+    done: bool,
+    entries: Vec<(String, bool, u64)>,
+}
+
+impl TarParser {
+    fn feed(&mut self, mut data: &[u8]) -> io::Result<()> {
+        while !data.is_empty() && !self.done {
+            if self.skip > 0 {
+                let n = self.skip.min(data.len() as u64);
+                self.skip -= n;
+                data = &data[n as usize..];
+                continue;
+            }
+            if let Some((kind, left, mut bytes)) = self.extension.take() {
+                let n = left.min(data.len() as u64);
+                bytes.extend_from_slice(&data[..n as usize]);
+                data = &data[n as usize..];
+                if left > n {
+                    self.extension = Some((kind, left - n, bytes));
+                    continue;
+                }
+                self.skip = padding(bytes.len() as u64);
+                self.next_name = match kind {
+                    b'L' => Some(c_string(&bytes)),
+                    _ => pax_path(&bytes).or(self.next_name.take()),
+                };
+                continue;
+            }
+            let n = (BLOCK - self.header.len()).min(data.len());
+            self.header.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            if self.header.len() == BLOCK {
+                let header = std::mem::take(&mut self.header);
+                self.header_block(&header)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn header_block(&mut self, h: &[u8]) -> io::Result<()> {
+        if h.iter().all(|&b| b == 0) {
+            self.done = true;
+            return Ok(());
+        }
+        let stored = octal(&h[148..156]).ok_or_else(|| invalid("not a tar archive"))?;
+        let sum: u64 = h
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| {
+                if (148..156).contains(&i) {
+                    32
+                } else {
+                    u64::from(b)
+                }
+            })
+            .sum();
+        if stored != sum {
+            return Err(invalid("not a tar archive"));
+        }
+        let size = field_size(&h[124..136]).ok_or_else(|| invalid("bad tar entry size"))?;
+        let typeflag = h[156];
+        match typeflag {
+            b'L' | b'x' => {
+                self.extension = Some((typeflag, size, Vec::new()));
+                return Ok(());
+            }
+            // Global pax headers and GNU long link names name no entry.
+            b'g' | b'K' => {
+                self.skip = size + padding(size);
+                return Ok(());
+            }
+            _ => {}
+        }
+        let name = self.next_name.take().unwrap_or_else(|| {
+            let name = c_string(&h[..100]);
+            let prefix = if &h[257..262] == b"ustar" {
+                c_string(&h[345..500])
+            } else {
+                String::new()
+            };
+            if prefix.is_empty() {
+                name
+            } else {
+                format!("{prefix}/{name}")
+            }
+        });
+        let is_dir = typeflag == b'5' || name.ends_with('/');
+        self.entries.push((name, is_dir, size));
+        // Only regular files carry data; the size of a link is its target's.
+        let data = if matches!(typeflag, b'0' | 0 | b'7') {
+            size
+        } else {
+            0
+        };
+        self.skip = data + padding(data);
+        Ok(())
+    }
+
+    /// The entries, once the input has run out; an archive may stop
+    /// without its end blocks, but not partway through an entry.
+    fn finish(self) -> io::Result<Vec<(String, bool, u64)>> {
+        if !self.done && (!self.header.is_empty() || self.skip > 0 || self.extension.is_some()) {
+            return Err(invalid("truncated tar archive"));
+        }
+        Ok(self.entries)
+    }
+}
+
+fn padding(len: u64) -> u64 {
+    (BLOCK as u64 - len % BLOCK as u64) % BLOCK as u64
+}
+
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// An octal number padded with spaces or NULs.
+fn octal(field: &[u8]) -> Option<u64> {
+    let text = std::str::from_utf8(field).ok()?;
+    let text = text.trim_matches(|c: char| c == ' ' || c == '\0');
+    if text.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(text, 8).ok()
+}
+
+/// A size field: octal, or GNU base-256 when the high bit is set.
+fn field_size(field: &[u8]) -> Option<u64> {
+    if field[0] & 0x80 == 0 {
+        return octal(field);
+    }
+    let mut value = u64::from(field[0] & 0x7f);
+    for &b in &field[1..] {
+        value = value.checked_mul(256)? | u64::from(b);
+    }
+    Some(value)
+}
+
+/// The `path` record of a pax extended header: `<len> path=<name>\n`.
+fn pax_path(bytes: &[u8]) -> Option<String> {
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|&b| b == b' ')?;
+        let len: usize = std::str::from_utf8(&rest[..space]).ok()?.parse().ok()?;
+        let record = rest.get(space + 1..len)?;
+        if let Some(path) = record.strip_prefix(b"path=") {
+            let path = path.strip_suffix(b"\n").unwrap_or(path);
+            return Some(String::from_utf8_lossy(path).into_owned());
+        }
+        rest = &rest[len..];
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use crate::gzip::GzipEncoder;
+
+    /// A ustar header with a valid checksum.
+    fn header(name: &str, typeflag: u8, size: u64) -> Vec<u8> {
+        let mut h = vec![0; BLOCK];
+        h[..name.len()].copy_from_slice(name.as_bytes());
+        h[124..135].copy_from_slice(format!("{size:011o}").as_bytes());
+        h[156] = typeflag;
+        h[257..263].copy_from_slice(b"ustar\0");
+        checksum(&mut h);
+        h
+    }
+
+    fn checksum(h: &mut [u8]) {
+        h[148..156].fill(b' ');
+        let sum: u32 = h.iter().map(|&b| u32::from(b)).sum();
+        h[148..155].copy_from_slice(format!("{sum:06o}\0").as_bytes());
+    }
+
+    /// Appends a header and its data, padded to a whole block.
+    fn entry(tar: &mut Vec<u8>, header: Vec<u8>, data: &[u8]) {
+        tar.extend(header);
+        tar.extend_from_slice(data);
+        tar.resize(tar.len() + padding(data.len() as u64) as usize, 0);
+    }
+
+    fn sample_tar() -> Vec<u8> {
+        let mut tar = Vec::new();
+        entry(&mut tar, header("a.txt", b'0', 5), b"hello");
+        entry(&mut tar, header("d/", b'5', 0), b"");
+        let mut prefixed = header("b", b'0', 0);
+        prefixed[345..356].copy_from_slice(b"long/prefix");
+        checksum(&mut prefixed);
+        entry(&mut tar, prefixed, b"");
+        let long = "x".repeat(150);
+        entry(
+            &mut tar,
+            header("././@LongLink", b'L', 151),
+            format!("{long}\0").as_bytes(),
+        );
+        entry(&mut tar, header("truncated", b'0', 2), b"ab");
+        let record = "17 path=pax/name\n";
+        entry(&mut tar, header("PaxHeader", b'x', 17), record.as_bytes());
+        entry(&mut tar, header("ignored", b'2', 9), b"");
+        tar.resize(tar.len() + 2 * BLOCK, 0);
+        tar
+    }
+
+    fn parsed(tar: &[u8], chunk: usize) -> io::Result<Vec<(String, bool, u64)>> {
+        let mut parser = TarParser::default();
+        for piece in tar.chunks(chunk) {
+            parser.feed(piece)?;
+        }
+        parser.finish()
+    }
+
+    fn error(result: io::Result<Vec<(String, bool, u64)>>) -> String {
+        match result {
+            Ok(_) => panic!("parsed a broken archive"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    /// A file in the temp directory, removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &[u8]) -> TempFile {
+            let path =
+                std::env::temp_dir().join(format!("folderwalk-{}-{name}", std::process::id()));
+            std::fs::write(&path, contents).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn tar_headers_name_entries() {
+        let expected = vec![
+            ("a.txt".to_string(), false, 5),
+            ("d/".to_string(), true, 0),
+            ("long/prefix/b".to_string(), false, 0),
+            ("x".repeat(150), false, 2),
+            ("pax/name".to_string(), false, 9),
+        ];
+        // Whole, and in pieces that split every header and data block.
+        assert_eq!(parsed(&sample_tar(), usize::MAX).unwrap(), expected);
+        assert_eq!(parsed(&sample_tar(), 7).unwrap(), expected);
+        // The end blocks are optional.
+        let tar = sample_tar();
+        assert_eq!(
+            parsed(&tar[..tar.len() - 2 * BLOCK], 100).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn bad_tar_headers_are_rejected() {
+        let mut tar = header("a", b'0', 0);
+        tar[0] = b'b';
+        assert_eq!(error(parsed(&tar, BLOCK)), "not a tar archive");
+
+        let mut tar = header("a", b'0', 0);
+        tar[148..156].copy_from_slice(b"99999999");
+        assert_eq!(error(parsed(&tar, BLOCK)), "not a tar archive");
+
+        let mut tar = header("a", b'0', 0);
+        tar[124..135].copy_from_slice(b"0000000000z");
+        checksum(&mut tar);
+        assert_eq!(error(parsed(&tar, BLOCK)), "bad tar entry size");
+    }
+
+    #[test]
+    fn truncated_tars_are_rejected() {
+        let tar = sample_tar();
+        // Partway through the first header, its data and the long name.
+        for len in [100, BLOCK + 3, 5 * BLOCK + 10] {
+            assert_eq!(
+                error(parsed(&tar[..len], 64)),
+                "truncated tar archive",
+                "{len}"
+            );
+        }
+    }
+
+    #[test]
+    fn sizes_may_be_base_256() {
+        let mut field = [0; 12];
+        field[0] = 0x80;
+        field[8..].copy_from_slice(&[1, 0, 0, 0]);
+        assert_eq!(field_size(&field), Some(1 << 24));
+        assert_eq!(field_size(b"00000000017 "), Some(15));
+        assert_eq!(field_size(&[0xff; 12]), None);
+    }
+
+    #[test]
+    fn compressed_tars_are_listed() {
+        let mut gz = GzipEncoder::new(Vec::new());
+        gz.write_all(&sample_tar()).unwrap();
+        let file = TempFile::new("listed.tar.gz", &gz.finish().unwrap());
+        let root = read_members(&file.0).unwrap();
+        assert_eq!(
+            root.keys().collect::<Vec<_>>(),
+            ["a.txt", "d", "long", "pax", "x".repeat(150).as_str()]
+        );
+        assert_eq!(root["a.txt"].size, 5);
+        assert!(root["long"].children["prefix"].is_dir);
+        assert!(root["d"].is_dir && root["d"].children.is_empty());
+    }
+
+    #[test]
+    fn zip_central_directories_are_listed() {
+        let mut central = Vec::new();
+        for (name, size) in [("dir/", 0u32), ("dir/file.txt", 12), ("./top", 3)] {
+            central.extend_from_slice(&CENTRAL_SIGNATURE.to_le_bytes());
+            central.extend_from_slice(&[0; 20]);
+            central.extend_from_slice(&size.to_le_bytes());
+            central.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            central.extend_from_slice(&[0; 16]);
+            central.extend_from_slice(name.as_bytes());
+        }
+        let mut zip = b"local headers and data".to_vec();
+        let offset = zip.len() as u32;
+        zip.extend_from_slice(&central);
+        zip.extend_from_slice(&EOCD_SIGNATURE.to_le_bytes());
+        zip.extend_from_slice(&[0; 4]);
+        zip.extend_from_slice(&[3, 0, 3, 0]);
+        zip.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        zip.extend_from_slice(&offset.to_le_bytes());
+        zip.extend_from_slice(&[2, 0]);
+        zip.extend_from_slice(b"hi");
+
+        let file = TempFile::new("listed.zip", &zip);
+        let root = read_members(&file.0).unwrap();
+        assert_eq!(root.keys().collect::<Vec<_>>(), ["dir", "top"]);
+        assert_eq!(root["dir"].children["file.txt"].size, 12);
+        assert_eq!(root["top"].size, 3);
+
+        // One entry fewer than the end record claims.
+        let mut short = zip.clone();
+        let eocd = short.len() - 24;
+        short[eocd + 10] = 4;
+        let file = TempFile::new("short.zip", &short);
+        assert_eq!(
+            read_members(&file.0).err().unwrap().to_string(),
+            "bad central directory entry"
+        );
+        let file = TempFile::new("plain.zip", b"not a zip at all");
+        assert_eq!(
+            read_members(&file.0).err().unwrap().to_string(),
+            "not a zip archive"
+        );
+    }
+}
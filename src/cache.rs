@@ -0,0 +1,467 @@
+//! `--cache FILE`: directory listings saved between runs, so a directory
+//! whose modification time hasn't changed is not read again.
+//!
+//! A directory's mtime changes when entries are added, removed or renamed
+//! in it, not when a file in it is rewritten, so the sizes and times of
+//! files in an unchanged directory come from the cache and can be behind.
+//! Listings are only saved when they had no problems, every name in them
+//! is valid UTF-8, and the directory was last changed a couple of seconds
+//! before it was read; anything else is read afresh each time. The cache
+//! also records the options that decide which entries a listing keeps
+//! (`--all`, `--exclude`, `--ignore`, `--ignore-case`, `--fast`,
+//! `--symlinks-as-files`, `--one-file-system`) and is thrown away when they
+//! differ.
+//!
+//! The file is plain text: a header line, the options line, then for each
+//! directory a `dir` line followed by one tab-separated line per entry.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::{DirEntryInfo, Listing, WalkOptions, links_to_ancestor};
+
+const HEADER: &str = "folderwalk-cache 1";
+
+/// How recently a directory may have changed and still be cached: a change
+/// made in the same instant as the read could otherwise go unnoticed.
+const SETTLE: Duration = Duration::from_secs(2);
+
+pub struct DirCache {
+    path: PathBuf,
+    /// `path` made absolute, to leave the cache itself out of listings.
+    real_path: Option<PathBuf>,
+    options: String,
+    dirs: HashMap<PathBuf, CachedDir>,
+    /// Directories listed during this run, for [`DirCache::save`].
+    seen: HashSet<PathBuf>,
+    /// The type of a regular file, borrowed from the cache file itself:
+    /// `fs::FileType` can't be built from scratch.
+    file_type: Option<fs::FileType>,
+}
+
+struct CachedDir {
+    mtime: SystemTime,
+    skipped: usize,
+    entries: Vec<CachedEntry>,
+}
+
+struct CachedEntry {
+    name: String,
+    /// `d`, `f`, `l` (symlink) or `o` (anything else).
+    kind: char,
+    is_symlink_dir: bool,
+    len: u64,
+    modified: Option<SystemTime>,
+    created: Option<SystemTime>,
+    dev: Option<u64>,
+    executable: bool,
+    hidden: bool,
+}
+
+impl DirCache {
+    /// Reads the cache at `path`; a missing, unreadable or outdated file
+    /// gives an empty cache, to be filled by this run.
+    pub fn load(path: &Path, walk: &WalkOptions) -> DirCache {
+        let options = options_key(walk);
+        let real_path = match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => {
+                let parent = if parent.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    parent
+                };
+                fs::canonicalize(parent).ok().map(|p| p.join(name))
+            }
+            _ => None,
+        };
+        let mut cache = DirCache {
+            path: path.to_path_buf(),
+            real_path,
+            options,
+            dirs: HashMap::new(),
+            seen: HashSet::new(),
+            file_type: fs::metadata(path).ok().map(|m| m.file_type()),
+        };
+        if let Ok(text) = fs::read_to_string(path)
+            && let Some(dirs) = parse(&text, &cache.options)
+        {
+            cache.dirs = dirs;
+        }
+        cache
+    }
+
+    /// Whether `entry` is the cache file.
+    pub fn is_cache_file(&self, entry: &DirEntryInfo) -> bool {
+        self.real_path.as_deref().is_some_and(|real| {
+            real.file_name() == Some(entry.file_name.as_os_str())
+                && fs::canonicalize(&entry.path).is_ok_and(|p| p == real)
+        })
+    }
+
+    /// The listing of `dir` from the cache, if the directory hasn't changed
+    /// since it was saved.
+    pub fn listing(&mut self, dir: &Path, walk: &WalkOptions) -> Option<Listing> {
+        self.seen.insert(dir.to_path_buf());
+        let cached = self.dirs.get(dir)?;
+        let meta = fs::metadata(dir).ok()?;
+        if meta.modified().ok()? != cached.mtime {
+            return None;
+        }
+        let mut entries = Vec::with_capacity(cached.entries.len());
+        for entry in &cached.entries {
+            let path = dir.join(&entry.name);
+            let file_type = match entry.kind {
+                'd' => meta.file_type(),
+                'f' => self.file_type?,
+                _ => fs::symlink_metadata(&path).ok()?.file_type(),
+            };
+            let followed =
+                entry.is_symlink_dir && walk.follow_symlinks && !links_to_ancestor(&path);
+            entries.push(DirEntryInfo {
+                file_name: entry.name.clone().into(),
+                path,
+                file_type,
+                is_symlink_dir: entry.is_symlink_dir,
+                followed,
+                len: entry.len,
+                modified: entry.modified,
+                created: entry.created,
+                dev: entry.dev,
+                executable: entry.executable,
+                hidden: entry.hidden,
+            });
+        }
+        Some(Listing {
+            entries,
+            problems: Vec::new(),
+            skipped: cached.skipped,
+        })
+    }
+
+    /// Records a fresh listing of `dir`, when it can be trusted next time.
+    pub fn store(&mut self, dir: &Path, listing: &Listing) {
+        self.seen.insert(dir.to_path_buf());
+        self.dirs.remove(dir);
+        if !listing.problems.is_empty() || dir.to_str().is_none() {
+            return;
+        }
+        let Some(mtime) = fs::metadata(dir).and_then(|m| m.modified()).ok() else {
+            return;
+        };
+        match SystemTime::now().duration_since(mtime) {
+            Ok(age) if age >= SETTLE => {}
+            _ => return,
+        }
+        let mut entries = Vec::with_capacity(listing.entries.len());
+        for entry in &listing.entries {
+            let Some(name) = entry.file_name.to_str() else {
+                return;
+            };
+            let kind = if entry.file_type.is_symlink() {
+                'l'
+            } else if entry.file_type.is_dir() {
+                'd'
+            } else if entry.file_type.is_file() {
+                'f'
+            } else {
+                'o'
+            };
+            entries.push(CachedEntry {
+                name: name.to_string(),
+                kind,
+                is_symlink_dir: entry.is_symlink_dir,
+                len: entry.len,
+                modified: entry.modified,
+                created: entry.created,
+                dev: entry.dev,
+                executable: entry.executable,
+                hidden: entry.hidden,
+            });
+        }
+        self.dirs.insert(
+            dir.to_path_buf(),
+            CachedDir {
+                mtime,
+                skipped: listing.skipped,
+                entries,
+            },
+        );
+    }
+
+    /// Writes the cache back. Directories below `root` that weren't listed
+    /// this run are dropped, since they may be gone; others (from runs over
+    /// other roots) are kept.
+    pub fn save(&self, root: &Path) -> io::Result<()> {
+        let mut out = format!("{HEADER}\noptions\t{}\n", escape(&self.options));
+        let mut dirs: Vec<_> = self
+            .dirs
+            .iter()
+            .filter(|(dir, _)| self.seen.contains(*dir) || !dir.starts_with(root))
+            .collect();
+        dirs.sort_by(|a, b| a.0.cmp(b.0));
+        for (dir, cached) in dirs {
+            let Some(dir) = dir.to_str() else {
+                continue;
+            };
+            out.push_str(&format!(
+                "dir\t{}\t{}\t{}\n",
+                time_field(Some(cached.mtime)),
+                cached.skipped,
+                escape(dir)
+            ));
+            for e in &cached.entries {
+                let mut flags = String::new();
+                for (set, flag) in [
+                    (e.is_symlink_dir, 'S'),
+                    (e.executable, 'X'),
+                    (e.hidden, 'H'),
+                ] {
+                    if set {
+                        flags.push(flag);
+                    }
+                }
+                if flags.is_empty() {
+                    flags.push('-');
+                }
+                let dev = e.dev.map_or("-".to_string(), |d| d.to_string());
+                out.push_str(&format!(
+                    "{}\t{flags}\t{}\t{}\t{}\t{dev}\t{}\n",
+                    e.kind,
+                    e.len,
+                    time_field(e.modified),
+                    time_field(e.created),
+                    escape(&e.name)
+                ));
+            }
+        }
+        // Written to a sibling and renamed over, so an interrupted run
+        // leaves the previous cache intact.
+        let tmp = self.path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(out.as_bytes())?;
+        drop(file);
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+/// The options that decide which entries a listing holds.
+fn options_key(walk: &WalkOptions) -> String {
+    let mut excludes: Vec<&str> = walk.excludes.iter().map(String::as_str).collect();
+    excludes.sort_unstable();
+    let ignores: Vec<&str> = walk.name_ignores.iter().map(|re| re.as_str()).collect();
+    let globs: Vec<String> = walk
+        .exclude_globs
+        .iter()
+        .map(|g| format!("{}{}{}", g.regex.as_str(), g.dir_only, g.anchored))
+        .collect();
+    // The globs' text doesn't show whether they ignore case.
+    format!(
+        "all={} fast={} symlinks_as_files={} one_file_system={} ignore_case={} excludes={excludes:?} ignores={ignores:?} globs={globs:?}",
+        walk.all, walk.fast, walk.symlinks_as_files, walk.one_file_system, walk.ignore_case
+    )
+}
+
+fn parse(text: &str, options: &str) -> Option<HashMap<PathBuf, CachedDir>> {
+    let mut lines = text.lines();
+    if lines.next()? != HEADER {
+        return None;
+    }
+    if unescape(lines.next()?.strip_prefix("options\t")?) != options {
+        return None;
+    }
+    let mut dirs = HashMap::new();
+    let mut current: Option<(PathBuf, CachedDir)> = None;
+    for line in lines {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if let ["dir", mtime, skipped, path] = fields[..] {
+            if let Some((path, dir)) = current.take() {
+                dirs.insert(path, dir);
+            }
+            current = Some((
+                PathBuf::from(unescape(path)),
+                CachedDir {
+                    mtime: parse_time(mtime)?,
+                    skipped: skipped.parse().ok()?,
+                    entries: Vec::new(),
+                },
+            ));
+        } else if let [kind, flags, len, modified, created, dev, name] = fields[..] {
+            let (_, dir) = current.as_mut()?;
+            dir.entries.push(CachedEntry {
+                name: unescape(name),
+                kind: kind.chars().next()?,
+                is_symlink_dir: flags.contains('S'),
+                len: len.parse().ok()?,
+                modified: parse_time(modified),
+                created: parse_time(created),
+                dev: dev.parse().ok(),
+                executable: flags.contains('X'),
+                hidden: flags.contains('H'),
+            });
+        } else {
+            return None;
+        }
+    }
+    if let Some((path, dir)) = current {
+        dirs.insert(path, dir);
+    }
+    Some(dirs)
+}
+
+/// `SECS.NANOS` since the Unix epoch, or `-`.
+fn time_field(t: Option<SystemTime>) -> String {
+    match t.and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok()) {
+        Some(d) => format!("{}.{:09}", d.as_secs(), d.subsec_nanos()),
+        None => "-".to_string(),
+    }
+}
+
+fn parse_time(field: &str) -> Option<SystemTime> {
+    let (secs, nanos) = field.split_once('.')?;
+    let since = Duration::new(secs.parse().ok()?, nanos.parse().ok()?);
+    SystemTime::UNIX_EPOCH.checked_add(since)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan_dir;
+
+    /// A directory in the temp directory, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let path = std::env::temp_dir()
+                .join(format!("folderwalk-cache-{}-{name}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Sets `dir`'s modification time `age` in the past.
+    fn age(dir: &Path, age: Duration) {
+        let times = fs::FileTimes::new().set_modified(SystemTime::now() - age);
+        fs::File::open(dir).unwrap().set_times(times).unwrap();
+    }
+
+    fn names(listing: &Listing) -> Vec<(String, bool, u64)> {
+        listing
+            .entries
+            .iter()
+            .map(|e| {
+                (
+                    e.file_name.to_string_lossy().into_owned(),
+                    e.file_type.is_dir(),
+                    e.len,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fields_escape_and_round_trip() {
+        for s in ["plain", "tab\there", "line\nbreak\r", "back\\slash\\t"] {
+            assert!(!escape(s).contains(['\t', '\n', '\r']), "{s:?}");
+            assert_eq!(unescape(&escape(s)), s);
+        }
+        let t = SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 42);
+        assert_eq!(time_field(Some(t)), "1700000000.000000042");
+        assert_eq!(parse_time(&time_field(Some(t))), Some(t));
+        assert_eq!(time_field(None), "-");
+        assert_eq!(parse_time("-"), None);
+    }
+
+    #[test]
+    fn other_files_and_options_give_an_empty_cache() {
+        let options = options_key(&WalkOptions::default());
+        let doc = format!("{HEADER}\noptions\t{options}\n");
+        assert!(parse(&doc, &options).is_some_and(|dirs| dirs.is_empty()));
+        assert!(parse("folderwalk-cache 0\n", &options).is_none());
+        assert!(parse(&doc, "all=true").is_none());
+        // An entry before any directory, and a line of the wrong shape.
+        assert!(parse(&format!("{doc}f\t-\t1\t-\t-\t-\tname\n"), &options).is_none());
+        assert!(parse(&format!("{doc}dir\t1.0\n"), &options).is_none());
+
+        let walk = WalkOptions {
+            ignore_case: true,
+            ..WalkOptions::default()
+        };
+        assert_ne!(options_key(&walk), options);
+    }
+
+    #[test]
+    fn settled_listings_are_saved_and_reused() {
+        let tmp = TempDir::new("reuse");
+        let dir = tmp.0.join("tree");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("a\tb.txt"), "four").unwrap();
+        age(&dir, Duration::from_secs(60));
+        let walk = WalkOptions::default();
+        let file = tmp.0.join("cache.txt");
+
+        let listing = scan_dir(&dir, &dir, &walk);
+        let mut cache = DirCache::load(&file, &walk);
+        assert!(cache.listing(&dir, &walk).is_none());
+        cache.store(&dir, &listing);
+        cache.save(&dir).unwrap();
+
+        let mut cache = DirCache::load(&file, &walk);
+        let cached = cache.listing(&dir, &walk).unwrap();
+        let mut expected = names(&listing);
+        let mut got = names(&cached);
+        expected.sort();
+        got.sort();
+        assert_eq!(got, expected);
+        assert_eq!(got[0], ("a\tb.txt".to_string(), false, 4));
+
+        // A change to the directory makes it read again.
+        fs::write(dir.join("new"), "").unwrap();
+        assert!(cache.listing(&dir, &walk).is_none());
+    }
+
+    #[test]
+    fn fresh_directories_are_not_saved() {
+        let tmp = TempDir::new("fresh");
+        let walk = WalkOptions::default();
+        let mut cache = DirCache::load(&tmp.0.join("cache.txt"), &walk);
+        cache.store(&tmp.0, &scan_dir(&tmp.0, &tmp.0, &walk));
+        assert!(cache.listing(&tmp.0, &walk).is_none());
+    }
+}
@@ -0,0 +1,1278 @@
+//! The command line's shape, shared by the parser, `--help` and `folderwalk
+//! completions`: every flag once in [`FLAGS`], with its aliases, what
+//! follows it and its help, and the subcommands with the options each one
+//! takes. [`usage`] lays the flags out as `--help` shows them.
+
+use std::fmt::Write;
+
+pub const SHELLS: [&str; 4] = ["bash", "zsh", "fish", "powershell"];
+
+/// Columns `--help` is wrapped to.
+const WIDTH: usize = 80;
+/// Where explanations start in `--help`, after `  - --flag:`.
+const HELP_COLUMN: usize = 18;
+
+/// What follows a flag on the command line.
+#[derive(Clone, Copy)]
+pub enum Value {
+    None,
+    /// An argument of any kind, named by the placeholder.
+    Any(&'static str),
+    /// A file, named by the placeholder.
+    File(&'static str),
+    /// One of these words, named by the placeholder.
+    OneOf(&'static str, &'static [&'static str]),
+    /// A number, taken only when the next argument is one.
+    OptionalNumber(&'static str),
+    /// One of these words, taken only when the next argument is one.
+    OptionalOneOf(&'static str, &'static [&'static str]),
+}
+
+impl Value {
+    /// Whether the next argument always belongs to the flag.
+    pub fn required(self) -> bool {
+        matches!(self, Value::Any(_) | Value::File(_) | Value::OneOf(..))
+    }
+}
+
+pub struct Flag {
+    /// The flag as `--help` shows it first.
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub value: Value,
+    /// A short description, for `--help` and the completion scripts. Flags
+    /// without one are unstable, and left out of both.
+    pub help: &'static str,
+    /// The rest of the `--help` explanation, starting with its own
+    /// punctuation.
+    pub more: &'static str,
+}
+
+impl Flag {
+    /// The name, then the aliases.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> {
+        std::iter::once(self.name).chain(self.aliases.iter().copied())
+    }
+
+    pub fn documented(&self) -> bool {
+        !self.help.is_empty()
+    }
+}
+
+/// The flag spelled `arg`, by its name or an alias.
+pub fn flag(arg: &str) -> Option<&'static Flag> {
+    FLAGS
+        .iter()
+        .find(|flag| flag.names().any(|name| name == arg))
+}
+
+/// Every flag, in the order `--help` lists them.
+pub const FLAGS: &[Flag] = &[
+    Flag {
+        name: "--max-depth",
+        aliases: &[],
+        value: Value::Any("N"),
+        help: "limit recursion depth",
+        more: "; directories cut off at the limit get a `... (N more entries)` line",
+    },
+    Flag {
+        name: "--no-depth-marker",
+        aliases: &[],
+        value: Value::None,
+        help: "stop at --max-depth without the `... (N more entries)` line",
+        more: "",
+    },
+    Flag {
+        name: "--jobs",
+        aliases: &["-j", "--threads"],
+        value: Value::Any("N"),
+        help: "read directories on N threads ahead of the walk",
+        more: "; the output is identical, only faster on large trees",
+    },
+    Flag {
+        name: "--ascii",
+        aliases: &[],
+        value: Value::None,
+        help: "use ASCII tree characters instead of Unicode",
+        more: "",
+    },
+    Flag {
+        name: "--content",
+        aliases: &["-c"],
+        value: Value::None,
+        help: "include file contents",
+        more: "",
+    },
+    Flag {
+        name: "--stdout",
+        aliases: &["-o"],
+        value: Value::None,
+        help: "output to stdout instead of files.txt",
+        more: "",
+    },
+    Flag {
+        name: "--output",
+        aliases: &["-f", "-O"],
+        value: Value::File("PATH"),
+        help: "write the output to PATH instead of files.txt",
+        more: "",
+    },
+    Flag {
+        name: "--gzip",
+        aliases: &[],
+        value: Value::None,
+        help: "compress the output file with gzip",
+        more: "; the default name becomes files.txt.gz",
+    },
+    Flag {
+        name: "--split-size",
+        aliases: &[],
+        value: Value::Any("SIZE"),
+        help: "split the output file into numbered parts of at most SIZE bytes of text",
+        more: " (e.g. 10M): files.001.txt, files.002.txt, ... Parts end at line breaks, so \
+               each tree line stays whole",
+    },
+    Flag {
+        name: "--root-separator",
+        aliases: &[],
+        value: Value::Any("STR"),
+        help: "head each root's tree with STR when several paths go to stdout or one --output",
+        more: ", `{}` standing for the root's path (default `=== {} ===`, after a blank \
+               line); an empty STR leaves only the blank line",
+    },
+    Flag {
+        name: "--watch",
+        aliases: &[],
+        value: Value::None,
+        help: "keep running and regenerate the output whenever something in the tree changes",
+        more: " (checked twice a second)",
+    },
+    Flag {
+        name: "--interactive",
+        aliases: &[],
+        value: Value::None,
+        help: "browse the tree in the terminal",
+        more: ": arrows (or hjkl) move and open or close directories, `.` shows or hides \
+               hidden entries, the right pane previews the selection, `e` writes the rows \
+               shown to files.txt and `q` quits",
+    },
+    Flag {
+        name: "--exec",
+        aliases: &[],
+        value: Value::Any("CMD"),
+        help: "run CMD for every file listed, after excludes and filters",
+        more: ", with each `{}` replaced by the file's path (added at the end when there is \
+               none). CMD is split into words with '...', \"...\" and \\ quoting, but no \
+               shell is involved. Failures are reported on stderr and make folderwalk exit \
+               with status 1; --summary counts the commands run and failed",
+    },
+    Flag {
+        name: "--exec-parallel",
+        aliases: &[],
+        value: Value::Any("N"),
+        help: "let up to N --exec commands run at once",
+        more: " (default 1)",
+    },
+    Flag {
+        name: "--dirs-last",
+        aliases: &["--files-first"],
+        value: Value::None,
+        help: "list directories after files",
+        more: "",
+    },
+    Flag {
+        name: "--color",
+        aliases: &[],
+        value: Value::OneOf("WHEN", &["auto", "always", "never"]),
+        help: "color directories, symlinks, broken symlinks and executables",
+        more: ": auto (default, when stdout is a terminal and NO_COLOR isn't set), always \
+               or never; files.txt is never colored",
+    },
+    Flag {
+        name: "--error-format",
+        aliases: &[],
+        value: Value::OneOf("F", &["text", "json"]),
+        help: "report errors on stderr as text (default) or json",
+        more: "",
+    },
+    Flag {
+        name: "--count-hidden-separately",
+        aliases: &[],
+        value: Value::None,
+        help: "print a count line with hidden entries broken out",
+        more: " (hidden entries are only listed with --all)",
+    },
+    Flag {
+        name: "--summary",
+        aliases: &[],
+        value: Value::None,
+        help: "end the tree with directory, file and symlink counts and the total size",
+        more: ", like `tree`, plus how many entries were skipped (hidden, excluded or \
+               filtered) or unreadable",
+    },
+    Flag {
+        name: "--stats",
+        aliases: &[],
+        value: Value::None,
+        help: "end the tree with files and bytes per extension",
+        more: " (largest first, `(none)` for files without one), the largest file and the \
+               deepest path",
+    },
+    Flag {
+        name: "--flatten-below",
+        aliases: &[],
+        value: Value::Any("N"),
+        help: "below depth N, list entries as relative paths instead of nesting",
+        more: "",
+    },
+    Flag {
+        name: "--json",
+        aliases: &[],
+        value: Value::None,
+        help: "shorthand for --format json",
+        more: "",
+    },
+    Flag {
+        name: "--markdown",
+        aliases: &["--md"],
+        value: Value::None,
+        help: "shorthand for --format markdown",
+        more: "; with --content, files follow as fenced code blocks",
+    },
+    Flag {
+        name: "--relative-names",
+        aliases: &[],
+        value: Value::None,
+        help: "show each entry as its path relative to the root",
+        more: "",
+    },
+    Flag {
+        name: "--format",
+        aliases: &[],
+        value: Value::OneOf(
+            "F",
+            &[
+                "tree",
+                "shasum",
+                "json-tree",
+                "json",
+                "markdown",
+                "html",
+                "llm",
+                "csv",
+                "tsv",
+                "dot",
+                "yaml",
+                "delete-list",
+            ],
+        ),
+        help: "choose what to write",
+        more: ": tree (default), shasum (a `sha256sum -c` compatible manifest), json-tree \
+               (nested id/label/icon/meta/children nodes), json (nested \
+               name/path/type/target/content/children nodes), markdown (a nested bullet \
+               list), html (a standalone page with a collapsible tree and a filter box, \
+               written to files.html), llm (the tree, then each file's text in a fenced \
+               block under its relative path, and an estimated token count), csv and tsv \
+               (one path,depth,type,size,mtime,target row per entry), dot (a Graphviz \
+               digraph of the directories, for `dot -Tsvg`), yaml (the json document as \
+               YAML, also spelled yml), or delete-list (the paths the filters picked, each \
+               ending in a NUL byte, for `xargs -0 rm -d --`; needs a filter, and never \
+               lists `/`, anything directly under it, the root, the current directory, \
+               $HOME or a directory above them)",
+    },
+    Flag {
+        name: "--bundle",
+        aliases: &[],
+        value: Value::None,
+        help: "shorthand for --format llm",
+        more: "",
+    },
+    Flag {
+        name: "--max-tokens",
+        aliases: &[],
+        value: Value::Any("N"),
+        help: "with --format llm, include files only while the estimate stays within N tokens",
+        more: ", shallowest and smallest first, and list the ones left out",
+    },
+    Flag {
+        name: "--progress",
+        aliases: &[],
+        value: Value::None,
+        help: "show a live count of directories and entries read",
+        more: ", and the current directory, on stderr (when it is a terminal)",
+    },
+    Flag {
+        name: "--progress-file",
+        aliases: &[],
+        value: Value::File("PATH"),
+        help: "periodically write entry count and elapsed time to PATH",
+        more: "",
+    },
+    Flag {
+        name: "--symlinks-as-files",
+        aliases: &[],
+        value: Value::None,
+        help: "treat every symlink as a leaf without inspecting its target",
+        more: "",
+    },
+    Flag {
+        name: "--follow-symlinks",
+        aliases: &["-L"],
+        value: Value::None,
+        help: "descend into symlinked directories",
+        more: "; links back to a directory they are inside are shown as `-> <cycle>`",
+    },
+    Flag {
+        name: "--content-max-line-length",
+        aliases: &[],
+        value: Value::Any("N"),
+        help: "cut content lines longer than N bytes",
+        more: "",
+    },
+    Flag {
+        name: "--max-content-bytes",
+        aliases: &["--max-content-size"],
+        value: Value::Any("N"),
+        help: "show at most the first N bytes of each file's content",
+        more: "",
+    },
+    Flag {
+        name: "--content-lines",
+        aliases: &[],
+        value: Value::Any("START:END"),
+        help: "show only these lines of each file",
+        more: " (1-based, inclusive; `START:` or `:END` leave one side open)",
+    },
+    Flag {
+        name: "--content-depth",
+        aliases: &[],
+        value: Value::Any("N"),
+        help: "like --content, but only for files within N levels of the root",
+        more: " (1: the root's own files); the tree still goes deeper",
+    },
+    Flag {
+        name: "--line-numbers",
+        aliases: &["-n", "--numbered"],
+        value: Value::None,
+        help: "number the content lines",
+        more: "",
+    },
+    Flag {
+        name: "--size",
+        aliases: &[],
+        value: Value::None,
+        help: "show each entry's size",
+        more: " (directories: the total below them)",
+    },
+    Flag {
+        name: "--long",
+        aliases: &["-l"],
+        value: Value::None,
+        help: "prefix each line with permissions, owner, group and modification time",
+        more: " (UTC) in aligned columns; on Windows, attribute flags",
+    },
+    Flag {
+        name: "--du",
+        aliases: &[],
+        value: Value::None,
+        help: "show the total size below each directory and the root",
+        more: ", like `du`, without file sizes (add --size for those)",
+    },
+    Flag {
+        name: "--bar",
+        aliases: &[],
+        value: Value::OptionalNumber("N"),
+        help: "like --du, with a bar of N cells (default 8)",
+        more: ", e.g. `████░░░░`, after each directory's total showing its share of the \
+               parent's",
+    },
+    Flag {
+        name: "--bytes",
+        aliases: &[],
+        value: Value::None,
+        help: "like --size, in exact bytes",
+        more: "; with --du, totals in bytes",
+    },
+    Flag {
+        name: "--include-binary",
+        aliases: &[],
+        value: Value::None,
+        help: "with --content, dump binary files too instead of just noting their size",
+        more: "",
+    },
+    Flag {
+        name: "--clipboard",
+        aliases: &[],
+        value: Value::None,
+        help: "copy the output to the system clipboard instead of files.txt",
+        more: " (requires the `clipboard` build feature)",
+    },
+    Flag {
+        name: "--age-histogram",
+        aliases: &[],
+        value: Value::None,
+        help: "after the tree, bucket files by modification age",
+        more: "",
+    },
+    Flag {
+        name: "--canonical",
+        aliases: &[],
+        value: Value::None,
+        help: "resolve the path (symlinks, `.`, `..`) to an absolute one first",
+        more: "",
+    },
+    Flag {
+        name: "--show-fs",
+        aliases: &[],
+        value: Value::None,
+        help: "annotate the root and mount boundaries with the filesystem",
+        more: " (Unix)",
+    },
+    Flag {
+        name: "--fast",
+        aliases: &[],
+        value: Value::None,
+        help: "use only the file type reported while listing",
+        more: "; no per-entry stat",
+    },
+    Flag {
+        name: "--path-regex",
+        aliases: &[],
+        value: Value::Any("RE"),
+        help: "only list files whose root-relative path (using `/`) matches RE",
+        more: "",
+    },
+    Flag {
+        name: "--prune",
+        aliases: &["--prune-empty"],
+        value: Value::None,
+        help: "omit directories that end up with nothing listed in them",
+        more: ", at any depth",
+    },
+    Flag {
+        name: "--collapse",
+        aliases: &[],
+        value: Value::None,
+        help: "show directories that only hold one subdirectory on one line",
+        more: ", e.g. `src/main/java/`",
+    },
+    Flag {
+        name: "--bfs",
+        aliases: &[],
+        value: Value::None,
+        help: "list the tree breadth-first, level by level",
+        more: ": every depth-1 entry under a `Depth 1` heading, then depth 2, ... as paths \
+               from the root, so --max-depth shows the broad structure first",
+    },
+    Flag {
+        name: "--sort",
+        aliases: &[],
+        value: Value::OneOf(
+            "KEY",
+            &["name", "hash", "created", "size", "mtime", "ext", "none"],
+        ),
+        help: "choose the order of entries",
+        more: ": name (default), hash (identical files side by side), created, mtime \
+               (oldest first), size (smallest first; directories by their total), ext, or \
+               none (filesystem order, ungrouped)",
+    },
+    Flag {
+        name: "--reverse",
+        aliases: &["-r"],
+        value: Value::None,
+        help: "reverse the sort order",
+        more: "; directories stay grouped",
+    },
+    Flag {
+        name: "--natural-sort",
+        aliases: &[],
+        value: Value::None,
+        help: "order digits in names by their value",
+        more: ": file2 before file10",
+    },
+    Flag {
+        name: "--locale-sort",
+        aliases: &[],
+        value: Value::None,
+        help: "order names by the alphabet of the collation locale",
+        more: " (LC_ALL, LC_COLLATE or LANG): accented letters next to their base letter, \
+               and e.g. Swedish å ä ö after z",
+    },
+    Flag {
+        name: "--no-group-dirs",
+        aliases: &[],
+        value: Value::None,
+        help: "sort directories in among files instead of first",
+        more: "",
+    },
+    Flag {
+        name: "--defer-errors",
+        aliases: &[],
+        value: Value::None,
+        help: "print walk warnings together after the output instead of inline",
+        more: "",
+    },
+    Flag {
+        name: "--errors-section",
+        aliases: &[],
+        value: Value::None,
+        help: "end the output with the entries that couldn't be read",
+        more: " (an `errors` array in json and json-tree)",
+    },
+    Flag {
+        name: "--strict",
+        aliases: &[],
+        value: Value::None,
+        help: "stop with an error at the first entry that can't be read",
+        more: "",
+    },
+    Flag {
+        name: "--quiet",
+        aliases: &["-q"],
+        value: Value::None,
+        help: "don't print warnings about entries that can't be read",
+        more: "",
+    },
+    Flag {
+        name: "--content-changed-only",
+        aliases: &[],
+        value: Value::None,
+        help: "like --content, but only for files git reports as modified or untracked",
+        more: "",
+    },
+    Flag {
+        name: "--depth-markers",
+        aliases: &[],
+        value: Value::None,
+        help: "mark each line with its depth",
+        more: ", e.g. `[2]`, and indent with plain spaces instead of tree glyphs",
+    },
+    Flag {
+        name: "--detect-encoding",
+        aliases: &[],
+        value: Value::None,
+        help: "annotate files with their likely encoding",
+        more: " and decode Latin-1 content for --content (UTF-16 is decoded either way)",
+    },
+    Flag {
+        name: "--verify",
+        aliases: &[],
+        value: Value::None,
+        help: "re-read the written output files and check they are well-formed",
+        more: "",
+    },
+    Flag {
+        name: "--context-depth",
+        aliases: &[],
+        value: Value::Any("N"),
+        help: "with a filter, show only matches plus the full contents of directories up \
+               to N levels above each match",
+        more: "",
+    },
+    Flag {
+        name: "--ignore-case",
+        aliases: &["-i"],
+        value: Value::None,
+        help: "match every pattern filter case-insensitively",
+        more: "",
+    },
+    Flag {
+        name: "--relative-time",
+        aliases: &[],
+        value: Value::None,
+        help: "show when each entry was modified",
+        more: ", e.g. `(3 days ago)`",
+    },
+    Flag {
+        name: "--strip-components",
+        aliases: &[],
+        value: Value::Any("N"),
+        help: "drop the first N components of displayed relative paths",
+        more: " (--relative-names, --format shasum)",
+    },
+    Flag {
+        name: "--created",
+        aliases: &["--btime"],
+        value: Value::None,
+        help: "show each entry's creation time",
+        more: " (UTC), `?` if unknown",
+    },
+    Flag {
+        name: "--content-base64",
+        aliases: &[],
+        value: Value::None,
+        help: "like --content, but emit file bytes base64-encoded",
+        more: "",
+    },
+    Flag {
+        name: "--skip-loops",
+        aliases: &[],
+        value: Value::None,
+        help: "never walk the same real directory twice",
+        more: " (bind mounts, loops)",
+    },
+    Flag {
+        name: "--one-file-system",
+        aliases: &["-x"],
+        value: Value::None,
+        help: "list mount points of other filesystems but don't descend into them",
+        more: ", like `du -x` (Unix)",
+    },
+    Flag {
+        name: "--tree-from-json",
+        aliases: &[],
+        value: Value::File("FILE"),
+        help: "re-render a tree saved with --format json-tree (or json) instead of \
+               scanning a directory",
+        more: "; hidden names, --exclude, --ignore, --dirs-only, --max-depth, --size, --du \
+               and --summary apply to it, options needing the files themselves are refused",
+    },
+    Flag {
+        name: "--snapshot",
+        aliases: &[],
+        value: Value::File("FILE"),
+        help: "save the tree to FILE as --format json-tree",
+        more: ", for --diff",
+    },
+    Flag {
+        name: "--hash",
+        aliases: &[],
+        value: Value::OptionalOneOf("sha256", &["sha256"]),
+        help: "show each file's SHA-256 next to it",
+        more: "; with --snapshot or --format json-tree, record it for --diff",
+    },
+    Flag {
+        name: "--find-duplicates",
+        aliases: &[],
+        value: Value::None,
+        help: "end the tree with the sets of files that have identical contents",
+        more: " (by SHA-256), largest waste first",
+    },
+    Flag {
+        name: "--archives",
+        aliases: &[],
+        value: Value::None,
+        help: "list the members of .zip, .tar and .tar.gz/.tgz files under them like a \
+               directory's",
+        more: "; only --max-depth applies inside",
+    },
+    Flag {
+        name: "--git-status",
+        aliases: &[],
+        value: Value::None,
+        help: "mark entries git sees as [modified], [staged], [untracked], [ignored] or \
+               [conflicted]",
+        more: "",
+    },
+    Flag {
+        name: "--diff",
+        aliases: &[],
+        value: Value::File("FILE"),
+        help: "compare the tree with a snapshot",
+        more: " and print `+ path` (added), `- path` (removed) and `~ path` (modified: \
+               size, mtime, or the hash if recorded) lines to stdout",
+    },
+    Flag {
+        name: "--count-extensions-threshold",
+        aliases: &[],
+        value: Value::OptionalNumber("N"),
+        help: "after the tree, list file extensions seen fewer than N times",
+        more: " (default 2), e.g. a stray `.exe`",
+    },
+    Flag {
+        name: "--width",
+        aliases: &["--render-width"],
+        value: Value::Any("N"),
+        help: "truncate tree lines to N terminal columns",
+        more: ", marking cut names with `…`",
+    },
+    Flag {
+        name: "--executables",
+        aliases: &["--only-executables"],
+        value: Value::None,
+        help: "only list files with an execute bit set",
+        more: " (on Windows: .exe, .bat, .cmd, .com); directories left empty are pruned",
+    },
+    Flag {
+        name: "--dirs-only",
+        aliases: &["-d"],
+        value: Value::None,
+        help: "list directories only",
+        more: ", like `tree -d`",
+    },
+    Flag {
+        name: "--files-only",
+        aliases: &[],
+        value: Value::None,
+        help: "list only files (and symlinks to files)",
+        more: ", leaving out directories with none below them",
+    },
+    Flag {
+        name: "--min-size",
+        aliases: &[],
+        value: Value::Any("SIZE"),
+        help: "only list files of at least SIZE bytes",
+        more: " (`10K`, `5M`, `1G`: powers of 1024), leaving out directories with none \
+               below",
+    },
+    Flag {
+        name: "--max-size",
+        aliases: &[],
+        value: Value::Any("SIZE"),
+        help: "only list files of at most SIZE bytes",
+        more: ", as for --min-size",
+    },
+    Flag {
+        name: "--newer-than",
+        aliases: &[],
+        value: Value::Any("WHEN"),
+        help: "only list files modified after WHEN",
+        more: ": a time ago (`90s`, `15m`, `2h`, `2d`, `3w`) or a UTC date (`2024-05-01`, \
+               `2024-05-01 14:30`), leaving out directories with none below",
+    },
+    Flag {
+        name: "--older-than",
+        aliases: &[],
+        value: Value::Any("WHEN"),
+        help: "only list files modified before WHEN",
+        more: ", as for --newer-than",
+    },
+    Flag {
+        name: "--only-empty",
+        aliases: &[],
+        value: Value::None,
+        help: "only list empty files and directories with nothing at all in them",
+        more: " (hidden entries included); with --newer-than or --older-than, such \
+               directories must match too",
+    },
+    Flag {
+        name: "--grep",
+        aliases: &[],
+        value: Value::Any("RE"),
+        help: "only list files whose contents match RE",
+        more: "",
+    },
+    Flag {
+        name: "--grep-context",
+        aliases: &[],
+        value: Value::Any("N"),
+        help: "with --grep, show each file's matching lines (numbered) plus N lines around \
+               each under it",
+        more: "; implies --content, but never whole files",
+    },
+    Flag {
+        name: "--stats-json",
+        aliases: &[],
+        value: Value::File("PATH"),
+        help: "also write counts, sizes, a per-extension breakdown, the largest file, the \
+               deepest path and timing to PATH as JSON",
+        more: "",
+    },
+    Flag {
+        name: "--exclude",
+        aliases: &[],
+        value: Value::Any("PATTERNS"),
+        help: "skip entries with these names or matching these globs",
+        more: " (comma-separated, repeatable), in addition to node_modules, .git and \
+               target; `dir/` only matches directories, and a pattern with another `/` \
+               matches the root-relative path, e.g. `docs/*.pdf`",
+    },
+    Flag {
+        name: "--exclude-from",
+        aliases: &[],
+        value: Value::File("FILE"),
+        help: "like --exclude, with one pattern per line of FILE",
+        more: " (blank lines and lines starting with `#` are skipped)",
+    },
+    Flag {
+        name: "--no-default-excludes",
+        aliases: &[],
+        value: Value::None,
+        help: "don't skip node_modules, .git and target",
+        more: "",
+    },
+    Flag {
+        name: "--gitignore",
+        aliases: &[],
+        value: Value::None,
+        help: "skip entries ignored by .gitignore and .ignore files in the tree",
+        more: "",
+    },
+    Flag {
+        name: "--match",
+        aliases: &[],
+        value: Value::Any("GLOB"),
+        help: "only list files whose name matches GLOB",
+        more: " (repeatable; `*`, `?`, `[...]`); directories are still searched",
+    },
+    Flag {
+        name: "--include",
+        aliases: &[],
+        value: Value::Any("GLOB"),
+        help: "like --match, and leave out directories with no match below",
+        more: "",
+    },
+    Flag {
+        name: "--ext",
+        aliases: &[],
+        value: Value::Any("EXTS"),
+        help: "only list files with these extensions",
+        more: " (comma-separated, e.g. `rs,toml`), leaving out directories with none below",
+    },
+    Flag {
+        name: "--ignore",
+        aliases: &[],
+        value: Value::Any("GLOB"),
+        help: "skip files and directories whose name matches GLOB",
+        more: " (repeatable); wins over --match",
+    },
+    Flag {
+        name: "--all",
+        aliases: &["-a"],
+        value: Value::None,
+        help: "also list hidden entries",
+        more: " (names starting with `.`; on Windows, also those with the hidden \
+               attribute), left out by default",
+    },
+    Flag {
+        name: "--no-hidden",
+        aliases: &[],
+        value: Value::None,
+        help: "leave hidden entries out again",
+        more: ", e.g. after `all = true` in a config file",
+    },
+    Flag {
+        name: "--no-config",
+        aliases: &[],
+        value: Value::None,
+        help: "ignore folderwalk.toml files",
+        more: "",
+    },
+    Flag {
+        name: "--icons",
+        aliases: &[],
+        value: Value::OptionalOneOf("STYLE", &["emoji", "nerd"]),
+        help: "put an icon for each entry's kind before its name",
+        more: ": emoji (default) or nerd (Nerd Font glyphs). The kind (code, image, \
+               archive, ...) comes from the extension, else from the file's first bytes; \
+               json, json-tree, csv and tsv output get a `kind` field instead",
+    },
+    Flag {
+        name: "--kind",
+        aliases: &[],
+        value: Value::Any("EXT=KIND"),
+        help: "treat files ending in .EXT as KIND",
+        more: " (repeatable), e.g. `proto=code`",
+    },
+    Flag {
+        name: "--icon",
+        aliases: &[],
+        value: Value::Any("KIND=ICON"),
+        help: "show ICON for KIND",
+        more: " (repeatable)",
+    },
+    Flag {
+        name: "--cache",
+        aliases: &[],
+        value: Value::File("FILE"),
+        help: "keep directory listings in FILE and only read a directory again when its \
+               modification time changed",
+        more: "; sizes and times of files in an unchanged directory may be out of date",
+    },
+    // Unstable developer option: times N renders of the same tree.
+    Flag {
+        name: "--repeat",
+        aliases: &[],
+        value: Value::Any("N"),
+        help: "",
+        more: "",
+    },
+];
+
+pub struct Subcommand {
+    pub name: &'static str,
+    pub help: &'static str,
+    /// The rest of the `--help` explanation, as for a [`Flag`].
+    more: &'static str,
+    /// The flag the name stands for; `tree` stands for none.
+    flag: Option<&'static str>,
+    /// What must follow the name, before any option.
+    pub operand: Operand,
+    pub options: Options,
+    /// Why an option left out of `options` is refused.
+    refusal: &'static str,
+}
+
+pub enum Operand {
+    None,
+    /// A file, named by the placeholder.
+    File(&'static str),
+    /// One of these words, named by the placeholder.
+    OneOf(&'static str, &'static [&'static str]),
+}
+
+/// The flags a subcommand accepts, by name.
+pub enum Options {
+    Except(&'static [&'static str]),
+    Only(&'static [&'static str]),
+}
+
+/// The options that choose what is walked, and how problems are reported:
+/// all `diff` needs, as it prints its own report.
+const WALK_OPTIONS: &[&str] = &[
+    "--max-depth",
+    "--jobs",
+    "--error-format",
+    "--symlinks-as-files",
+    "--follow-symlinks",
+    "--canonical",
+    "--fast",
+    "--path-regex",
+    "--prune",
+    "--defer-errors",
+    "--strict",
+    "--quiet",
+    "--ignore-case",
+    "--skip-loops",
+    "--one-file-system",
+    "--executables",
+    "--grep",
+    "--dirs-only",
+    "--files-only",
+    "--min-size",
+    "--max-size",
+    "--newer-than",
+    "--older-than",
+    "--only-empty",
+    "--exclude",
+    "--exclude-from",
+    "--no-default-excludes",
+    "--gitignore",
+    "--match",
+    "--include",
+    "--ext",
+    "--ignore",
+    "--all",
+    "--no-hidden",
+    "--no-config",
+];
+
+pub const SUBCOMMANDS: [Subcommand; 4] = [
+    Subcommand {
+        name: "tree",
+        help: "list the tree (the default)",
+        more: ", so it can be left out (name a directory called `tree` as `./tree`)",
+        flag: None,
+        operand: Operand::None,
+        options: Options::Except(&["--diff", "--watch"]),
+        refusal: "use `folderwalk diff` or `folderwalk watch` instead",
+    },
+    Subcommand {
+        name: "diff",
+        help: "compare the tree with a snapshot",
+        more: "; the same as --diff SNAPSHOT, taking only the options that choose what is \
+               walked (--exclude, --match, --max-depth, ...)",
+        flag: Some("--diff"),
+        operand: Operand::File("SNAPSHOT"),
+        options: Options::Only(WALK_OPTIONS),
+        refusal: "it prints its own report, so only options that choose what is walked apply",
+    },
+    Subcommand {
+        name: "watch",
+        help: "regenerate the output whenever the tree changes",
+        more: "; the same as --watch, refusing --interactive, --exec and --tree-from-json",
+        flag: Some("--watch"),
+        operand: Operand::None,
+        options: Options::Except(&[
+            "--diff",
+            "--watch",
+            "--interactive",
+            "--exec",
+            "--exec-parallel",
+            "--tree-from-json",
+        ]),
+        refusal: "it keeps rewriting the output of a scan",
+    },
+    Subcommand {
+        name: "completions",
+        help: "print a completion script",
+        more: " for bash, zsh, fish or powershell to stdout, e.g. `folderwalk completions \
+               bash > ~/.local/share/bash-completion/completions/folderwalk`",
+        flag: None,
+        operand: Operand::OneOf("SHELL", &SHELLS),
+        options: Options::Only(&[]),
+        refusal: "it only prints a script",
+    },
+];
+
+impl Subcommand {
+    /// Whether `flag` may follow the subcommand.
+    pub fn takes(&self, flag: &Flag) -> bool {
+        match self.options {
+            Options::Except(names) => !names.contains(&flag.name),
+            Options::Only(names) => names.contains(&flag.name),
+        }
+    }
+}
+
+/// Replaces a leading subcommand with the flags it stands for, after
+/// checking it is followed by its operand and only by options it takes:
+/// `diff SNAPSHOT` becomes `--diff SNAPSHOT`, `watch` becomes `--watch`
+/// and `tree` goes away. (`completions` is handled before any parsing.)
+pub fn expand_subcommand(mut args: Vec<String>) -> Result<Vec<String>, String> {
+    let Some(sub) = args
+        .first()
+        .and_then(|arg| SUBCOMMANDS.iter().find(|sub| sub.name == arg))
+    else {
+        return Ok(args);
+    };
+    let mut expanded: Vec<String> = sub.flag.iter().map(|flag| flag.to_string()).collect();
+    let mut rest = args.split_off(1).into_iter().peekable();
+    match sub.operand {
+        Operand::None => {}
+        Operand::File(placeholder) | Operand::OneOf(placeholder, _) => {
+            let operand = rest.next_if(|arg| !arg.starts_with('-')).ok_or_else(|| {
+                format!(
+                    "folderwalk {} requires {placeholder} before any options",
+                    sub.name
+                )
+            })?;
+            expanded.push(operand);
+        }
+    }
+
+    while let Some(arg) = rest.next() {
+        if let Some(flag) = flag(&arg) {
+            if !sub.takes(flag) {
+                return Err(format!(
+                    "folderwalk {} doesn't take {arg}: {}",
+                    sub.name, sub.refusal
+                ));
+            }
+            expanded.push(arg);
+            if flag.value.required() {
+                expanded.extend(rest.next());
+            }
+            continue;
+        }
+        expanded.push(arg);
+    }
+    Ok(expanded)
+}
+
+const PATHS_HELP: &str = "directories to scan (default: current directory). Each one gets \
+    its own tree and its own files.txt; with --stdout (or into one file, with --output) the \
+    trees follow one after another, separated by a blank line and a `=== path ===` header \
+    (see --root-separator). Paths that aren't directories are skipped with a warning";
+
+const FOOTER: &str = "\
+Defaults are read from folderwalk.toml in ~/.config/folderwalk/ and then in the
+first path, one key per flag (`max_depth = 3`, `ascii = true`,
+`exclude = [\"dist\"]`); the command line overrides them.
+Output: files.txt (files.html with --format html, .gz added with --gzip) is
+created in the target directory unless --output, --stdout or --clipboard is
+used.
+Exit status: 0 on success, 1 on failure, 2 for invalid arguments, 3 when some
+entries couldn't be read (or were refused by --format delete-list) and were
+left out.";
+
+/// The `--help` text: how to call each subcommand, a synopsis of the
+/// flags, then every subcommand and flag explained.
+pub fn usage() -> String {
+    let mut out = String::new();
+    for (i, sub) in SUBCOMMANDS.iter().enumerate() {
+        let lead = if i == 0 { "Usage:" } else { "" };
+        let mut line = match sub.flag {
+            None if matches!(sub.operand, Operand::None) => format!("[{}]", sub.name),
+            _ => sub.name.to_string(),
+        };
+        match sub.operand {
+            Operand::None => {}
+            Operand::File(placeholder) => line = format!("{line} {placeholder}"),
+            Operand::OneOf(_, words) => line = format!("{line} {}", words.join("|")),
+        }
+        if !matches!(sub.options, Options::Only(&[])) {
+            line.push_str(" [path...] [options]");
+        }
+        let _ = writeln!(out, "{lead:<6} folderwalk {line}");
+    }
+
+    let groups: Vec<String> = FLAGS
+        .iter()
+        .filter(|flag| flag.documented())
+        .map(|flag| match flag.value {
+            Value::None => format!("[{}]", flag.name),
+            Value::Any(placeholder) | Value::File(placeholder) => {
+                format!("[{} {placeholder}]", flag.name)
+            }
+            Value::OneOf(_, words) => format!("[{} {}]", flag.name, words.join("|")),
+            Value::OptionalNumber(placeholder) => format!("[{} [{placeholder}]]", flag.name),
+            Value::OptionalOneOf(_, words) => format!("[{} [{}]]", flag.name, words.join("|")),
+        })
+        .collect();
+    out.push_str(&fill("Options: ", &groups, "         "));
+
+    for sub in &SUBCOMMANDS {
+        let head = match sub.operand {
+            Operand::None => sub.name.to_string(),
+            Operand::File(placeholder) | Operand::OneOf(placeholder, _) => {
+                format!("{} {placeholder}", sub.name)
+            }
+        };
+        explain(&mut out, &head, &format!("{}{}", sub.help, sub.more));
+    }
+    explain(&mut out, "path...", PATHS_HELP);
+    for flag in FLAGS.iter().filter(|flag| flag.documented()) {
+        let placeholder = match flag.value {
+            Value::None => String::new(),
+            Value::Any(placeholder) | Value::File(placeholder) | Value::OneOf(placeholder, _) => {
+                format!(" {placeholder}")
+            }
+            Value::OptionalNumber(placeholder) | Value::OptionalOneOf(placeholder, _) => {
+                format!(" [{placeholder}]")
+            }
+        };
+        let head: Vec<String> = flag
+            .names()
+            .map(|name| format!("{name}{placeholder}"))
+            .collect();
+        explain(
+            &mut out,
+            &head.join(", "),
+            &format!("{}{}", flag.help, flag.more),
+        );
+    }
+    out.push_str(FOOTER);
+    out
+}
+
+/// `  - head: text`, the text starting in its own column and wrapped.
+fn explain(out: &mut String, head: &str, text: &str) {
+    let lead = format!("  - {head}:");
+    let lead = if lead.len() < HELP_COLUMN {
+        format!("{lead:<HELP_COLUMN$}")
+    } else {
+        format!("{lead} ")
+    };
+    out.push_str(&fill(&lead, &words(text), &" ".repeat(HELP_COLUMN)));
+}
+
+/// The words of `text`, a `code span` counting as one.
+fn words(text: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut in_code = false;
+    for word in text.split_whitespace() {
+        match words.last_mut() {
+            Some(last) if in_code => {
+                last.push(' ');
+                last.push_str(word);
+            }
+            _ => words.push(word.to_string()),
+        }
+        in_code ^= !word.matches('`').count().is_multiple_of(2);
+    }
+    words
+}
+
+/// `words` after `lead`, as many to a line as fit in [`WIDTH`] columns,
+/// the lines after the first starting with `indent`.
+fn fill(lead: &str, words: &[String], indent: &str) -> String {
+    let mut out = String::new();
+    let mut line = lead.to_string();
+    let mut empty = true;
+    for word in words {
+        let width = line.chars().count() + usize::from(!empty) + word.chars().count();
+        if !empty && width > WIDTH {
+            out.push_str(line.trim_end());
+            out.push('\n');
+            line = indent.to_string();
+            empty = true;
+        }
+        if !empty {
+            line.push(' ');
+        }
+        line.push_str(word);
+        empty = false;
+    }
+    out.push_str(line.trim_end());
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn option_sets_name_real_flags() {
+        for sub in &SUBCOMMANDS {
+            let (Options::Except(names) | Options::Only(names)) = sub.options;
+            for name in names {
+                assert!(
+                    FLAGS.iter().any(|f| f.name == *name),
+                    "{} lists {name}, which isn't a flag",
+                    sub.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_spelling_is_unique() {
+        let mut seen = std::collections::HashSet::new();
+        for name in FLAGS.iter().flat_map(Flag::names) {
+            assert!(seen.insert(name), "{name} is spelled twice");
+            assert!(name.starts_with('-'));
+        }
+    }
+
+    #[test]
+    fn subcommands_become_their_flags() {
+        let expand = |line| expand_subcommand(args(line));
+        assert_eq!(expand("tree src -o"), Ok(args("src -o")));
+        assert_eq!(
+            expand("diff snap.json src --exclude --format"),
+            Ok(args("--diff snap.json src --exclude --format"))
+        );
+        assert_eq!(expand("watch -o"), Ok(args("--watch -o")));
+        assert!(expand("diff --exclude x").is_err());
+        assert!(expand("diff snap.json --format json").is_err());
+        assert!(expand("watch --stdout --exec ls").is_err());
+        assert_eq!(expand("./tree --watch"), Ok(args("./tree --watch")));
+    }
+
+    #[test]
+    fn usage_explains_every_documented_flag() {
+        let usage = usage();
+        for flag in FLAGS {
+            let explained = usage.contains(&format!("\n  - {}", flag.name));
+            assert_eq!(explained, flag.documented(), "{}", flag.name);
+        }
+        assert!(usage.contains("\n  - --jobs N, -j N, --threads N: read directories"));
+        assert!(usage.contains(" [--bar [N]] "));
+        assert!(usage.contains("[--color auto|always|never]"));
+    }
+
+    #[test]
+    fn usage_is_wrapped() {
+        assert_eq!(
+            fill("  - x: ", &words("a `b c` d"), "    "),
+            "  - x: a `b c` d\n"
+        );
+        let long: Vec<String> = (0..30).map(|i| format!("w{i}")).collect();
+        for line in fill("Options: ", &long, "         ").lines() {
+            assert!(line.len() <= WIDTH, "{line}");
+        }
+        // Only a word or synopsis group too long for any line may overflow,
+        // alone on its own.
+        for line in usage().lines().filter(|line| line.chars().count() > WIDTH) {
+            let rest = line.trim_start();
+            let group = rest.starts_with('[') && !rest.contains("] [");
+            assert!(group || words(rest).len() == 1, "{line}");
+        }
+    }
+}
@@ -0,0 +1,349 @@
+//! Name order for `--natural-sort` and `--locale-sort`.
+//!
+//! Names normally compare lowercased, character by character, which puts
+//! `file10` before `file2` and `école` after `zebra`. `--natural-sort`
+//! compares runs of digits by their value instead, so `file2` comes
+//! first. `--locale-sort` compares letters the way a dictionary for the
+//! collation locale (`LC_ALL`, `LC_COLLATE` or `LANG`) would: accented
+//! letters sort with their base letter (`é` with `e`, `ß` as `ss`) and only
+//! break ties, lowercase before uppercase. Swedish and Finnish put `å`, `ä`
+//! and `ö` after `z`, Danish and Norwegian `æ`, `ø` and `å`, and Spanish
+//! sorts `ñ` after `n`. Other languages, and `C`/`POSIX`, get that default
+//! order. Either option leaves no two different names equal.
+
+use std::cmp::Ordering;
+use std::sync::OnceLock;
+
+use crate::WalkOptions;
+
+/// A name as it sorts per the `--sort name` options in `walk`, built once
+/// per entry so a sort doesn't redo the work on every comparison.
+pub struct NameKey {
+    /// The name lowercased, which is all that is compared without either
+    /// option.
+    lower: String,
+    /// Set with `--natural-sort` or `--locale-sort`.
+    key: Option<(Key, String)>,
+}
+
+impl NameKey {
+    pub fn new(name: &str, walk: &WalkOptions) -> NameKey {
+        NameKey::build(name, walk.natural_sort, walk.locale_sort.then(tailoring))
+    }
+
+    fn build(name: &str, natural: bool, locale: Option<Tailoring>) -> NameKey {
+        let key = (natural || locale.is_some())
+            .then(|| (Key::new(name, natural, locale), name.to_string()));
+        NameKey {
+            lower: name.to_lowercase(),
+            key,
+        }
+    }
+}
+
+impl Ord for NameKey {
+    fn cmp(&self, other: &NameKey) -> Ordering {
+        let (Some((ka, a)), Some((kb, b))) = (&self.key, &other.key) else {
+            return self.lower.cmp(&other.lower);
+        };
+        ka.units
+            .cmp(&kb.units)
+            .then_with(|| ka.accents.cmp(&kb.accents))
+            .then_with(|| ka.uppercase.cmp(&kb.uppercase))
+            .then_with(|| self.lower.cmp(&other.lower))
+            .then_with(|| a.cmp(b))
+    }
+}
+
+impl PartialOrd for NameKey {
+    fn partial_cmp(&self, other: &NameKey) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for NameKey {
+    fn eq(&self, other: &NameKey) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for NameKey {}
+
+/// The languages whose alphabets order some letters differently.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Tailoring {
+    Default,
+    /// Swedish, Finnish: `å ä ö` after `z`.
+    Swedish,
+    /// Danish, Norwegian: `æ ø å` after `z`.
+    Danish,
+    /// Spanish: `ñ` after `n`.
+    Spanish,
+}
+
+/// The tailoring for the collation locale, looked up once per run.
+fn tailoring() -> Tailoring {
+    static TAILORING: OnceLock<Tailoring> = OnceLock::new();
+    *TAILORING.get_or_init(|| {
+        let locale = ["LC_ALL", "LC_COLLATE", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|v| !v.is_empty())
+            .unwrap_or_default();
+        let language = locale
+            .split(['_', '.', '@', '-'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match language.as_str() {
+            "sv" | "fi" => Tailoring::Swedish,
+            "da" | "nb" | "nn" | "no" => Tailoring::Danish,
+            "es" => Tailoring::Spanish,
+            _ => Tailoring::Default,
+        }
+    })
+}
+
+/// What a name is compared by: first `units`, then accents, then case.
+struct Key {
+    units: Vec<Unit>,
+    accents: Vec<u8>,
+    uppercase: Vec<bool>,
+}
+
+#[derive(PartialEq, Eq)]
+enum Unit {
+    /// A character's weight; see [`weight`].
+    Char(u32),
+    /// A run of digits, without leading zeros.
+    Number(String),
+}
+
+impl Ord for Unit {
+    fn cmp(&self, other: &Unit) -> Ordering {
+        // Numbers sort where their first digit would.
+        let digit = weight('0');
+        match (self, other) {
+            (Unit::Char(a), Unit::Char(b)) => a.cmp(b),
+            (Unit::Number(a), Unit::Number(b)) => a.len().cmp(&b.len()).then_with(|| a.cmp(b)),
+            (Unit::Number(_), Unit::Char(c)) => digit.cmp(c).then(Ordering::Less),
+            (Unit::Char(c), Unit::Number(_)) => c.cmp(&digit).then(Ordering::Greater),
+        }
+    }
+}
+
+impl PartialOrd for Unit {
+    fn partial_cmp(&self, other: &Unit) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Key {
+    fn new(name: &str, natural: bool, tailoring: Option<Tailoring>) -> Key {
+        let mut key = Key {
+            units: Vec::new(),
+            accents: Vec::new(),
+            uppercase: Vec::new(),
+        };
+        let mut chars = name.chars().peekable();
+        while let Some(c) = chars.next() {
+            if natural && c.is_ascii_digit() {
+                let mut digits = String::from(c);
+                while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                    digits.push(d);
+                    chars.next();
+                }
+                let value = digits.trim_start_matches('0');
+                key.units.push(Unit::Number(value.to_string()));
+                continue;
+            }
+            for lower in c.to_lowercase() {
+                match tailoring {
+                    Some(tailoring) => key.push_letter(lower, tailoring),
+                    None => key.units.push(Unit::Char(weight(lower))),
+                }
+            }
+            key.uppercase.push(c.is_uppercase());
+        }
+        key
+    }
+
+    /// Adds a lowercase character under `--locale-sort`.
+    fn push_letter(&mut self, c: char, tailoring: Tailoring) {
+        if let Some(w) = tailored(c, tailoring) {
+            self.units.push(Unit::Char(w));
+            self.accents.push(0);
+            return;
+        }
+        match fold(c) {
+            Some((base, accent)) => {
+                for b in base.chars() {
+                    self.units.push(Unit::Char(weight(b)));
+                }
+                self.accents.push(accent);
+            }
+            None => {
+                self.units.push(Unit::Char(weight(c)));
+                self.accents.push(0);
+            }
+        }
+    }
+}
+
+/// A character's primary weight: its code point, spaced out so tailored
+/// letters fit in between.
+fn weight(c: char) -> u32 {
+    u32::from(c) * 4
+}
+
+/// The weight of a letter the language places apart, e.g. Swedish `ö`
+/// after `z`.
+fn tailored(c: char, tailoring: Tailoring) -> Option<u32> {
+    let after = |letter: char, n: u32| Some(weight(letter) + n);
+    match (tailoring, c) {
+        (Tailoring::Swedish, 'å') => after('z', 1),
+        (Tailoring::Swedish, 'ä' | 'æ') => after('z', 2),
+        (Tailoring::Swedish, 'ö' | 'ø') => after('z', 3),
+        (Tailoring::Danish, 'æ' | 'ä') => after('z', 1),
+        (Tailoring::Danish, 'ø' | 'ö') => after('z', 2),
+        (Tailoring::Danish, 'å') => after('z', 3),
+        (Tailoring::Spanish, 'ñ') => after('n', 1),
+        _ => None,
+    }
+}
+
+/// A Latin letter with a diacritic as its base letters and a nonzero rank
+/// for the accent; `None` for everything else.
+fn fold(c: char) -> Option<(&'static str, u8)> {
+    const LETTERS: [(&str, &str); 20] = [
+        ("a", "àáâãäåāăą"),
+        ("c", "çćĉċč"),
+        ("d", "ďđð"),
+        ("e", "èéêëēĕėęě"),
+        ("g", "ĝğġģ"),
+        ("h", "ĥħ"),
+        ("i", "ìíîïĩīĭįı"),
+        ("j", "ĵ"),
+        ("k", "ķ"),
+        ("l", "ĺļľŀł"),
+        ("n", "ñńņňŉ"),
+        ("o", "òóôõöøōŏő"),
+        ("r", "ŕŗř"),
+        ("s", "śŝşš"),
+        ("t", "ţťŧ"),
+        ("u", "ùúûüũūŭůűų"),
+        ("w", "ŵ"),
+        ("y", "ýÿŷ"),
+        ("z", "źżž"),
+        ("ss", "ß"),
+    ];
+    match c {
+        'æ' => return Some(("ae", 1)),
+        'œ' => return Some(("oe", 1)),
+        'þ' => return Some(("th", 1)),
+        _ => {}
+    }
+    LETTERS.iter().find_map(|(base, accented)| {
+        let rank = accented.chars().position(|a| a == c)?;
+        Some((*base, rank as u8 + 1))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `names` sorted with `--natural-sort` as `natural` and `locale` as
+    /// the `--locale-sort` tailoring.
+    fn sorted(names: &[&str], natural: bool, locale: Option<Tailoring>) -> Vec<String> {
+        let mut keyed: Vec<_> = names
+            .iter()
+            .map(|name| (NameKey::build(name, natural, locale), name.to_string()))
+            .collect();
+        keyed.sort_by(|a, b| a.0.cmp(&b.0));
+        keyed.into_iter().map(|(_, name)| name).collect()
+    }
+
+    #[test]
+    fn plain_order_is_lowercased_code_points() {
+        assert_eq!(
+            sorted(&["file2", "File10", "école", "zebra"], false, None),
+            ["File10", "file2", "zebra", "école"]
+        );
+    }
+
+    #[test]
+    fn natural_order_compares_numbers_by_value() {
+        assert_eq!(
+            sorted(
+                &["file10", "file2", "file1", "v1.10.0", "v1.9.2"],
+                true,
+                None
+            ),
+            ["file1", "file2", "file10", "v1.9.2", "v1.10.0"]
+        );
+        // Equal values fall back to the text, so the order is total.
+        assert_eq!(
+            sorted(&["a1", "a01", "a001"], true, None),
+            ["a001", "a01", "a1"]
+        );
+        // A number sorts where its first digit would among other characters.
+        assert_eq!(sorted(&["a_", "a9", "a-"], true, None), ["a-", "a9", "a_"]);
+    }
+
+    #[test]
+    fn locale_order_puts_accents_with_their_base_letter() {
+        let order = sorted(
+            &[
+                "zebra", "ecole", "école", "egg", "Ärger", "apple", "straße", "strasse", "strat",
+            ],
+            false,
+            Some(Tailoring::Default),
+        );
+        assert_eq!(
+            order,
+            [
+                "apple", "Ärger", "ecole", "école", "egg", "strasse", "straße", "strat", "zebra"
+            ]
+        );
+    }
+
+    #[test]
+    fn locale_ties_are_broken_by_case_only_after_letters_and_accents() {
+        let locale = Some(Tailoring::Default);
+        assert_eq!(
+            sorted(&["Apple", "apple"], false, locale),
+            ["apple", "Apple"]
+        );
+        assert_eq!(
+            sorted(&["Ecole", "école"], false, locale),
+            ["Ecole", "école"]
+        );
+        assert_eq!(sorted(&["Ab", "aC"], false, locale), ["Ab", "aC"]);
+    }
+
+    #[test]
+    fn tailorings_place_their_letters() {
+        let names = ["öl", "zebra", "oxe", "åsna", "ärta", "nube", "ñu", "ola"];
+        assert_eq!(
+            sorted(&names, false, Some(Tailoring::Swedish)),
+            ["ñu", "nube", "ola", "oxe", "zebra", "åsna", "ärta", "öl"]
+        );
+        assert_eq!(
+            sorted(&names, false, Some(Tailoring::Danish)),
+            ["ñu", "nube", "ola", "oxe", "zebra", "ärta", "öl", "åsna"]
+        );
+        assert_eq!(
+            sorted(&names, false, Some(Tailoring::Spanish)),
+            ["ärta", "åsna", "nube", "ñu", "öl", "ola", "oxe", "zebra"]
+        );
+    }
+
+    #[test]
+    fn natural_and_locale_combine() {
+        assert_eq!(
+            sorted(&["é10", "e9", "E2"], true, Some(Tailoring::Default)),
+            ["E2", "e9", "é10"]
+        );
+    }
+}
@@ -0,0 +1,311 @@
+//! `folderwalk completions SHELL`: completion scripts for bash, zsh, fish
+//! and PowerShell.
+//!
+//! The flags and subcommands come from the table in [`cli`](crate::cli)
+//! that `--help` is generated from, so the scripts offer exactly what
+//! `--help` documents, and after a subcommand only the options it takes.
+
+use crate::cli::{FLAGS, Flag, Operand, SUBCOMMANDS, Subcommand, Value};
+
+/// The completion script for `shell`, or `None` for a shell not in
+/// [`SHELLS`](crate::cli::SHELLS).
+pub fn script(shell: &str) -> Option<String> {
+    let flags: Vec<&Flag> = FLAGS.iter().filter(|flag| flag.documented()).collect();
+    Some(match shell {
+        "bash" => bash(&flags),
+        "zsh" => zsh(&flags),
+        "fish" => fish(&flags),
+        "powershell" => powershell(&flags),
+        _ => return None,
+    })
+}
+
+/// The spellings of every flag `sub` takes; all of them with no subcommand.
+fn names(flags: &[&Flag], sub: Option<&Subcommand>) -> Vec<&'static str> {
+    flags
+        .iter()
+        .filter(|flag| sub.is_none_or(|sub| sub.takes(flag)))
+        .flat_map(|flag| flag.names())
+        .collect()
+}
+
+/// The subcommands that refuse `flag`.
+fn refusing(flag: &Flag) -> Vec<&'static str> {
+    SUBCOMMANDS
+        .iter()
+        .filter(|sub| !sub.takes(flag))
+        .map(|sub| sub.name)
+        .collect()
+}
+
+fn bash(flags: &[&Flag]) -> String {
+    let mut cases = String::new();
+    for flag in flags {
+        let action = match flag.value {
+            Value::None | Value::OptionalNumber(_) | Value::OptionalOneOf(..) => continue,
+            Value::Any(_) => "return".to_string(),
+            Value::File(_) => "COMPREPLY=($(compgen -f -- \"$cur\")); return".to_string(),
+            Value::OneOf(_, words) => format!(
+                "COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return",
+                words.join(" ")
+            ),
+        };
+        cases.push_str(&format!(
+            "        {})\n            {action} ;;\n",
+            flag.names().collect::<Vec<_>>().join("|")
+        ));
+    }
+    let mut operands = String::new();
+    let mut option_sets = String::new();
+    for sub in &SUBCOMMANDS {
+        match sub.operand {
+            Operand::None => {}
+            Operand::File(_) => operands.push_str(&format!(
+                "        {})\n            COMPREPLY=($(compgen -f -- \"$cur\")); return ;;\n",
+                sub.name
+            )),
+            Operand::OneOf(_, words) => operands.push_str(&format!(
+                "        {})\n            COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return ;;\n",
+                sub.name,
+                words.join(" ")
+            )),
+        }
+        let names = names(flags, Some(sub));
+        if names.is_empty() {
+            option_sets.push_str(&format!("    {}) return ;;\n", sub.name));
+        } else {
+            option_sets.push_str(&format!(
+                "    {}) names=\"{}\" ;;\n",
+                sub.name,
+                names.join(" ")
+            ));
+        }
+    }
+    let subcommands: Vec<&str> = SUBCOMMANDS.iter().map(|sub| sub.name).collect();
+    format!(
+        r#"# bash completion for folderwalk
+_folderwalk() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" prev="${{COMP_WORDS[COMP_CWORD-1]}}" names
+    if [[ $COMP_CWORD -eq 2 ]]; then
+        case "$prev" in
+{operands}        esac
+    fi
+    case "$prev" in
+{cases}    esac
+    case "${{COMP_WORDS[1]}}" in
+{option_sets}    *) names="{names}" ;;
+    esac
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "$names" -- "$cur"))
+    elif [[ $COMP_CWORD -eq 1 ]]; then
+        COMPREPLY=($(compgen -W "{subcommands}" -- "$cur") $(compgen -d -- "$cur"))
+    else
+        COMPREPLY=($(compgen -d -- "$cur"))
+    fi
+}}
+complete -o filenames -F _folderwalk folderwalk
+"#,
+        names = names(flags, None).join(" "),
+        subcommands = subcommands.join(" "),
+    )
+}
+
+fn zsh(flags: &[&Flag]) -> String {
+    // Inside '...' and the [...] of an _arguments spec.
+    let escape = |s: &str| {
+        s.replace('\'', "'\\''")
+            .replace('[', "(")
+            .replace(']', ")")
+            .replace(':', "\\:")
+    };
+    let specs = |sub: Option<&Subcommand>| {
+        let mut specs = String::new();
+        for flag in flags.iter().filter(|f| sub.is_none_or(|sub| sub.takes(f))) {
+            let action = match flag.value {
+                Value::None | Value::OptionalNumber(_) | Value::OptionalOneOf(..) => String::new(),
+                Value::Any(_) => ":value: ".to_string(),
+                Value::File(_) => ":file:_files".to_string(),
+                Value::OneOf(_, words) => format!(":value:({})", words.join(" ")),
+            };
+            for name in flag.names() {
+                specs.push_str(&format!(
+                    "        '*{name}[{}]{action}'\n",
+                    escape(flag.help)
+                ));
+            }
+        }
+        specs
+    };
+    let mut operands = String::new();
+    let mut option_sets = String::new();
+    for sub in &SUBCOMMANDS {
+        match sub.operand {
+            Operand::None => {}
+            Operand::File(_) => {
+                operands.push_str(&format!("        {}) _files; return ;;\n", sub.name))
+            }
+            Operand::OneOf(placeholder, words) => operands.push_str(&format!(
+                "        {}) _values {} {}; return ;;\n",
+                sub.name,
+                placeholder.to_lowercase(),
+                words.join(" ")
+            )),
+        }
+        let specs = specs(Some(sub));
+        if specs.is_empty() {
+            option_sets.push_str(&format!("    {}) return ;;\n", sub.name));
+        } else {
+            option_sets.push_str(&format!("    {}) specs=(\n{specs}    ) ;;\n", sub.name));
+        }
+    }
+    let subcommands: Vec<String> = SUBCOMMANDS
+        .iter()
+        .map(|sub| format!("{}\\:'{}'", sub.name, sub.help))
+        .collect();
+    format!(
+        r#"#compdef folderwalk
+
+_folderwalk() {{
+    if (( CURRENT == 2 )) && [[ $words[2] != -* ]]; then
+        _alternative \
+            "commands:command:(({subcommands}))" \
+            'directories:directory:_directories'
+        return
+    fi
+    if (( CURRENT == 3 )); then
+        case $words[2] in
+{operands}        esac
+    fi
+    local -a specs
+    case $words[2] in
+{option_sets}    *) specs=(
+{all}    ) ;;
+    esac
+    _arguments -s $specs '*:directory:_directories'
+}}
+
+_folderwalk "$@"
+"#,
+        subcommands = subcommands.join(" "),
+        all = specs(None),
+    )
+}
+
+fn fish(flags: &[&Flag]) -> String {
+    let quote = |s: &str| format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"));
+    let mut out = String::from(
+        "# fish completion for folderwalk\n\
+         complete -c folderwalk -f\n\
+         complete -c folderwalk -a '(__fish_complete_directories (commandline -ct))'\n",
+    );
+    for sub in &SUBCOMMANDS {
+        out.push_str(&format!(
+            "complete -c folderwalk -n __fish_use_subcommand -a {} -d {}\n",
+            sub.name,
+            quote(sub.help)
+        ));
+    }
+    for sub in &SUBCOMMANDS {
+        let seen = quote(&format!("__fish_seen_subcommand_from {}", sub.name));
+        match sub.operand {
+            Operand::None => {}
+            Operand::File(_) => out.push_str(&format!("complete -c folderwalk -n {seen} -F\n")),
+            Operand::OneOf(_, words) => out.push_str(&format!(
+                "complete -c folderwalk -n {seen} -a {}\n",
+                quote(&words.join(" "))
+            )),
+        }
+    }
+    for flag in flags {
+        let mut line = String::from("complete -c folderwalk");
+        let refusing = refusing(flag);
+        if !refusing.is_empty() {
+            let condition = format!("not __fish_seen_subcommand_from {}", refusing.join(" "));
+            line.push_str(&format!(" -n {}", quote(&condition)));
+        }
+        for name in flag.names() {
+            match name.strip_prefix("--") {
+                Some(long) => line.push_str(&format!(" -l {long}")),
+                None => line.push_str(&format!(" -s {}", name.trim_start_matches('-'))),
+            }
+        }
+        match flag.value {
+            Value::None | Value::OptionalNumber(_) | Value::OptionalOneOf(..) => {}
+            Value::Any(_) => line.push_str(" -x"),
+            Value::File(_) => line.push_str(" -r -F"),
+            Value::OneOf(_, words) => line.push_str(&format!(" -x -a {}", quote(&words.join(" ")))),
+        }
+        line.push_str(&format!(" -d {}", quote(flag.help)));
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+fn powershell(flags: &[&Flag]) -> String {
+    let quote = |s: &str| format!("'{}'", s.replace('\'', "''"));
+    let mut choices = Vec::new();
+    for sub in &SUBCOMMANDS {
+        if let Operand::OneOf(_, words) = sub.operand {
+            let words: Vec<String> = words.iter().map(|w| quote(w)).collect();
+            choices.push(format!(
+                "        {} = @({})",
+                quote(sub.name),
+                words.join(", ")
+            ));
+        }
+    }
+    let mut names = Vec::new();
+    for flag in flags {
+        let refusing: Vec<String> = refusing(flag).into_iter().map(quote).collect();
+        for name in flag.names() {
+            if let Value::OneOf(_, words) = flag.value {
+                let words: Vec<String> = words.iter().map(|w| quote(w)).collect();
+                choices.push(format!("        {} = @({})", quote(name), words.join(", ")));
+            }
+            names.push(format!(
+                "        @({}, {}, @({}))",
+                quote(name),
+                quote(flag.help),
+                refusing.join(", ")
+            ));
+        }
+    }
+    let subcommands: Vec<String> = SUBCOMMANDS
+        .iter()
+        .map(|sub| format!("@({}, {})", quote(sub.name), quote(sub.help)))
+        .collect();
+    format!(
+        r#"# PowerShell completion for folderwalk
+Register-ArgumentCompleter -Native -CommandName folderwalk -ScriptBlock {{
+    param($wordToComplete, $commandAst, $cursorPosition)
+    $words = @($commandAst.CommandElements | ForEach-Object {{ $_.ToString() }})
+    $choices = @{{
+{choices}
+    }}
+    # Each flag with the subcommands that don't take it.
+    $flags = @(
+{names}
+    )
+    $position = if ($wordToComplete) {{ $words.Count - 1 }} else {{ $words.Count }}
+    $subcommand = if ($position -gt 1) {{ $words[1] }} else {{ '' }}
+    $prev = $words[$position - 1]
+    if ($choices.ContainsKey($prev)) {{
+        $candidates = $choices[$prev] | ForEach-Object {{ ,@($_, $_) }}
+    }} elseif ($wordToComplete -like '-*') {{
+        $candidates = $flags | Where-Object {{ $_[2] -notcontains $subcommand }}
+    }} elseif ($position -eq 1) {{
+        $candidates = @({subcommands})
+    }} else {{
+        return
+    }}
+    $candidates | Where-Object {{ $_[0] -like "$wordToComplete*" }} | ForEach-Object {{
+        [System.Management.Automation.CompletionResult]::new($_[0], $_[0], 'ParameterValue', $_[1])
+    }}
+}}
+"#,
+        choices = choices.join("\n"),
+        names = names.join(",\n"),
+        subcommands = subcommands.join(", "),
+    )
+}
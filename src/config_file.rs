@@ -0,0 +1,348 @@
+//! `folderwalk.toml`: default options, one key per command-line flag.
+//!
+//! Each `key = value` stands for `--key value`, with `_` in the key read as
+//! `-`: `max_depth = 3` is `--max-depth 3`, `ascii = true` is `--ascii`
+//! (and `false` leaves it out) and an array repeats the flag, so
+//! `exclude = ["dist", "*.log"]` is `--exclude dist --exclude '*.log'`.
+//! Only that much TOML is read: top-level keys with strings, integers,
+//! booleans or arrays of strings and integers, and `#` comments.
+//!
+//! The file in `~/.config/folderwalk/` is read first, then the one in the
+//! directory being listed, then the command line, so a value can be
+//! replaced at each step.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const CONFIG_FILE_NAME: &str = "folderwalk.toml";
+
+/// The config files that apply to a run over `root`, in the order they are
+/// read: the user's, then the project's.
+pub fn config_files(root: &Path) -> Vec<PathBuf> {
+    let user_dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME")
+                .or_else(|| env::var_os("USERPROFILE"))
+                .map(|home| PathBuf::from(home).join(".config"))
+        });
+    let mut files = Vec::new();
+    if let Some(dir) = user_dir {
+        files.push(dir.join("folderwalk").join(CONFIG_FILE_NAME));
+    }
+    files.push(root.join(CONFIG_FILE_NAME));
+    files.retain(|path| path.is_file());
+    files
+}
+
+/// Reads a config file into the command-line arguments it stands for.
+pub fn config_file_args(path: &Path) -> io::Result<Vec<String>> {
+    args_from(&fs::read_to_string(path)?)
+}
+
+fn args_from(text: &str) -> io::Result<Vec<String>> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        pos: 0,
+        line: 1,
+    };
+    parser.args().map_err(|(line, message)| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("line {line}: {message}"),
+        )
+    })
+}
+
+enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+type ParseResult<T> = Result<T, (usize, String)>;
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn fail<T>(&self, message: impl Into<String>) -> ParseResult<T> {
+        Err((self.line, message.into()))
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.bump();
+        }
+    }
+
+    /// Skips spaces, line breaks and comments.
+    fn skip_blank(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t' | '\r' | '\n') => {
+                    self.bump();
+                }
+                Some('#') => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.bump();
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn args(&mut self) -> ParseResult<Vec<String>> {
+        let mut args = Vec::new();
+        loop {
+            self.skip_blank();
+            let Some(c) = self.peek() else {
+                return Ok(args);
+            };
+            if c == '[' {
+                return self.fail("tables are not supported; set options at the top level");
+            }
+            let key = self.key()?;
+            self.skip_spaces();
+            if self.bump() != Some('=') {
+                return self.fail(format!("expected `=` after `{key}`"));
+            }
+            self.skip_spaces();
+            let value = self.value()?;
+            self.skip_spaces();
+            match self.peek() {
+                None | Some('\n' | '\r' | '#') => {}
+                Some(c) => return self.fail(format!("unexpected `{c}` after the value")),
+            }
+
+            let flag = format!("--{}", key.replace('_', "-"));
+            match value {
+                Value::Bool(true) => args.push(flag),
+                Value::Bool(false) => {}
+                Value::Str(s) => args.extend([flag, s]),
+                Value::Int(n) => args.extend([flag, n.to_string()]),
+                Value::Array(items) => {
+                    for item in items {
+                        match item {
+                            Value::Str(s) => args.extend([flag.clone(), s]),
+                            Value::Int(n) => args.extend([flag.clone(), n.to_string()]),
+                            _ => {
+                                return self
+                                    .fail(format!("`{key}` can only hold strings and integers"));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn key(&mut self) -> ParseResult<String> {
+        if matches!(self.peek(), Some('"' | '\'')) {
+            return self.string();
+        }
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            self.bump();
+        }
+        if self.pos == start {
+            return self.fail("expected a key");
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn value(&mut self) -> ParseResult<Value> {
+        match self.peek() {
+            Some('"' | '\'') => Ok(Value::Str(self.string()?)),
+            Some('[') => {
+                self.bump();
+                let mut items = Vec::new();
+                loop {
+                    self.skip_blank();
+                    if self.peek() == Some(']') {
+                        self.bump();
+                        return Ok(Value::Array(items));
+                    }
+                    items.push(self.value()?);
+                    self.skip_blank();
+                    match self.bump() {
+                        Some(',') => {}
+                        Some(']') => return Ok(Value::Array(items)),
+                        _ => return self.fail("expected `,` or `]` in an array"),
+                    }
+                }
+            }
+            _ => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-'))
+                {
+                    self.bump();
+                }
+                let word: String = self.chars[start..self.pos].iter().collect();
+                match word.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    _ => match word.replace('_', "").parse() {
+                        Ok(n) => Ok(Value::Int(n)),
+                        Err(_) if word.is_empty() => self.fail("expected a value"),
+                        Err(_) => self.fail(format!("unsupported value `{word}`")),
+                    },
+                }
+            }
+        }
+    }
+
+    /// A `"basic"` string with escapes, or a `'literal'` one without.
+    fn string(&mut self) -> ParseResult<String> {
+        let quote = self.bump();
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                None | Some('\n') => return self.fail("unterminated string"),
+                Some(c) if Some(c) == quote => return Ok(out),
+                Some('\\') if quote == Some('"') => {
+                    let escaped = match self.bump() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some(u @ ('u' | 'U')) => {
+                            let len = if u == 'u' { 4 } else { 8 };
+                            let hex: String = (0..len).filter_map(|_| self.bump()).collect();
+                            match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                                Some(c) => c,
+                                None => return self.fail(format!("invalid escape `\\{u}{hex}`")),
+                            }
+                        }
+                        Some(c) => return self.fail(format!("invalid escape `\\{c}`")),
+                        None => return self.fail("unterminated string"),
+                    };
+                    out.push(escaped);
+                }
+                Some(c) => out.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(text: &str) -> Vec<String> {
+        args_from(text).unwrap()
+    }
+
+    fn error(text: &str) -> String {
+        match args_from(text) {
+            Ok(args) => panic!("read {args:?}"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn keys_become_flags() {
+        let text = r#"
+# Defaults for this project.
+max_depth = 3      # a comment after a value
+ascii = true
+hidden = false
+format = "json"
+"sort-by" = 'name'
+exclude = [
+    "dist",  # built files
+    '*.log',
+    1_000,
+]
+min_size = -1
+"#;
+        assert_eq!(
+            args(text),
+            [
+                "--max-depth",
+                "3",
+                "--ascii",
+                "--format",
+                "json",
+                "--sort-by",
+                "name",
+                "--exclude",
+                "dist",
+                "--exclude",
+                "*.log",
+                "--exclude",
+                "1000",
+                "--min-size",
+                "-1",
+            ]
+        );
+        assert!(args("").is_empty());
+        assert_eq!(args("exclude = []\r\nascii = true\r\n"), ["--ascii"]);
+    }
+
+    #[test]
+    fn strings_unescape() {
+        assert_eq!(
+            args(r#"match = "a\tb\"c\\dé\U0001F600""#)[1],
+            "a\tb\"c\\dé😀"
+        );
+        // Literal strings keep backslashes.
+        assert_eq!(args(r"match = '\d+\.rs'")[1], r"\d+\.rs");
+    }
+
+    #[test]
+    fn errors_name_their_line() {
+        assert_eq!(
+            error("ascii = true\n[walk]\n"),
+            "line 2: tables are not supported; set options at the top level"
+        );
+        assert_eq!(error("ascii true"), "line 1: expected `=` after `ascii`");
+        assert_eq!(error("= 1"), "line 1: expected a key");
+        assert_eq!(error("a = "), "line 1: expected a value");
+        assert_eq!(error("a = 1.5"), "line 1: unexpected `.` after the value");
+        assert_eq!(error("a = yes"), "line 1: unsupported value `yes`");
+        assert_eq!(error("a = 1 2"), "line 1: unexpected `2` after the value");
+        assert_eq!(error("\n\na = \"open\n"), "line 4: unterminated string");
+        assert_eq!(error(r#"a = "\x""#), r"line 1: invalid escape `\x`");
+        assert_eq!(error(r#"a = "\uzzzz""#), r"line 1: invalid escape `\uzzzz`");
+        assert_eq!(
+            error("a = [1 2]"),
+            "line 1: expected `,` or `]` in an array"
+        );
+        assert_eq!(
+            error("a = [true]"),
+            "line 1: `a` can only hold strings and integers"
+        );
+        assert_eq!(
+            error("a = [[\"x\"]]"),
+            "line 1: `a` can only hold strings and integers"
+        );
+    }
+}
@@ -0,0 +1,132 @@
+//! `--format csv` and `--format tsv`: one row per listed entry, for
+//! spreadsheets and data frames.
+//!
+//! Rows come in walk order under a header naming the columns. `depth` is 0
+//! for the root's own entries, `size` is in bytes and empty for
+//! directories, `mtime` is UTC and `target` is only set on symlinks; with
+//! `--icons`, a last `kind` column holds each entry's kind. CSV
+//! quotes fields as RFC 4180 describes; TSV has no quoting, so tabs, line
+//! breaks and backslashes in a field are written as `\t`, `\n`, `\r` and
+//! `\\`.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::{WalkContext, format_timestamp, is_walkable_dir, kind, list_dir, relative_path, winfs};
+
+pub const HEADER: [&str; 6] = ["path", "depth", "type", "size", "mtime", "target"];
+
+pub fn write_table(ctx: &mut WalkContext, writer: &mut dyn Write, sep: char) -> io::Result<()> {
+    let mut header = HEADER.map(str::to_string).to_vec();
+    if ctx.config.icons.is_some() {
+        header.push("kind".to_string());
+    }
+    write_row(writer, sep, &header)?;
+    let start = ctx.config.start_dir.clone();
+    write_rows(&start, ctx, writer, sep, 0)
+}
+
+fn write_rows(
+    dir: &Path,
+    ctx: &mut WalkContext,
+    writer: &mut dyn Write,
+    sep: char,
+    depth: usize,
+) -> io::Result<()> {
+    if ctx.config.walk.max_depth.is_some_and(|maxd| depth >= maxd) {
+        return Ok(());
+    }
+    for entry in list_dir(dir, ctx, depth)? {
+        ctx.record(&entry)?;
+        let entry_type = if entry.file_type.is_symlink() {
+            "symlink"
+        } else if entry.file_type.is_dir() {
+            "dir"
+        } else {
+            "file"
+        };
+        let size = if entry.file_type.is_dir() {
+            String::new()
+        } else {
+            entry.len.to_string()
+        };
+        let target = if entry.file_type.is_symlink() {
+            winfs::read_link(&entry.path)
+                .map(|t| t.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+        let mut row = vec![
+            relative_path(ctx.config, &entry.path, false),
+            depth.to_string(),
+            entry_type.to_string(),
+            size,
+            entry.modified.map(format_timestamp).unwrap_or_default(),
+            target,
+        ];
+        if ctx.config.icons.is_some() {
+            row.push(kind::kind_of(&entry, ctx.config).to_string());
+        }
+        write_row(writer, sep, &row)?;
+        if is_walkable_dir(&entry) && ctx.first_visit(&entry.path) {
+            write_rows(&entry.path, ctx, writer, sep, depth + 1)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_row(writer: &mut dyn Write, sep: char, fields: &[String]) -> io::Result<()> {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            if sep == '\t' {
+                field
+                    .replace('\\', "\\\\")
+                    .replace('\t', "\\t")
+                    .replace('\n', "\\n")
+                    .replace('\r', "\\r")
+            } else if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect();
+    writeln!(writer, "{}", fields.join(&sep.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(sep: char, fields: &[&str]) -> String {
+        let mut out = Vec::new();
+        let fields: Vec<String> = fields.iter().map(|f| f.to_string()).collect();
+        write_row(&mut out, sep, &fields).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn csv_quotes_fields_that_need_it() {
+        assert_eq!(row(',', &["a.txt", "0", ""]), "a.txt,0,\n");
+        assert_eq!(
+            row(
+                ',',
+                &["a,b", "say \"hi\"", "two\nlines", "cr\r", "tab\there"]
+            ),
+            "\"a,b\",\"say \"\"hi\"\"\",\"two\nlines\",\"cr\r\",tab\there\n"
+        );
+    }
+
+    #[test]
+    fn tsv_escapes_instead_of_quoting() {
+        assert_eq!(
+            row(
+                '\t',
+                &["a\tb", "two\nlines\r", "back\\slash", "\"quoted\",x"]
+            ),
+            "a\\tb\ttwo\\nlines\\r\tback\\\\slash\t\"quoted\",x\n"
+        );
+    }
+}
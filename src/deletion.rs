@@ -0,0 +1,134 @@
+//! `--format delete-list`: the paths a filter picked out, each terminated
+//! by a NUL byte, for `xargs -0 rm -d --`.
+//!
+//! Paths are the walked ones, the root as given joined with the names
+//! below it, so they work from the directory folderwalk was run in. Names
+//! are written byte for byte: a NUL can't occur in one, so nothing needs
+//! quoting and spaces, quotes or newlines reach `rm` unchanged. A path
+//! that would start with `-` gets `./` in front so it can't be taken for
+//! an option. Directories come after what is listed inside them, and only
+//! with `--only-empty`, when they have nothing in them at all.
+//!
+//! Every path is checked before it is written, with symlinks in its
+//! directory resolved, and refused (with a warning, and exit status 3) if
+//! it is `/` or directly under it, or the root, the current directory or
+//! `$HOME`, or a directory above one of them. The root itself is never
+//! listed, and neither is anything reached through a symlink, as the
+//! format can't be combined with `--follow-symlinks`.
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{WalkContext, is_empty_dir, is_walkable_dir, list_dir, warn};
+
+pub fn write_delete_list(ctx: &mut WalkContext, writer: &mut dyn Write) -> io::Result<()> {
+    let config = ctx.config;
+    if !config.walk.filters_files() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--format delete-list needs a filter such as --only-empty or --older-than",
+        ));
+    }
+    if config.walk.follow_symlinks {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--format delete-list cannot be combined with --follow-symlinks",
+        ));
+    }
+    let denylist = Denylist::new(&config.start_dir);
+    write_dir(&config.start_dir, &denylist, ctx, writer, 0)
+}
+
+fn write_dir(
+    dir: &Path,
+    denylist: &Denylist,
+    ctx: &mut WalkContext,
+    writer: &mut dyn Write,
+    depth: usize,
+) -> io::Result<()> {
+    if let Some(maxd) = ctx.config.walk.max_depth
+        && depth >= maxd
+    {
+        return Ok(());
+    }
+
+    for entry in list_dir(dir, ctx, depth)? {
+        ctx.record(&entry)?;
+        if is_walkable_dir(&entry) {
+            if ctx.first_visit(&entry.path) {
+                write_dir(&entry.path, denylist, ctx, writer, depth + 1)?;
+            }
+            if !is_empty_dir(ctx.config, &entry) {
+                continue;
+            }
+        }
+        match denylist.refusal(&entry.path) {
+            Some(why) => warn(ctx, "refused", &entry.path, &io::Error::other(why))?,
+            None => write_path(writer, &entry.path)?,
+        }
+    }
+    Ok(())
+}
+
+/// Writes `path` and its terminating NUL.
+fn write_path(writer: &mut dyn Write, path: &Path) -> io::Result<()> {
+    let bytes = path.as_os_str().as_encoded_bytes();
+    if bytes.starts_with(b"-") {
+        writer.write_all(b"./")?;
+    }
+    writer.write_all(bytes)?;
+    writer.write_all(b"\0")
+}
+
+/// The directories no listed path may be or lie above, resolved once.
+struct Denylist {
+    protected: Vec<(PathBuf, &'static str)>,
+}
+
+impl Denylist {
+    fn new(root: &Path) -> Denylist {
+        let home = env::var_os("HOME")
+            .or_else(|| env::var_os("USERPROFILE"))
+            .filter(|home| !home.is_empty())
+            .map(PathBuf::from);
+        let protected = [
+            (Some(root.to_path_buf()), "the root"),
+            (env::current_dir().ok(), "the current directory"),
+            (home, "$HOME"),
+        ]
+        .into_iter()
+        .filter_map(|(dir, what)| Some((fs::canonicalize(dir?).ok()?, what)))
+        .collect();
+        Denylist { protected }
+    }
+
+    /// Why `path` must not be listed, if it must not.
+    fn refusal(&self, path: &Path) -> Option<String> {
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return Some("not a path below the root".to_string());
+        };
+        // The entry itself stays unresolved: a symlink is removed, not
+        // what it points at.
+        let real = match fs::canonicalize(parent) {
+            Ok(parent) => parent.join(name),
+            Err(err) => return Some(format!("its directory can't be resolved: {err}")),
+        };
+        if real.parent().and_then(Path::parent).is_none() {
+            return Some(format!(
+                "{} is / or directly under it",
+                real.to_string_lossy()
+            ));
+        }
+        self.protected
+            .iter()
+            .find(|(dir, _)| dir.starts_with(&real))
+            .map(|(_, what)| {
+                format!(
+                    "{} is {what} or a directory above it",
+                    real.to_string_lossy()
+                )
+            })
+    }
+}
@@ -0,0 +1,219 @@
+//! `--diff`: what changed since a `--snapshot`.
+//!
+//! The saved json-tree document and a fresh walk (with the same excludes
+//! and filters) are both flattened to root-relative paths and compared.
+//! Files count as modified when their size or modification time (to the
+//! second) differ, or, if the snapshot was taken with `--hash`, when their
+//! SHA-256 does; symlinks when their target does. An entry that changed
+//! type (a file replaced by a directory, say) is modified too. Directories
+//! themselves are only ever added or removed.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::{
+    Config, DirEntryInfo, WalkError, hash_file, relative_path, report_deferred_errors, sha256,
+    snapshot, walk_with, winfs,
+};
+
+/// What is compared of one entry.
+struct State {
+    kind: &'static str,
+    size: Option<u64>,
+    modified: Option<u64>,
+    target: Option<String>,
+    sha256: Option<String>,
+}
+
+/// Writes one `+ path`, `- path` or `~ path` line per difference, sorted by
+/// path, and a count line, to stdout. Returns how many entries of the
+/// current tree couldn't be read.
+pub fn run_diff(config: &Config, path: &Path) -> io::Result<usize> {
+    let root = snapshot::load(path).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {e}", path.to_string_lossy()),
+        )
+    })?;
+    let mut before = BTreeMap::new();
+    flatten(&root.children, &mut before);
+
+    // The snapshot may well sit inside the tree it describes.
+    let own = fs::canonicalize(path).ok();
+    let mut after = BTreeMap::new();
+    let mut unreadable = Vec::new();
+    let mut errors = walk_with(&config.start_dir, &config.walk, |entry, _| {
+        if own.is_some()
+            && entry.path.file_name() == path.file_name()
+            && fs::canonicalize(&entry.path).ok() == own
+        {
+            return;
+        }
+        let rel = relative_path(config, &entry.path, false);
+        let hashed = before.get(&rel).is_some_and(|s: &State| s.sha256.is_some());
+        let state = match current_state(entry, hashed) {
+            Ok(state) => state,
+            Err(err) => {
+                unreadable.push(WalkError {
+                    kind: "read_file",
+                    path: entry.path.clone(),
+                    message: err.to_string(),
+                });
+                return;
+            }
+        };
+        after.insert(rel, state);
+    })?;
+
+    let mut out = io::stdout().lock();
+    let (mut added, mut removed, mut modified) = (0, 0, 0);
+    let mut paths: Vec<&String> = before.keys().chain(after.keys()).collect();
+    paths.sort();
+    paths.dedup();
+    for rel in paths {
+        let (mark, state) = match (before.get(rel), after.get(rel)) {
+            (None, Some(now)) => {
+                added += 1;
+                ('+', now)
+            }
+            (Some(then), None) => {
+                removed += 1;
+                ('-', then)
+            }
+            (Some(then), Some(now)) if changed(then, now) => {
+                modified += 1;
+                ('~', now)
+            }
+            _ => continue,
+        };
+        let slash = if state.kind == "folder" { "/" } else { "" };
+        writeln!(out, "{mark} {rel}{slash}")?;
+    }
+    writeln!(out, "{added} added, {removed} removed, {modified} modified")?;
+    out.flush()?;
+    drop(out);
+
+    errors.extend(unreadable);
+    report_deferred_errors(config, &errors);
+    Ok(errors.len())
+}
+
+fn flatten(nodes: &[snapshot::Node], out: &mut BTreeMap<String, State>) {
+    for node in nodes {
+        let kind = node.kind.icon();
+        let file = node.kind == snapshot::NodeKind::File;
+        out.insert(
+            node.id.clone(),
+            State {
+                kind,
+                size: file.then(|| node.meta_u64("size")).flatten(),
+                modified: file.then(|| node.meta_u64("modified")).flatten(),
+                target: node.target().map(str::to_string),
+                sha256: node.meta_str("sha256").map(str::to_string),
+            },
+        );
+        flatten(&node.children, out);
+    }
+}
+
+/// `hashed`: the snapshot holds a hash for this path, so one is needed now.
+fn current_state(entry: &DirEntryInfo, hashed: bool) -> io::Result<State> {
+    let (kind, file) = if entry.file_type.is_symlink() {
+        ("symlink", false)
+    } else if entry.file_type.is_dir() {
+        ("folder", false)
+    } else {
+        ("file", true)
+    };
+    let target = if entry.file_type.is_symlink() {
+        Some(
+            winfs::read_link(&entry.path)
+                .map(|t| t.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        )
+    } else {
+        None
+    };
+    let sha256 = if file && hashed {
+        Some(sha256::to_hex(&hash_file(&entry.path)?))
+    } else {
+        None
+    };
+    Ok(State {
+        kind,
+        size: file.then_some(entry.len),
+        modified: entry
+            .modified
+            .filter(|_| file)
+            .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs()),
+        target,
+        sha256,
+    })
+}
+
+fn changed(then: &State, now: &State) -> bool {
+    if then.kind != now.kind || then.target != now.target {
+        return true;
+    }
+    match (&then.sha256, &now.sha256) {
+        (Some(a), Some(b)) => a != b,
+        _ => then.size != now.size || then.modified != now.modified,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(size: u64, modified: u64, sha256: Option<&str>) -> State {
+        State {
+            kind: "file",
+            size: Some(size),
+            modified: Some(modified),
+            target: None,
+            sha256: sha256.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn files_change_by_size_and_time_unless_hashed() {
+        assert!(!changed(&file(1, 100, None), &file(1, 100, None)));
+        assert!(changed(&file(1, 100, None), &file(2, 100, None)));
+        assert!(changed(&file(1, 100, None), &file(1, 101, None)));
+        // A recorded hash decides alone: a touched file is unchanged, an
+        // edit that kept size and time is not.
+        assert!(!changed(
+            &file(1, 100, Some("ab")),
+            &file(1, 101, Some("ab"))
+        ));
+        assert!(changed(
+            &file(1, 100, Some("ab")),
+            &file(1, 100, Some("cd"))
+        ));
+    }
+
+    #[test]
+    fn links_change_by_target_and_entries_by_type() {
+        let link = |target: &str| State {
+            kind: "symlink",
+            size: None,
+            modified: None,
+            target: Some(target.to_string()),
+            sha256: None,
+        };
+        assert!(!changed(&link("a"), &link("a")));
+        assert!(changed(&link("a"), &link("b")));
+        let folder = State {
+            kind: "folder",
+            size: None,
+            modified: None,
+            target: None,
+            sha256: None,
+        };
+        assert!(changed(&folder, &file(0, 0, None)));
+    }
+}
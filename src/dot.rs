@@ -0,0 +1,69 @@
+//! `--format dot`: the directory structure as a Graphviz digraph, for
+//! `dot -Tsvg files.txt > layout.svg`.
+//!
+//! Only directories become nodes, with an edge from each to the
+//! directories inside it; files are summed up in their directory's label
+//! so a large project still renders as a readable map. Node ids are the
+//! root-relative paths, `.` for the root.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::{WalkContext, display_root_name, is_walkable_dir, list_dir, relative_path};
+
+pub fn write_dot(ctx: &mut WalkContext, writer: &mut dyn Write) -> io::Result<()> {
+    let config = ctx.config;
+    let root = display_root_name(&config.start_dir).unwrap_or_else(|| ".".to_string());
+    writeln!(writer, "digraph folderwalk {{")?;
+    writeln!(writer, "  rankdir=LR;")?;
+    writeln!(writer, "  node [shape=folder];")?;
+    write_dir_node(&config.start_dir, ".", &root, ctx, writer, 0)?;
+    writeln!(writer, "}}")
+}
+
+/// The node for `dir` (id `id`), then an edge to and the subgraph of each
+/// directory listed in it.
+fn write_dir_node(
+    dir: &Path,
+    id: &str,
+    name: &str,
+    ctx: &mut WalkContext,
+    writer: &mut dyn Write,
+    depth: usize,
+) -> io::Result<()> {
+    let entries = if ctx.config.walk.max_depth.is_some_and(|maxd| depth >= maxd) {
+        None
+    } else {
+        Some(list_dir(dir, ctx, depth)?)
+    };
+    let mut label = format!("{name}/");
+    if let Some(entries) = &entries {
+        let files = entries.iter().filter(|e| !is_walkable_dir(e)).count();
+        if files > 0 {
+            let noun = if files == 1 { "file" } else { "files" };
+            label.push_str(&format!("\n{files} {noun}"));
+        }
+    }
+    writeln!(writer, "  {} [label={}];", quote(id), quote(&label))?;
+
+    for entry in entries.unwrap_or_default() {
+        ctx.record(&entry)?;
+        if !is_walkable_dir(&entry) || !ctx.first_visit(&entry.path) {
+            continue;
+        }
+        let child = relative_path(ctx.config, &entry.path, false);
+        writeln!(writer, "  {} -> {};", quote(id), quote(&child))?;
+        let name = entry.file_name.to_string_lossy();
+        write_dir_node(&entry.path, &child, &name, ctx, writer, depth + 1)?;
+    }
+    Ok(())
+}
+
+/// A DOT double-quoted string.
+fn quote(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
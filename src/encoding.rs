@@ -0,0 +1,202 @@
+//! Small text-encoding heuristic for `--detect-encoding`.
+//!
+//! Only the first `SNIFF_BYTES` of a file are inspected: byte order marks
+//! win, then NUL patterns (UTF-16 without BOM, or binary), then UTF-8
+//! validity, and finally a Latin-1 guess when the bytes are mostly
+//! printable.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+pub const SNIFF_BYTES: usize = 8 * 1024;
+
+/// Code units a file without a BOM needs before its NULs are taken for
+/// UTF-16 rather than binary.
+const MIN_UTF16_UNITS: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    Empty,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    Binary,
+}
+
+impl Encoding {
+    pub fn label(self) -> &'static str {
+        match self {
+            Encoding::Empty => "empty",
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Latin1 => "Latin-1",
+            Encoding::Binary => "binary",
+        }
+    }
+
+    /// Decodes a whole file's bytes. Binary data is not decoded.
+    pub fn decode(self, bytes: &[u8]) -> Option<String> {
+        match self {
+            Encoding::Empty => Some(String::new()),
+            Encoding::Utf8 => {
+                let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+                Some(String::from_utf8_lossy(bytes).into_owned())
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let bom: &[u8] = if self == Encoding::Utf16Le {
+                    b"\xFF\xFE"
+                } else {
+                    b"\xFE\xFF"
+                };
+                let bytes = bytes.strip_prefix(bom).unwrap_or(bytes);
+                let units = bytes.chunks_exact(2).map(|pair| {
+                    if self == Encoding::Utf16Le {
+                        u16::from_le_bytes([pair[0], pair[1]])
+                    } else {
+                        u16::from_be_bytes([pair[0], pair[1]])
+                    }
+                });
+                Some(
+                    char::decode_utf16(units)
+                        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                        .collect(),
+                )
+            }
+            Encoding::Latin1 => Some(bytes.iter().map(|&b| b as char).collect()),
+            Encoding::Binary => None,
+        }
+    }
+}
+
+pub fn detect_file(path: &Path) -> io::Result<Encoding> {
+    let mut buf = Vec::with_capacity(SNIFF_BYTES);
+    File::open(path)?
+        .take(SNIFF_BYTES as u64)
+        .read_to_end(&mut buf)?;
+    Ok(detect(&buf))
+}
+
+pub fn detect(buf: &[u8]) -> Encoding {
+    if buf.is_empty() {
+        return Encoding::Empty;
+    }
+    if buf.starts_with(b"\xEF\xBB\xBF") {
+        return Encoding::Utf8;
+    }
+    if buf.starts_with(b"\xFF\xFE") {
+        return Encoding::Utf16Le;
+    }
+    if buf.starts_with(b"\xFE\xFF") {
+        return Encoding::Utf16Be;
+    }
+
+    if buf.contains(&0) {
+        // ASCII-range text in UTF-16 leaves every other byte zero. Too few
+        // bytes, or an odd number, say nothing either way.
+        let pairs = buf.len() / 2;
+        if pairs < MIN_UTF16_UNITS || !buf.len().is_multiple_of(2) {
+            return Encoding::Binary;
+        }
+        let even_zero = buf.iter().step_by(2).filter(|&&b| b == 0).count();
+        let odd_zero = buf.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+        if odd_zero * 10 >= pairs * 7 && even_zero * 10 <= pairs {
+            return Encoding::Utf16Le;
+        }
+        if even_zero * 10 >= pairs * 7 && odd_zero * 10 <= pairs {
+            return Encoding::Utf16Be;
+        }
+        return Encoding::Binary;
+    }
+
+    match std::str::from_utf8(buf) {
+        Ok(_) => return Encoding::Utf8,
+        // A multi-byte character cut off by the sniff limit is still UTF-8.
+        Err(e) if e.error_len().is_none() && buf.len() == SNIFF_BYTES => return Encoding::Utf8,
+        Err(_) => {}
+    }
+
+    let control = buf
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0C | 0x1B))
+        .count();
+    if control * 100 <= buf.len() {
+        Encoding::Latin1
+    } else {
+        Encoding::Binary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(text: &str, little_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| {
+                if little_endian {
+                    unit.to_le_bytes()
+                } else {
+                    unit.to_be_bytes()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn byte_order_marks_win() {
+        assert_eq!(detect(b""), Encoding::Empty);
+        assert_eq!(detect(b"\xEF\xBB\xBFplain"), Encoding::Utf8);
+        assert_eq!(detect(b"\xFF\xFEh\0"), Encoding::Utf16Le);
+        assert_eq!(detect(b"\xFE\xFF\0h"), Encoding::Utf16Be);
+    }
+
+    #[test]
+    fn utf16_needs_enough_text() {
+        assert_eq!(detect(&utf16("hello, world", true)), Encoding::Utf16Le);
+        assert_eq!(detect(&utf16("hello, world", false)), Encoding::Utf16Be);
+        // Mostly non-ASCII text still has enough zeros in one lane.
+        assert_eq!(detect(&utf16("naïve café menu", true)), Encoding::Utf16Le);
+        for short in [
+            &b"x\0y"[..],
+            b"a\0b\0c\0d\0",
+            &utf16("hello, world", true)[1..],
+        ] {
+            assert_eq!(detect(short), Encoding::Binary, "{short:?}");
+        }
+        assert_eq!(
+            detect(b"\0\0\0\0\x7fELF\x02\x01\x01\0\0\0\0\0\0\0"),
+            Encoding::Binary
+        );
+    }
+
+    #[test]
+    fn text_without_nuls_is_utf8_or_latin1() {
+        assert_eq!(detect("grüße\n".as_bytes()), Encoding::Utf8);
+        assert_eq!(detect(b"gr\xfc\xdfe\n"), Encoding::Latin1);
+        assert_eq!(
+            detect(b"\x01\x02\x03\x04\xff mostly control"),
+            Encoding::Binary
+        );
+        // A character cut off by the sniff limit.
+        let mut cut = vec![b'a'; SNIFF_BYTES - 1];
+        cut.push(0xC3);
+        assert_eq!(detect(&cut), Encoding::Utf8);
+        cut.truncate(100);
+        cut.push(0xC3);
+        assert_eq!(detect(&cut), Encoding::Latin1);
+    }
+
+    #[test]
+    fn decoding_drops_the_bom() {
+        let mut bytes = b"\xFF\xFE".to_vec();
+        bytes.extend(utf16("é😀\n", true));
+        assert_eq!(Encoding::Utf16Le.decode(&bytes).unwrap(), "é😀\n");
+        assert_eq!(Encoding::Utf16Be.decode(&utf16("ok", false)).unwrap(), "ok");
+        assert_eq!(Encoding::Utf8.decode(b"\xEF\xBB\xBFok").unwrap(), "ok");
+        assert_eq!(Encoding::Latin1.decode(b"\xe9t\xe9").unwrap(), "été");
+        assert_eq!(Encoding::Binary.decode(b"\0"), None);
+    }
+}
@@ -0,0 +1,236 @@
+//! `--exec CMD`: run a command for every file listed, as `find -exec`
+//! does, after folderwalk's excludes and filters have had their say.
+//!
+//! CMD is split into words once, with `'...'`, `"..."` and `\` quoting as
+//! in a shell but no shell involved, so a path is always exactly one
+//! argument: every `{}` in a word, the command's own included, is replaced
+//! by the file's path, and a command without `{}` gets the path as its
+//! last argument. Commands start
+//! as their files are listed, up to `--exec-parallel` at a time, and the
+//! walk only waits when that many are still running. Their output goes to
+//! the terminal, or to stderr when the tree itself is going to stdout.
+
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use crate::{Config, ErrorFormat, WalkError};
+
+/// How often running commands are checked on while waiting for one.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+pub struct Exec {
+    words: Vec<String>,
+    parallel: usize,
+    output_to_stderr: bool,
+    error_format: ErrorFormat,
+    quiet: bool,
+    running: Vec<(PathBuf, Child)>,
+    /// Commands started so far.
+    pub started: usize,
+    /// Commands that exited unsuccessfully.
+    pub failed: usize,
+}
+
+impl Exec {
+    /// The runner for `config.exec`, if set.
+    pub fn new(config: &Config) -> Option<Exec> {
+        Some(Exec {
+            words: config.exec.clone()?,
+            parallel: config.exec_parallel.max(1),
+            output_to_stderr: config.to_stdout && !config.clipboard,
+            error_format: config.error_format,
+            quiet: config.quiet,
+            running: Vec::new(),
+            started: 0,
+            failed: 0,
+        })
+    }
+
+    /// Starts the command for `path`, first waiting for a free slot. A
+    /// command that can't be started at all fails the run, as it would
+    /// fail for every file.
+    pub fn spawn(&mut self, path: &Path) -> io::Result<()> {
+        self.reap(false);
+        while self.running.len() >= self.parallel {
+            self.reap(true);
+        }
+        let program = substitute(&self.words[0], path);
+        let mut command = Command::new(&program);
+        command.args(self.words[1..].iter().map(|word| substitute(word, path)));
+        if self.output_to_stderr {
+            command.stdout(Stdio::from(io::stderr()));
+        }
+        let child = command.spawn().map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("--exec cannot run {}: {e}", program.to_string_lossy()),
+            )
+        })?;
+        self.started += 1;
+        self.running.push((path.to_path_buf(), child));
+        Ok(())
+    }
+
+    /// Waits for every command still running.
+    pub fn finish(&mut self) {
+        while !self.running.is_empty() {
+            self.reap(true);
+        }
+    }
+
+    /// Collects the commands that have exited, reporting failures; with
+    /// `block`, waits until at least one has.
+    fn reap(&mut self, block: bool) {
+        loop {
+            let mut i = 0;
+            let mut reaped = false;
+            while i < self.running.len() {
+                let status = match self.running[i].1.try_wait() {
+                    Ok(Some(status)) => Ok(status),
+                    Ok(None) => {
+                        i += 1;
+                        continue;
+                    }
+                    Err(e) => Err(e),
+                };
+                let (path, _) = self.running.swap_remove(i);
+                reaped = true;
+                let message = match status {
+                    Ok(status) if status.success() => continue,
+                    Ok(status) => format!("{} ({status})", self.words[0]),
+                    Err(e) => format!("{}: {e}", self.words[0]),
+                };
+                self.failed += 1;
+                if !self.quiet {
+                    WalkError {
+                        kind: "exec",
+                        path,
+                        message,
+                    }
+                    .report(self.error_format);
+                }
+            }
+            if reaped || !block || self.running.is_empty() {
+                return;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// `word` with every `{}` replaced by `path`, kept as an `OsString` so a
+/// path that isn't UTF-8 reaches the command unchanged.
+fn substitute(word: &str, path: &Path) -> OsString {
+    let mut arg = OsString::new();
+    for (i, piece) in word.split("{}").enumerate() {
+        if i > 0 {
+            arg.push(path.as_os_str());
+        }
+        arg.push(piece);
+    }
+    arg
+}
+
+/// Splits `--exec`'s CMD into words, adding a `{}` word when it has none.
+pub fn split_command(cmd: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = cmd.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated ' in --exec command".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err("unterminated \" in --exec command".to_string()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated \" in --exec command".to_string()),
+                    }
+                }
+            }
+            '\\' => {
+                let word = word.get_or_insert_with(String::new);
+                word.extend(chars.next());
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    if words.is_empty() {
+        return Err("--exec needs a command".to_string());
+    }
+    if !words.iter().any(|w| w.contains("{}")) {
+        words.push("{}".to_string());
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(cmd: &str) -> Vec<String> {
+        split_command(cmd).unwrap()
+    }
+
+    #[test]
+    fn words_split_on_unquoted_whitespace() {
+        assert_eq!(split("  wc  -l {}  "), ["wc", "-l", "{}"]);
+        assert_eq!(split("echo 'a b' \"c d\""), ["echo", "a b", "c d", "{}"]);
+        assert_eq!(split("echo a' 'b\"\""), ["echo", "a b", "{}"]);
+        assert_eq!(split("echo ''"), ["echo", "", "{}"]);
+    }
+
+    #[test]
+    fn backslashes_escape_outside_single_quotes() {
+        assert_eq!(split(r"echo a\ b"), ["echo", "a b", "{}"]);
+        assert_eq!(split(r"echo '\n'"), ["echo", r"\n", "{}"]);
+        assert_eq!(split(r#"echo "\"\\\n""#), ["echo", r#""\\n"#, "{}"]);
+    }
+
+    #[test]
+    fn bad_commands_are_rejected() {
+        assert!(split_command("echo 'open").is_err());
+        assert!(split_command("echo \"open").is_err());
+        assert!(split_command("echo \"open\\").is_err());
+        assert!(split_command("   ").is_err());
+    }
+
+    #[test]
+    fn the_path_goes_where_braces_are() {
+        assert_eq!(split("cp {} {}.bak"), ["cp", "{}", "{}.bak"]);
+        assert_eq!(split("{}"), ["{}"]);
+        // Quoting doesn't hide the braces.
+        assert_eq!(split("echo '{}'"), ["echo", "{}"]);
+        let path = Path::new("dir/a b.txt");
+        assert_eq!(substitute("{}.bak", path), "dir/a b.txt.bak");
+        assert_eq!(
+            substitute("--in={}:{}", path),
+            "--in=dir/a b.txt:dir/a b.txt"
+        );
+        assert_eq!(substitute("plain", path), "plain");
+    }
+}
@@ -0,0 +1,81 @@
+//! Filesystem identification for `--show-fs`.
+//!
+//! Device ids come from `st_dev` on Unix. On Linux they are mapped to a
+//! filesystem type by stat-ing every mount point listed in `/proc/mounts`;
+//! elsewhere the raw device id is shown. Other platforms report nothing.
+
+use std::collections::HashMap;
+use std::fs;
+
+pub struct FsInfo {
+    types: HashMap<u64, String>,
+}
+
+impl FsInfo {
+    pub fn load() -> Self {
+        let mut types = HashMap::new();
+        if let Ok(mounts) = fs::read_to_string("/proc/mounts") {
+            for line in mounts.lines() {
+                let mut fields = line.split_whitespace();
+                let (Some(_source), Some(target), Some(fstype)) =
+                    (fields.next(), fields.next(), fields.next())
+                else {
+                    continue;
+                };
+                if let Some(dev) = fs::metadata(unescape_mount_path(target))
+                    .ok()
+                    .as_ref()
+                    .and_then(device_id)
+                {
+                    // Later mounts shadow earlier ones on the same point.
+                    types.insert(dev, fstype.to_string());
+                }
+            }
+        }
+        FsInfo { types }
+    }
+
+    /// Label for a device, e.g. `ext4`, or `dev 0x801` when the type is unknown.
+    pub fn describe(&self, dev: u64) -> String {
+        match self.types.get(&dev) {
+            Some(name) => name.clone(),
+            None => format!("dev {dev:#x}"),
+        }
+    }
+}
+
+#[cfg(unix)]
+pub fn device_id(meta: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(meta.dev())
+}
+
+#[cfg(not(unix))]
+pub fn device_id(_meta: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// `/proc/mounts` encodes whitespace and backslashes as octal escapes.
+fn unescape_mount_path(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && i + 4 <= bytes.len()
+            && bytes[i + 1..i + 4]
+                .iter()
+                .all(|b| (b'0'..=b'7').contains(b))
+        {
+            let v = bytes[i + 1..i + 4]
+                .iter()
+                .fold(0u32, |acc, b| acc * 8 + u32::from(b - b'0'));
+            out.push(v as u8);
+            i += 4;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
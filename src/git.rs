@@ -0,0 +1,135 @@
+//! Queries against the git repository containing the scanned directory.
+//! Everything shells out to the `git` binary; when it is missing or the
+//! directory is not inside a work tree, callers get `None`.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Paths git reports as modified, added, renamed or untracked, relative to
+/// `root` and joined with `/`. Changes outside `root` are dropped.
+pub fn changed_paths(root: &Path) -> Option<HashSet<String>> {
+    let (toplevel, sub) = locate(root)?;
+    let status = git_output(
+        &toplevel,
+        &["status", "--porcelain=v1", "-z", "--untracked-files=all"],
+    )?;
+
+    let mut changed = HashSet::new();
+    for (xy, path) in status_records(&status) {
+        if xy == *b"!!" || xy.contains(&b'D') {
+            continue;
+        }
+        if let Some(rel) = below(&sub, &path) {
+            changed.insert(rel.to_string());
+        }
+    }
+    Some(changed)
+}
+
+/// Status labels for `--git-status`, keyed like [`changed_paths`]: files
+/// that are `staged`, `modified` (in the work tree), both, `conflicted`,
+/// `untracked` or `ignored`. A directory that is untracked or ignored as a
+/// whole is listed once, with a trailing `/`; see [`status_of`].
+pub fn statuses(root: &Path) -> Option<HashMap<String, &'static str>> {
+    let (toplevel, sub) = locate(root)?;
+    let status = git_output(&toplevel, &["status", "--porcelain=v1", "-z", "--ignored"])?;
+
+    let mut labels = HashMap::new();
+    for (xy, path) in status_records(&status) {
+        let [x, y] = xy;
+        let label = match (x, y) {
+            (b'?', b'?') => "untracked",
+            (b'!', b'!') => "ignored",
+            (b'U', _) | (_, b'U') | (b'A', b'A') | (b'D', b'D') => "conflicted",
+            (b' ', _) => "modified",
+            (_, b' ') => "staged",
+            _ => "staged, modified",
+        };
+        if let Some(rel) = below(&sub, &path) {
+            labels.insert(rel.to_string(), label);
+        }
+    }
+    Some(labels)
+}
+
+/// The label for the entry at `rel` (a directory's without its trailing
+/// `/`), which falls back to the nearest untracked or ignored directory
+/// above it.
+pub fn status_of(
+    labels: &HashMap<String, &'static str>,
+    rel: &str,
+    is_dir: bool,
+) -> Option<&'static str> {
+    let own = if is_dir {
+        format!("{rel}/")
+    } else {
+        rel.to_string()
+    };
+    if let Some(label) = labels.get(&own) {
+        return Some(label);
+    }
+    let mut dir = rel;
+    while let Some(cut) = dir.rfind('/') {
+        dir = &dir[..cut];
+        if let Some(label) = labels.get(&format!("{dir}/")) {
+            return Some(label);
+        }
+    }
+    None
+}
+
+/// The work tree's top level, and `root` relative to it (`/`-joined,
+/// empty at the top).
+fn locate(root: &Path) -> Option<(PathBuf, String)> {
+    let toplevel = git_output(root, &["rev-parse", "--show-toplevel"])?;
+    let toplevel = PathBuf::from(String::from_utf8_lossy(&toplevel).trim_end());
+    let root = root.canonicalize().ok()?;
+    let toplevel = toplevel.canonicalize().ok()?;
+    let sub = root
+        .strip_prefix(&toplevel)
+        .ok()?
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join("/");
+    Some((toplevel, sub))
+}
+
+/// The `XY` code and path of each `status --porcelain=v1 -z` entry.
+fn status_records(status: &[u8]) -> Vec<([u8; 2], String)> {
+    let mut out = Vec::new();
+    let mut records = status.split(|&b| b == 0).filter(|r| !r.is_empty());
+    while let Some(record) = records.next() {
+        if record.len() < 4 {
+            continue;
+        }
+        let xy = [record[0], record[1]];
+        // Renames and copies are followed by a second record holding the
+        // original path, which no longer exists on disk.
+        if xy.contains(&b'R') || xy.contains(&b'C') {
+            records.next();
+        }
+        out.push((xy, String::from_utf8_lossy(&record[3..]).into_owned()));
+    }
+    out
+}
+
+/// `path` (relative to the top level) relative to `sub`, if it is inside.
+fn below<'a>(sub: &str, path: &'a str) -> Option<&'a str> {
+    if sub.is_empty() {
+        Some(path)
+    } else {
+        path.strip_prefix(sub).and_then(|p| p.strip_prefix('/'))
+    }
+}
+
+fn git_output(dir: &Path, args: &[&str]) -> Option<Vec<u8>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .ok()?;
+    output.status.success().then_some(output.stdout)
+}
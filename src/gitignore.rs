@@ -0,0 +1,316 @@
+//! `.gitignore` and `.ignore` matching for `--gitignore`.
+//!
+//! Each directory's rules are its own `.gitignore` and `.ignore` (if any)
+//! on top of its parent's, so nested files refine the ones above them.
+//! `.ignore` uses the same syntax and is read after `.gitignore`, so it
+//! wins where the two disagree, as in ripgrep and fd. Patterns follow
+//! gitignore(5): `#` comments, `!` negation, a trailing `/` for
+//! directories only, `*`, `?`, `[...]` and `**`. A pattern with a slash
+//! anywhere but the end is anchored to the directory holding the
+//! `.gitignore`; one without matches a name at any depth below it. The
+//! last matching pattern wins, and the innermost file is consulted first.
+//!
+//! `.git/info/exclude` and the global excludes file are not read.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use regex::{Regex, RegexBuilder};
+
+struct Pattern {
+    regex: Regex,
+    negate: bool,
+    dir_only: bool,
+    /// Matched against the whole relative path rather than the name.
+    anchored: bool,
+}
+
+/// Read in this order; later patterns win.
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+pub struct Rules {
+    parent: Option<Arc<Rules>>,
+    base: PathBuf,
+    patterns: Vec<Pattern>,
+}
+
+impl Rules {
+    /// Rules in effect inside `dir`: `parent` plus `dir/.gitignore` and
+    /// `dir/.ignore`. Returns `parent` itself when neither file is readable
+    /// or holds a pattern.
+    pub fn for_dir(
+        dir: &Path,
+        parent: Option<Arc<Rules>>,
+        case_insensitive: bool,
+    ) -> Option<Arc<Rules>> {
+        let patterns: Vec<Pattern> = IGNORE_FILES
+            .iter()
+            .filter_map(|name| fs::read_to_string(dir.join(name)).ok())
+            .flat_map(|text| {
+                text.lines()
+                    .filter_map(|line| parse_line(line, case_insensitive))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        if patterns.is_empty() {
+            return parent;
+        }
+        Some(Arc::new(Rules {
+            parent,
+            base: dir.to_path_buf(),
+            patterns,
+        }))
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut rules = Some(self);
+        while let Some(current) = rules {
+            if let Some(ignored) = current.decide(path, is_dir) {
+                return ignored;
+            }
+            rules = current.parent.as_deref();
+        }
+        false
+    }
+
+    fn decide(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let rel = path.strip_prefix(&self.base).ok()?;
+        let rel = rel
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let name = rel.rsplit('/').next().unwrap_or(&rel);
+        self.patterns
+            .iter()
+            .rev()
+            .find(|p| {
+                (is_dir || !p.dir_only) && p.regex.is_match(if p.anchored { &rel } else { name })
+            })
+            .map(|p| !p.negate)
+    }
+}
+
+fn parse_line(line: &str, case_insensitive: bool) -> Option<Pattern> {
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    // Trailing spaces are dropped unless escaped with a backslash.
+    let mut line = line.to_string();
+    while line.ends_with(' ') && !line.ends_with("\\ ") {
+        line.pop();
+    }
+    let (negate, mut pat) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest.to_string()),
+        None => (
+            false,
+            line.strip_prefix('\\').map_or(line.clone(), str::to_string),
+        ),
+    };
+    let dir_only = pat.ends_with('/');
+    if dir_only {
+        pat.pop();
+    }
+    if pat.is_empty() {
+        return None;
+    }
+    let anchored = pat.contains('/');
+    let pat = pat.strip_prefix('/').unwrap_or(&pat);
+    let regex = RegexBuilder::new(&format!("^{}$", glob_to_regex(pat)))
+        .case_insensitive(case_insensitive)
+        .build()
+        .ok()?;
+    Some(Pattern {
+        regex,
+        negate,
+        dir_only,
+        anchored,
+    })
+}
+
+pub fn glob_to_regex(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let at_start = i == 0 || chars[i - 1] == '/';
+                let at_end = i + 2 == chars.len();
+                if at_start && chars.get(i + 2) == Some(&'/') {
+                    // `**/` matches zero or more leading directories.
+                    out.push_str("(?:.*/)?");
+                    i += 3;
+                    continue;
+                }
+                if at_start && at_end {
+                    out.push_str(".*");
+                } else {
+                    out.push_str("[^/]*");
+                }
+                i += 2;
+                continue;
+            }
+            '*' => out.push_str("[^/]*"),
+            '?' => out.push_str("[^/]"),
+            '[' => {
+                if let Some(len) = chars[i + 1..].iter().skip(1).position(|&c| c == ']') {
+                    let class: String = chars[i + 1..i + 2 + len].iter().collect();
+                    let class = match class.strip_prefix('!') {
+                        Some(rest) => format!("^{rest}"),
+                        None => class,
+                    };
+                    out.push('[');
+                    out.push_str(&class.replace('\\', "\\\\").replace('[', "\\["));
+                    out.push(']');
+                    i += len + 3;
+                    continue;
+                }
+                out.push_str("\\[");
+            }
+            '\\' if i + 1 < chars.len() => {
+                out.push_str(&regex::escape(&chars[i + 1].to_string()));
+                i += 2;
+                continue;
+            }
+            c => out.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether `pattern`, read from a `.gitignore` in `/repo`, ignores
+    /// `rel` (a path relative to `/repo`).
+    fn ignores(pattern: &str, rel: &str, is_dir: bool) -> bool {
+        let rules = Rules {
+            parent: None,
+            base: PathBuf::from("/repo"),
+            patterns: parse_line(pattern, false).into_iter().collect(),
+        };
+        rules.is_ignored(&Path::new("/repo").join(rel), is_dir)
+    }
+
+    #[test]
+    fn globs_become_anchored_regexes() {
+        assert_eq!(glob_to_regex("*.rs"), r"[^/]*\.rs");
+        assert_eq!(glob_to_regex("a?c"), "a[^/]c");
+        assert_eq!(glob_to_regex("**/x"), "(?:.*/)?x");
+        assert_eq!(glob_to_regex("x/**"), "x/.*");
+        assert_eq!(glob_to_regex("a**b"), "a[^/]*b");
+        assert_eq!(glob_to_regex("[!ab]"), "[^ab]");
+        assert_eq!(glob_to_regex("[]]"), "[]]");
+        assert_eq!(glob_to_regex("[a"), r"\[a");
+        assert_eq!(glob_to_regex(r"\*"), r"\*");
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        assert!(parse_line("", false).is_none());
+        assert!(parse_line("# note", false).is_none());
+        assert!(parse_line("   ", false).is_none());
+        assert!(parse_line("/", false).is_none());
+        // An escaped `#` is a pattern.
+        assert!(ignores(r"\#note", "#note", false));
+    }
+
+    #[test]
+    fn names_match_at_any_depth_unless_anchored() {
+        assert!(ignores("*.log", "a.log", false));
+        assert!(ignores("*.log", "deep/er/a.log", false));
+        assert!(!ignores("*.log", "a.log.txt", false));
+        assert!(ignores("/build", "build", true));
+        assert!(!ignores("/build", "sub/build", true));
+        assert!(ignores("doc/*.md", "doc/a.md", false));
+        assert!(!ignores("doc/*.md", "sub/doc/a.md", false));
+        assert!(!ignores("doc/*.md", "doc/x/a.md", false));
+    }
+
+    #[test]
+    fn double_stars_span_directories() {
+        assert!(ignores("**/cache", "cache", true));
+        assert!(ignores("**/cache", "a/b/cache", true));
+        assert!(ignores("out/**", "out/a/b.txt", false));
+        assert!(!ignores("out/**", "out", true));
+        assert!(ignores("a/**/z", "a/z", false));
+        assert!(ignores("a/**/z", "a/b/c/z", false));
+    }
+
+    #[test]
+    fn trailing_slash_matches_only_directories() {
+        assert!(ignores("target/", "target", true));
+        assert!(!ignores("target/", "target", false));
+    }
+
+    #[test]
+    fn classes_and_escaped_spaces() {
+        assert!(ignores("[!a]x", "bx", false));
+        assert!(!ignores("[!a]x", "ax", false));
+        assert!(ignores("file  ", "file", false));
+        assert!(ignores(r"file\ ", "file ", false));
+        assert!(!ignores(r"file\ ", "file", false));
+    }
+
+    #[test]
+    fn the_last_matching_pattern_wins() {
+        let rules = Rules {
+            parent: None,
+            base: PathBuf::from("/repo"),
+            patterns: ["*.log", "!keep.log"]
+                .iter()
+                .filter_map(|line| parse_line(line, false))
+                .collect(),
+        };
+        assert!(rules.is_ignored(Path::new("/repo/a.log"), false));
+        assert!(!rules.is_ignored(Path::new("/repo/keep.log"), false));
+    }
+
+    #[test]
+    fn case_folding_is_opt_in() {
+        let pattern = |ci| parse_line("*.LOG", ci).unwrap();
+        assert!(!pattern(false).regex.is_match("a.log"));
+        assert!(pattern(true).regex.is_match("a.log"));
+    }
+
+    struct TempDir(PathBuf);
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn ignore_files_and_inner_files_win() {
+        let tmp = TempDir(
+            std::env::temp_dir().join(format!("folderwalk-gitignore-{}", std::process::id())),
+        );
+        let root = &tmp.0;
+        let _ = fs::remove_dir_all(root);
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join(".gitignore"), "*.tmp\n!keep.tmp\nnotes\n").unwrap();
+        fs::write(root.join(".ignore"), "keep.tmp\n").unwrap();
+        fs::write(root.join("sub/.gitignore"), "!notes\n").unwrap();
+
+        let outer = Rules::for_dir(root, None, false).unwrap();
+        assert!(outer.is_ignored(&root.join("a.tmp"), false));
+        // `.ignore` is read after `.gitignore`, so its pattern wins.
+        assert!(outer.is_ignored(&root.join("keep.tmp"), false));
+        assert!(outer.is_ignored(&root.join("sub/notes"), false));
+
+        let inner = Rules::for_dir(&root.join("sub"), Some(outer.clone()), false).unwrap();
+        assert!(!inner.is_ignored(&root.join("sub/notes"), false));
+        assert!(inner.is_ignored(&root.join("sub/b.tmp"), false));
+
+        // A directory without ignore files shares its parent's rules.
+        fs::create_dir(root.join("plain")).unwrap();
+        let plain = Rules::for_dir(&root.join("plain"), Some(outer.clone()), false).unwrap();
+        assert!(Arc::ptr_eq(&plain, &outer));
+    }
+}
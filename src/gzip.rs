@@ -0,0 +1,421 @@
+//! gzip (RFC 1952) for `--gzip`: an encoder for the output file, and the
+//! member parsing shared with `.tar.gz` listing and `--verify`.
+//!
+//! The encoder is a greedy LZ77 matcher emitting DEFLATE's fixed Huffman
+//! codes. It compresses trees and source text to a fraction of their size
+//! without a dependency, though not as tightly as zlib's dynamic codes.
+//! Input is encoded in blocks that can refer back into the previous 32 KiB.
+
+use std::io::{self, Write};
+
+use crate::inflate::{DIST_BASE, DIST_EXTRA, LENGTH_BASE, LENGTH_EXTRA, inflate};
+
+/// Input gathered before a block is encoded.
+const BLOCK: usize = 256 * 1024;
+/// How far back a match may reach.
+const WINDOW: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// Earlier positions tried per match, trading speed for size.
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+pub struct GzipEncoder<W: Write> {
+    inner: W,
+    /// The last `WINDOW` bytes already encoded, then the input not yet
+    /// encoded.
+    data: Vec<u8>,
+    encoded: usize,
+    bits: BitWriter,
+    crc: u32,
+    /// Input length modulo 2^32, as the trailer records it.
+    len: u32,
+}
+
+impl<W: Write> GzipEncoder<W> {
+    pub fn new(inner: W) -> Self {
+        let mut bits = BitWriter::default();
+        // Magic, deflate, no flags, no mtime, no extra flags, unknown OS.
+        bits.out
+            .extend_from_slice(&[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255]);
+        GzipEncoder {
+            inner,
+            data: Vec::with_capacity(WINDOW + BLOCK),
+            encoded: 0,
+            bits,
+            crc: !0,
+            len: 0,
+        }
+    }
+
+    /// Encodes what is left and writes the trailer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.encode_block(true)?;
+        self.bits.align();
+        self.bits.out.extend_from_slice(&(!self.crc).to_le_bytes());
+        self.bits.out.extend_from_slice(&self.len.to_le_bytes());
+        self.inner.write_all(&self.bits.out)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn encode_block(&mut self, last: bool) -> io::Result<()> {
+        let bits = &mut self.bits;
+        bits.put(u32::from(last), 1);
+        bits.put(1, 2);
+        compress(&self.data, self.encoded, bits);
+        bits.put_literal(256);
+        self.inner.write_all(&bits.out)?;
+        bits.out.clear();
+
+        let keep_from = self.data.len().saturating_sub(WINDOW);
+        self.data.drain(..keep_from);
+        self.encoded = self.data.len();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for GzipEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.crc = crc32_update(self.crc, buf);
+        self.len = self.len.wrapping_add(buf.len() as u32);
+        self.data.extend_from_slice(buf);
+        if self.data.len() - self.encoded >= BLOCK {
+            self.encode_block(false)?;
+        }
+        Ok(buf.len())
+    }
+
+    /// Input of the block being gathered stays buffered: cutting a block
+    /// short would cost compression.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Emits LZ77 literals and matches for `data[start..]`, with `data[..start]`
+/// as history that matches may refer back into.
+fn compress(data: &[u8], start: usize, bits: &mut BitWriter) {
+    let mask = (1 << HASH_BITS) - 1;
+    let hash = |i: usize| {
+        let v = u32::from(data[i]) | u32::from(data[i + 1]) << 8 | u32::from(data[i + 2]) << 16;
+        (v.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize & mask
+    };
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; data.len()];
+    let insert = |i: usize, head: &mut [usize], prev: &mut [usize]| {
+        if i + MIN_MATCH <= data.len() {
+            let h = hash(i);
+            prev[i] = head[h];
+            head[h] = i;
+        }
+    };
+    for i in 0..start {
+        insert(i, &mut head, &mut prev);
+    }
+
+    let mut i = start;
+    while i < data.len() {
+        let (mut best_len, mut best_dist) = (0, 0);
+        if i + MIN_MATCH <= data.len() {
+            let max = MAX_MATCH.min(data.len() - i);
+            let mut candidate = head[hash(i)];
+            let mut chain = MAX_CHAIN;
+            while candidate != usize::MAX && i - candidate <= WINDOW && chain > 0 {
+                let len = data[candidate..]
+                    .iter()
+                    .zip(&data[i..i + max])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best_len {
+                    (best_len, best_dist) = (len, i - candidate);
+                    if len == max {
+                        break;
+                    }
+                }
+                candidate = prev[candidate];
+                chain -= 1;
+            }
+        }
+        if best_len >= MIN_MATCH {
+            bits.put_match(best_len, best_dist);
+            for j in i..i + best_len {
+                insert(j, &mut head, &mut prev);
+            }
+            i += best_len;
+        } else {
+            bits.put_literal(u16::from(data[i]));
+            insert(i, &mut head, &mut prev);
+            i += 1;
+        }
+    }
+}
+
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    /// Appends the low `n` bits of `value`, least significant first.
+    fn put(&mut self, value: u32, n: u32) {
+        self.acc |= u64::from(value) << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Appends a Huffman code, which is stored most significant bit first.
+    fn put_code(&mut self, code: u32, n: u32) {
+        self.put(code.reverse_bits() >> (32 - n), n);
+    }
+
+    /// A literal byte or length symbol in the fixed code.
+    fn put_literal(&mut self, symbol: u16) {
+        let symbol = u32::from(symbol);
+        let (code, n) = match symbol {
+            0..=143 => (0x30 + symbol, 8),
+            144..=255 => (0x190 + symbol - 144, 9),
+            256..=279 => (symbol - 256, 7),
+            _ => (0xC0 + symbol - 280, 8),
+        };
+        self.put_code(code, n);
+    }
+
+    fn put_match(&mut self, len: usize, dist: usize) {
+        let l = LENGTH_BASE
+            .iter()
+            .rposition(|&base| usize::from(base) <= len)
+            .unwrap_or(0);
+        self.put_literal(257 + l as u16);
+        self.put(
+            (len - usize::from(LENGTH_BASE[l])) as u32,
+            u32::from(LENGTH_EXTRA[l]),
+        );
+        let d = DIST_BASE
+            .iter()
+            .rposition(|&base| usize::from(base) <= dist)
+            .unwrap_or(0);
+        self.put_code(d as u32, 5);
+        self.put(
+            (dist - usize::from(DIST_BASE[d])) as u32,
+            u32::from(DIST_EXTRA[d]),
+        );
+    }
+
+    /// Pads to a byte boundary.
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.acc = 0;
+        self.count = 0;
+    }
+}
+
+/// Decompresses every member of a gzip file, checking each one's CRC-32.
+pub fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut rest = data;
+    while rest.len() >= 2 && rest[..2] == [0x1f, 0x8b] {
+        let start = header_len(rest)?;
+        let member_start = out.len();
+        let used = inflate(&rest[start..], |chunk| {
+            out.extend_from_slice(chunk);
+            Ok(())
+        })?;
+        let trailer = rest
+            .get(start + used..start + used + 8)
+            .ok_or_else(|| invalid("truncated gzip trailer"))?;
+        let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        if crc != !crc32_update(!0, &out[member_start..]) {
+            return Err(invalid("gzip CRC-32 mismatch"));
+        }
+        rest = &rest[start + used + 8..];
+    }
+    if !rest.is_empty() {
+        return Err(invalid("not a gzip stream"));
+    }
+    Ok(out)
+}
+
+/// Length of the gzip member header at the start of `data`.
+pub fn header_len(data: &[u8]) -> io::Result<usize> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+    let short = || invalid("truncated gzip header");
+    if data.len() < 10 || data[2] != 8 {
+        return Err(invalid("not a gzip stream"));
+    }
+    let flags = data[3];
+    let mut at = 10;
+    if flags & FEXTRA != 0 {
+        let len = data.get(at..at + 2).ok_or_else(short)?;
+        at += 2 + usize::from(u16::from_le_bytes([len[0], len[1]]));
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let end = data
+                .get(at..)
+                .and_then(|rest| rest.iter().position(|&b| b == 0))
+                .ok_or_else(short)?;
+            at += end + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        at += 2;
+    }
+    if at > data.len() {
+        return Err(short());
+    }
+    Ok(at)
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what.to_string())
+}
+
+/// The CRC-32 (IEEE) register after `data`; start from `!0` and invert the
+/// result.
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut n = 0;
+        while n < 256 {
+            let mut c = n as u32;
+            let mut k = 0;
+            while k < 8 {
+                c = if c & 1 != 0 {
+                    0xEDB8_8320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+                k += 1;
+            }
+            table[n] = c;
+            n += 1;
+        }
+        table
+    };
+    for &byte in data {
+        crc = TABLE[((crc ^ u32::from(byte)) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compressed(data: &[u8], write_size: usize) -> Vec<u8> {
+        let mut gz = GzipEncoder::new(Vec::new());
+        for piece in data.chunks(write_size) {
+            gz.write_all(piece).unwrap();
+        }
+        gz.finish().unwrap()
+    }
+
+    fn error(data: &[u8]) -> String {
+        match decompress(data) {
+            Ok(_) => panic!("decompressed a broken stream"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    /// Text that compresses well, then bytes that barely do, so both
+    /// matches and literals cross block boundaries.
+    fn sample(len: usize) -> Vec<u8> {
+        let mut data: Vec<u8> = (0..)
+            .flat_map(|i| format!("├── file{i}.rs ({} bytes)\n", i * 37 % 1000).into_bytes())
+            .take(len / 2)
+            .collect();
+        let mut state = 0x2545_f491u32;
+        data.extend((data.len()..len).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        }));
+        data
+    }
+
+    #[test]
+    fn crc32_matches_the_check_value() {
+        assert_eq!(!crc32_update(!0, b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn encoded_data_round_trips() {
+        for (len, write_size) in [(0, 1), (1, 1), (1000, 7), (BLOCK * 2 + 12_345, 10_000)] {
+            let data = sample(len);
+            let gz = compressed(&data, write_size);
+            assert_eq!(decompress(&gz).unwrap(), data, "{len} bytes");
+            let trailer = &gz[gz.len() - 4..];
+            assert_eq!(u32::from_le_bytes(trailer.try_into().unwrap()), len as u32);
+        }
+        let text = sample(100_000);
+        assert!(compressed(&text[..50_000], 4096).len() < 50_000 / 4);
+    }
+
+    #[test]
+    fn members_are_concatenated() {
+        let mut gz = compressed(b"first\n", 64);
+        gz.extend(compressed(b"", 64));
+        gz.extend(compressed(b"second\n", 64));
+        assert_eq!(decompress(&gz).unwrap(), b"first\nsecond\n");
+    }
+
+    #[test]
+    fn optional_header_fields_are_skipped() {
+        let plain = compressed(b"named\n", 64);
+        let mut gz = plain[..10].to_vec();
+        gz[3] = 0x02 | 0x04 | 0x08 | 0x10;
+        gz.extend_from_slice(&[3, 0, b'x', b'y', b'z']);
+        gz.extend_from_slice(b"tree.txt\0a comment\0");
+        gz.extend_from_slice(&[0xab, 0xcd]);
+        assert_eq!(header_len(&gz).unwrap(), gz.len());
+        gz.extend_from_slice(&plain[10..]);
+        assert_eq!(decompress(&gz).unwrap(), b"named\n");
+
+        // The name runs to the end of the input.
+        let mut gz = plain[..10].to_vec();
+        gz[3] = 0x08;
+        gz.extend_from_slice(b"tree.txt");
+        assert_eq!(error(&gz), "truncated gzip header");
+        gz[3] = 0x04;
+        assert_eq!(
+            header_len(&gz[..11]).err().unwrap().to_string(),
+            "truncated gzip header"
+        );
+    }
+
+    #[test]
+    fn damaged_streams_are_rejected() {
+        let gz = compressed(b"some text, some text\n", 64);
+        assert_eq!(error(&gz[..gz.len() - 4]), "truncated gzip trailer");
+
+        let mut bad_crc = gz.clone();
+        let at = gz.len() - 8;
+        bad_crc[at] ^= 1;
+        assert_eq!(error(&bad_crc), "gzip CRC-32 mismatch");
+
+        let mut junk = gz.clone();
+        junk.extend_from_slice(b"junk");
+        assert_eq!(error(&junk), "not a gzip stream");
+
+        let mut stored = gz.clone();
+        stored[2] = 0;
+        assert_eq!(error(&stored), "not a gzip stream");
+
+        for len in 10..gz.len() - 8 {
+            assert!(decompress(&gz[..len]).is_err(), "{len} bytes");
+        }
+    }
+}
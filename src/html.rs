@@ -0,0 +1,213 @@
+//! `--format html`: one standalone page with a collapsible tree.
+//!
+//! Folders are `<details>` elements, collapsed by default except for the
+//! root, so the page works without scripts. A small inline script adds a
+//! search box that shows only entries whose name contains the query
+//! (opening the folders above them). With `--content`, each file is a
+//! `<details>` of its own holding the text in a `<pre>`, classed
+//! `language-…` by extension for highlighters like Prism or highlight.js.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::{
+    WalkContext, content_range, decoded_text, display_root_name, encoding, is_walkable_dir,
+    list_dir, markdown_language, read_capped, winfs,
+};
+
+const HEAD: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<style>
+body { font: 14px/1.5 ui-monospace, SFMono-Regular, Menlo, Consolas, monospace; margin: 1.5em; }
+ul { list-style: none; margin: 0; padding-left: 1.4em; }
+summary { cursor: pointer; }
+.dir > details > summary { font-weight: bold; }
+.link { color: #0a7f8f; }
+.note { color: #777; font-style: italic; }
+pre { background: #f6f8fa; padding: .6em; overflow-x: auto; margin: .3em 0; }
+#search { font: inherit; margin-bottom: 1em; width: 20em; }
+.hidden { display: none; }
+</style>
+</head>
+<body>
+<input id="search" type="search" placeholder="Filter by name" autocomplete="off">
+"##;
+
+const TAIL: &str = r##"<script>
+const search = document.getElementById("search");
+search.addEventListener("input", () => {
+  const query = search.value.toLowerCase();
+  const items = Array.from(document.querySelectorAll("#tree li")).reverse();
+  for (const li of items) {
+    const own = li.dataset.name.toLowerCase().includes(query);
+    const child = li.querySelector(":scope > details > ul > li:not(.hidden)") !== null;
+    li.classList.toggle("hidden", query !== "" && !own && !child);
+    const details = li.querySelector(":scope > details");
+    if (details && query !== "" && child) details.open = true;
+  }
+});
+</script>
+</body>
+</html>
+"##;
+
+pub fn write_html(ctx: &mut WalkContext, writer: &mut dyn Write) -> io::Result<()> {
+    let config = ctx.config;
+    let root = display_root_name(&config.start_dir).unwrap_or_else(|| ".".to_string());
+    let root = html_escape(&root);
+    write!(writer, "{}", HEAD.replace("{title}", &root))?;
+    writeln!(
+        writer,
+        "<ul id=\"tree\"><li class=\"dir\" data-name=\"{root}\"><details open><summary>{root}/</summary>"
+    )?;
+    write_items(&config.start_dir, ctx, writer, 0)?;
+    writeln!(writer, "</details></li></ul>")?;
+    write!(writer, "{TAIL}")
+}
+
+fn write_items(
+    dir: &Path,
+    ctx: &mut WalkContext,
+    writer: &mut dyn Write,
+    depth: usize,
+) -> io::Result<()> {
+    let config = ctx.config;
+    writeln!(writer, "<ul>")?;
+    if config.walk.max_depth.is_none_or(|maxd| depth < maxd) {
+        for entry in list_dir(dir, ctx, depth)? {
+            ctx.record(&entry)?;
+            let name = html_escape(&entry.file_name.to_string_lossy());
+            if config.walk.follow_symlinks && entry.is_symlink_dir && !entry.followed {
+                writeln!(
+                    writer,
+                    "<li class=\"link\" data-name=\"{name}\">{name} -&gt; &lt;cycle&gt;</li>"
+                )?;
+                continue;
+            }
+            if is_walkable_dir(&entry) {
+                let label = if entry.file_type.is_symlink() {
+                    format!("{name}/ -&gt; {}", link_target(&entry.path))
+                } else {
+                    format!("{name}/")
+                };
+                writeln!(
+                    writer,
+                    "<li class=\"dir\" data-name=\"{name}\"><details><summary>{label}</summary>"
+                )?;
+                if ctx.first_visit(&entry.path) {
+                    write_items(&entry.path, ctx, writer, depth + 1)?;
+                } else {
+                    writeln!(writer, "<ul></ul>")?;
+                }
+                writeln!(writer, "</details></li>")?;
+            } else if entry.file_type.is_symlink() {
+                writeln!(
+                    writer,
+                    "<li class=\"link\" data-name=\"{name}\">{name} -&gt; {}</li>",
+                    link_target(&entry.path)
+                )?;
+            } else if ctx.wants_content(&entry) {
+                writeln!(
+                    writer,
+                    "<li class=\"file\" data-name=\"{name}\"><details><summary>{name}</summary>"
+                )?;
+                write_content(&entry.path, ctx, writer)?;
+                writeln!(writer, "</details></li>")?;
+            } else {
+                writeln!(
+                    writer,
+                    "<li class=\"file\" data-name=\"{name}\">{name}</li>"
+                )?;
+            }
+        }
+    }
+    writeln!(writer, "</ul>")
+}
+
+fn link_target(path: &Path) -> String {
+    winfs::read_link(path)
+        .map(|t| html_escape(&t.to_string_lossy()))
+        .unwrap_or_else(|_| "&lt;unreadable&gt;".to_string())
+}
+
+fn write_content(path: &Path, ctx: &WalkContext, writer: &mut dyn Write) -> io::Result<()> {
+    let config = ctx.config;
+    let (bytes, more) = match read_capped(path, config.max_content_bytes) {
+        Ok(read) => read,
+        Err(err) => {
+            let message = html_escape(&err.to_string());
+            return writeln!(
+                writer,
+                "<p class=\"note\">Could not read file: {message}</p>"
+            );
+        }
+    };
+    let encoding = encoding::detect(&bytes[..bytes.len().min(encoding::SNIFF_BYTES)]);
+    if encoding == encoding::Encoding::Binary && !config.include_binary {
+        let size = bytes.len() as u64 + more;
+        return writeln!(writer, "<p class=\"note\">binary file, {size} bytes</p>");
+    }
+    let text = decoded_text(config, encoding, &bytes);
+    let lines: Vec<&str> = text.lines().collect();
+    let (start, end) = content_range(config, lines.len());
+    let width = end.to_string().len();
+    let language = path
+        .extension()
+        .map(|ext| markdown_language(&ext.to_string_lossy().to_lowercase()))
+        .unwrap_or("");
+    if language.is_empty() {
+        write!(writer, "<pre>")?;
+    } else {
+        write!(writer, "<pre class=\"language-{language}\">")?;
+    }
+    for (i, line) in lines.iter().enumerate().take(end).skip(start) {
+        if config.line_numbers {
+            writeln!(writer, "{:>width$}  {}", i + 1, html_escape(line))?;
+        } else {
+            writeln!(writer, "{}", html_escape(line))?;
+        }
+    }
+    writeln!(writer, "</pre>")?;
+    if more > 0 && end == lines.len() {
+        writeln!(
+            writer,
+            "<p class=\"note\">… truncated, {more} more bytes</p>"
+        )?;
+    }
+    Ok(())
+}
+
+fn html_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markup_characters_are_escaped() {
+        assert_eq!(html_escape("plain name.rs"), "plain name.rs");
+        assert_eq!(
+            html_escape(r#"<b>"Tom" & 'Jerry'</b>"#),
+            "&lt;b&gt;&quot;Tom&quot; &amp; &#39;Jerry&#39;&lt;/b&gt;"
+        );
+        // Already-escaped text is escaped again, not passed through.
+        assert_eq!(html_escape("&amp;"), "&amp;amp;");
+    }
+}
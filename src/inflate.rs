@@ -0,0 +1,440 @@
+//! DEFLATE (RFC 1951) decompression, for reading `.tar.gz` listings.
+//!
+//! A straightforward canonical-Huffman decoder in the style of zlib's
+//! `puff`: slow next to zlib, but small. Output is handed to a sink in
+//! chunks, keeping only the 32 KiB window that back-references can reach,
+//! so a large archive doesn't have to fit in memory decompressed.
+
+use std::io;
+
+const MAX_BITS: usize = 15;
+const WINDOW: usize = 32 * 1024;
+/// Output collected before it is handed to the sink.
+const CHUNK: usize = 1024 * 1024;
+
+pub(crate) const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+pub(crate) const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+pub(crate) const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+pub(crate) const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order in which code length code lengths are stored.
+const CLEN_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn corrupt(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("corrupt deflate data: {what}"),
+    )
+}
+
+struct Huffman {
+    /// Number of codes of each length.
+    count: [u16; MAX_BITS + 1],
+    /// Symbols ordered by code.
+    symbol: Vec<u16>,
+    /// Codes of the longest length left unassigned; 0 for a complete code.
+    left: u32,
+}
+
+impl Huffman {
+    /// The code with these lengths per symbol (0 for unused ones).
+    /// Lengths that ask for more codes than there are, which no encoder
+    /// writes, are rejected; an incomplete code is left to the caller.
+    fn new(lengths: &[u8]) -> io::Result<Huffman> {
+        let mut count = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            count[len as usize] += 1;
+        }
+        let mut left = 1u32;
+        for &n in &count[1..] {
+            left = (left << 1)
+                .checked_sub(u32::from(n))
+                .ok_or_else(|| corrupt("over-subscribed Huffman code"))?;
+        }
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + count[len];
+        }
+        let mut symbol = vec![0; lengths.len()];
+        for (sym, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbol[offsets[len as usize] as usize] = sym as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(Huffman {
+            count,
+            symbol,
+            left,
+        })
+    }
+
+    /// Whether every code is assigned, or there is at most one code of a
+    /// single bit, which is how an encoder writes a block with one distance
+    /// or none.
+    fn complete_enough(&self) -> bool {
+        self.left == 0 || usize::from(self.count[0] + self.count[1]) == self.symbol.len()
+    }
+}
+
+struct Inflater<'a, F> {
+    input: &'a [u8],
+    pos: usize,
+    bit_buf: u32,
+    bit_count: u32,
+    out: Vec<u8>,
+    sink: F,
+}
+
+/// Decompresses a raw deflate stream, passing the output to `sink` in
+/// order. Returns how many input bytes the stream took.
+pub fn inflate<F>(input: &[u8], sink: F) -> io::Result<usize>
+where
+    F: FnMut(&[u8]) -> io::Result<()>,
+{
+    let mut inflater = Inflater {
+        input,
+        pos: 0,
+        bit_buf: 0,
+        bit_count: 0,
+        out: Vec::with_capacity(CHUNK + WINDOW),
+        sink,
+    };
+    loop {
+        let last = inflater.bits(1)? == 1;
+        match inflater.bits(2)? {
+            0 => inflater.stored()?,
+            1 => {
+                let (lit, dist) = fixed_tables()?;
+                inflater.codes(&lit, &dist)?;
+            }
+            2 => {
+                let (lit, dist) = inflater.dynamic_tables()?;
+                inflater.codes(&lit, &dist)?;
+            }
+            _ => return Err(corrupt("invalid block type")),
+        }
+        if last {
+            break;
+        }
+    }
+    let out = std::mem::take(&mut inflater.out);
+    (inflater.sink)(&out)?;
+    Ok(inflater.pos)
+}
+
+fn fixed_tables() -> io::Result<(Huffman, Huffman)> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+impl<F> Inflater<'_, F>
+where
+    F: FnMut(&[u8]) -> io::Result<()>,
+{
+    fn bits(&mut self, need: u32) -> io::Result<u32> {
+        while self.bit_count < need {
+            let byte = *self
+                .input
+                .get(self.pos)
+                .ok_or_else(|| corrupt("unexpected end of input"))?;
+            self.pos += 1;
+            self.bit_buf |= u32::from(byte) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buf & ((1u32 << need) - 1);
+        self.bit_buf >>= need;
+        self.bit_count -= need;
+        Ok(value)
+    }
+
+    fn push(&mut self, byte: u8) -> io::Result<()> {
+        self.out.push(byte);
+        if self.out.len() >= CHUNK + WINDOW {
+            let done = self.out.len() - WINDOW;
+            (self.sink)(&self.out[..done])?;
+            self.out.drain(..done);
+        }
+        Ok(())
+    }
+
+    fn stored(&mut self) -> io::Result<()> {
+        self.bit_buf = 0;
+        self.bit_count = 0;
+        let header = self
+            .input
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| corrupt("unexpected end of input"))?;
+        let len = u16::from_le_bytes([header[0], header[1]]);
+        let nlen = u16::from_le_bytes([header[2], header[3]]);
+        if len != !nlen {
+            return Err(corrupt("stored block length mismatch"));
+        }
+        self.pos += 4;
+        let end = self.pos + len as usize;
+        if end > self.input.len() {
+            return Err(corrupt("unexpected end of input"));
+        }
+        for i in self.pos..end {
+            self.push(self.input[i])?;
+        }
+        self.pos = end;
+        Ok(())
+    }
+
+    fn decode(&mut self, h: &Huffman) -> io::Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..=MAX_BITS {
+            code |= self.bits(1)? as i32;
+            let count = i32::from(h.count[len]);
+            if code - count < first {
+                return Ok(h.symbol[(index + (code - first)) as usize]);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        Err(corrupt("invalid Huffman code"))
+    }
+
+    fn dynamic_tables(&mut self) -> io::Result<(Huffman, Huffman)> {
+        let nlen = self.bits(5)? as usize + 257;
+        let ndist = self.bits(5)? as usize + 1;
+        let ncode = self.bits(4)? as usize + 4;
+        if nlen > 286 || ndist > 30 {
+            return Err(corrupt("too many codes"));
+        }
+        let mut clen = [0u8; 19];
+        for &idx in &CLEN_ORDER[..ncode] {
+            clen[idx] = self.bits(3)? as u8;
+        }
+        let clen = Huffman::new(&clen)?;
+        if clen.left != 0 {
+            return Err(corrupt("incomplete code length code"));
+        }
+
+        let mut lengths = vec![0u8; nlen + ndist];
+        let mut idx = 0;
+        while idx < nlen + ndist {
+            let sym = self.decode(&clen)?;
+            let (value, repeat) = match sym {
+                0..=15 => (sym as u8, 1),
+                16 => {
+                    let prev = *lengths[..idx]
+                        .last()
+                        .ok_or_else(|| corrupt("repeat with no previous length"))?;
+                    (prev, 3 + self.bits(2)? as usize)
+                }
+                17 => (0, 3 + self.bits(3)? as usize),
+                _ => (0, 11 + self.bits(7)? as usize),
+            };
+            if idx + repeat > lengths.len() {
+                return Err(corrupt("too many lengths"));
+            }
+            lengths[idx..idx + repeat].fill(value);
+            idx += repeat;
+        }
+        if lengths[256] == 0 {
+            return Err(corrupt("no end-of-block code"));
+        }
+        let lit = Huffman::new(&lengths[..nlen])?;
+        let dist = Huffman::new(&lengths[nlen..])?;
+        if !lit.complete_enough() || !dist.complete_enough() {
+            return Err(corrupt("incomplete Huffman code"));
+        }
+        Ok((lit, dist))
+    }
+
+    fn codes(&mut self, lit: &Huffman, dist: &Huffman) -> io::Result<()> {
+        loop {
+            let sym = self.decode(lit)? as usize;
+            if sym < 256 {
+                self.push(sym as u8)?;
+                continue;
+            }
+            if sym == 256 {
+                return Ok(());
+            }
+            let sym = sym - 257;
+            if sym >= LENGTH_BASE.len() {
+                return Err(corrupt("invalid length code"));
+            }
+            let len = LENGTH_BASE[sym] as usize + self.bits(u32::from(LENGTH_EXTRA[sym]))? as usize;
+            let dsym = self.decode(dist)? as usize;
+            if dsym >= DIST_BASE.len() {
+                return Err(corrupt("invalid distance code"));
+            }
+            let distance =
+                DIST_BASE[dsym] as usize + self.bits(u32::from(DIST_EXTRA[dsym]))? as usize;
+            if distance > self.out.len() {
+                return Err(corrupt("distance too far back"));
+            }
+            for _ in 0..len {
+                let byte = self.out[self.out.len() - distance];
+                self.push(byte)?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `line 0: 0\n` to `line 14: 196\n`, as zlib's deflate writes it at
+    /// level 9: a single block with dynamic codes.
+    const DYNAMIC: [u8; 87] = [
+        0x35, 0x8d, 0x3b, 0x0e, 0xc0, 0x30, 0x08, 0x43, 0xf7, 0x9e, 0xc2, 0x47, 0x08, 0xf9, 0xd0,
+        0xc4, 0xf7, 0xe9, 0x50, 0x29, 0xea, 0xfd, 0xc7, 0x82, 0x20, 0xdb, 0x13, 0xc6, 0x7e, 0xfb,
+        0xfd, 0x1e, 0x14, 0xa2, 0x5c, 0xdb, 0x49, 0x08, 0x09, 0xaa, 0x44, 0x0f, 0x6a, 0xc4, 0x0a,
+        0xea, 0x96, 0x6a, 0xe0, 0x20, 0xea, 0x08, 0x54, 0xa2, 0xe5, 0xf5, 0xb6, 0x52, 0xfe, 0x4e,
+        0x42, 0x73, 0x60, 0x11, 0x33, 0x57, 0xc5, 0x54, 0x52, 0x8e, 0xcc, 0x6d, 0xf5, 0x24, 0x26,
+        0x94, 0x9e, 0x0d, 0x69, 0x6e, 0xca, 0x25, 0x71, 0xed, 0xd2, 0xeb, 0x07,
+    ];
+
+    fn dynamic_text() -> Vec<u8> {
+        (0..15)
+            .map(|i| format!("line {i}: {}\n", i * i))
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    fn inflated(input: &[u8]) -> io::Result<(Vec<u8>, usize)> {
+        let mut out = Vec::new();
+        let used = inflate(input, |chunk| {
+            out.extend_from_slice(chunk);
+            Ok(())
+        })?;
+        Ok((out, used))
+    }
+
+    fn error(input: &[u8]) -> String {
+        match inflated(input) {
+            Ok(_) => panic!("inflated a broken stream"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    /// Packs `(value, bits)` fields from the least significant bit up, as
+    /// deflate stores them. Huffman codes go in bit-reversed.
+    fn pack(fields: &[(u32, u32)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut used = 0;
+        for &(value, bits) in fields {
+            for bit in 0..bits {
+                if used % 8 == 0 {
+                    out.push(0);
+                }
+                *out.last_mut().unwrap() |= (((value >> bit) & 1) as u8) << (used % 8);
+                used += 1;
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn dynamic_blocks_decode() {
+        assert_eq!(inflated(&DYNAMIC).unwrap(), (dynamic_text(), DYNAMIC.len()));
+        // Whatever follows the stream is left alone.
+        let mut followed = DYNAMIC.to_vec();
+        followed.extend_from_slice(b"trailer");
+        assert_eq!(inflated(&followed).unwrap().1, DYNAMIC.len());
+    }
+
+    #[test]
+    fn stored_blocks_are_copied() {
+        let mut input = vec![0b000];
+        input.extend_from_slice(&[3, 0, !3, !0]);
+        input.extend_from_slice(b"abc");
+        input.push(0b001);
+        input.extend_from_slice(&[2, 0, !2, !0]);
+        input.extend_from_slice(b"de");
+        assert_eq!(inflated(&input).unwrap(), (b"abcde".to_vec(), input.len()));
+
+        input[1] = 4;
+        assert!(error(&input).contains("stored block length mismatch"));
+    }
+
+    #[test]
+    fn fixed_blocks_copy_matches() {
+        // `a`, then a match of length 3 at distance 1, then end of block.
+        let a = (0x30 + u32::from(b'a')).reverse_bits() >> 24;
+        let input = pack(&[(1, 1), (1, 2), (a, 8), (0b0000001 << 6, 7), (0, 5), (0, 7)]);
+        assert_eq!(inflated(&input).unwrap().0, b"aaaa");
+    }
+
+    #[test]
+    fn truncated_streams_are_errors() {
+        for len in 0..DYNAMIC.len() {
+            assert!(
+                error(&DYNAMIC[..len]).contains("unexpected end of input"),
+                "{len} bytes"
+            );
+        }
+    }
+
+    #[test]
+    fn corrupted_streams_do_not_panic() {
+        for bit in 0..DYNAMIC.len() * 8 {
+            let mut input = DYNAMIC;
+            input[bit / 8] ^= 1 << (bit % 8);
+            let _ = inflated(&input);
+        }
+    }
+
+    #[test]
+    fn malformed_blocks_are_rejected() {
+        assert!(error(&pack(&[(1, 1), (3, 2)])).contains("invalid block type"));
+        // A match before any output.
+        let input = pack(&[(1, 1), (1, 2), (0b0000001 << 6, 7), (0, 5)]);
+        assert!(error(&input).contains("distance too far back"));
+        // 287 literal/length codes, more than the 286 there are.
+        let input = pack(&[(1, 1), (2, 2), (30, 5), (0, 5), (0, 4)]);
+        assert!(error(&input).contains("too many codes"));
+    }
+
+    #[test]
+    fn over_subscribed_codes_are_rejected() {
+        // Code length codes: four symbols of one bit.
+        let header = [(1, 1), (2, 2), (0, 5), (0, 5), (0, 4)];
+        let mut fields = header.to_vec();
+        fields.extend([(1, 3); 4]);
+        assert!(error(&pack(&fields)).contains("over-subscribed Huffman code"));
+
+        // Literal/length codes: a complete code length code giving one bit
+        // to lengths `1` and `18`, then literals 0 to 2 and end of block
+        // one bit each.
+        let mut fields = header.to_vec();
+        fields[4] = (14, 4);
+        for symbol in &CLEN_ORDER[..18] {
+            fields.push((u32::from(matches!(symbol, 1 | 18)), 3));
+        }
+        // Length 1 is code 0, a run of zeros (`18`) is code 1.
+        fields.extend([(0, 1), (0, 1), (0, 1)]);
+        fields.extend([(1, 1), (138 - 11, 7), (1, 1), (115 - 11, 7)]);
+        fields.extend([(0, 1), (0, 1)]);
+        assert!(error(&pack(&fields)).contains("over-subscribed Huffman code"));
+
+        // Code length codes: a single one-bit code, leaving the other free.
+        let mut fields = header.to_vec();
+        fields.extend([(1, 3), (0, 3), (0, 3), (0, 3)]);
+        assert!(error(&pack(&fields)).contains("incomplete code length code"));
+    }
+}
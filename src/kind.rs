@@ -0,0 +1,155 @@
+//! File kinds for `--icons`: what an entry is (`code`, `image`,
+//! `archive`, ...), shown as an icon in the tree and as a `kind` field in
+//! structured output.
+//!
+//! The extension decides; a file whose extension isn't known has its first
+//! bytes checked for a few common signatures (PNG, PDF, ELF, zip, ...)
+//! before falling back to `executable`, `text` or `binary`. `--kind
+//! EXT=KIND` adds to or replaces the extension table and `--icon
+//! KIND=ICON` sets a kind's icon, so both can be extended from
+//! `folderwalk.toml`.
+
+use std::fs::File;
+use std::io::Read;
+
+use crate::{Config, DirEntryInfo, IconStyle, encoding, is_walkable_dir};
+
+/// Bytes read from a file whose extension says nothing.
+const MAGIC_BYTES: usize = 512;
+
+/// The kind of `entry`.
+pub fn kind_of<'c>(entry: &DirEntryInfo, config: &'c Config) -> &'c str {
+    if entry.file_type.is_symlink() && !entry.followed {
+        return "symlink";
+    }
+    if is_walkable_dir(entry) || entry.file_type.is_dir() {
+        return "directory";
+    }
+    let ext = entry
+        .path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    if let Some(ext) = &ext {
+        if let Some((_, kind)) = config.file_kinds.iter().rev().find(|(e, _)| e == ext) {
+            return kind;
+        }
+        if let Some(kind) = by_extension(ext) {
+            return kind;
+        }
+    }
+    if !entry.file_type.is_file() {
+        return "file";
+    }
+    let mut head = Vec::with_capacity(MAGIC_BYTES);
+    if File::open(&entry.path)
+        .and_then(|f| f.take(MAGIC_BYTES as u64).read_to_end(&mut head))
+        .is_err()
+    {
+        return "file";
+    }
+    if let Some(kind) = by_magic(&head) {
+        return kind;
+    }
+    if entry.executable {
+        return "executable";
+    }
+    match encoding::detect(&head) {
+        encoding::Encoding::Binary => "binary",
+        _ => "text",
+    }
+}
+
+/// The icon shown for `kind`.
+pub fn icon<'c>(kind: &str, style: IconStyle, config: &'c Config) -> &'c str {
+    if let Some((_, icon)) = config.kind_icons.iter().rev().find(|(k, _)| k == kind) {
+        return icon;
+    }
+    let (emoji, nerd) = match kind {
+        "directory" => ("📁", "\u{f07b}"),
+        "symlink" => ("🔗", "\u{f0c1}"),
+        "code" => ("📜", "\u{f121}"),
+        "config" => ("🔧", "\u{f013}"),
+        "data" => ("📊", "\u{f1c0}"),
+        "document" => ("📘", "\u{f15c}"),
+        "text" => ("📝", "\u{f0f6}"),
+        "image" => ("🎨", "\u{f1c5}"),
+        "audio" => ("🎵", "\u{f1c7}"),
+        "video" => ("🎬", "\u{f1c8}"),
+        "archive" => ("📦", "\u{f1c6}"),
+        "font" => ("🔤", "\u{f031}"),
+        "executable" => ("⚡", "\u{f489}"),
+        "binary" => ("💾", "\u{f471}"),
+        _ => ("📄", "\u{f15b}"),
+    };
+    match style {
+        IconStyle::Emoji => emoji,
+        IconStyle::Nerd => nerd,
+    }
+}
+
+fn by_extension(ext: &str) -> Option<&'static str> {
+    Some(match ext {
+        "rs" | "py" | "js" | "mjs" | "cjs" | "ts" | "tsx" | "jsx" | "go" | "java" | "kt" | "c"
+        | "h" | "cc" | "cpp" | "cxx" | "hpp" | "cs" | "rb" | "php" | "swift" | "scala" | "lua"
+        | "pl" | "r" | "sh" | "bash" | "zsh" | "fish" | "ps1" | "sql" | "html" | "htm" | "css"
+        | "scss" | "vue" | "svelte" | "dart" | "ex" | "exs" | "erl" | "hs" | "ml" | "zig"
+        | "nim" | "clj" | "el" | "vim" => "code",
+        "toml" | "yaml" | "yml" | "ini" | "cfg" | "conf" | "env" | "lock" | "editorconfig"
+        | "properties" => "config",
+        "json" | "jsonl" | "ndjson" | "csv" | "tsv" | "xml" | "parquet" | "sqlite" | "db"
+        | "avro" | "arrow" => "data",
+        "md" | "markdown" | "rst" | "adoc" | "pdf" | "doc" | "docx" | "odt" | "rtf" | "tex"
+        | "epub" | "xls" | "xlsx" | "ods" | "ppt" | "pptx" | "odp" => "document",
+        "txt" | "log" => "text",
+        "png" | "jpg" | "jpeg" | "gif" | "bmp" | "svg" | "webp" | "ico" | "tif" | "tiff"
+        | "heic" | "avif" | "psd" => "image",
+        "mp3" | "wav" | "flac" | "ogg" | "m4a" | "aac" | "opus" | "mid" | "midi" => "audio",
+        "mp4" | "mkv" | "mov" | "avi" | "webm" | "wmv" | "m4v" => "video",
+        "zip" | "tar" | "gz" | "tgz" | "bz2" | "xz" | "zst" | "7z" | "rar" | "jar" | "deb"
+        | "rpm" | "whl" => "archive",
+        "ttf" | "otf" | "woff" | "woff2" => "font",
+        "exe" | "dll" | "so" | "dylib" | "bin" | "wasm" | "app" | "msi" => "executable",
+        "o" | "a" | "lib" | "class" | "pyc" | "rlib" => "binary",
+        _ => return None,
+    })
+}
+
+fn by_magic(head: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: [(&[u8], &str); 17] = [
+        (b"\x89PNG", "image"),
+        (b"\xFF\xD8\xFF", "image"),
+        (b"GIF8", "image"),
+        (b"%PDF", "document"),
+        (b"PK\x03\x04", "archive"),
+        (b"\x1F\x8B", "archive"),
+        (b"7z\xBC\xAF", "archive"),
+        (b"Rar!", "archive"),
+        (b"\x7FELF", "executable"),
+        (b"MZ", "executable"),
+        (b"\xCF\xFA\xED\xFE", "executable"),
+        (b"\xCA\xFE\xBA\xBE", "executable"),
+        (b"\0asm", "executable"),
+        (b"SQLite format 3\0", "data"),
+        (b"ID3", "audio"),
+        (b"fLaC", "audio"),
+        (b"OggS", "audio"),
+    ];
+    if let Some((_, kind)) = SIGNATURES.iter().find(|(sig, _)| head.starts_with(sig)) {
+        return Some(kind);
+    }
+    if head.starts_with(b"RIFF") && head.len() >= 12 {
+        return match &head[8..12] {
+            b"WAVE" => Some("audio"),
+            b"AVI " => Some("video"),
+            b"WEBP" => Some("image"),
+            _ => None,
+        };
+    }
+    if head.len() >= 8 && &head[4..8] == b"ftyp" {
+        return Some("video");
+    }
+    if head.starts_with(b"#!") {
+        return Some("code");
+    }
+    None
+}
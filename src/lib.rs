@@ -0,0 +1,4541 @@
+//! Directory walking and tree rendering behind the `folderwalk` binary.
+//!
+//! [`Walker`] renders a tree (or JSON, Markdown, a shasum manifest) into
+//! any writer, configured with builder methods. [`walk_with`] lists a
+//! directory tree with the same excludes, filters and ordering the command
+//! line uses, handing each entry to a callback, and [`Walker::entries`]
+//! yields the same entries from an iterator. [`run`] is the whole
+//! command-line tool: it walks according to a [`Config`] and writes the
+//! requested output, and [`watch`] does so again after every change.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File, ReadDir};
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+mod archive;
+mod base64;
+mod cache;
+mod collate;
+mod config_file;
+mod csv;
+mod deletion;
+mod diff;
+mod dot;
+mod encoding;
+mod exec;
+mod fsinfo;
+mod git;
+mod gitignore;
+mod gzip;
+mod html;
+mod inflate;
+mod kind;
+mod llm;
+mod long;
+mod output;
+mod prefetch;
+mod sha256;
+mod snapshot;
+mod tui;
+mod watch;
+mod width;
+mod winfs;
+mod yaml;
+
+pub use config_file::{config_file_args, config_files};
+pub use exec::split_command;
+pub use tui::interactive;
+pub use watch::watch;
+
+/// Names skipped by default wherever they appear in the tree.
+pub const DEFAULT_EXCLUDES: [&str; 3] = ["node_modules", ".git", "target"];
+
+/// What gets listed, and in which order. The default matches the command
+/// line without any flags.
+#[derive(Clone)]
+pub struct WalkOptions {
+    /// Levels below the root to descend into; `Some(1)` lists only the
+    /// root's own entries.
+    pub max_depth: Option<usize>,
+    /// Entry names skipped wherever they appear, matched exactly.
+    pub excludes: HashSet<String>,
+    /// Entries skipped by glob, by name or by root-relative path.
+    pub exclude_globs: Vec<ExcludeGlob>,
+    /// List hidden entries (names starting with `.`, and on Windows those
+    /// with the hidden attribute) too.
+    pub all: bool,
+    /// Only files whose root-relative path (using `/`) matches.
+    pub path_regex: Option<regex::Regex>,
+    /// Only files whose contents match.
+    pub grep: Option<regex::Regex>,
+    /// Only files whose name matches one of these (see [`glob_regex`]).
+    pub name_matches: Vec<regex::Regex>,
+    /// Skip files and directories whose name matches one of these; wins
+    /// over `name_matches`.
+    pub name_ignores: Vec<regex::Regex>,
+    /// Only files with an execute bit set (by extension on Windows).
+    pub executables: bool,
+    /// Only directories (and symlinks to them), like `tree -d`.
+    pub dirs_only: bool,
+    /// Only files and symlinks to files; other special entries and
+    /// symlinks to directories are left out.
+    pub files_only: bool,
+    /// Only regular files of at least this many bytes.
+    pub min_size: Option<u64>,
+    /// Only regular files of at most this many bytes.
+    pub max_size: Option<u64>,
+    /// Only regular files modified after this.
+    pub newer_than: Option<SystemTime>,
+    /// Only regular files modified before this.
+    pub older_than: Option<SystemTime>,
+    /// Only empty regular files, and directories with nothing in them.
+    pub only_empty: bool,
+    /// With a filter, also list everything in directories up to this many
+    /// levels above each match.
+    pub context_depth: Option<usize>,
+    /// Leave out directories with nothing listed in them.
+    pub prune: bool,
+    pub sort: SortKey,
+    /// Reverse the `sort` order (directories stay grouped).
+    pub reverse: bool,
+    /// Compare runs of digits in names by their value: `file2` before
+    /// `file10`.
+    pub natural_sort: bool,
+    /// Compare names by the collation locale's alphabet: accented letters
+    /// with their base letter, language-specific letters in their place.
+    pub locale_sort: bool,
+    /// Keep directories together, before files (or after, with `dirs_last`).
+    pub group_dirs: bool,
+    /// List directories after files rather than before.
+    pub dirs_last: bool,
+    /// Treat every symlink as a leaf without inspecting its target.
+    pub symlinks_as_files: bool,
+    /// Descend into symlinked directories, except ones leading back to a
+    /// directory they sit in.
+    pub follow_symlinks: bool,
+    /// Use only the file type reported while listing: no sizes, times or
+    /// device ids, and symlinks are not resolved.
+    pub fast: bool,
+    /// Never descend into the same real directory twice.
+    pub skip_loops: bool,
+    /// List directories on another filesystem than the root (mount points)
+    /// without descending into them, like `du -x`. Unix only.
+    pub one_file_system: bool,
+    /// Skip entries matched by `.gitignore` files at or below the root.
+    pub gitignore: bool,
+    /// Match `.gitignore` patterns regardless of case, as `--ignore-case`
+    /// does for the patterns compiled from the command line.
+    pub ignore_case: bool,
+    /// Threads reading directories ahead of the walk; 0 or 1 reads each
+    /// one only when it is listed. Output is the same either way.
+    pub jobs: usize,
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        WalkOptions {
+            max_depth: None,
+            excludes: DEFAULT_EXCLUDES
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            exclude_globs: Vec::new(),
+            all: false,
+            path_regex: None,
+            grep: None,
+            name_matches: Vec::new(),
+            name_ignores: Vec::new(),
+            executables: false,
+            dirs_only: false,
+            files_only: false,
+            min_size: None,
+            max_size: None,
+            newer_than: None,
+            older_than: None,
+            only_empty: false,
+            context_depth: None,
+            prune: false,
+            sort: SortKey::Name,
+            reverse: false,
+            natural_sort: false,
+            locale_sort: false,
+            group_dirs: true,
+            dirs_last: false,
+            symlinks_as_files: false,
+            follow_symlinks: false,
+            fast: false,
+            skip_loops: false,
+            one_file_system: false,
+            gitignore: false,
+            ignore_case: false,
+            jobs: 1,
+        }
+    }
+}
+
+impl WalkOptions {
+    /// Whether any option restricts which files are listed.
+    pub fn filters_files(&self) -> bool {
+        self.path_regex.is_some()
+            || self.grep.is_some()
+            || self.executables
+            || self.files_only
+            || self.min_size.is_some()
+            || self.max_size.is_some()
+            || self.newer_than.is_some()
+            || self.older_than.is_some()
+            || self.only_empty
+            || !self.name_matches.is_empty()
+    }
+}
+
+/// Compiles a shell-style glob (`*`, `?`, `[...]`, `**`) into a regex that
+/// must match the whole name, as used for `--match` and `--ignore`.
+pub fn glob_regex(glob: &str, case_insensitive: bool) -> Result<regex::Regex, regex::Error> {
+    regex::RegexBuilder::new(&format!("^{}$", gitignore::glob_to_regex(glob)))
+        .case_insensitive(case_insensitive)
+        .build()
+}
+
+/// A glob for `--exclude`. A trailing `/` limits it to directories; any
+/// other `/` matches it against the root-relative path (using `/`) instead
+/// of the name, so `dist/` skips every `dist` directory and `docs/*.pdf`
+/// only the PDFs directly in `docs`.
+#[derive(Clone)]
+pub struct ExcludeGlob {
+    regex: regex::Regex,
+    dir_only: bool,
+    anchored: bool,
+}
+
+impl ExcludeGlob {
+    pub fn new(glob: &str, case_insensitive: bool) -> Result<Self, regex::Error> {
+        let dir_only = glob.len() > 1 && glob.ends_with('/');
+        let glob = if dir_only {
+            &glob[..glob.len() - 1]
+        } else {
+            glob
+        };
+        let anchored = glob.contains('/');
+        Ok(ExcludeGlob {
+            regex: glob_regex(glob.strip_prefix('/').unwrap_or(glob), case_insensitive)?,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn excludes(&self, entry: &DirEntryInfo, root: &Path) -> bool {
+        let rel = || {
+            let rel = entry.path.strip_prefix(root).unwrap_or(&entry.path);
+            rel.components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/")
+        };
+        let is_dir = entry.file_type.is_dir() || entry.is_symlink_dir;
+        self.matches(is_dir, &entry.file_name.to_string_lossy(), rel)
+    }
+
+    /// Whether the glob excludes an entry named `name`, with `rel` giving
+    /// its root-relative path when needed; also used for the entries of a
+    /// `--tree-from-json` snapshot.
+    fn matches(&self, is_dir: bool, name: &str, rel: impl FnOnce() -> String) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            self.regex.is_match(&rel())
+        } else {
+            self.regex.is_match(name)
+        }
+    }
+}
+
+/// Everything the command-line tool can be asked to do; see [`run`].
+#[derive(Clone, Default)]
+pub struct Config {
+    pub start_dir: PathBuf,
+    pub walk: WalkOptions,
+    pub ascii: bool,
+    pub show_content: bool,
+    pub to_stdout: bool,
+    /// Where to write the output instead of `files.txt` in the root.
+    pub output: Option<PathBuf>,
+    /// Add to the end of `output` after a blank line instead of replacing
+    /// it, for several roots written to one file.
+    pub append_output: bool,
+    /// `--root-separator`: the line put before a root's output when several
+    /// paths are written to one output, with `{}` standing for the root's
+    /// path. Parsing leaves the template here and `main` fills it in for
+    /// each root, or clears it when there is only one.
+    pub root_header: Option<String>,
+    /// `--gzip`: compress the output file.
+    pub gzip: bool,
+    /// `--split-size`: split the output file into parts of at most this
+    /// many bytes of text.
+    pub split_size: Option<u64>,
+    /// `--exec`: a command to run for every file listed, already split
+    /// into words, with `{}` standing for the path.
+    pub exec: Option<Vec<String>>,
+    /// `--exec-parallel`: how many `exec` commands may run at once.
+    pub exec_parallel: usize,
+    /// Whether tree names are colored; only ever applies to stdout.
+    pub color: ColorMode,
+    pub error_format: ErrorFormat,
+    pub count_hidden: bool,
+    /// End the tree with a `tree`-style totals line.
+    pub summary: bool,
+    /// End the tree with a per-extension table of files and bytes.
+    pub stats: bool,
+    /// Show chains of directories holding only one subdirectory on one line.
+    pub collapse: bool,
+    /// `--bfs`: list the tree level by level instead of nesting it.
+    pub bfs: bool,
+    /// Stop at `max_depth` without a line saying how many entries were cut.
+    pub silent_max_depth: bool,
+    pub flatten_below: Option<usize>,
+    pub relative_names: bool,
+    pub format: OutputFormat,
+    pub progress_file: Option<PathBuf>,
+    /// Keep a live count of directories and entries on stderr, when it is
+    /// a terminal.
+    pub progress: bool,
+    pub content_max_line_length: Option<usize>,
+    pub max_content_bytes: Option<u64>,
+    /// Only show content for files this many levels below the root or
+    /// fewer; 1 is the root's own files.
+    pub content_depth: Option<usize>,
+    /// Only these content lines: 1-based, inclusive, END open when `None`.
+    pub content_lines: Option<(usize, Option<usize>)>,
+    /// Number content lines.
+    pub line_numbers: bool,
+    pub include_binary: bool,
+    /// Append each entry's size; directories show the total below them.
+    pub show_size: bool,
+    /// Prefix each tree line with permissions, owner, group and mtime.
+    pub long: bool,
+    /// Append the total size below each directory (and the root), but not
+    /// file sizes unless `show_size` is set too.
+    pub du: bool,
+    /// `--bar`: after each directory's `du` total, a bar this many cells
+    /// wide showing its share of its parent's total.
+    pub bar: Option<usize>,
+    /// With `show_size` or `du`, exact byte counts instead of `1.2 KiB`.
+    pub size_bytes: bool,
+    pub clipboard: bool,
+    pub age_histogram: bool,
+    pub canonical: bool,
+    pub show_fs: bool,
+    pub repeat: Option<usize>,
+    pub defer_errors: bool,
+    /// End the output with the entries that couldn't be read: an `Errors`
+    /// section, or an `errors` array in JSON.
+    pub errors_section: bool,
+    /// Fail on the first entry that can't be read instead of skipping it.
+    pub strict: bool,
+    /// Don't print warnings about unreadable entries (they are still counted).
+    pub quiet: bool,
+    pub content_changed_only: bool,
+    pub depth_markers: bool,
+    pub detect_encoding: bool,
+    pub verify: bool,
+    pub relative_time: bool,
+    pub strip_components: usize,
+    pub show_created: bool,
+    pub content_base64: bool,
+    pub tree_from_json: Option<PathBuf>,
+    pub rare_extension_threshold: Option<usize>,
+    pub render_width: Option<usize>,
+    pub grep_context: Option<usize>,
+    pub stats_json: Option<PathBuf>,
+    /// Keep running and regenerate the output whenever the tree changes.
+    pub watch: bool,
+    /// `--interactive`: browse the tree in the terminal instead of writing
+    /// it.
+    pub interactive: bool,
+    /// Show each file's SHA-256 in the tree, or record it in json-tree
+    /// `meta` for `--diff`.
+    pub hash_files: bool,
+    /// End the tree with the groups of files that have identical contents.
+    pub find_duplicates: bool,
+    /// List the members of zip and tar archives under them, like a
+    /// directory's entries.
+    pub archives: bool,
+    /// Mark entries with their git status.
+    pub git_status: bool,
+    /// Prefix names with an icon for their kind, and record the kind in
+    /// structured output.
+    pub icons: Option<IconStyle>,
+    /// Extra `(extension, kind)` pairs for `icons`, winning over the
+    /// built-in table; later pairs win over earlier ones.
+    pub file_kinds: Vec<(String, String)>,
+    /// `(kind, icon)` pairs replacing or adding to the built-in icons.
+    pub kind_icons: Vec<(String, String)>,
+    /// Keep directory listings in this file between runs, and only read
+    /// directories again when their modification time changed.
+    pub cache: Option<PathBuf>,
+    /// With `--format llm`, leave out files once the estimated token count
+    /// would pass this.
+    pub max_tokens: Option<usize>,
+    /// Report what changed since this json-tree snapshot instead of
+    /// listing the tree.
+    pub diff: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortKey {
+    #[default]
+    Name,
+    /// Files with identical content (by SHA-256) next to each other.
+    Hash,
+    /// Oldest creation time first; entries without one go last.
+    Created,
+    /// Smallest first; directories by the total listed below them.
+    Size,
+    /// Least recently modified first.
+    Mtime,
+    /// By lowercased extension, files without one first.
+    Ext,
+    /// The order the filesystem lists entries in, without grouping.
+    None,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Tree,
+    Shasum,
+    JsonTree,
+    Json,
+    /// A nested Markdown bullet list.
+    Markdown,
+    /// A standalone HTML page with a collapsible tree.
+    Html,
+    /// The tree followed by every file's text, for a language model.
+    Llm,
+    /// One comma-separated row per entry.
+    Csv,
+    /// One tab-separated row per entry.
+    Tsv,
+    /// A Graphviz digraph of the directories.
+    Dot,
+    /// The `Json` document as YAML.
+    Yaml,
+    /// NUL-terminated paths of what the filters picked, for `rm`.
+    DeleteList,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    /// Color when stdout is a terminal.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// The icons `--icons` puts before names.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum IconStyle {
+    #[default]
+    Emoji,
+    /// Glyphs from a Nerd Font patched terminal font.
+    Nerd,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Walks `root`, calling `visit` with every listed entry and its depth
+/// (0 for the root's own entries), parents before their children. Problems
+/// with individual entries don't stop the walk; they are collected and
+/// returned instead of being printed.
+///
+/// ```no_run
+/// use folderwalk::{WalkOptions, walk_with};
+///
+/// let errors = walk_with(".".as_ref(), &WalkOptions::default(), |entry, depth| {
+///     println!("{}{}", "  ".repeat(depth), entry.file_name.to_string_lossy());
+/// })?;
+/// eprintln!("{} entries could not be read", errors.len());
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn walk_with<F>(root: &Path, opts: &WalkOptions, mut visit: F) -> io::Result<Vec<WalkError>>
+where
+    F: FnMut(&DirEntryInfo, usize),
+{
+    let start_meta = fs::metadata(root)?;
+    if !start_meta.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Path is not a directory: {}", root.to_string_lossy()),
+        ));
+    }
+    let config = Config {
+        start_dir: root.to_path_buf(),
+        walk: opts.clone(),
+        defer_errors: true,
+        ..Config::default()
+    };
+    let mut ctx = WalkContext::new(&config, &start_meta);
+    prepare_filters(&mut ctx)?;
+    visit_dir(root, &mut ctx, 0, &mut visit)?;
+    Ok(ctx.deferred_errors)
+}
+
+/// Renders a directory the way the command line does, into a writer of
+/// your choosing. Options not covered by a builder method are reachable
+/// through [`Walker::options`] and [`Walker::config`].
+///
+/// ```no_run
+/// use folderwalk::Walker;
+///
+/// let errors = Walker::new("my_project").max_depth(3).run(std::io::stdout())?;
+/// eprintln!("{} entries could not be read", errors.len());
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Clone)]
+pub struct Walker {
+    config: Config,
+}
+
+impl Walker {
+    /// A walker for `root` with the command line's defaults.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Walker {
+            config: Config {
+                start_dir: root.into(),
+                defer_errors: true,
+                ..Config::default()
+            },
+        }
+    }
+
+    /// Levels below the root to descend into.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.config.walk.max_depth = Some(depth);
+        self
+    }
+
+    /// Also list hidden entries.
+    pub fn all(mut self, all: bool) -> Self {
+        self.config.walk.all = all;
+        self
+    }
+
+    /// Skips entries with this exact name, on top of [`DEFAULT_EXCLUDES`].
+    pub fn exclude(mut self, name: impl Into<String>) -> Self {
+        self.config.walk.excludes.insert(name.into());
+        self
+    }
+
+    pub fn sort(mut self, key: SortKey) -> Self {
+        self.config.walk.sort = key;
+        self
+    }
+
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.config.format = format;
+        self
+    }
+
+    /// Include file contents under each file.
+    pub fn content(mut self, content: bool) -> Self {
+        self.config.show_content = content;
+        self
+    }
+
+    /// Draw the tree with ASCII characters instead of Unicode.
+    pub fn ascii(mut self, ascii: bool) -> Self {
+        self.config.ascii = ascii;
+        self
+    }
+
+    /// Replaces every listing option at once.
+    pub fn options(mut self, opts: WalkOptions) -> Self {
+        self.config.walk = opts;
+        self
+    }
+
+    /// The full configuration, for rendering options without a builder
+    /// method. Where the output goes (`to_stdout`, `output`, `clipboard`)
+    /// is ignored: it always goes to the writer passed to [`Walker::run`].
+    pub fn config(&mut self) -> &mut Config {
+        &mut self.config
+    }
+
+    /// Writes the output to `writer`. Problems with individual entries
+    /// don't stop the walk; they are returned instead of being printed.
+    pub fn run(&self, mut writer: impl Write) -> io::Result<Vec<WalkError>> {
+        let config = &self.config;
+        let start_meta = fs::metadata(&config.start_dir)?;
+        if !start_meta.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Path is not a directory: {}",
+                    config.start_dir.to_string_lossy()
+                ),
+            ));
+        }
+        let mut ctx = WalkContext::new(config, &start_meta);
+        render(&mut ctx, &mut writer)?;
+        writer.flush()?;
+        Ok(ctx.deferred_errors)
+    }
+
+    /// Calls `visit` with every listed entry and its depth, like
+    /// [`walk_with`].
+    pub fn walk<F>(&self, visit: F) -> io::Result<Vec<WalkError>>
+    where
+        F: FnMut(&DirEntryInfo, usize),
+    {
+        walk_with(&self.config.start_dir, &self.config.walk, visit)
+    }
+
+    /// The listed entries as an iterator, in the same order as
+    /// [`Walker::walk`]. Directories are read as the walk reaches them, so
+    /// stopping early skips the rest of the tree.
+    ///
+    /// ```no_run
+    /// use folderwalk::Walker;
+    ///
+    /// let walker = Walker::new("my_project");
+    /// let mut entries = walker.entries()?;
+    /// let first_rust_file = entries.find(|e| e.entry.path.extension() == Some("rs".as_ref()));
+    /// eprintln!("{} entries could not be read", entries.errors().len());
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn entries(&self) -> io::Result<WalkEntries<'_>> {
+        let config = &self.config;
+        let start_meta = fs::metadata(&config.start_dir)?;
+        if !start_meta.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Path is not a directory: {}",
+                    config.start_dir.to_string_lossy()
+                ),
+            ));
+        }
+        let mut ctx = WalkContext::new(config, &start_meta);
+        prepare_filters(&mut ctx)?;
+        let mut entries = WalkEntries {
+            ctx,
+            stack: Vec::new(),
+        };
+        entries.descend(&config.start_dir, 0);
+        Ok(entries)
+    }
+}
+
+/// One entry from [`WalkEntries`], with its depth (0 for the root's own
+/// entries).
+pub struct WalkEntry {
+    pub depth: usize,
+    pub entry: DirEntryInfo,
+}
+
+/// The iterator returned by [`Walker::entries`].
+pub struct WalkEntries<'a> {
+    ctx: WalkContext<'a>,
+    /// The listings being walked, innermost last, with their depth.
+    stack: Vec<(std::vec::IntoIter<DirEntryInfo>, usize)>,
+}
+
+impl WalkEntries<'_> {
+    /// Problems met so far. Entries that couldn't be read are left out and
+    /// recorded here; if the walk had to stop (with `strict`, say), the
+    /// reason is the last one.
+    pub fn errors(&self) -> &[WalkError] {
+        &self.ctx.deferred_errors
+    }
+
+    fn descend(&mut self, dir: &Path, depth: usize) {
+        if self
+            .ctx
+            .config
+            .walk
+            .max_depth
+            .is_some_and(|maxd| depth >= maxd)
+        {
+            return;
+        }
+        match list_dir(dir, &mut self.ctx, depth) {
+            Ok(entries) => self.stack.push((entries.into_iter(), depth)),
+            Err(err) => {
+                self.ctx.deferred_errors.push(WalkError {
+                    kind: "read_dir",
+                    path: dir.to_path_buf(),
+                    message: err.to_string(),
+                });
+                self.stack.clear();
+            }
+        }
+    }
+}
+
+impl Iterator for WalkEntries<'_> {
+    type Item = WalkEntry;
+
+    fn next(&mut self) -> Option<WalkEntry> {
+        loop {
+            let (listing, depth) = self.stack.last_mut()?;
+            let depth = *depth;
+            let Some(entry) = listing.next() else {
+                self.stack.pop();
+                continue;
+            };
+            if is_walkable_dir(&entry) && self.ctx.first_visit(&entry.path) {
+                self.descend(&entry.path, depth + 1);
+            }
+            return Some(WalkEntry { depth, entry });
+        }
+    }
+}
+
+fn visit_dir(
+    dir: &Path,
+    ctx: &mut WalkContext,
+    depth: usize,
+    visit: &mut dyn FnMut(&DirEntryInfo, usize),
+) -> io::Result<()> {
+    if ctx.config.walk.max_depth.is_some_and(|maxd| depth >= maxd) {
+        return Ok(());
+    }
+    for entry in list_dir(dir, ctx, depth)? {
+        visit(&entry, depth);
+        if is_walkable_dir(&entry) && ctx.first_visit(&entry.path) {
+            visit_dir(&entry.path, ctx, depth + 1, visit)?;
+        }
+    }
+    Ok(())
+}
+
+/// Walks `config.start_dir` and writes the configured output, exactly as
+/// the command-line tool does. Returns how many entries couldn't be read
+/// and were left out, so 0 means the output is complete.
+pub fn run(config: &Config) -> io::Result<usize> {
+    if let Some(snapshot) = &config.tree_from_json {
+        return run_snapshot(config, snapshot).map(|()| 0);
+    }
+    if let Some(snapshot) = &config.diff {
+        return diff::run_diff(config, snapshot);
+    }
+
+    let resolved;
+    let config = if config.canonical {
+        let start_dir = fs::canonicalize(&config.start_dir).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "cannot canonicalize {}: {e}",
+                    config.start_dir.to_string_lossy()
+                ),
+            )
+        })?;
+        resolved = Config {
+            start_dir,
+            ..config.clone()
+        };
+        &resolved
+    } else {
+        config
+    };
+
+    let start_meta = fs::metadata(&config.start_dir)?;
+    if !start_meta.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Path is not a directory: {}",
+                config.start_dir.to_string_lossy()
+            ),
+        ));
+    }
+
+    if config.walk.sort == SortKey::Hash && config.error_format == ErrorFormat::Text {
+        eprintln!("Note: --sort hash reads every file in full to hash it; this can be slow");
+    }
+
+    if let Some(runs) = config.repeat {
+        return benchmark(config, &start_meta, runs).map(|()| 0);
+    }
+
+    let output_path = output_path(config);
+    let writes_file = !config.to_stdout && !config.clipboard;
+
+    let mut clip_buf: Vec<u8> = Vec::new();
+    let mut outfile = if writes_file {
+        Some(output::OutputFile::create(output_path.clone(), config)?)
+    } else {
+        None
+    };
+    let mut writer: Box<dyn Write + '_> = match &mut outfile {
+        Some(outfile) => Box::new(outfile),
+        None if config.clipboard => Box::new(&mut clip_buf),
+        None => Box::new(io::stdout()),
+    };
+
+    let mut ctx = WalkContext::new(config, &start_meta);
+    if writes_file {
+        ctx.output_path = output::canonical(&output_path);
+    }
+    ctx.cache = config
+        .cache
+        .as_deref()
+        .map(|path| cache::DirCache::load(path, &config.walk));
+    ctx.progress = config.progress_file.as_deref().map(ProgressFile::new);
+    ctx.exec = exec::Exec::new(config);
+    // Not drawn over a tree going to the same terminal.
+    if config.progress
+        && io::stderr().is_terminal()
+        && !(config.to_stdout && !config.clipboard && io::stdout().is_terminal())
+    {
+        ctx.progress_line = Some(ProgressLine::new());
+    }
+    ctx.color = config.to_stdout
+        && !config.clipboard
+        && match config.color {
+            // https://no-color.org: any non-empty NO_COLOR turns it off.
+            ColorMode::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && io::stdout().is_terminal()
+            }
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        };
+
+    let started = Instant::now();
+    render(&mut ctx, &mut *writer)?;
+    if let Some(exec) = &mut ctx.exec {
+        exec.finish();
+    }
+    let elapsed = started.elapsed();
+
+    writer.flush()?;
+    drop(writer);
+    let written = match outfile {
+        Some(outfile) => outfile.finish()?,
+        None => Vec::new(),
+    };
+    if let Some(line) = &mut ctx.progress_line {
+        line.clear();
+    }
+    report_deferred_errors(config, &ctx.deferred_errors);
+    if let Some(progress) = &ctx.progress {
+        progress.write("done")?;
+    }
+    if let Some(cache) = &ctx.cache {
+        cache.save(&config.start_dir)?;
+    }
+    if let Some(path) = &config.stats_json {
+        write_stats_json(path, &ctx, elapsed)?;
+    }
+
+    if config.clipboard {
+        let text = String::from_utf8_lossy(&clip_buf).into_owned();
+        copy_to_clipboard(text)?;
+        if config.to_stdout {
+            io::stdout().write_all(&clip_buf)?;
+        }
+    }
+
+    if config.verify {
+        let mut outputs = Vec::new();
+        if writes_file {
+            outputs.push((written, ProducedOutput::Main(config.format)));
+        }
+        if let Some(path) = &config.progress_file {
+            outputs.push((vec![path.clone()], ProducedOutput::Progress));
+        }
+        verify_outputs(&outputs)?;
+    }
+    if let Some(exec) = &ctx.exec
+        && exec.failed > 0
+    {
+        return Err(io::Error::other(format!(
+            "{} of {} --exec commands failed",
+            exec.failed, exec.started
+        )));
+    }
+    Ok(ctx.problems)
+}
+
+/// `--output`, or `files.txt` (`files.html` for HTML, plus `.gz` with
+/// `--gzip`) in the root. With `--split-size` this names the parts rather
+/// than a file; see [`output::part_path`].
+fn output_path(config: &Config) -> PathBuf {
+    let default = match (config.format == OutputFormat::Html, config.gzip) {
+        (false, false) => "files.txt",
+        (true, false) => "files.html",
+        (false, true) => "files.txt.gz",
+        (true, true) => "files.html.gz",
+    };
+    config
+        .output
+        .clone()
+        .unwrap_or_else(|| config.start_dir.join(default))
+}
+
+/// `--tree-from-json`: renders a saved json-tree (or json) document without
+/// touching the directory it describes. Output goes wherever a normal run's
+/// would. The saved listings go through the filters a walk applies to its
+/// own (hidden names, `--exclude`, `--ignore`, `--dirs-only`, `--max-depth`)
+/// and are counted the same way, so `--size`, `--du`, `--summary` and
+/// `--count-hidden-separately` show what the walk would have; options that
+/// need the files themselves are rejected before this runs.
+fn run_snapshot(config: &Config, path: &Path) -> io::Result<()> {
+    let invalid = |e: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {e}", path.to_string_lossy()),
+        )
+    };
+    let mut root = snapshot::load(path).map_err(invalid)?;
+    let wants_sizes = config.format == OutputFormat::Tree
+        && (config.show_size || config.du || config.summary && !config.walk.fast);
+    if wants_sizes && !has_sizes(&root) {
+        return Err(invalid(
+            "--size, --du and --summary need the sizes a json-tree snapshot records".to_string(),
+        ));
+    }
+    let mut ctx = WalkContext::new(config, &fs::metadata(path)?);
+    let total = filter_snapshot(&mut root.children, &mut ctx, &root.id, 0);
+
+    let mut out = Vec::new();
+    if config.format == OutputFormat::JsonTree {
+        let mut json = String::new();
+        write_snapshot_json(&root, config, 0, &mut json);
+        writeln!(out, "{json}")?;
+    } else if config.format == OutputFormat::Json {
+        let node = Node::Dir {
+            name: root.label.clone(),
+            children: snapshot_nodes(&root.children, config, 0),
+            error: root.error.clone(),
+        };
+        let mut json = String::new();
+        node.write_json(".", &mut json);
+        writeln!(out, "{json}")?;
+    } else if config.format == OutputFormat::Yaml {
+        let node = Node::Dir {
+            name: root.label.clone(),
+            children: snapshot_nodes(&root.children, config, 0),
+            error: root.error.clone(),
+        };
+        let mut yaml = String::new();
+        yaml::write_node(&node, ".", "", "", &mut yaml);
+        out.write_all(yaml.as_bytes())?;
+    } else if config.format == OutputFormat::Markdown {
+        writeln!(out, "- {}/", markdown_escape(&root.label))?;
+        write_snapshot_markdown(&root.children, config, &mut out, 0)?;
+    } else {
+        let mut root_name = root.label.clone();
+        if config.du {
+            root_name.push_str(&format!(" ({})", size_label(config, total)));
+        }
+        if config.depth_markers {
+            root_name.insert_str(0, "[0] ");
+        }
+        let root_name = fit_width(config, "", root_name);
+        writeln!(out, "{root_name}")?;
+        write_snapshot_tree(&root.children, &ctx, &mut out, "", 0, total)?;
+        if config.count_hidden {
+            write_hidden_counts(&ctx.stats, &mut out)?;
+        }
+        if config.summary {
+            write_summary(&ctx, &mut out)?;
+        }
+    }
+
+    let output_path = output_path(config);
+    if config.clipboard {
+        copy_to_clipboard(String::from_utf8_lossy(&out).into_owned())?;
+    }
+    if config.to_stdout {
+        io::stdout().write_all(&out)?;
+    } else if !config.clipboard {
+        let mut outfile = output::OutputFile::create(output_path, config)?;
+        outfile.write_all(&out)?;
+        let written = outfile.finish()?;
+        if config.verify {
+            verify_outputs(&[(written, ProducedOutput::Main(config.format))])?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether every file and symlink below `node` that could be read has a
+/// recorded size, which a `--format json` document never has.
+fn has_sizes(node: &snapshot::Node) -> bool {
+    node.children.iter().all(|child| match child.kind {
+        snapshot::NodeKind::Folder => has_sizes(child),
+        // Entries that couldn't be statted have nothing to record.
+        _ => child.error.is_some() || child.meta_u64("size").is_some(),
+    })
+}
+
+/// Drops the saved entries of a listing at `depth` that the walk's filters
+/// would have left out, and counts the rest in `ctx` as if they had been
+/// listed: the stats, the skipped entries for `--summary`, and each
+/// directory's total in `dir_sizes` (by id) for `--du`. Returns the
+/// listing's total size. Listings below `--max-depth` are filtered too, as
+/// sizes and cut-off markers still count them, but not counted as listed.
+fn filter_snapshot(
+    nodes: &mut Vec<snapshot::Node>,
+    ctx: &mut WalkContext,
+    dir_id: &str,
+    depth: usize,
+) -> u64 {
+    let walk = &ctx.config.walk;
+    let listed = walk.max_depth.is_none_or(|maxd| depth < maxd);
+    let before = nodes.len();
+    nodes.retain(|node| {
+        let is_dir = node.kind == snapshot::NodeKind::Folder;
+        let name = node.label.as_str();
+        (walk.all || !name.starts_with('.'))
+            && !walk.excludes.contains(name)
+            && !walk.name_ignores.iter().any(|re| re.is_match(name))
+            && !walk
+                .exclude_globs
+                .iter()
+                .any(|g| g.matches(is_dir, name, || node.id.clone()))
+            && (is_dir || !walk.dirs_only)
+    });
+    if listed {
+        ctx.skipped
+            .insert(PathBuf::from(dir_id), before - nodes.len());
+    }
+    let mut total = 0;
+    for node in nodes.iter_mut() {
+        let hidden = node.label.starts_with('.');
+        let size = if node.kind == snapshot::NodeKind::Folder {
+            let size = filter_snapshot(&mut node.children, ctx, &node.id, depth + 1);
+            ctx.dir_sizes.insert(PathBuf::from(&node.id), size);
+            size
+        } else {
+            node.meta_u64("size").unwrap_or(0)
+        };
+        total += size;
+        if !listed {
+            continue;
+        }
+        let stats = &mut ctx.stats;
+        match node.kind {
+            snapshot::NodeKind::Folder => {
+                stats.dirs += 1;
+                stats.hidden_dirs += usize::from(hidden);
+            }
+            kind => {
+                stats.files += 1;
+                stats.hidden_files += usize::from(hidden);
+                if kind == snapshot::NodeKind::Symlink {
+                    stats.symlinks += 1;
+                } else {
+                    stats.bytes += size;
+                }
+            }
+        }
+    }
+    total
+}
+
+/// The `--tree-from-json` tree, drawn with the walk's glyphs, size labels
+/// and cut-off markers from what [`filter_snapshot`] left and counted.
+fn write_snapshot_tree(
+    nodes: &[snapshot::Node],
+    ctx: &WalkContext,
+    writer: &mut dyn Write,
+    prefix: &str,
+    depth: usize,
+    parent_total: u64,
+) -> io::Result<()> {
+    let config = ctx.config;
+    if config.walk.max_depth.is_some_and(|maxd| depth >= maxd) {
+        if !config.silent_max_depth {
+            write_cutoff_line(config, writer, prefix, depth, nodes.len())?;
+        }
+        return Ok(());
+    }
+    let (tee, elbow, pipe, space) = tree_glyphs(config);
+    for (idx, node) in nodes.iter().enumerate() {
+        let is_last = idx == nodes.len() - 1;
+        let branch = if is_last { elbow } else { tee };
+        let mut name = if config.relative_names {
+            strip_components(config, &node.id).to_string()
+        } else {
+            node.label.clone()
+        };
+        match node.kind {
+            snapshot::NodeKind::Folder => name.push('/'),
+            snapshot::NodeKind::Symlink => {
+                name = format!("{name} -> {}", node.target().unwrap_or("<unreadable>"));
+            }
+            snapshot::NodeKind::File => {}
+        }
+        let is_dir = node.kind == snapshot::NodeKind::Folder;
+        let size = if is_dir {
+            ctx.dir_sizes.get(Path::new(&node.id)).copied().unwrap_or(0)
+        } else {
+            node.meta_u64("size").unwrap_or(0)
+        };
+        if config.show_size || (config.du && is_dir) {
+            name.push_str(&format!(" ({})", size_label(config, size)));
+        }
+        if let Some(width) = config.bar
+            && is_dir
+        {
+            name.push_str(&format!(" {}", size_bar(config, width, size, parent_total)));
+        }
+        if config.depth_markers {
+            name.insert_str(0, &format!("[{}] ", depth + 1));
+        }
+        let name = fit_width(config, &format!("{prefix}{branch}"), name);
+        writeln!(writer, "{prefix}{branch}{name}")?;
+        if is_dir {
+            let child_prefix = format!("{prefix}{}", if is_last { space } else { pipe });
+            write_snapshot_tree(&node.children, ctx, writer, &child_prefix, depth + 1, size)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_snapshot_markdown(
+    nodes: &[snapshot::Node],
+    config: &Config,
+    writer: &mut dyn Write,
+    depth: usize,
+) -> io::Result<()> {
+    if config.walk.max_depth.is_some_and(|maxd| depth >= maxd) {
+        return Ok(());
+    }
+    for node in nodes {
+        let name = markdown_escape(&node.label);
+        let indent = "  ".repeat(depth + 1);
+        match node.kind {
+            snapshot::NodeKind::Folder => writeln!(writer, "{indent}- {name}/")?,
+            snapshot::NodeKind::File => writeln!(writer, "{indent}- {name}")?,
+            snapshot::NodeKind::Symlink => {
+                let target = markdown_escape(node.target().unwrap_or("<unreadable>"));
+                writeln!(writer, "{indent}- {name} -> {target}")?;
+            }
+        }
+        if node.kind == snapshot::NodeKind::Folder {
+            write_snapshot_markdown(&node.children, config, writer, depth + 1)?;
+        }
+    }
+    Ok(())
+}
+
+fn snapshot_nodes(nodes: &[snapshot::Node], config: &Config, depth: usize) -> Vec<Node> {
+    if config.walk.max_depth.is_some_and(|maxd| depth >= maxd) {
+        return Vec::new();
+    }
+    nodes
+        .iter()
+        .map(|node| match node.kind {
+            snapshot::NodeKind::Folder => Node::Dir {
+                name: node.label.clone(),
+                children: snapshot_nodes(&node.children, config, depth + 1),
+                error: node.error.clone(),
+            },
+            snapshot::NodeKind::File => Node::File {
+                name: node.label.clone(),
+                kind: None,
+                content: None,
+                error: node.error.clone(),
+            },
+            snapshot::NodeKind::Symlink => Node::Symlink {
+                name: node.label.clone(),
+                target: node.target().unwrap_or_default().to_string(),
+                children: None,
+                error: node.error.clone(),
+            },
+        })
+        .collect()
+}
+
+/// Writes `node` back out as json-tree, dropping children below --max-depth.
+fn write_snapshot_json(node: &snapshot::Node, config: &Config, depth: usize, out: &mut String) {
+    out.push_str(&format!(
+        "{{\"id\":\"{}\",\"label\":\"{}\",\"icon\":\"{}\",\"meta\":",
+        json_escape(&node.id),
+        json_escape(&node.label),
+        node.kind.icon()
+    ));
+    snapshot::write_json_object(&node.meta, out);
+    if node.kind == snapshot::NodeKind::Folder {
+        out.push_str(",\"children\":[");
+        if config.walk.max_depth.is_none_or(|maxd| depth < maxd) {
+            for (idx, child) in node.children.iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+                write_snapshot_json(child, config, depth + 1, out);
+            }
+        }
+        out.push(']');
+    }
+    if let Some(error) = &node.error {
+        out.push_str(&format!(",\"error\":\"{}\"", json_escape(error)));
+    }
+    out.push('}');
+}
+
+/// Bumped whenever a field of the `--stats-json` document changes meaning
+/// or is removed; new fields may appear without a bump. Version 2 added
+/// the `(none)` bucket to `extensions`.
+const STATS_SCHEMA_VERSION: u32 = 2;
+
+fn write_stats_json(path: &Path, ctx: &WalkContext, elapsed: Duration) -> io::Result<()> {
+    let config = ctx.config;
+    let stats = &ctx.stats;
+    let format = match config.format {
+        OutputFormat::Tree => "tree",
+        OutputFormat::Shasum => "shasum",
+        OutputFormat::JsonTree => "json-tree",
+        OutputFormat::Json => "json",
+        OutputFormat::Markdown => "markdown",
+        OutputFormat::Html => "html",
+        OutputFormat::Llm => "llm",
+        OutputFormat::Csv => "csv",
+        OutputFormat::Tsv => "tsv",
+        OutputFormat::Dot => "dot",
+        OutputFormat::Yaml => "yaml",
+        OutputFormat::DeleteList => "delete-list",
+    };
+    let mut extensions: Vec<_> = stats.extensions.iter().collect();
+    extensions.sort_by(|a, b| a.0.cmp(b.0));
+    let extensions = extensions
+        .iter()
+        .map(|(ext, seen)| {
+            format!(
+                "\"{}\":{{\"files\":{},\"bytes\":{}}}",
+                json_escape(ext),
+                seen.count,
+                seen.bytes
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    let largest = match &stats.largest {
+        Some((path, len)) => format!(
+            "{{\"path\":\"{}\",\"bytes\":{len}}}",
+            json_escape(&relative_path(config, path, false))
+        ),
+        None => "null".to_string(),
+    };
+    let deepest = match &stats.deepest {
+        Some((path, _)) => format!("\"{}\"", json_escape(&relative_path(config, path, false))),
+        None => "null".to_string(),
+    };
+    let json = format!(
+        "{{\"schema_version\":{STATS_SCHEMA_VERSION},\"root\":\"{}\",\"format\":\"{format}\",\
+         \"elapsed_ms\":{},\"directories\":{},\"files\":{},\"hidden_directories\":{},\
+         \"hidden_files\":{},\"total_bytes\":{},\"extensions\":{{{extensions}}},\
+         \"largest_file\":{largest},\"deepest_path\":{deepest}}}\n",
+        json_escape(&config.start_dir.to_string_lossy()),
+        elapsed.as_millis(),
+        stats.dirs,
+        stats.files,
+        stats.hidden_dirs,
+        stats.hidden_files,
+        stats.bytes,
+    );
+    fs::write(path, json)
+}
+
+/// What a file written by this run is expected to contain, for `--verify`.
+enum ProducedOutput {
+    Main(OutputFormat),
+    Progress,
+}
+
+/// Re-reads every produced file and checks it is non-empty, UTF-8, and
+/// shaped like its format. A split output is checked as its parts joined
+/// up, after decompressing them with `--gzip`. Prints one line per output
+/// and a summary on stderr, and fails the run if any check failed.
+fn verify_outputs(outputs: &[(Vec<PathBuf>, ProducedOutput)]) -> io::Result<()> {
+    let mut failed = 0;
+    for (paths, kind) in outputs {
+        let path = match paths.as_slice() {
+            [first, .., last] => {
+                format!("{} .. {}", first.to_string_lossy(), last.to_string_lossy())
+            }
+            [only] => only.to_string_lossy().into_owned(),
+            [] => continue,
+        };
+        let result = output::read_text(paths)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| {
+                let text = String::from_utf8(bytes).map_err(|_| "not valid UTF-8".to_string())?;
+                if text.is_empty() {
+                    return Err("file is empty".to_string());
+                }
+                check_output(&text, kind)
+            });
+        match result {
+            Ok(()) => eprintln!("verify: ok   {path}"),
+            Err(why) => {
+                failed += 1;
+                eprintln!("verify: FAIL {path}: {why}");
+            }
+        }
+    }
+    eprintln!(
+        "verify: {} of {} output(s) passed",
+        outputs.len() - failed,
+        outputs.len()
+    );
+    if failed > 0 {
+        return Err(io::Error::other(format!(
+            "{failed} output(s) failed verification"
+        )));
+    }
+    Ok(())
+}
+
+fn check_output(text: &str, kind: &ProducedOutput) -> Result<(), String> {
+    match kind {
+        ProducedOutput::Main(OutputFormat::Tree | OutputFormat::Markdown | OutputFormat::Llm) => {
+            if text.ends_with('\n') {
+                Ok(())
+            } else {
+                Err("output does not end with a newline".to_string())
+            }
+        }
+        ProducedOutput::Main(format @ (OutputFormat::Csv | OutputFormat::Tsv)) => {
+            let sep = if *format == OutputFormat::Csv {
+                ","
+            } else {
+                "\t"
+            };
+            if text
+                .lines()
+                .next()
+                .is_some_and(|header| header.starts_with(&csv::HEADER.join(sep)))
+            {
+                Ok(())
+            } else {
+                Err("missing the header row".to_string())
+            }
+        }
+        ProducedOutput::Main(OutputFormat::Dot) => {
+            if text.starts_with("digraph ") && text.trim_end().ends_with('}') {
+                Ok(())
+            } else {
+                Err("not a complete digraph".to_string())
+            }
+        }
+        ProducedOutput::Main(OutputFormat::Html) => {
+            if text.trim_end().ends_with("</html>") {
+                Ok(())
+            } else {
+                Err("HTML document is not closed".to_string())
+            }
+        }
+        ProducedOutput::Main(OutputFormat::DeleteList) => {
+            if !text.ends_with('\0') {
+                Err("the last path is not NUL-terminated".to_string())
+            } else if text.starts_with('\0') || text.contains("\0\0") {
+                Err("an empty path".to_string())
+            } else {
+                Ok(())
+            }
+        }
+        ProducedOutput::Main(OutputFormat::Shasum) => {
+            for (n, line) in text.lines().enumerate() {
+                let line = line.strip_prefix('\\').unwrap_or(line);
+                let well_formed = line.len() > 66
+                    && line.as_bytes()[..64]
+                        .iter()
+                        .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(b))
+                    && &line[64..66] == "  ";
+                if !well_formed {
+                    return Err(format!("line {} is not `<sha256>  <path>`", n + 1));
+                }
+            }
+            Ok(())
+        }
+        ProducedOutput::Main(OutputFormat::JsonTree | OutputFormat::Json) => {
+            let trimmed = text.trim_end();
+            if trimmed.starts_with('{') && trimmed.ends_with('}') && text.lines().count() == 1 {
+                Ok(())
+            } else {
+                Err("not a single JSON object".to_string())
+            }
+        }
+        ProducedOutput::Main(OutputFormat::Yaml) => {
+            if text.starts_with("name: ") && text.ends_with('\n') {
+                Ok(())
+            } else {
+                Err("not a YAML document for the root".to_string())
+            }
+        }
+        ProducedOutput::Progress => {
+            if text.lines().any(|l| l == "status=done") {
+                Ok(())
+            } else {
+                Err("progress file does not report status=done".to_string())
+            }
+        }
+    }
+}
+
+fn render(ctx: &mut WalkContext, writer: &mut dyn Write) -> io::Result<()> {
+    prepare_filters(ctx)?;
+    match ctx.config.format {
+        OutputFormat::Shasum => write_shasum(&ctx.config.start_dir, ctx, writer, 0),
+        OutputFormat::JsonTree => write_json_tree(ctx, writer),
+        OutputFormat::Json => write_json(ctx, writer),
+        OutputFormat::Markdown => write_markdown(ctx, writer),
+        OutputFormat::Html => html::write_html(ctx, writer),
+        OutputFormat::Llm => llm::write_llm(ctx, writer),
+        OutputFormat::Csv => csv::write_table(ctx, writer, ','),
+        OutputFormat::Tsv => csv::write_table(ctx, writer, '\t'),
+        OutputFormat::Dot => dot::write_dot(ctx, writer),
+        OutputFormat::Yaml => yaml::write_yaml(ctx, writer),
+        OutputFormat::DeleteList => deletion::write_delete_list(ctx, writer),
+        OutputFormat::Tree => write_tree(ctx, writer),
+    }
+}
+
+/// Gets ready before anything is listed: starts the `--jobs` threads
+/// reading ahead of the walk, and runs the first pass of `--context-depth`.
+fn prepare_filters(ctx: &mut WalkContext) -> io::Result<()> {
+    if ctx.config.walk.jobs > 1 {
+        ctx.prefetch = Some(prefetch::Prefetch::start(
+            &ctx.config.start_dir,
+            &ctx.config.walk,
+        ));
+    }
+    if ctx.config.walk.context_depth.is_some() {
+        let mut distances = HashMap::new();
+        ctx.prescan = true;
+        let scanned = match_distance(&ctx.config.start_dir, ctx, 0, &mut distances);
+        ctx.prescan = false;
+        scanned?;
+        ctx.match_distances = Some(distances);
+    }
+    Ok(())
+}
+
+/// `--repeat N`: renders the walk N times into a sink and reports timings
+/// on stderr. Nothing is written to files.txt or stdout.
+fn benchmark(config: &Config, start_meta: &fs::Metadata, runs: usize) -> io::Result<()> {
+    let mut timings = Vec::with_capacity(runs);
+    for _ in 0..runs {
+        let mut ctx = WalkContext::new(config, start_meta);
+        let started = Instant::now();
+        render(&mut ctx, &mut io::sink())?;
+        timings.push(started.elapsed());
+    }
+
+    let min = timings.iter().min().copied().unwrap_or_default();
+    let max = timings.iter().max().copied().unwrap_or_default();
+    let mean = timings.iter().sum::<Duration>() / runs as u32;
+    eprintln!("{runs} runs: min {min:.2?}, mean {mean:.2?}, max {max:.2?}");
+    Ok(())
+}
+
+#[cfg(feature = "clipboard")]
+fn copy_to_clipboard(text: String) -> io::Result<()> {
+    let unavailable = |e: arboard::Error| io::Error::other(format!("clipboard unavailable: {e}"));
+    let mut clipboard = arboard::Clipboard::new().map_err(unavailable)?;
+    clipboard.set_text(text).map_err(unavailable)
+}
+
+#[cfg(not(feature = "clipboard"))]
+fn copy_to_clipboard(_text: String) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "clipboard support not compiled in; rebuild with `--features clipboard`",
+    ))
+}
+
+fn write_tree(ctx: &mut WalkContext, writer: &mut dyn Write) -> io::Result<()> {
+    let config = ctx.config;
+
+    let mut root_name = display_root_name(&config.start_dir).unwrap_or_else(|| ".".to_string());
+    if let (Some(info), Some(dev)) = (&ctx.fs_info, ctx.current_dev) {
+        root_name.push_str(&format!(" [{}]", info.describe(dev)));
+    }
+    if config.du {
+        ctx.prescan = true;
+        let total = dir_size(&config.start_dir, ctx, 0);
+        ctx.prescan = false;
+        let total = total?;
+        root_name.push_str(&format!(" ({})", size_label(config, total)));
+    }
+    if config.depth_markers {
+        root_name.insert_str(0, "[0] ");
+    }
+    // With --long every line starts with the columns; lines that have
+    // none (content, cut-off markers) get blanks instead, so they are part
+    // of the prefix handed down the walk.
+    let (columns, prefix) = match &ctx.owners {
+        Some(owners) => (
+            owners.columns(fs::symlink_metadata(&config.start_dir).ok().as_ref()),
+            " ".repeat(long::WIDTH),
+        ),
+        None => (String::new(), String::new()),
+    };
+    let root_name = fit_width(config, &columns, root_name);
+    writeln!(writer, "{columns}{root_name}")?;
+
+    if config.bfs {
+        walk_levels(ctx, writer, &prefix)?;
+    } else {
+        walk_dir(&config.start_dir, ctx, writer, &prefix, 0)?;
+    }
+
+    if config.count_hidden {
+        write_hidden_counts(&ctx.stats, writer)?;
+    }
+    if config.age_histogram {
+        write_age_histogram(&ctx.stats.ages, writer)?;
+    }
+    if let Some(threshold) = config.rare_extension_threshold {
+        write_rare_extensions(ctx, threshold, writer)?;
+    }
+    if config.stats {
+        write_stats_table(ctx, writer)?;
+    }
+    if config.find_duplicates {
+        write_duplicates(ctx, writer)?;
+    }
+    if let Some(exec) = &mut ctx.exec {
+        exec.finish();
+    }
+    if config.summary {
+        write_summary(ctx, writer)?;
+    }
+    if config.errors_section {
+        write_errors_section(ctx, writer)?;
+    }
+    Ok(())
+}
+
+/// `--count-hidden-separately`: the totals with how many of each were hidden.
+fn write_hidden_counts(stats: &WalkStats, writer: &mut dyn Write) -> io::Result<()> {
+    writeln!(
+        writer,
+        "\n{} directories ({} hidden), {} files ({} hidden)",
+        stats.dirs, stats.hidden_dirs, stats.files, stats.hidden_files
+    )
+}
+
+/// `--errors-section`: the entries that couldn't be read, after the tree,
+/// so they aren't lost among the warnings on stderr.
+fn write_errors_section(ctx: &WalkContext, writer: &mut dyn Write) -> io::Result<()> {
+    if ctx.section_errors.is_empty() {
+        return Ok(());
+    }
+    writeln!(writer, "\nErrors ({}):", ctx.section_errors.len())?;
+    for error in &ctx.section_errors {
+        writeln!(writer, "  {}", error.describe())?;
+    }
+    Ok(())
+}
+
+/// `--errors-section` in JSON output: `,"errors":[...]`, to go before the
+/// root object's closing brace.
+fn errors_json(ctx: &WalkContext) -> String {
+    let errors: Vec<String> = ctx
+        .section_errors
+        .iter()
+        .map(|e| error_json(e.kind, Some(&e.path), &e.message))
+        .collect();
+    format!(",\"errors\":[{}]", errors.join(","))
+}
+
+/// `--find-duplicates`: every set of two or more files with the same
+/// SHA-256, the sets wasting the most space first. Empty files are left
+/// out, as they are all trivially the same.
+fn write_duplicates(ctx: &WalkContext, writer: &mut dyn Write) -> io::Result<()> {
+    let config = ctx.config;
+    let mut groups: Vec<_> = ctx
+        .duplicates
+        .iter()
+        .filter(|(_, (_, paths))| paths.len() > 1)
+        .collect();
+    if groups.is_empty() {
+        return writeln!(writer, "\nNo duplicate files.");
+    }
+    let wasted = |len: u64, copies: usize| len * (copies as u64 - 1);
+    groups.sort_by(|a, b| {
+        let (a_len, a_paths) = a.1;
+        let (b_len, b_paths) = b.1;
+        wasted(*b_len, b_paths.len())
+            .cmp(&wasted(*a_len, a_paths.len()))
+            .then_with(|| a_paths.cmp(b_paths))
+    });
+    let total: u64 = groups
+        .iter()
+        .map(|(_, (len, paths))| wasted(*len, paths.len()))
+        .sum();
+    let size = |bytes: u64| {
+        if config.size_bytes {
+            bytes.to_string()
+        } else {
+            human_size(bytes)
+        }
+    };
+    let sets = if groups.len() == 1 { "set" } else { "sets" };
+    writeln!(
+        writer,
+        "\nDuplicate files ({} {sets}, {} in extra copies):",
+        groups.len(),
+        size(total)
+    )?;
+    for (digest, (len, paths)) in groups {
+        writeln!(
+            writer,
+            "  {} x {}  sha256:{}",
+            paths.len(),
+            size(*len),
+            &sha256::to_hex(digest)[..12]
+        )?;
+        for path in paths {
+            writeln!(writer, "    {}", relative_path(config, path, false))?;
+        }
+    }
+    Ok(())
+}
+
+/// `--stats`: files and bytes per extension, largest first, then the
+/// largest file and the deepest entry.
+fn write_stats_table(ctx: &WalkContext, writer: &mut dyn Write) -> io::Result<()> {
+    let config = ctx.config;
+    let stats = &ctx.stats;
+    let size = |bytes: u64| {
+        if config.size_bytes {
+            bytes.to_string()
+        } else {
+            human_size(bytes)
+        }
+    };
+    let mut extensions: Vec<_> = stats.extensions.iter().collect();
+    extensions.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then_with(|| a.0.cmp(b.0)));
+    writeln!(
+        writer,
+        "\n{:<12} {:>8} {:>12}",
+        "Extension", "Files", "Size"
+    )?;
+    for (ext, seen) in extensions {
+        let label = if ext == NO_EXTENSION {
+            ext.clone()
+        } else {
+            format!(".{ext}")
+        };
+        writeln!(
+            writer,
+            "{label:<12} {:>8} {:>12}",
+            seen.count,
+            size(seen.bytes)
+        )?;
+    }
+    if let Some((path, len)) = &stats.largest {
+        writeln!(
+            writer,
+            "Largest file: {} ({})",
+            relative_path(config, path, false),
+            size(*len)
+        )?;
+    }
+    if let Some((path, _)) = &stats.deepest {
+        let rel = relative_path(config, path, false);
+        let levels = Path::new(&rel).components().count();
+        let unit = if levels == 1 { "level" } else { "levels" };
+        writeln!(writer, "Deepest path: {rel} ({levels} {unit})")?;
+    }
+    Ok(())
+}
+
+/// `--summary`: `3 directories, 12 files, 1 symlink, 40.2 KiB`. Symlinks
+/// are split out of the file count, and the size (regular files only) is
+/// left out under `--fast`, which never learns it.
+fn write_summary(ctx: &WalkContext, writer: &mut dyn Write) -> io::Result<()> {
+    let stats = &ctx.stats;
+    let plural =
+        |n: usize, one: &str, many: &str| format!("{n} {}", if n == 1 { one } else { many });
+    let mut parts = vec![
+        plural(stats.dirs, "directory", "directories"),
+        plural(stats.files - stats.symlinks, "file", "files"),
+    ];
+    if stats.symlinks > 0 {
+        parts.push(plural(stats.symlinks, "symlink", "symlinks"));
+    }
+    if !ctx.config.walk.fast {
+        parts.push(human_size(stats.bytes));
+    }
+    let skipped: usize = ctx.skipped.values().sum();
+    if skipped > 0 {
+        parts.push(format!("{skipped} skipped"));
+    }
+    if ctx.problems > 0 {
+        parts.push(format!("{} unreadable", ctx.problems));
+    }
+    if let Some(exec) = &ctx.exec {
+        parts.push(plural(exec.started, "command run", "commands run"));
+        if exec.failed > 0 {
+            parts.push(format!("{} failed", exec.failed));
+        }
+    }
+    writeln!(writer, "\n{}", parts.join(", "))
+}
+
+fn write_rare_extensions(
+    ctx: &WalkContext,
+    threshold: usize,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let mut rare: Vec<_> = ctx
+        .stats
+        .extensions
+        .iter()
+        .filter(|(ext, seen)| *ext != NO_EXTENSION && seen.count < threshold)
+        .collect();
+    if rare.is_empty() {
+        return Ok(());
+    }
+    rare.sort_by(|a, b| a.1.count.cmp(&b.1.count).then_with(|| a.0.cmp(b.0)));
+    writeln!(
+        writer,
+        "\n[!] Rare extensions (fewer than {threshold} files):"
+    )?;
+    for (ext, seen) in rare {
+        let paths: Vec<_> = seen
+            .paths
+            .iter()
+            .map(|p| relative_path(ctx.config, p, false))
+            .collect();
+        writeln!(
+            writer,
+            "  .{ext:<10} {:>4}  {}",
+            seen.count,
+            paths.join(", ")
+        )?;
+    }
+    Ok(())
+}
+
+fn write_age_histogram(ages: &AgeHistogram, writer: &mut dyn Write) -> io::Result<()> {
+    writeln!(writer, "\nFiles by modification age:")?;
+    for (label, bucket) in AGE_BUCKET_LABELS.iter().zip(&ages.buckets) {
+        writeln!(
+            writer,
+            "  {label:<11} {:>8} files {:>12}",
+            bucket.files,
+            human_size(bucket.bytes)
+        )?;
+    }
+    if ages.unknown.files > 0 {
+        writeln!(
+            writer,
+            "  {:<11} {:>8} files {:>12}",
+            "unknown",
+            ages.unknown.files,
+            human_size(ages.unknown.bytes)
+        )?;
+    }
+    Ok(())
+}
+
+/// Formats a timestamp as `YYYY-MM-DD HH:MM:SS` in UTC.
+fn format_timestamp(t: SystemTime) -> String {
+    let secs = match t.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+    let days = secs.div_euclid(86_400);
+    let rem = secs.rem_euclid(86_400);
+    // Civil-from-days conversion (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Describes how long before `now` a timestamp was, e.g. `3 days ago`.
+fn time_ago(then: SystemTime, now: SystemTime) -> String {
+    let secs = match now.duration_since(then) {
+        Ok(age) => age.as_secs(),
+        Err(_) => return "in the future".to_string(),
+    };
+    const UNITS: [(u64, &str); 6] = [
+        (365 * 24 * 3600, "year"),
+        (30 * 24 * 3600, "month"),
+        (7 * 24 * 3600, "week"),
+        (24 * 3600, "day"),
+        (3600, "hour"),
+        (60, "minute"),
+    ];
+    for (size, unit) in UNITS {
+        if secs >= size {
+            let n = secs / size;
+            let plural = if n == 1 { "" } else { "s" };
+            return format!("{n} {unit}{plural} ago");
+        }
+    }
+    "just now".to_string()
+}
+
+/// Formats a byte count with binary units, e.g. `1.2 KiB`.
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+/// A `--size` or `--du` figure: human-readable, or in bytes with
+/// `--size-bytes`.
+fn size_label(config: &Config, bytes: u64) -> String {
+    if config.size_bytes {
+        bytes.to_string()
+    } else {
+        human_size(bytes)
+    }
+}
+
+/// A `--bar` of `width` cells, as many of them filled as `size` is a share
+/// of `total`.
+fn size_bar(config: &Config, width: usize, size: u64, total: u64) -> String {
+    let (full, empty) = if config.ascii {
+        ('#', '.')
+    } else {
+        ('█', '░')
+    };
+    let filled = if total == 0 {
+        0
+    } else {
+        ((size as f64 / total as f64 * width as f64).round() as usize).min(width)
+    };
+    std::iter::repeat_n(full, filled)
+        .chain(std::iter::repeat_n(empty, width - filled))
+        .collect()
+}
+
+fn display_root_name(p: &Path) -> Option<String> {
+    p.file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .or_else(|| Some(p.to_string_lossy().to_string()))
+}
+
+/// State shared across the recursion of a single walk.
+struct WalkContext<'a> {
+    config: &'a Config,
+    /// The file being written, canonicalized, so it is never listed.
+    output_path: Option<PathBuf>,
+    stats: WalkStats,
+    progress: Option<ProgressFile>,
+    progress_line: Option<ProgressLine>,
+    /// Mount table for `--show-fs`, loaded once per run.
+    fs_info: Option<fsinfo::FsInfo>,
+    /// Owner and group names, with `--long`.
+    owners: Option<long::Owners>,
+    /// Size and paths of the files seen per content hash, with
+    /// `--find-duplicates`.
+    duplicates: HashMap<[u8; 32], (u64, Vec<PathBuf>)>,
+    /// Device of the directory currently being listed.
+    current_dev: Option<u64>,
+    deferred_errors: Vec<WalkError>,
+    /// Every problem reported, with `--errors-section`.
+    section_errors: Vec<WalkError>,
+    /// Root-relative paths git reports as changed, for `--content-changed-only`.
+    changed_files: Option<HashSet<String>>,
+    /// For `--context-depth`: each directory with a filter match below it,
+    /// mapped to the distance to its nearest match (1 = a direct child).
+    match_distances: Option<HashMap<PathBuf, usize>>,
+    /// Set while pre-scanning (`--context-depth`, `--size`) so problems and
+    /// progress are only reported once.
+    prescan: bool,
+    /// Memoized `--size` totals per directory.
+    dir_sizes: HashMap<PathBuf, u64>,
+    /// Memoized `--prune` lookahead: whether each directory lists anything.
+    has_entries: HashMap<PathBuf, bool>,
+    /// Directories already descended into, for `--skip-loops`.
+    visited: HashSet<DirKey>,
+    /// `.gitignore` rules in effect inside each listed directory.
+    ignore_rules: HashMap<PathBuf, Option<Arc<gitignore::Rules>>>,
+    /// Color names in the tree (`--color`, resolved against the terminal).
+    color: bool,
+    /// `--jobs` worker threads reading ahead of the walk.
+    prefetch: Option<prefetch::Prefetch>,
+    /// Listings already taken from `prefetch`, for later passes.
+    prefetched: HashMap<PathBuf, Listing>,
+    /// Entries left out because they couldn't be read.
+    problems: usize,
+    /// For `--summary`: entries each listed directory held that were left
+    /// out (hidden, excluded, ignored or filtered), from its latest listing
+    /// so that passes reading it again don't count it twice.
+    skipped: HashMap<PathBuf, usize>,
+    /// `--git-status` labels by root-relative path.
+    git_status: Option<HashMap<String, &'static str>>,
+    /// Files whose text `--format llm` appends after the tree.
+    bundle: Vec<PathBuf>,
+    /// Listings kept between runs, with `--cache`.
+    cache: Option<cache::DirCache>,
+    /// The `--exec` commands, run as files are listed.
+    exec: Option<exec::Exec>,
+    /// For json, json-tree and yaml output: the first problem reported for
+    /// each path and its kind, until the path's node takes it.
+    node_errors: Option<HashMap<PathBuf, (&'static str, String)>>,
+}
+
+/// Identity of a directory on disk: device and inode on Unix, the
+/// canonical path elsewhere.
+#[derive(Hash, PartialEq, Eq)]
+enum DirKey {
+    #[cfg_attr(not(unix), allow(dead_code))]
+    Inode(u64, u64),
+    #[cfg_attr(unix, allow(dead_code))]
+    Path(PathBuf),
+}
+
+impl DirKey {
+    #[cfg(unix)]
+    fn of(path: &Path) -> Option<DirKey> {
+        use std::os::unix::fs::MetadataExt;
+        let meta = fs::metadata(path).ok()?;
+        Some(DirKey::Inode(meta.dev(), meta.ino()))
+    }
+
+    #[cfg(not(unix))]
+    fn of(path: &Path) -> Option<DirKey> {
+        fs::canonicalize(path).ok().map(DirKey::Path)
+    }
+}
+
+impl<'a> WalkContext<'a> {
+    fn new(config: &'a Config, start_meta: &fs::Metadata) -> Self {
+        let changed_files = if config.content_changed_only {
+            let changed = git::changed_paths(&config.start_dir);
+            if changed.is_none() && config.error_format == ErrorFormat::Text {
+                eprintln!("Note: not inside a git work tree; no file contents will be shown");
+            }
+            Some(changed.unwrap_or_default())
+        } else {
+            None
+        };
+        let git_status = if config.git_status {
+            let labels = git::statuses(&config.start_dir);
+            if labels.is_none() && config.error_format == ErrorFormat::Text {
+                eprintln!("Note: not inside a git work tree; no git status will be shown");
+            }
+            labels
+        } else {
+            None
+        };
+        let mut ctx = WalkContext {
+            config,
+            output_path: None,
+            stats: WalkStats {
+                track_extensions: config.rare_extension_threshold.is_some()
+                    || config.stats
+                    || config.stats_json.is_some(),
+                extension_path_limit: config.rare_extension_threshold.unwrap_or(0),
+                ..WalkStats::default()
+            },
+            progress: None,
+            progress_line: None,
+            fs_info: config.show_fs.then(fsinfo::FsInfo::load),
+            owners: config.long.then(long::Owners::load),
+            duplicates: HashMap::new(),
+            current_dev: fsinfo::device_id(start_meta),
+            deferred_errors: Vec::new(),
+            section_errors: Vec::new(),
+            changed_files,
+            match_distances: None,
+            prescan: false,
+            dir_sizes: HashMap::new(),
+            has_entries: HashMap::new(),
+            visited: HashSet::new(),
+            ignore_rules: HashMap::new(),
+            color: false,
+            prefetch: None,
+            prefetched: HashMap::new(),
+            problems: 0,
+            skipped: HashMap::new(),
+            git_status,
+            bundle: Vec::new(),
+            cache: None,
+            exec: None,
+            node_errors: matches!(
+                config.format,
+                OutputFormat::Json | OutputFormat::JsonTree | OutputFormat::Yaml
+            )
+            .then(HashMap::new),
+        };
+        ctx.first_visit(&config.start_dir);
+        ctx
+    }
+
+    /// Whether `entry` is the output file, or one of its parts with
+    /// `--split-size`. Only entries with the output's name are
+    /// canonicalized, so this stays cheap.
+    fn is_output(&self, entry: &DirEntryInfo) -> bool {
+        self.output_path.as_deref().is_some_and(|out| {
+            let name = entry.file_name.as_os_str();
+            (out.file_name() == Some(name)
+                || self.config.split_size.is_some() && output::is_part_name(out, name))
+                && fs::canonicalize(&entry.path).is_ok_and(|p| p == out.with_file_name(name))
+        })
+    }
+
+    /// Counts a listed entry in the stats and, if it is a file, starts its
+    /// `--exec` command.
+    fn record(&mut self, entry: &DirEntryInfo) -> io::Result<()> {
+        self.stats.record(entry);
+        if let Some(exec) = &mut self.exec
+            && entry.file_type.is_file()
+        {
+            exec.spawn(&entry.path)?;
+        }
+        Ok(())
+    }
+
+    /// The problem reported for `path`, for its node's `error` field.
+    fn take_error(&mut self, path: &Path) -> Option<String> {
+        let (_, message) = self.node_errors.as_mut()?.remove(path)?;
+        Some(message)
+    }
+
+    /// The entries of `dir` left out of its listing because they couldn't
+    /// be statted, by name, with why; they get nodes of their own.
+    fn take_unstatted(&mut self, dir: &Path) -> Vec<(PathBuf, String)> {
+        let Some(errors) = &mut self.node_errors else {
+            return Vec::new();
+        };
+        let paths: Vec<PathBuf> = errors
+            .iter()
+            .filter(|(path, (kind, _))| *kind == "stat" && path.parent() == Some(dir))
+            .map(|(path, _)| path.clone())
+            .collect();
+        let mut unstatted: Vec<(PathBuf, String)> = paths
+            .into_iter()
+            .filter_map(|path| errors.remove(&path).map(|(_, message)| (path, message)))
+            .collect();
+        unstatted.sort();
+        unstatted
+    }
+
+    /// With `--skip-loops`, records `dir` as walked and reports whether this
+    /// is the first time; always true otherwise. Directories whose identity
+    /// can't be determined are walked.
+    fn first_visit(&mut self, dir: &Path) -> bool {
+        if !self.config.walk.skip_loops {
+            return true;
+        }
+        match DirKey::of(dir) {
+            Some(key) => self.visited.insert(key),
+            None => true,
+        }
+    }
+
+    /// Whether `--content` output applies to this file.
+    fn wants_content(&self, entry: &DirEntryInfo) -> bool {
+        if !self.config.show_content || !entry.file_type.is_file() {
+            return false;
+        }
+        if let Some(n) = self.config.content_depth {
+            let levels = entry
+                .path
+                .strip_prefix(&self.config.start_dir)
+                .map_or(0, |rel| rel.components().count());
+            if levels > n {
+                return false;
+            }
+        }
+        match &self.changed_files {
+            Some(changed) => changed.contains(&relative_path(self.config, &entry.path, false)),
+            None => true,
+        }
+    }
+}
+
+/// Liveness report for `--progress-file`: rewritten every
+/// `PROGRESS_EVERY_ENTRIES` entries or `PROGRESS_INTERVAL`, whichever comes
+/// first. Each update goes to a temporary sibling that is renamed over the
+/// target, so a poller never observes a half-written file.
+struct ProgressFile {
+    path: PathBuf,
+    started: Instant,
+    last_write: Instant,
+    entries: usize,
+    entries_at_last_write: usize,
+}
+
+const PROGRESS_EVERY_ENTRIES: usize = 1000;
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+impl ProgressFile {
+    fn new(path: &Path) -> Self {
+        let now = Instant::now();
+        ProgressFile {
+            path: path.to_path_buf(),
+            started: now,
+            last_write: now,
+            entries: 0,
+            entries_at_last_write: 0,
+        }
+    }
+
+    fn tick(&mut self, entries: usize) -> io::Result<()> {
+        self.entries += entries;
+        if self.entries - self.entries_at_last_write >= PROGRESS_EVERY_ENTRIES
+            || self.last_write.elapsed() >= PROGRESS_INTERVAL
+        {
+            self.write("running")?;
+            self.last_write = Instant::now();
+            self.entries_at_last_write = self.entries;
+        }
+        Ok(())
+    }
+
+    fn write(&self, status: &str) -> io::Result<()> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        fs::write(
+            &tmp,
+            format!(
+                "status={status}\nentries={}\nelapsed_ms={}\n",
+                self.entries,
+                self.started.elapsed().as_millis()
+            ),
+        )?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+/// `--progress`: one stderr line, redrawn at most every
+/// `PROGRESS_REDRAW`, with the directories read so far, the entries in
+/// them and the directory being read.
+struct ProgressLine {
+    dirs: usize,
+    entries: usize,
+    last_draw: Option<Instant>,
+    drawn: bool,
+}
+
+const PROGRESS_REDRAW: Duration = Duration::from_millis(100);
+/// Characters of the current path shown; longer ones keep their end.
+const PROGRESS_PATH_CHARS: usize = 50;
+
+impl ProgressLine {
+    fn new() -> Self {
+        ProgressLine {
+            dirs: 0,
+            entries: 0,
+            last_draw: None,
+            drawn: false,
+        }
+    }
+
+    fn tick(&mut self, dir: &Path, entries: usize) {
+        self.dirs += 1;
+        self.entries += entries;
+        if self
+            .last_draw
+            .is_some_and(|t| t.elapsed() < PROGRESS_REDRAW)
+        {
+            return;
+        }
+        self.last_draw = Some(Instant::now());
+        let path = dir.to_string_lossy();
+        let count = path.chars().count();
+        let path = if count > PROGRESS_PATH_CHARS {
+            let tail: String = path.chars().skip(count - PROGRESS_PATH_CHARS + 1).collect();
+            format!("…{tail}")
+        } else {
+            path.into_owned()
+        };
+        let noun = if self.dirs == 1 {
+            "directory"
+        } else {
+            "directories"
+        };
+        eprint!(
+            "\r\x1b[K{} {noun}, {} entries  {path}",
+            self.dirs, self.entries
+        );
+        self.drawn = true;
+    }
+
+    /// Removes the line, before a warning or once the walk is done.
+    fn clear(&mut self) {
+        if self.drawn {
+            eprint!("\r\x1b[K");
+            self.drawn = false;
+            // Redraw straight away on the next directory.
+            self.last_draw = None;
+        }
+    }
+}
+
+/// Counters accumulated while walking; directories and files are counted
+/// as they are printed, symlinks count as files (and also in `symlinks`).
+#[derive(Default)]
+struct WalkStats {
+    dirs: usize,
+    files: usize,
+    symlinks: usize,
+    hidden_dirs: usize,
+    hidden_files: usize,
+    ages: AgeHistogram,
+    /// Total size of regular files.
+    bytes: u64,
+    /// Files per lowercased extension, kept only when `track_extensions`
+    /// is set (`--count-extensions-threshold`, `--stats`, `--stats-json`).
+    /// Files without one are under [`NO_EXTENSION`].
+    extensions: HashMap<String, ExtensionSeen>,
+    track_extensions: bool,
+    /// Paths remembered per extension.
+    extension_path_limit: usize,
+    /// The largest regular file and its size; the first seen wins a tie.
+    largest: Option<(PathBuf, u64)>,
+    /// The entry with the most path components and that count.
+    deepest: Option<(PathBuf, usize)>,
+}
+
+/// The extension bucket for files that have none.
+const NO_EXTENSION: &str = "(none)";
+
+#[derive(Default)]
+struct ExtensionSeen {
+    count: usize,
+    bytes: u64,
+    /// The first few files seen, enough to list every file of a rare extension.
+    paths: Vec<PathBuf>,
+}
+
+const AGE_BUCKET_LABELS: [&str; 4] = ["last day", "last week", "last month", "older"];
+const AGE_BUCKET_LIMITS: [Duration; 3] = [
+    Duration::from_secs(24 * 60 * 60),
+    Duration::from_secs(7 * 24 * 60 * 60),
+    Duration::from_secs(30 * 24 * 60 * 60),
+];
+
+/// Regular files bucketed by how long ago they were modified, for
+/// `--age-histogram`. Files modified in the future count as "last day".
+#[derive(Default)]
+struct AgeHistogram {
+    buckets: [AgeBucket; 4],
+    unknown: AgeBucket,
+}
+
+#[derive(Default)]
+struct AgeBucket {
+    files: usize,
+    bytes: u64,
+}
+
+impl AgeHistogram {
+    fn record(&mut self, modified: Option<SystemTime>, len: u64, now: SystemTime) {
+        let bucket = match modified {
+            Some(mtime) => {
+                let age = now.duration_since(mtime).unwrap_or(Duration::ZERO);
+                let idx = AGE_BUCKET_LIMITS
+                    .iter()
+                    .position(|limit| age < *limit)
+                    .unwrap_or(AGE_BUCKET_LIMITS.len());
+                &mut self.buckets[idx]
+            }
+            None => &mut self.unknown,
+        };
+        bucket.files += 1;
+        bucket.bytes += len;
+    }
+}
+
+impl WalkStats {
+    fn record(&mut self, entry: &DirEntryInfo) {
+        if entry.file_type.is_file() {
+            self.ages
+                .record(entry.modified, entry.len, SystemTime::now());
+            self.bytes += entry.len;
+            if self
+                .largest
+                .as_ref()
+                .is_none_or(|(_, len)| entry.len > *len)
+            {
+                self.largest = Some((entry.path.clone(), entry.len));
+            }
+            if self.track_extensions {
+                let ext = entry.path.extension().map_or_else(
+                    || NO_EXTENSION.to_string(),
+                    |ext| ext.to_string_lossy().to_lowercase(),
+                );
+                let seen = self.extensions.entry(ext).or_default();
+                seen.count += 1;
+                seen.bytes += entry.len;
+                if seen.paths.len() < self.extension_path_limit {
+                    seen.paths.push(entry.path.clone());
+                }
+            }
+        }
+        let components = entry.path.components().count();
+        if self.deepest.as_ref().is_none_or(|(_, n)| components > *n) {
+            self.deepest = Some((entry.path.clone(), components));
+        }
+        if entry.file_type.is_dir() {
+            self.dirs += 1;
+            if entry.hidden {
+                self.hidden_dirs += 1;
+            }
+        } else {
+            self.files += 1;
+            if entry.hidden {
+                self.hidden_files += 1;
+            }
+            if entry.file_type.is_symlink() {
+                self.symlinks += 1;
+            }
+        }
+    }
+}
+
+/// Where `--max-depth` stops the tree, a last line under the directory
+/// saying how many entries (after filters) it holds, so a cut-off directory
+/// doesn't look empty. Nothing is printed for one that is really empty.
+fn write_cutoff_marker(
+    dir: &Path,
+    ctx: &mut WalkContext,
+    writer: &mut dyn Write,
+    prefix: &str,
+    depth: usize,
+) -> io::Result<()> {
+    ctx.prescan = true;
+    let hidden = list_dir(dir, ctx, depth).map(|entries| entries.len());
+    ctx.prescan = false;
+    write_cutoff_line(ctx.config, writer, prefix, depth, hidden?)
+}
+
+/// The line [`write_cutoff_marker`] writes for a directory holding
+/// `hidden` entries; nothing when it holds none.
+fn write_cutoff_line(
+    config: &Config,
+    writer: &mut dyn Write,
+    prefix: &str,
+    depth: usize,
+    hidden: usize,
+) -> io::Result<()> {
+    if hidden == 0 {
+        return Ok(());
+    }
+    let (_, elbow, _, _) = tree_glyphs(config);
+    let entries = if hidden == 1 { "entry" } else { "entries" };
+    let mut line = format!("... ({hidden} more {entries})");
+    if config.depth_markers {
+        line.insert_str(0, &format!("[{}] ", depth + 1));
+    }
+    let line = fit_width(config, &format!("{prefix}{elbow}"), line);
+    writeln!(writer, "{prefix}{elbow}{line}")
+}
+
+/// `--collapse`: when `entry` is a directory whose only listed entry is
+/// another directory (and so on), returns the innermost one, the names
+/// passed on the way (`a/b/`) and how many levels that skipped. The
+/// directories passed are counted as listed and marked as walked.
+fn collapse_chain(
+    entry: &DirEntryInfo,
+    ctx: &mut WalkContext,
+    depth: usize,
+) -> io::Result<Option<(DirEntryInfo, String, usize)>> {
+    let mut current: Option<DirEntryInfo> = None;
+    let mut chain = String::new();
+    let mut levels = 0;
+    loop {
+        let dir = current.as_ref().unwrap_or(entry);
+        let listed_depth = depth + levels + 1;
+        if !is_walkable_dir(dir)
+            || ctx
+                .config
+                .walk
+                .max_depth
+                .is_some_and(|maxd| listed_depth >= maxd)
+        {
+            break;
+        }
+        // Peeking must not report problems or progress twice.
+        ctx.prescan = true;
+        let children = list_dir(&dir.path, ctx, listed_depth);
+        ctx.prescan = false;
+        let mut children = children?;
+        if children.len() != 1 || !is_walkable_dir(&children[0]) || !ctx.first_visit(&dir.path) {
+            break;
+        }
+        ctx.stats.record(dir);
+        chain.push_str(&dir.file_name.to_string_lossy());
+        chain.push('/');
+        levels += 1;
+        current = children.pop();
+    }
+    Ok(current.map(|inner| (inner, chain, levels)))
+}
+
+/// ANSI color for an entry's name under `--color`, as `ls` does: bold blue
+/// directories, bold cyan symlinks and bold green executables (Unix only,
+/// since elsewhere there is no execute bit to go by).
+fn name_color(entry: &DirEntryInfo) -> Option<&'static str> {
+    if entry.file_type.is_symlink() && fs::metadata(&entry.path).is_err() {
+        // Dangling: the target is missing (or can't be reached).
+        Some("\x1b[1;31m")
+    } else if entry.file_type.is_symlink() {
+        Some("\x1b[1;36m")
+    } else if entry.file_type.is_dir() {
+        Some("\x1b[1;34m")
+    } else if cfg!(unix) && entry.file_type.is_file() && entry.executable {
+        Some("\x1b[1;32m")
+    } else {
+        None
+    }
+}
+
+/// With `--width`, shortens `name` so that `lead` (the prefix and branch
+/// glyphs) plus the name fit in the configured number of columns.
+fn fit_width(config: &Config, lead: &str, name: String) -> String {
+    match config.render_width {
+        Some(cols) => width::truncate(&name, cols.saturating_sub(width::display_width(lead))),
+        None => name,
+    }
+}
+
+/// Branch glyphs as (tee, elbow, pipe, space).
+fn tree_glyphs(config: &Config) -> (&'static str, &'static str, &'static str, &'static str) {
+    if config.depth_markers {
+        ("", "", "  ", "  ")
+    } else if config.ascii {
+        ("|-- ", "`-- ", "|   ", "    ")
+    } else {
+        ("├── ", "└── ", "│   ", "    ")
+    }
+}
+
+fn walk_dir(
+    dir: &Path,
+    ctx: &mut WalkContext,
+    writer: &mut dyn Write,
+    prefix: &str,
+    depth: usize,
+) -> io::Result<()> {
+    let config = ctx.config;
+    if let Some(maxd) = config.walk.max_depth
+        && depth >= maxd
+    {
+        if !config.silent_max_depth {
+            write_cutoff_marker(dir, ctx, writer, prefix, depth)?;
+        }
+        return Ok(());
+    }
+
+    let flattened = config.flatten_below.is_some_and(|n| depth >= n);
+    let entries = if flattened {
+        let mut flat = Vec::new();
+        collect_flat(dir, ctx, "", depth, &mut flat)?;
+        flat
+    } else {
+        list_dir(dir, ctx, depth)?
+    };
+
+    let (tee, elbow, pipe, space) = tree_glyphs(config);
+
+    for (idx, entry) in entries.iter().enumerate() {
+        let is_last = idx == entries.len().saturating_sub(1);
+        let branch = if is_last { elbow } else { tee };
+
+        let collapsed = if config.collapse && !flattened {
+            collapse_chain(entry, ctx, depth)?
+        } else {
+            None
+        };
+        let (entry, chain, levels) = match &collapsed {
+            Some((inner, chain, levels)) => (inner, chain.as_str(), *levels),
+            None => (entry, "", 0),
+        };
+
+        let mut name = if config.relative_names {
+            strip_components(config, &relative_path(config, &entry.path, false)).to_string()
+        } else {
+            format!("{chain}{}", entry.file_name.to_string_lossy())
+        };
+        if entry.file_type.is_dir() {
+            name.push('/');
+        }
+
+        let revisit = is_walkable_dir(entry) && !flattened && !ctx.first_visit(&entry.path);
+        let (display_name, encoding) = annotate(entry, &name, ctx, depth, revisit)?;
+
+        let lead = format!("{prefix}{branch}");
+        write_entry_line(entry, &name, display_name, depth, ctx, writer, &lead)?;
+        if !flattened {
+            ctx.record(entry)?;
+        }
+
+        // What goes under the entry (content, archive members, children)
+        // continues the branches of the siblings after it.
+        let child_prefix = format!("{prefix}{}", if is_last { space } else { pipe });
+        if ctx.wants_content(entry) {
+            if config.format == OutputFormat::Llm {
+                ctx.bundle.push(entry.path.clone());
+            } else {
+                write_content(&entry.path, config, encoding, writer, &child_prefix)?;
+            }
+        }
+
+        if config.archives && entry.file_type.is_file() && archive::is_archive(&entry.file_name) {
+            match archive::read_members(&entry.path) {
+                Ok(members) => {
+                    write_archive_members(&members, config, writer, &child_prefix, depth + 1)?;
+                }
+                Err(err) => warn(ctx, "read_archive", &entry.path, &err)?,
+            }
+        }
+
+        if is_walkable_dir(entry) && !flattened && !revisit {
+            let parent_dev = ctx.current_dev;
+            if entry.dev.is_some() {
+                ctx.current_dev = entry.dev;
+            }
+            walk_dir(&entry.path, ctx, writer, &child_prefix, depth + 1 + levels)?;
+            ctx.current_dev = parent_dev;
+        }
+    }
+
+    Ok(())
+}
+
+/// `--bfs`: the tree level by level, each level's entries under a `Depth
+/// N (M entries):` heading as root-relative paths, so a shallow
+/// `--max-depth` shows the breadth of a wide tree. A level holds the
+/// entries of every directory of the level before, in the order those were
+/// listed.
+fn walk_levels(ctx: &mut WalkContext, writer: &mut dyn Write, prefix: &str) -> io::Result<()> {
+    let config = ctx.config;
+    let (tee, elbow, pipe, space) = tree_glyphs(config);
+    let root_dev = ctx.current_dev;
+    // Each directory to list, with the device it is on.
+    let mut level = vec![(config.start_dir.clone(), root_dev)];
+    let mut depth = 0;
+    while !level.is_empty() {
+        if let Some(maxd) = config.walk.max_depth
+            && depth >= maxd
+        {
+            if !config.silent_max_depth {
+                write_level_cutoff(&level, ctx, writer, depth)?;
+            }
+            break;
+        }
+        let mut entries = Vec::new();
+        for (dir, dev) in &level {
+            ctx.current_dev = *dev;
+            entries.extend(list_dir(dir, ctx, depth)?.into_iter().map(|e| (e, *dev)));
+        }
+        if entries.is_empty() {
+            break;
+        }
+        let unit = if entries.len() == 1 {
+            "entry"
+        } else {
+            "entries"
+        };
+        writeln!(writer, "\nDepth {} ({} {unit}):", depth + 1, entries.len())?;
+
+        let mut next = Vec::new();
+        for (idx, (entry, dev)) in entries.iter().enumerate() {
+            let is_last = idx + 1 == entries.len();
+            let branch = if is_last { elbow } else { tee };
+            ctx.current_dev = *dev;
+            let mut name = relative_path(config, &entry.path, false);
+            if entry.file_type.is_dir() {
+                name.push('/');
+            }
+            let revisit = is_walkable_dir(entry) && !ctx.first_visit(&entry.path);
+            let (display_name, encoding) = annotate(entry, &name, ctx, depth, revisit)?;
+            let lead = format!("{prefix}{branch}");
+            write_entry_line(entry, &name, display_name, depth, ctx, writer, &lead)?;
+            ctx.record(entry)?;
+
+            if ctx.wants_content(entry) {
+                if config.format == OutputFormat::Llm {
+                    ctx.bundle.push(entry.path.clone());
+                } else {
+                    let child_prefix = format!("{prefix}{}", if is_last { space } else { pipe });
+                    write_content(&entry.path, config, encoding, writer, &child_prefix)?;
+                }
+            }
+            if is_walkable_dir(entry) && !revisit {
+                next.push((entry.path.clone(), entry.dev.or(*dev)));
+            }
+        }
+        level = next;
+        depth += 1;
+    }
+    ctx.current_dev = root_dev;
+    Ok(())
+}
+
+/// The `--bfs` counterpart of [`write_cutoff_marker`]: one line counting
+/// the entries of all the directories at the cut-off depth.
+fn write_level_cutoff(
+    level: &[(PathBuf, Option<u64>)],
+    ctx: &mut WalkContext,
+    writer: &mut dyn Write,
+    depth: usize,
+) -> io::Result<()> {
+    ctx.prescan = true;
+    let hidden: io::Result<usize> = level
+        .iter()
+        .map(|(dir, _)| list_dir(dir, ctx, depth).map(|entries| entries.len()))
+        .sum();
+    ctx.prescan = false;
+    let hidden = hidden?;
+    if hidden == 0 {
+        return Ok(());
+    }
+    let entries = if hidden == 1 { "entry" } else { "entries" };
+    let line = format!("... ({hidden} more {entries} below depth {depth})");
+    writeln!(writer, "\n{}", fit_width(ctx.config, "", line))
+}
+
+/// Writes the tree line for `entry` after `lead`, the prefix and branch:
+/// the depth marker and icon, `display_name` fitted to `--width` with the
+/// name colored, and the `--long` columns in place of the lead's blanks.
+fn write_entry_line(
+    entry: &DirEntryInfo,
+    name: &str,
+    display_name: String,
+    depth: usize,
+    ctx: &WalkContext,
+    writer: &mut dyn Write,
+    lead: &str,
+) -> io::Result<()> {
+    let config = ctx.config;
+    let mut marker = if config.depth_markers {
+        format!("[{}] ", depth + 1)
+    } else {
+        String::new()
+    };
+    if let Some(style) = config.icons {
+        let icon = kind::icon(kind::kind_of(entry, config), style, config);
+        marker.push_str(&format!("{icon} "));
+    }
+    let display_name = format!("{marker}{display_name}");
+
+    let display_name = fit_width(config, lead, display_name);
+    let display_name = match (name_color(entry), display_name.strip_prefix(&marker)) {
+        (Some(color), Some(rest)) if ctx.color => {
+            // Only the name is colored, not the annotations after it;
+            // a name cut short by --width is colored up to the end.
+            let end = if rest.starts_with(name) {
+                name.len()
+            } else {
+                rest.len()
+            };
+            format!("{marker}{color}{}\x1b[0m{}", &rest[..end], &rest[end..])
+        }
+        _ => display_name,
+    };
+
+    match &ctx.owners {
+        Some(owners) => {
+            let columns = owners.columns(fs::symlink_metadata(&entry.path).ok().as_ref());
+            let tree = &lead[long::WIDTH..];
+            writeln!(writer, "{columns}{tree}{display_name}")
+        }
+        None => writeln!(writer, "{lead}{display_name}"),
+    }
+}
+
+/// `name` with what the tree line adds after it: the symlink target, the
+/// size, encoding, hash and git status, times and filesystem notes, and
+/// `[already visited]` when `revisit`. Also returns the encoding detected
+/// for `--detect-encoding`, which `--content` reuses.
+fn annotate(
+    entry: &DirEntryInfo,
+    name: &str,
+    ctx: &mut WalkContext,
+    depth: usize,
+    revisit: bool,
+) -> io::Result<(String, Option<encoding::Encoding>)> {
+    let config = ctx.config;
+    let cycle = config.walk.follow_symlinks && entry.is_symlink_dir && !entry.followed;
+    let display_name = if cycle {
+        format!("{name} -> <cycle>")
+    } else if entry.file_type.is_symlink() {
+        let display_name = match winfs::read_link(&entry.path) {
+            Ok(target) => format!("{name} -> {}", target.to_string_lossy()),
+            Err(_) => format!("{name} -> <unreadable>"),
+        };
+        if entry.is_symlink_dir && winfs::is_junction(&entry.path) {
+            format!("{display_name} [junction]")
+        } else {
+            display_name
+        }
+    } else {
+        name.to_string()
+    };
+    let display_name = if config.show_size || (config.du && is_walkable_dir(entry)) {
+        let size = if is_walkable_dir(entry) {
+            ctx.prescan = true;
+            let total = dir_size(&entry.path, ctx, depth + 1);
+            ctx.prescan = false;
+            total?
+        } else {
+            entry.len
+        };
+        let display_name = format!("{display_name} ({})", size_label(config, size));
+        match (config.bar, entry.path.parent()) {
+            (Some(width), Some(parent)) if is_walkable_dir(entry) => {
+                // Already totalled: the parent's own line, or the root's,
+                // came first.
+                ctx.prescan = true;
+                let total = dir_size(parent, ctx, depth);
+                ctx.prescan = false;
+                format!("{display_name} {}", size_bar(config, width, size, total?))
+            }
+            _ => display_name,
+        }
+    } else {
+        display_name
+    };
+    let encoding = if config.detect_encoding && entry.file_type.is_file() {
+        encoding::detect_file(&entry.path).ok()
+    } else {
+        None
+    };
+    let display_name = match encoding {
+        Some(enc) => format!("{display_name} [{}]", enc.label()),
+        None => display_name,
+    };
+    let digest = if (config.hash_files || config.find_duplicates) && entry.file_type.is_file() {
+        match hash_file(&entry.path) {
+            Ok(digest) => Some(digest),
+            Err(err) => {
+                warn(ctx, "read_file", &entry.path, &err)?;
+                None
+            }
+        }
+    } else {
+        None
+    };
+    if let Some(digest) = digest
+        && config.find_duplicates
+        && entry.len > 0
+    {
+        let (_, paths) = ctx
+            .duplicates
+            .entry(digest)
+            .or_insert((entry.len, Vec::new()));
+        paths.push(entry.path.clone());
+    }
+    let display_name = match digest {
+        Some(digest) if config.hash_files => {
+            format!("{display_name} [sha256:{}]", sha256::to_hex(&digest))
+        }
+        _ => display_name,
+    };
+    let status = ctx.git_status.as_ref().and_then(|labels| {
+        let rel = relative_path(config, &entry.path, false);
+        git::status_of(labels, &rel, is_walkable_dir(entry))
+    });
+    let display_name = match status {
+        Some(status) => format!("{display_name} [{status}]"),
+        None => display_name,
+    };
+    let display_name = match entry.modified {
+        Some(mtime) if config.relative_time => {
+            format!("{display_name} ({})", time_ago(mtime, SystemTime::now()))
+        }
+        _ => display_name,
+    };
+    let display_name = if config.show_created {
+        let created = entry
+            .created
+            .map(format_timestamp)
+            .unwrap_or_else(|| "?".to_string());
+        format!("{display_name} (created {created})")
+    } else {
+        display_name
+    };
+    let display_name = if revisit {
+        format!("{display_name} [already visited]")
+    } else {
+        display_name
+    };
+    let crosses_fs = entry.file_type.is_dir()
+        && !entry.file_type.is_symlink()
+        && entry.dev.is_some()
+        && entry.dev != ctx.current_dev;
+    let display_name = match (&ctx.fs_info, entry.dev) {
+        (Some(info), Some(dev)) if crosses_fs => {
+            format!("{display_name} [{}]", info.describe(dev))
+        }
+        _ if crosses_fs && config.walk.one_file_system => {
+            format!("{display_name} [other filesystem]")
+        }
+        _ => display_name,
+    };
+    Ok((display_name, encoding))
+}
+
+/// The members of an `--archives` archive, drawn like directory entries.
+/// `--max-depth` counts the levels inside the archive too; the other
+/// filters only decide which archives are shown.
+fn write_archive_members(
+    members: &BTreeMap<String, archive::Member>,
+    config: &Config,
+    writer: &mut dyn Write,
+    prefix: &str,
+    depth: usize,
+) -> io::Result<()> {
+    if config.walk.max_depth.is_some_and(|maxd| depth >= maxd) {
+        return Ok(());
+    }
+    let mut members: Vec<(&String, &archive::Member)> = members.iter().collect();
+    members.sort_by_cached_key(|(name, member)| {
+        let grouped = config.walk.group_dirs && member.is_dir != config.walk.dirs_last;
+        (!grouped, collate::NameKey::new(name, &config.walk))
+    });
+    let (tee, elbow, pipe, space) = tree_glyphs(config);
+    for (idx, (name, member)) in members.iter().enumerate() {
+        let is_last = idx + 1 == members.len();
+        let branch = if is_last { elbow } else { tee };
+        let marker = if config.depth_markers {
+            format!("[{}] ", depth + 1)
+        } else {
+            String::new()
+        };
+        let display_name = if member.is_dir {
+            format!("{marker}{name}/")
+        } else if config.show_size && config.size_bytes {
+            format!("{marker}{name} ({})", member.size)
+        } else if config.show_size {
+            format!("{marker}{name} ({})", human_size(member.size))
+        } else {
+            format!("{marker}{name}")
+        };
+        let display_name = fit_width(config, &format!("{prefix}{branch}"), display_name);
+        writeln!(writer, "{prefix}{branch}{display_name}")?;
+        if member.is_dir {
+            let new_prefix = format!("{prefix}{}", if is_last { space } else { pipe });
+            write_archive_members(&member.children, config, writer, &new_prefix, depth + 1)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes a file's content under its tree line, each line after `prefix`:
+/// the prefix its children would get, so the branches to its later
+/// siblings carry on past it. `encoding` is the `--detect-encoding` result,
+/// if any; text that isn't UTF-8 is decoded from it and binary files are
+/// not dumped. Without it the file is still sniffed, unless with
+/// `--include-binary`, so binary files are noted and UTF-16 is decoded
+/// rather than dumped with its NULs.
+fn write_content(
+    path: &Path,
+    config: &Config,
+    encoding: Option<encoding::Encoding>,
+    writer: &mut dyn Write,
+    prefix: &str,
+) -> io::Result<()> {
+    use encoding::Encoding;
+    if config.content_base64 {
+        return write_content_base64(path, writer, prefix);
+    }
+    // Only a prefix is sniffed; see `encoding::SNIFF_BYTES`.
+    let encoding = encoding.or_else(|| {
+        if config.include_binary {
+            return None;
+        }
+        encoding::detect_file(path).ok().filter(|enc| {
+            matches!(
+                enc,
+                Encoding::Binary | Encoding::Utf16Le | Encoding::Utf16Be
+            )
+        })
+    });
+    if encoding == Some(Encoding::Binary) && !config.include_binary {
+        let size = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        return writeln!(writer, "{prefix}[binary file, {size} bytes]");
+    }
+    if let Some(enc @ (Encoding::Utf16Le | Encoding::Utf16Be | Encoding::Latin1)) = encoding {
+        let (content, more) = match read_capped(path, config.max_content_bytes) {
+            Ok((bytes, more)) => (enc.decode(&bytes).unwrap_or_default(), more),
+            Err(err) => {
+                return writeln!(writer, "{prefix}[Could not read file: {}]", err);
+            }
+        };
+        return write_content_lines(&content, more, config, writer, prefix);
+    }
+
+    if let (Some(re), Some(context)) = (&config.walk.grep, config.grep_context) {
+        return write_content_matches(path, re, context, writer, prefix);
+    }
+    if let Some(max) = config.content_max_line_length {
+        return write_content_bounded(path, max, config.max_content_bytes, writer, prefix);
+    }
+
+    let content = match config.max_content_bytes {
+        Some(_) => read_capped(path, config.max_content_bytes).and_then(|(bytes, more)| {
+            String::from_utf8(bytes)
+                .map(|text| (text, more))
+                .map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "stream did not contain valid UTF-8",
+                    )
+                })
+        }),
+        None => fs::read_to_string(path).map(|text| (text, 0)),
+    };
+    match content {
+        Ok((content, more)) => write_content_lines(&content, more, config, writer, prefix)?,
+        Err(err) => {
+            writeln!(writer, "{prefix}[Could not read file: {}]", err)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes a file's text between the content markers, limited to
+/// `--content-lines` and numbered with `--line-numbers`. `more` is what
+/// `--max-content-bytes` left unread; it is only reported when the range
+/// runs up to where reading stopped.
+fn write_content_lines(
+    content: &str,
+    more: u64,
+    config: &Config,
+    writer: &mut dyn Write,
+    prefix: &str,
+) -> io::Result<()> {
+    let lines: Vec<&str> = content.lines().collect();
+    let (start, end) = content_range(config, lines.len());
+    let width = end.to_string().len();
+    writeln!(writer, "{prefix}--- FILE CONTENT START ---")?;
+    for (i, line) in lines.iter().enumerate().take(end).skip(start) {
+        if config.line_numbers {
+            writeln!(writer, "{prefix}{:>width$}  {line}", i + 1)?;
+        } else {
+            writeln!(writer, "{prefix}{line}")?;
+        }
+    }
+    if end == lines.len() {
+        write_truncation_marker(writer, prefix, more)?;
+    }
+    writeln!(writer, "{prefix}--- FILE CONTENT END ---")
+}
+
+/// The 0-based, half-open slice of a `len`-line file that `--content-lines`
+/// selects, clamped to the file.
+fn content_range(config: &Config, len: usize) -> (usize, usize) {
+    match config.content_lines {
+        Some((start, end)) => {
+            let start = start.saturating_sub(1).min(len);
+            let end = end.map_or(len, |end| end.min(len)).max(start);
+            (start, end)
+        }
+        None => (0, len),
+    }
+}
+
+/// A file's bytes as text, given what `encoding::detect` made of them:
+/// UTF-16 is always decoded, so its NULs never reach the output, Latin-1
+/// only with `--detect-encoding`, and anything else is read as UTF-8.
+fn decoded_text(config: &Config, encoding: encoding::Encoding, bytes: &[u8]) -> String {
+    use encoding::Encoding;
+    match encoding {
+        Encoding::Utf16Le | Encoding::Utf16Be => encoding.decode(bytes).unwrap_or_default(),
+        Encoding::Latin1 if config.detect_encoding => encoding.decode(bytes).unwrap_or_default(),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// Reads at most `limit` bytes of `path` without loading the rest, and
+/// reports how many bytes were left unread. A cut that lands inside a
+/// multi-byte UTF-8 character is moved back to the character's start.
+fn read_capped(path: &Path, limit: Option<u64>) -> io::Result<(Vec<u8>, u64)> {
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut bytes = Vec::new();
+    file.take(limit.unwrap_or(u64::MAX))
+        .read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < size {
+        bytes.truncate(utf8_boundary(&bytes));
+    }
+    let more = size.saturating_sub(bytes.len() as u64);
+    Ok((bytes, more))
+}
+
+/// Length of `bytes` without a multi-byte UTF-8 character cut off at the
+/// end. Invalid bytes earlier on are left for the caller to deal with.
+fn utf8_boundary(bytes: &[u8]) -> usize {
+    match std::str::from_utf8(bytes) {
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        _ => bytes.len(),
+    }
+}
+
+fn write_truncation_marker(writer: &mut dyn Write, prefix: &str, more: u64) -> io::Result<()> {
+    if more > 0 {
+        writeln!(writer, "{prefix}... [truncated, {more} more bytes]")?;
+    }
+    Ok(())
+}
+
+/// `--content-base64`: the file's raw bytes, base64-encoded in 76-column
+/// lines, so any content survives embedding in a text document.
+fn write_content_base64(path: &Path, writer: &mut dyn Write, prefix: &str) -> io::Result<()> {
+    let mut file = match File::open(path) {
+        Ok(f) => f,
+        Err(err) => {
+            return writeln!(writer, "{prefix}[Could not read file: {}]", err);
+        }
+    };
+    let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+    writeln!(
+        writer,
+        "{prefix}--- FILE CONTENT START (encoding: base64, size: {size} bytes) ---"
+    )?;
+    // 57 input bytes encode to exactly one 76-character line.
+    let mut buf = vec![0u8; 57 * 1024];
+    loop {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match file.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    writeln!(writer, "{prefix}[Could not read file: {}]", err)?;
+                    return Ok(());
+                }
+            }
+        }
+        for line in buf[..filled].chunks(57) {
+            writeln!(writer, "{prefix}{}", base64::encode(line))?;
+        }
+        if filled < buf.len() {
+            break;
+        }
+    }
+    writeln!(writer, "{prefix}--- FILE CONTENT END ---")
+}
+
+/// `--grep-context N`: prints only the lines matching `re` plus `context`
+/// lines either side, numbered `grep -n` style (`12:` for a match, `12-`
+/// for context) with `--` between separate hunks.
+fn write_content_matches(
+    path: &Path,
+    re: &regex::Regex,
+    context: usize,
+    writer: &mut dyn Write,
+    prefix: &str,
+) -> io::Result<()> {
+    let content = match fs::read(path) {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(err) => {
+            return writeln!(writer, "{prefix}[Could not read file: {}]", err);
+        }
+    };
+    let lines: Vec<&str> = content.lines().collect();
+    let matches: Vec<usize> = (0..lines.len())
+        .filter(|&i| re.is_match(lines[i]))
+        .collect();
+
+    writeln!(writer, "{prefix}--- FILE CONTENT START ---")?;
+    let mut shown_to = 0;
+    for (n, &hit) in matches.iter().enumerate() {
+        let start = hit.saturating_sub(context).max(shown_to);
+        let end = (hit + context + 1).min(lines.len());
+        if start >= end {
+            continue;
+        }
+        if n > 0 && start > shown_to {
+            writeln!(writer, "{prefix}--")?;
+        }
+        for (i, line) in lines.iter().enumerate().take(end).skip(start) {
+            let mark = if re.is_match(line) { ':' } else { '-' };
+            writeln!(writer, "{prefix}{}{mark} {line}", i + 1)?;
+        }
+        shown_to = end;
+    }
+    writeln!(writer, "{prefix}--- FILE CONTENT END ---")
+}
+
+/// Streams a file's content line by line, keeping at most `max` bytes of
+/// any one line in memory and replacing the rest with `…[+M bytes]`.
+fn write_content_bounded(
+    path: &Path,
+    max: usize,
+    limit: Option<u64>,
+    writer: &mut dyn Write,
+    prefix: &str,
+) -> io::Result<()> {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(err) => {
+            writeln!(writer, "{prefix}[Could not read file: {}]", err)?;
+            return Ok(());
+        }
+    };
+    let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+    let mut reader = BufReader::new(file.take(limit.unwrap_or(u64::MAX)));
+    let mut read = 0u64;
+
+    writeln!(writer, "{prefix}--- FILE CONTENT START ---")?;
+    let mut line: Vec<u8> = Vec::with_capacity(max.min(64 * 1024));
+    let mut dropped = 0usize;
+    loop {
+        let (consumed, eol, eof) = {
+            let buf = match reader.fill_buf() {
+                Ok(buf) => buf,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    writeln!(writer, "{prefix}[Could not read file: {}]", err)?;
+                    return Ok(());
+                }
+            };
+            if buf.is_empty() {
+                (0, false, true)
+            } else {
+                let (chunk, eol) = match buf.iter().position(|&b| b == b'\n') {
+                    Some(i) => (&buf[..i], true),
+                    None => (buf, false),
+                };
+                let room = max.saturating_sub(line.len());
+                let keep = room.min(chunk.len());
+                line.extend_from_slice(&chunk[..keep]);
+                dropped += chunk.len() - keep;
+                (chunk.len() + usize::from(eol), eol, false)
+            }
+        };
+        reader.consume(consumed);
+        read += consumed as u64;
+        if eof && read < size {
+            let keep = utf8_boundary(&line);
+            read -= (line.len() - keep) as u64;
+            line.truncate(keep);
+        }
+
+        if eol || (eof && (!line.is_empty() || dropped > 0)) {
+            write_bounded_line(writer, prefix, &mut line, dropped)?;
+            line.clear();
+            dropped = 0;
+        }
+        if eof {
+            break;
+        }
+    }
+    write_truncation_marker(writer, prefix, size.saturating_sub(read))?;
+    writeln!(writer, "{prefix}--- FILE CONTENT END ---")
+}
+
+fn write_bounded_line(
+    writer: &mut dyn Write,
+    prefix: &str,
+    line: &mut Vec<u8>,
+    mut dropped: usize,
+) -> io::Result<()> {
+    if dropped == 0 && line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    // The cut may have landed inside a multi-byte character; move it back to
+    // the previous boundary so the kept part stays valid UTF-8.
+    if dropped > 0 {
+        let valid = utf8_boundary(line);
+        dropped += line.len() - valid;
+        line.truncate(valid);
+    }
+    let text = String::from_utf8_lossy(line);
+    if dropped > 0 {
+        writeln!(writer, "{prefix}{text}…[+{dropped} bytes]")
+    } else {
+        writeln!(writer, "{prefix}{text}")
+    }
+}
+
+/// In-memory tree for `--json` and `--format yaml`: the walk is collected
+/// first and serialized afterwards. `error` is what went wrong reading the
+/// entry (a directory's listing, a file's content), as reported on stderr.
+enum Node {
+    File {
+        name: String,
+        /// Set with `--icons`.
+        kind: Option<String>,
+        /// Set with `--content`.
+        content: Option<String>,
+        error: Option<String>,
+    },
+    Dir {
+        name: String,
+        children: Vec<Node>,
+        error: Option<String>,
+    },
+    Symlink {
+        name: String,
+        target: String,
+        /// Set for directories reached with `--follow-symlinks`.
+        children: Option<Vec<Node>>,
+        error: Option<String>,
+    },
+    /// An entry left out of its listing because it couldn't be statted, so
+    /// not even its type is known.
+    Unreadable { name: String, error: String },
+}
+
+impl Node {
+    /// Keys are always written in the order `name`, `path`, `type`, `error`
+    /// (only for an entry that couldn't be read), then `kind`, `target`,
+    /// `content` or `children`. `path` is root-relative with `/` separators,
+    /// `.` for the root itself. Entries that couldn't be statted have type
+    /// `unknown`.
+    fn write_json(&self, path: &str, out: &mut String) {
+        match self {
+            Node::File {
+                name,
+                kind,
+                content,
+                error,
+            } => {
+                out.push_str(&format!(
+                    "{{\"name\":\"{}\",\"path\":\"{}\",\"type\":\"file\"",
+                    json_escape(name),
+                    json_escape(path)
+                ));
+                push_json_error(error.as_deref(), out);
+                if let Some(kind) = kind {
+                    out.push_str(&format!(",\"kind\":\"{}\"", json_escape(kind)));
+                }
+                if let Some(content) = content {
+                    out.push_str(&format!(",\"content\":\"{}\"", json_escape(content)));
+                }
+                out.push('}');
+            }
+            Node::Dir {
+                name,
+                children,
+                error,
+            } => {
+                out.push_str(&format!(
+                    "{{\"name\":\"{}\",\"path\":\"{}\",\"type\":\"dir\"",
+                    json_escape(name),
+                    json_escape(path)
+                ));
+                push_json_error(error.as_deref(), out);
+                push_json_children(children, path, out);
+                out.push('}');
+            }
+            Node::Symlink {
+                name,
+                target,
+                children,
+                error,
+            } => {
+                out.push_str(&format!(
+                    "{{\"name\":\"{}\",\"path\":\"{}\",\"type\":\"symlink\"",
+                    json_escape(name),
+                    json_escape(path)
+                ));
+                push_json_error(error.as_deref(), out);
+                out.push_str(&format!(",\"target\":\"{}\"", json_escape(target)));
+                if let Some(children) = children {
+                    push_json_children(children, path, out);
+                }
+                out.push('}');
+            }
+            Node::Unreadable { name, error } => {
+                out.push_str(&format!(
+                    "{{\"name\":\"{}\",\"path\":\"{}\",\"type\":\"unknown\"",
+                    json_escape(name),
+                    json_escape(path)
+                ));
+                push_json_error(Some(error), out);
+                out.push('}');
+            }
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Node::File { name, .. }
+            | Node::Dir { name, .. }
+            | Node::Symlink { name, .. }
+            | Node::Unreadable { name, .. } => name,
+        }
+    }
+}
+
+fn push_json_error(error: Option<&str>, out: &mut String) {
+    if let Some(error) = error {
+        out.push_str(&format!(",\"error\":\"{}\"", json_escape(error)));
+    }
+}
+
+fn push_json_children(children: &[Node], parent: &str, out: &mut String) {
+    out.push_str(",\"children\":[");
+    for (idx, child) in children.iter().enumerate() {
+        if idx > 0 {
+            out.push(',');
+        }
+        let path = if parent == "." {
+            child.name().to_string()
+        } else {
+            format!("{parent}/{}", child.name())
+        };
+        child.write_json(&path, out);
+    }
+    out.push(']');
+}
+
+fn write_json(ctx: &mut WalkContext, writer: &mut dyn Write) -> io::Result<()> {
+    let config = ctx.config;
+    let children = collect_nodes(&config.start_dir, ctx, 0)?;
+    let root = Node::Dir {
+        name: display_root_name(&config.start_dir).unwrap_or_else(|| ".".to_string()),
+        children,
+        error: ctx.take_error(&config.start_dir),
+    };
+    let mut json = String::new();
+    root.write_json(".", &mut json);
+    if config.errors_section {
+        json.pop();
+        json.push_str(&errors_json(ctx));
+        json.push('}');
+    }
+    writeln!(writer, "{json}")
+}
+
+fn collect_nodes(dir: &Path, ctx: &mut WalkContext, depth: usize) -> io::Result<Vec<Node>> {
+    if ctx.config.walk.max_depth.is_some_and(|maxd| depth >= maxd) {
+        return Ok(Vec::new());
+    }
+    let mut nodes = Vec::new();
+    for entry in list_dir(dir, ctx, depth)? {
+        ctx.record(&entry)?;
+        let name = entry.file_name.to_string_lossy().into_owned();
+        let node = if entry.file_type.is_symlink() {
+            let target = winfs::read_link(&entry.path)
+                .map(|t| t.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let children = if entry.followed && ctx.first_visit(&entry.path) {
+                Some(collect_nodes(&entry.path, ctx, depth + 1)?)
+            } else {
+                None
+            };
+            Node::Symlink {
+                name,
+                target,
+                children,
+                error: ctx.take_error(&entry.path),
+            }
+        } else if entry.file_type.is_dir() {
+            let children = if ctx.first_visit(&entry.path) {
+                collect_nodes(&entry.path, ctx, depth + 1)?
+            } else {
+                Vec::new()
+            };
+            Node::Dir {
+                name,
+                children,
+                error: ctx.take_error(&entry.path),
+            }
+        } else {
+            let content = if ctx.wants_content(&entry) {
+                match fs::read(&entry.path) {
+                    Ok(bytes) => Some(String::from_utf8_lossy(&bytes).into_owned()),
+                    Err(err) => {
+                        warn(ctx, "read_file", &entry.path, &err)?;
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            let kind = ctx
+                .config
+                .icons
+                .map(|_| kind::kind_of(&entry, ctx.config).to_string());
+            Node::File {
+                name,
+                kind,
+                content,
+                error: ctx.take_error(&entry.path),
+            }
+        };
+        nodes.push(node);
+    }
+    for (path, error) in ctx.take_unstatted(dir) {
+        let name = path.file_name().unwrap_or_default();
+        nodes.push(Node::Unreadable {
+            name: name.to_string_lossy().into_owned(),
+            error,
+        });
+    }
+    Ok(nodes)
+}
+
+/// `--format json-tree`: one JSON document shaped for browser tree-view
+/// widgets. Every node is `{"id", "label", "icon", "meta", "children"}`;
+/// `id` is the root-relative path (`.` for the root) so it is stable across
+/// runs, `icon` is `folder`, `file` or `symlink`, and `children` is only
+/// present on directories. A node that couldn't be read ends with an
+/// `error`, after its children since a listing fails only once it is
+/// tried; entries that couldn't be statted are added as `file` nodes with
+/// just the error.
+fn write_json_tree(ctx: &mut WalkContext, writer: &mut dyn Write) -> io::Result<()> {
+    let config = ctx.config;
+    let label = display_root_name(&config.start_dir).unwrap_or_else(|| ".".to_string());
+    write!(
+        writer,
+        "{{\"id\":\".\",\"label\":\"{}\",\"icon\":\"folder\",\"meta\":{{}},\"children\":",
+        json_escape(&label)
+    )?;
+    write_json_children(&config.start_dir, ctx, writer, 0)?;
+    write_json_tree_error(ctx.take_error(&config.start_dir), writer)?;
+    if config.errors_section {
+        write!(writer, "{}", errors_json(ctx))?;
+    }
+    writeln!(writer, "}}")
+}
+
+fn write_json_children(
+    dir: &Path,
+    ctx: &mut WalkContext,
+    writer: &mut dyn Write,
+    depth: usize,
+) -> io::Result<()> {
+    let entries = if ctx.config.walk.max_depth.is_some_and(|maxd| depth >= maxd) {
+        Vec::new()
+    } else {
+        list_dir(dir, ctx, depth)?
+    };
+
+    write!(writer, "[")?;
+    for (idx, entry) in entries.iter().enumerate() {
+        if idx > 0 {
+            write!(writer, ",")?;
+        }
+        ctx.record(entry)?;
+        let id = relative_path(ctx.config, &entry.path, false);
+        let icon = if entry.file_type.is_symlink() {
+            "symlink"
+        } else if entry.file_type.is_dir() {
+            "folder"
+        } else {
+            "file"
+        };
+        let mut meta = format!("\"size\":{}", entry.len);
+        if let Some(secs) = entry
+            .modified
+            .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        {
+            meta.push_str(&format!(",\"modified\":{}", secs.as_secs()));
+        }
+        if entry.file_type.is_symlink() {
+            let target = winfs::read_link(&entry.path)
+                .map(|t| t.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            meta.push_str(&format!(",\"target\":\"{}\"", json_escape(&target)));
+        }
+        if ctx.config.icons.is_some() && !is_walkable_dir(entry) && !entry.file_type.is_symlink() {
+            let kind = kind::kind_of(entry, ctx.config);
+            meta.push_str(&format!(",\"kind\":\"{}\"", json_escape(kind)));
+        }
+        if ctx.config.hash_files && entry.file_type.is_file() {
+            match hash_file(&entry.path) {
+                Ok(digest) => {
+                    meta.push_str(&format!(",\"sha256\":\"{}\"", sha256::to_hex(&digest)))
+                }
+                Err(err) => warn(ctx, "read_file", &entry.path, &err)?,
+            }
+        }
+        let revisit = is_walkable_dir(entry) && !ctx.first_visit(&entry.path);
+        if revisit {
+            meta.push_str(",\"already_visited\":true");
+        }
+        write!(
+            writer,
+            "{{\"id\":\"{}\",\"label\":\"{}\",\"icon\":\"{icon}\",\"meta\":{{{meta}}}",
+            json_escape(&id),
+            json_escape(&entry.file_name.to_string_lossy())
+        )?;
+        if entry.file_type.is_dir() || entry.followed {
+            write!(writer, ",\"children\":")?;
+            if is_walkable_dir(entry) && !revisit {
+                write_json_children(&entry.path, ctx, writer, depth + 1)?;
+            } else {
+                write!(writer, "[]")?;
+            }
+        }
+        write_json_tree_error(ctx.take_error(&entry.path), writer)?;
+        write!(writer, "}}")?;
+    }
+    for (idx, (path, error)) in ctx.take_unstatted(dir).into_iter().enumerate() {
+        if idx > 0 || !entries.is_empty() {
+            write!(writer, ",")?;
+        }
+        write!(
+            writer,
+            "{{\"id\":\"{}\",\"label\":\"{}\",\"icon\":\"file\",\"meta\":{{}}",
+            json_escape(&relative_path(ctx.config, &path, false)),
+            json_escape(&path.file_name().unwrap_or_default().to_string_lossy())
+        )?;
+        write_json_tree_error(Some(error), writer)?;
+        write!(writer, "}}")?;
+    }
+    write!(writer, "]")
+}
+
+fn write_json_tree_error(error: Option<String>, writer: &mut dyn Write) -> io::Result<()> {
+    match error {
+        Some(error) => write!(writer, ",\"error\":\"{}\"", json_escape(&error)),
+        None => Ok(()),
+    }
+}
+
+/// `--markdown`: a nested bullet list indented two spaces per level, with
+/// directories suffixed `/`. Names are escaped so they render literally,
+/// and with `--content` each file's text follows in a fenced code block
+/// inside its list item.
+fn write_markdown(ctx: &mut WalkContext, writer: &mut dyn Write) -> io::Result<()> {
+    let config = ctx.config;
+    let root = display_root_name(&config.start_dir).unwrap_or_else(|| ".".to_string());
+    writeln!(writer, "- {}/", markdown_escape(&root))?;
+    write_markdown_items(&config.start_dir, ctx, writer, 0)?;
+    if config.errors_section && !ctx.section_errors.is_empty() {
+        writeln!(writer, "\n## Errors\n")?;
+        for error in &ctx.section_errors {
+            writeln!(writer, "- {}", markdown_escape(&error.describe()))?;
+        }
+    }
+    Ok(())
+}
+
+fn write_markdown_items(
+    dir: &Path,
+    ctx: &mut WalkContext,
+    writer: &mut dyn Write,
+    depth: usize,
+) -> io::Result<()> {
+    let config = ctx.config;
+    if config.walk.max_depth.is_some_and(|maxd| depth >= maxd) {
+        return Ok(());
+    }
+    let indent = "  ".repeat(depth + 1);
+    for entry in list_dir(dir, ctx, depth)? {
+        ctx.record(&entry)?;
+        let mut name = markdown_escape(&entry.file_name.to_string_lossy());
+        if entry.file_type.is_dir() {
+            name.push('/');
+        }
+        if config.walk.follow_symlinks && entry.is_symlink_dir && !entry.followed {
+            name.push_str(" -> &lt;cycle&gt;");
+        } else if entry.file_type.is_symlink() {
+            let target = winfs::read_link(&entry.path)
+                .map(|t| markdown_escape(&t.to_string_lossy()))
+                .unwrap_or_else(|_| "&lt;unreadable&gt;".to_string());
+            name.push_str(&format!(" -> {target}"));
+        }
+        match config.icons {
+            Some(style) => {
+                let icon = kind::icon(kind::kind_of(&entry, config), style, config);
+                writeln!(writer, "{indent}- {icon} {name}")?;
+            }
+            None => writeln!(writer, "{indent}- {name}")?,
+        }
+        if ctx.wants_content(&entry) {
+            write_markdown_content(&entry.path, config, writer, &format!("{indent}  "))?;
+        }
+        if is_walkable_dir(&entry) && ctx.first_visit(&entry.path) {
+            write_markdown_items(&entry.path, ctx, writer, depth + 1)?;
+        }
+    }
+    Ok(())
+}
+
+/// A file's text as a fenced block indented to sit inside its list item.
+/// The fence is made longer than any run of backticks in the text, and
+/// tagged with a language guessed from the extension for highlighting.
+fn write_markdown_content(
+    path: &Path,
+    config: &Config,
+    writer: &mut dyn Write,
+    indent: &str,
+) -> io::Result<()> {
+    let (bytes, more) = match read_capped(path, config.max_content_bytes) {
+        Ok(read) => read,
+        Err(err) => {
+            let message = markdown_escape(&err.to_string());
+            return writeln!(writer, "{indent}*Could not read file: {message}*");
+        }
+    };
+    let encoding = encoding::detect(&bytes[..bytes.len().min(encoding::SNIFF_BYTES)]);
+    if encoding == encoding::Encoding::Binary && !config.include_binary {
+        let size = bytes.len() as u64 + more;
+        return writeln!(writer, "{indent}*binary file, {size} bytes*");
+    }
+    let text = decoded_text(config, encoding, &bytes);
+    let fence = code_fence(&text);
+    let language = path
+        .extension()
+        .map(|ext| markdown_language(&ext.to_string_lossy().to_lowercase()))
+        .unwrap_or("");
+    let lines: Vec<&str> = text.lines().collect();
+    let (start, end) = content_range(config, lines.len());
+    let width = end.to_string().len();
+    writeln!(writer, "{indent}{fence}{language}")?;
+    for (i, line) in lines.iter().enumerate().take(end).skip(start) {
+        if config.line_numbers {
+            writeln!(writer, "{indent}{:>width$}  {line}", i + 1)?;
+        } else if line.is_empty() {
+            writeln!(writer)?;
+        } else {
+            writeln!(writer, "{indent}{line}")?;
+        }
+    }
+    writeln!(writer, "{indent}{fence}")?;
+    if more > 0 && end == lines.len() {
+        writeln!(writer, "{indent}*… truncated, {more} more bytes*")?;
+    }
+    Ok(())
+}
+
+/// A backtick fence longer than any run of backticks in `text`, so the
+/// text can't close it early.
+fn code_fence(text: &str) -> String {
+    let longest_run = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat(longest_run.max(2) + 1)
+}
+
+/// Info string for a fenced block holding a file with this extension, as
+/// understood by GitHub's highlighter; empty when unknown.
+fn markdown_language(ext: &str) -> &'static str {
+    match ext {
+        "rs" => "rust",
+        "py" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "ts" => "typescript",
+        "tsx" => "tsx",
+        "jsx" => "jsx",
+        "go" => "go",
+        "java" => "java",
+        "kt" => "kotlin",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" => "cpp",
+        "cs" => "csharp",
+        "rb" => "ruby",
+        "php" => "php",
+        "swift" => "swift",
+        "sh" | "bash" => "bash",
+        "ps1" => "powershell",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "json" => "json",
+        "toml" => "toml",
+        "yaml" | "yml" => "yaml",
+        "xml" => "xml",
+        "sql" => "sql",
+        "md" => "markdown",
+        "lua" => "lua",
+        "dockerfile" => "dockerfile",
+        _ => "",
+    }
+}
+
+/// Escapes characters that Markdown would otherwise read as formatting, so
+/// names like `__init__.py` or `[draft].md` show up as written.
+fn markdown_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for (idx, c) in s.chars().enumerate() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '|' | '~' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '#' | '+' | '-' if idx == 0 => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Writes one `<hexdigest>  <path>` line per regular file, in the format
+/// `sha256sum -c` verifies when run from the root. Paths are relative to
+/// the root with `/` separators; names containing a backslash or newline
+/// are escaped the way coreutils does, with a leading `\` on the line.
+fn write_shasum(
+    dir: &Path,
+    ctx: &mut WalkContext,
+    writer: &mut dyn Write,
+    depth: usize,
+) -> io::Result<()> {
+    if let Some(maxd) = ctx.config.walk.max_depth
+        && depth >= maxd
+    {
+        return Ok(());
+    }
+
+    for entry in list_dir(dir, ctx, depth)? {
+        ctx.record(&entry)?;
+        if is_walkable_dir(&entry) {
+            if ctx.first_visit(&entry.path) {
+                write_shasum(&entry.path, ctx, writer, depth + 1)?;
+            }
+            continue;
+        }
+        if !entry.file_type.is_file() {
+            continue;
+        }
+
+        let digest = match hash_file(&entry.path) {
+            Ok(d) => d,
+            Err(err) => {
+                warn(ctx, "read_file", &entry.path, &err)?;
+                continue;
+            }
+        };
+        let rel = relative_path(ctx.config, &entry.path, false);
+        let rel = strip_components(ctx.config, &rel);
+        if rel.contains(['\\', '\n']) {
+            let escaped = rel.replace('\\', "\\\\").replace('\n', "\\n");
+            writeln!(writer, "\\{}  {escaped}", sha256::to_hex(&digest))?;
+        } else {
+            writeln!(writer, "{}  {rel}", sha256::to_hex(&digest))?;
+        }
+    }
+    Ok(())
+}
+
+fn hash_file(path: &Path) -> io::Result<[u8; 32]> {
+    let mut file = File::open(path)?;
+    let mut hasher = sha256::Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize())
+}
+
+/// Reads `dir` (found at `depth`) and returns the entries that should be
+/// listed, in display order: the output file and entries rejected by
+/// filters are removed, and with `--prune` so are directories left with
+/// nothing to list.
+fn list_dir(dir: &Path, ctx: &mut WalkContext, depth: usize) -> io::Result<Vec<DirEntryInfo>> {
+    let config = ctx.config;
+    let mut entries = read_dir_entries(dir, ctx)?;
+    if ctx.output_path.is_some() {
+        entries.retain(|e| !ctx.is_output(e));
+    }
+    let unfiltered = entries.len();
+    if let (Some(distances), Some(n)) = (&ctx.match_distances, config.walk.context_depth) {
+        let near_match = distances.get(dir).is_some_and(|&d| d <= n);
+        if !near_match {
+            entries.retain(|e| {
+                distances.contains_key(&e.path)
+                    || (!is_walkable_dir(e) && matches_filters(config, e))
+            });
+        }
+    } else if config.walk.filters_files() {
+        entries.retain(|e| is_walkable_dir(e) || matches_filters(config, e));
+    }
+    if config.walk.dirs_only {
+        entries.retain(|e| e.file_type.is_dir() || e.is_symlink_dir);
+    }
+    if config.walk.prune {
+        let mut kept = Vec::with_capacity(entries.len());
+        for entry in entries {
+            if !is_walkable_dir(&entry)
+                || is_empty_dir(config, &entry)
+                || subtree_has_entries(&entry.path, ctx, depth + 1)?
+            {
+                kept.push(entry);
+            }
+        }
+        entries = kept;
+    }
+    if let Some(skipped) = ctx.skipped.get_mut(dir)
+        && !ctx.prescan
+    {
+        *skipped += unfiltered - entries.len();
+    }
+    if let Some(progress) = &mut ctx.progress
+        && !ctx.prescan
+    {
+        progress.tick(entries.len())?;
+    }
+    if let Some(line) = &mut ctx.progress_line
+        && !ctx.prescan
+    {
+        line.tick(dir, entries.len());
+    }
+
+    sort_entries(&mut entries, ctx, depth)?;
+    Ok(entries)
+}
+
+/// Orders a listing per `--sort`: directories grouped first (or last with
+/// `--dirs-last`, or mixed in with `--no-group-dirs`), then by the key,
+/// with the name breaking ties. `--reverse` flips everything but the
+/// grouping.
+fn sort_entries(
+    entries: &mut Vec<DirEntryInfo>,
+    ctx: &mut WalkContext,
+    depth: usize,
+) -> io::Result<()> {
+    let config = ctx.config;
+    match config.walk.sort {
+        SortKey::None => {
+            if config.walk.reverse {
+                entries.reverse();
+            }
+        }
+        SortKey::Name => {
+            let keys = vec![(); entries.len()];
+            sort_by_keys(entries, keys, config, |_, _| std::cmp::Ordering::Equal);
+        }
+        SortKey::Created => {
+            let keys = entries.iter().map(|e| e.created).collect();
+            sort_by_keys(entries, keys, config, |a, b| oldest_first(*a, *b));
+        }
+        SortKey::Mtime => {
+            let keys = entries.iter().map(|e| e.modified).collect();
+            sort_by_keys(entries, keys, config, |a, b| oldest_first(*a, *b));
+        }
+        SortKey::Ext => {
+            let keys = entries
+                .iter()
+                .map(|e| {
+                    e.path
+                        .extension()
+                        .map(|x| x.to_string_lossy().to_lowercase())
+                })
+                .collect();
+            sort_by_keys(entries, keys, config, Ord::cmp);
+        }
+        SortKey::Size => {
+            // Directories weigh what is listed below them, as with --size.
+            let was_prescan = ctx.prescan;
+            ctx.prescan = true;
+            let mut keys = Vec::with_capacity(entries.len());
+            for e in entries.iter() {
+                let size = if is_walkable_dir(e) {
+                    match dir_size(&e.path, ctx, depth + 1) {
+                        Ok(size) => size,
+                        Err(err) => {
+                            ctx.prescan = was_prescan;
+                            return Err(err);
+                        }
+                    }
+                } else {
+                    e.len
+                };
+                keys.push(size);
+            }
+            ctx.prescan = was_prescan;
+            sort_by_keys(entries, keys, config, Ord::cmp);
+        }
+        SortKey::Hash => {
+            let keys = entries
+                .iter()
+                .map(|e| {
+                    if e.file_type.is_file() {
+                        hash_file(&e.path).ok()
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+            sort_by_keys(entries, keys, config, Ord::cmp);
+        }
+    }
+    Ok(())
+}
+
+/// Sorts `entries` by `keys`, one per entry and compared with `cmp`, then
+/// by name. Name keys are built once per entry too, rather than on every
+/// comparison, as `--natural-sort` and `--locale-sort` ones take work.
+fn sort_by_keys<K>(
+    entries: &mut Vec<DirEntryInfo>,
+    keys: Vec<K>,
+    config: &Config,
+    cmp: impl Fn(&K, &K) -> std::cmp::Ordering,
+) {
+    let order = |o: std::cmp::Ordering| {
+        if config.walk.reverse { o.reverse() } else { o }
+    };
+    let mut keyed: Vec<(K, collate::NameKey, DirEntryInfo)> = keys
+        .into_iter()
+        .zip(entries.drain(..))
+        .map(|(key, e)| {
+            let name = collate::NameKey::new(&e.file_name.to_string_lossy(), &config.walk);
+            (key, name, e)
+        })
+        .collect();
+    keyed.sort_by(|(ka, na, a), (kb, nb, b)| {
+        group_dirs(config, a, b).then_with(|| order(cmp(ka, kb).then_with(|| na.cmp(nb))))
+    });
+    entries.extend(keyed.into_iter().map(|(_, _, e)| e));
+}
+
+/// Earliest time first; entries without one go last.
+fn oldest_first(a: Option<SystemTime>, b: Option<SystemTime>) -> std::cmp::Ordering {
+    match (a, b) {
+        (Some(x), Some(y)) => x.cmp(&y),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    }
+}
+
+fn group_dirs(config: &Config, a: &DirEntryInfo, b: &DirEntryInfo) -> std::cmp::Ordering {
+    if !config.walk.group_dirs {
+        return std::cmp::Ordering::Equal;
+    }
+    let ad = a.file_type.is_dir();
+    let bd = b.file_type.is_dir();
+    if config.walk.dirs_last {
+        ad.cmp(&bd)
+    } else {
+        ad.cmp(&bd).reverse()
+    }
+}
+
+/// Whether `entry` was modified within `--newer-than`/`--older-than`;
+/// true when neither is given.
+fn modified_within(config: &Config, entry: &DirEntryInfo) -> bool {
+    let (newer, older) = (config.walk.newer_than, config.walk.older_than);
+    (newer.is_none() && older.is_none())
+        || entry
+            .modified
+            .is_some_and(|mtime| newer.is_none_or(|t| mtime > t) && older.is_none_or(|t| mtime < t))
+}
+
+/// Whether `entry` is a directory `--only-empty` keeps: a real directory
+/// (not a symlink to one) with nothing at all in it on disk, hidden and
+/// excluded names included, modified within `--newer-than`/`--older-than`.
+fn is_empty_dir(config: &Config, entry: &DirEntryInfo) -> bool {
+    config.walk.only_empty
+        && entry.file_type.is_dir()
+        && modified_within(config, entry)
+        && fs::read_dir(&entry.path).is_ok_and(|mut listing| listing.next().is_none())
+}
+
+fn matches_filters(config: &Config, entry: &DirEntryInfo) -> bool {
+    if !config.walk.name_matches.is_empty() {
+        let name = entry.file_name.to_string_lossy();
+        if !config.walk.name_matches.iter().any(|re| re.is_match(&name)) {
+            return false;
+        }
+    }
+    if config.walk.executables && !(entry.file_type.is_file() && entry.executable) {
+        return false;
+    }
+    if config.walk.files_only && !is_file_like(entry) {
+        return false;
+    }
+    if (config.walk.min_size.is_some() || config.walk.max_size.is_some())
+        && !(entry.file_type.is_file()
+            && config.walk.min_size.is_none_or(|min| entry.len >= min)
+            && config.walk.max_size.is_none_or(|max| entry.len <= max))
+    {
+        return false;
+    }
+    if (config.walk.newer_than.is_some() || config.walk.older_than.is_some())
+        && !(entry.file_type.is_file() && modified_within(config, entry))
+    {
+        return false;
+    }
+    if config.walk.only_empty && !(entry.file_type.is_file() && entry.len == 0) {
+        return false;
+    }
+    if let Some(re) = &config.walk.grep {
+        // Unreadable files count as not matching.
+        let matched = entry.file_type.is_file()
+            && fs::read(&entry.path)
+                .is_ok_and(|bytes| re.is_match(&String::from_utf8_lossy(&bytes)));
+        if !matched {
+            return false;
+        }
+    }
+    match &config.walk.path_regex {
+        Some(re) => re.is_match(&relative_path(
+            config,
+            &entry.path,
+            entry.file_type.is_dir(),
+        )),
+        None => true,
+    }
+}
+
+/// First pass of `--context-depth`: records, for every directory with a
+/// matching entry below it, how many levels down the nearest match is.
+fn match_distance(
+    dir: &Path,
+    ctx: &mut WalkContext,
+    depth: usize,
+    distances: &mut HashMap<PathBuf, usize>,
+) -> io::Result<Option<usize>> {
+    if let Some(maxd) = ctx.config.walk.max_depth
+        && depth >= maxd
+    {
+        return Ok(None);
+    }
+
+    let mut entries = read_dir_entries(dir, ctx)?;
+    if ctx.output_path.is_some() {
+        entries.retain(|e| !ctx.is_output(e));
+    }
+
+    let mut nearest: Option<usize> = None;
+    for entry in &entries {
+        let distance = if is_walkable_dir(entry) {
+            match_distance(&entry.path, ctx, depth + 1, distances)?.map(|d| d + 1)
+        } else if matches_filters(ctx.config, entry) {
+            Some(1)
+        } else {
+            None
+        };
+        nearest = match (nearest, distance) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+    }
+    if let Some(d) = nearest {
+        distances.insert(dir.to_path_buf(), d);
+    }
+    Ok(nearest)
+}
+
+fn is_walkable_dir(entry: &DirEntryInfo) -> bool {
+    (entry.file_type.is_dir() && !entry.is_symlink_dir) || entry.followed
+}
+
+/// A regular file, or a symlink that doesn't lead to a directory, for
+/// `--files-only`.
+fn is_file_like(entry: &DirEntryInfo) -> bool {
+    entry.file_type.is_file() || (entry.file_type.is_symlink() && !entry.is_symlink_dir)
+}
+
+/// Whether the directory `link` points at contains `link` itself, so that
+/// following it would never end: `ln -s . loop`, or a link back up to where
+/// an earlier followed link started. Directories are compared by device and
+/// inode, so a way back in through a bind mount is caught too.
+fn links_to_ancestor(link: &Path) -> bool {
+    let Some(target) = DirKey::of(link) else {
+        return false;
+    };
+    link.ancestors()
+        .skip(1)
+        .any(|dir| DirKey::of(dir).is_some_and(|dir| dir == target))
+}
+
+/// Total size of everything listed below `dir`, however deep, counting
+/// symlinks as the link itself. Subdirectory totals are memoized so
+/// nested directories are only summed once.
+fn dir_size(dir: &Path, ctx: &mut WalkContext, depth: usize) -> io::Result<u64> {
+    if let Some(&size) = ctx.dir_sizes.get(dir) {
+        return Ok(size);
+    }
+    let mut total = 0;
+    for entry in list_dir(dir, ctx, depth)? {
+        total += if is_walkable_dir(&entry) {
+            dir_size(&entry.path, ctx, depth + 1)?
+        } else {
+            entry.len
+        };
+    }
+    ctx.dir_sizes.insert(dir.to_path_buf(), total);
+    Ok(total)
+}
+
+/// Whether a directory at `depth` would list anything at all, used by
+/// `--prune` to look ahead before the directory's own line is printed.
+/// Answers are memoized, so the lookahead from each ancestor doesn't list
+/// a subtree again.
+fn subtree_has_entries(dir: &Path, ctx: &mut WalkContext, depth: usize) -> io::Result<bool> {
+    if let Some(maxd) = ctx.config.walk.max_depth
+        && depth >= maxd
+    {
+        return Ok(false);
+    }
+    if let Some(&has) = ctx.has_entries.get(dir) {
+        return Ok(has);
+    }
+    let has = !list_dir(dir, ctx, depth)?.is_empty();
+    ctx.has_entries.insert(dir.to_path_buf(), has);
+    Ok(has)
+}
+
+/// Path of `path` relative to the root, joined with `/` whatever the
+/// platform separator. Directories get a trailing `/`.
+fn relative_path(config: &Config, path: &Path, is_dir: bool) -> String {
+    let mut rel = path
+        .strip_prefix(&config.start_dir)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join("/");
+    if is_dir {
+        rel.push('/');
+    }
+    rel
+}
+
+/// Drops the first `--strip-components` components of a root-relative
+/// path, like `tar --strip-components`, but never strips the last one.
+fn strip_components<'a>(config: &Config, rel: &'a str) -> &'a str {
+    let mut rest = rel;
+    for _ in 0..config.strip_components {
+        match rest.trim_end_matches('/').find('/') {
+            Some(i) => rest = &rest[i + 1..],
+            None => break,
+        }
+    }
+    rest
+}
+
+/// Collects everything below `dir` for `--flatten-below`, renaming each
+/// leaf to its path relative to the directory being flattened. Directories
+/// are only listed themselves when nothing is listed beneath them.
+fn collect_flat(
+    dir: &Path,
+    ctx: &mut WalkContext,
+    rel: &str,
+    depth: usize,
+    out: &mut Vec<DirEntryInfo>,
+) -> io::Result<()> {
+    if let Some(maxd) = ctx.config.walk.max_depth
+        && depth >= maxd
+    {
+        return Ok(());
+    }
+
+    for mut entry in list_dir(dir, ctx, depth)? {
+        ctx.record(&entry)?;
+        let rel_name = format!("{rel}{}", entry.file_name.to_string_lossy());
+        if is_walkable_dir(&entry) && ctx.first_visit(&entry.path) {
+            let before = out.len();
+            collect_flat(&entry.path, ctx, &format!("{rel_name}/"), depth + 1, out)?;
+            if out.len() > before {
+                continue;
+            }
+        }
+        entry.file_name = rel_name.into();
+        out.push(entry);
+    }
+    Ok(())
+}
+
+/// One listed directory entry.
+#[derive(Clone)]
+pub struct DirEntryInfo {
+    pub path: PathBuf,
+    pub file_name: std::ffi::OsString,
+    /// For symlinks, the type of the link itself.
+    pub file_type: fs::FileType,
+    /// A symlink that points at a directory.
+    pub is_symlink_dir: bool,
+    /// A symlinked directory that `follow_symlinks` descends into. Links back
+    /// to one of their own parent directories are never followed.
+    pub followed: bool,
+    /// Size of the entry itself; for symlinks, the link rather than its target.
+    /// Sizes, times and the device id are not gathered with `fast`.
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+    /// Birth time, where the platform and filesystem record one.
+    pub created: Option<SystemTime>,
+    /// Device id (`st_dev`) on Unix.
+    pub dev: Option<u64>,
+    /// Any execute bit set; on Windows, a `.exe`/`.bat`/`.cmd`/`.com` name.
+    pub executable: bool,
+    /// A dot name; on Windows, also the hidden attribute.
+    pub hidden: bool,
+}
+
+/// Any execute permission bit set (owner, group or other).
+#[cfg(unix)]
+fn is_executable(meta: &fs::Metadata, _path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o111 != 0
+}
+
+/// Windows has no execute bit, so this goes by extension. Scripts run
+/// through an interpreter (`.ps1`, `.py`, ...) are not counted.
+#[cfg(not(unix))]
+fn is_executable(_meta: &fs::Metadata, path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .is_some_and(|ext| matches!(ext.as_str(), "exe" | "bat" | "cmd" | "com"))
+}
+
+#[cfg(windows)]
+fn is_hidden(de: &fs::DirEntry, name: &str) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    // The attributes come with the listing, so this costs no extra stat.
+    name.starts_with('.')
+        || de
+            .metadata()
+            .is_ok_and(|m| m.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0)
+}
+
+#[cfg(not(windows))]
+fn is_hidden(_de: &fs::DirEntry, name: &str) -> bool {
+    name.starts_with('.')
+}
+
+/// A non-fatal problem encountered during the walk.
+#[derive(Clone)]
+pub struct WalkError {
+    /// `read_dir`, `stat`, `read_file`, `read_entry`, `read_archive` or
+    /// `exec`.
+    pub kind: &'static str,
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl WalkError {
+    fn report(&self, format: ErrorFormat) {
+        match format {
+            ErrorFormat::Json => {
+                eprintln!("{}", error_json(self.kind, Some(&self.path), &self.message));
+            }
+            ErrorFormat::Text => eprintln!("Warning: {}", self.describe()),
+        }
+    }
+
+    /// The problem as a sentence, e.g. `cannot read directory src: Permission
+    /// denied (os error 13)`.
+    fn describe(&self) -> String {
+        let what = match self.kind {
+            "read_dir" => "cannot read directory",
+            "stat" => "cannot stat",
+            "read_file" => "cannot read file",
+            "read_archive" => "cannot list archive",
+            "exec" => "command failed for",
+            "refused" => "left out of the delete list:",
+            _ => "error while reading in",
+        };
+        format!("{what} {}: {}", self.path.to_string_lossy(), self.message)
+    }
+}
+
+/// Reports a non-fatal problem on stderr, either as a `Warning:` line or as
+/// a JSON object per `--error-format`. With `--defer-errors` it is kept
+/// until the walk finishes instead, and with `--quiet` it is only counted.
+/// With `--errors-section` it is also kept for the end of the output. With
+/// `--strict` it becomes the error that ends the run.
+fn warn(ctx: &mut WalkContext, kind: &'static str, path: &Path, err: &io::Error) -> io::Result<()> {
+    if ctx.prescan {
+        return Ok(());
+    }
+    if ctx.config.strict {
+        return Err(io::Error::new(
+            err.kind(),
+            format!("{}: {err}", path.to_string_lossy()),
+        ));
+    }
+    ctx.problems += 1;
+    let error = WalkError {
+        kind,
+        path: path.to_path_buf(),
+        message: err.to_string(),
+    };
+    if let Some(errors) = &mut ctx.node_errors {
+        errors
+            .entry(error.path.clone())
+            .or_insert((kind, error.message.clone()));
+    }
+    if ctx.config.errors_section {
+        ctx.section_errors.push(error.clone());
+    }
+    if ctx.config.quiet {
+        return Ok(());
+    }
+    if let Some(line) = &mut ctx.progress_line {
+        line.clear();
+    }
+    if ctx.config.defer_errors {
+        ctx.deferred_errors.push(error);
+    } else {
+        error.report(ctx.config.error_format);
+    }
+    Ok(())
+}
+
+fn report_deferred_errors(config: &Config, errors: &[WalkError]) {
+    if errors.is_empty() {
+        return;
+    }
+    if config.error_format == ErrorFormat::Text {
+        eprintln!("\n{} error(s) during the walk:", errors.len());
+    }
+    for error in errors {
+        error.report(config.error_format);
+    }
+}
+
+/// One error as a JSON object, the form used with `--error-format json`.
+pub fn error_json(kind: &str, path: Option<&Path>, message: &str) -> String {
+    let path = match path {
+        Some(p) => format!("\"{}\"", json_escape(&p.to_string_lossy())),
+        None => "null".to_string(),
+    };
+    format!(
+        "{{\"kind\":\"{}\",\"path\":{},\"message\":\"{}\"}}",
+        json_escape(kind),
+        path,
+        json_escape(message)
+    )
+}
+
+fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// One directory as read from disk, before `.gitignore` rules, filters and
+/// sorting are applied. Problems are kept so they can be reported when the
+/// directory is actually listed.
+struct Listing {
+    entries: Vec<DirEntryInfo>,
+    problems: Vec<(&'static str, PathBuf, io::Error)>,
+    /// Entries left out as hidden or excluded.
+    skipped: usize,
+}
+
+/// Reads `dir` (somewhere below `root`) and stats its entries, skipping
+/// excluded ones. Needs nothing but the options, so `--jobs` can run it on
+/// worker threads.
+fn scan_dir(dir: &Path, root: &Path, walk: &WalkOptions) -> Listing {
+    let mut listing = Listing {
+        entries: Vec::with_capacity(64),
+        problems: Vec::new(),
+        skipped: 0,
+    };
+    if walk.one_file_system && dir != root && on_other_fs(dir, root) {
+        return listing;
+    }
+    let rd: ReadDir = match fs::read_dir(winfs::extended_length(dir)) {
+        Ok(rd) => rd,
+        Err(err) => {
+            listing.problems.push(("read_dir", dir.to_path_buf(), err));
+            return listing;
+        }
+    };
+
+    for res in rd {
+        match res {
+            Ok(de) => {
+                let file_name_os = de.file_name();
+                let file_name_str = file_name_os.to_string_lossy();
+                // `de.path()` carries the extended-length prefix on Windows:
+                // it is used to read the entry, `path` to show it.
+                let path = dir.join(&file_name_os);
+
+                let hidden = is_hidden(&de, &file_name_str);
+                if (!walk.all && hidden)
+                    || walk.excludes.contains(file_name_str.as_ref())
+                    || walk
+                        .name_ignores
+                        .iter()
+                        .any(|re| re.is_match(&file_name_str))
+                {
+                    listing.skipped += 1;
+                    continue;
+                }
+
+                if walk.fast {
+                    let file_type = match de.file_type() {
+                        Ok(ft) => ft,
+                        Err(err) => {
+                            listing.problems.push(("stat", path, err));
+                            continue;
+                        }
+                    };
+                    listing.entries.push(DirEntryInfo {
+                        path,
+                        file_name: de.file_name(),
+                        file_type,
+                        is_symlink_dir: false,
+                        followed: false,
+                        len: 0,
+                        modified: None,
+                        created: None,
+                        dev: None,
+                        executable: false,
+                        hidden,
+                    });
+                    continue;
+                }
+
+                let sy_meta = match fs::symlink_metadata(de.path()) {
+                    Ok(m) => m,
+                    Err(err) => {
+                        listing.problems.push(("stat", path, err));
+                        continue;
+                    }
+                };
+                let ft = sy_meta.file_type();
+                let is_symlink = ft.is_symlink();
+
+                let file_type = if is_symlink {
+                    ft
+                } else {
+                    match fs::metadata(de.path()) {
+                        Ok(m) => m.file_type(),
+                        Err(_) => ft,
+                    }
+                };
+
+                let is_symlink_dir = is_symlink
+                    && !walk.symlinks_as_files
+                    && fs::metadata(de.path()).map(|m| m.is_dir()).unwrap_or(false);
+                let followed =
+                    is_symlink_dir && walk.follow_symlinks && !links_to_ancestor(&de.path());
+
+                listing.entries.push(DirEntryInfo {
+                    path,
+                    file_name: de.file_name(),
+                    file_type,
+                    is_symlink_dir,
+                    followed,
+                    len: sy_meta.len(),
+                    modified: sy_meta.modified().ok(),
+                    created: sy_meta.created().ok(),
+                    dev: fsinfo::device_id(&sy_meta),
+                    executable: is_executable(&sy_meta, &de.path()),
+                    hidden,
+                });
+            }
+            Err(err) => {
+                listing
+                    .problems
+                    .push(("read_entry", dir.to_path_buf(), err));
+            }
+        }
+    }
+    if !walk.exclude_globs.is_empty() {
+        let before = listing.entries.len();
+        listing
+            .entries
+            .retain(|e| !walk.exclude_globs.iter().any(|g| g.excludes(e, root)));
+        listing.skipped += before - listing.entries.len();
+    }
+    listing
+}
+
+/// Whether `dir` (or the directory a symlink at `dir` leads to) is on
+/// another device than `root`. False when either can't be told.
+fn on_other_fs(dir: &Path, root: &Path) -> bool {
+    let dev = |p: &Path| fs::metadata(p).ok().as_ref().and_then(fsinfo::device_id);
+    matches!((dev(dir), dev(root)), (Some(a), Some(b)) if a != b)
+}
+
+fn read_dir_entries(dir: &Path, ctx: &mut WalkContext) -> io::Result<Vec<DirEntryInfo>> {
+    let config = ctx.config;
+    // A directory read ahead by `--jobs` is kept for later passes (--prune,
+    // --size, --context-depth), which report its problems again just as a
+    // fresh read would.
+    let taken = ctx
+        .prefetched
+        .remove(dir)
+        .or_else(|| ctx.prefetch.as_ref()?.take(dir));
+    let listing = match taken {
+        Some(listing) => listing,
+        None => match ctx
+            .cache
+            .as_mut()
+            .and_then(|c| c.listing(dir, &config.walk))
+        {
+            Some(listing) => listing,
+            None => {
+                let listing = scan_dir(dir, &config.start_dir, &config.walk);
+                if let Some(cache) = &mut ctx.cache {
+                    cache.store(dir, &listing);
+                }
+                listing
+            }
+        },
+    };
+    for (kind, path, err) in &listing.problems {
+        warn(ctx, kind, path, err)?;
+    }
+    let mut out = listing.entries.clone();
+    if let Some(cache) = &ctx.cache {
+        out.retain(|e| !cache.is_cache_file(e));
+    }
+    let skipped = listing.skipped;
+    let listing_len = out.len();
+    if config.walk.jobs > 1 {
+        ctx.prefetched.insert(dir.to_path_buf(), listing);
+    }
+
+    if config.walk.gitignore {
+        let parent = dir
+            .parent()
+            .and_then(|p| ctx.ignore_rules.get(p).cloned())
+            .flatten();
+        let rules = gitignore::Rules::for_dir(dir, parent, config.walk.ignore_case);
+        if let Some(rules) = &rules {
+            out.retain(|e| !rules.is_ignored(&e.path, is_walkable_dir(e)));
+        }
+        ctx.ignore_rules.insert(dir.to_path_buf(), rules);
+    }
+    if config.summary && !ctx.prescan {
+        let ignored = listing_len - out.len();
+        ctx.skipped.insert(dir.to_path_buf(), skipped + ignored);
+    }
+    Ok(out)
+}
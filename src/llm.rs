@@ -0,0 +1,128 @@
+//! `--format llm`: the tree, then every listed file's text in a fenced
+//! block under its root-relative path, for pasting a codebase into a
+//! language model.
+//!
+//! Tokens are estimated at four bytes each, which is close enough for
+//! English and code with the common tokenizers. With `--max-tokens`, files
+//! are taken by priority until the budget is spent: shallow before deep,
+//! then small before large, so a budget keeps the top-level picture (the
+//! README, manifests, entry points) rather than one huge generated file.
+//! Files left out are named at the end, as are binary ones.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::{
+    WalkContext, code_fence, decoded_text, encoding, markdown_language, read_capped, relative_path,
+    warn, write_tree,
+};
+
+/// Bytes per estimated token.
+const BYTES_PER_TOKEN: usize = 4;
+
+fn estimate_tokens(text: &str) -> usize {
+    text.len().div_ceil(BYTES_PER_TOKEN)
+}
+
+struct Block {
+    path: String,
+    depth: usize,
+    text: String,
+}
+
+pub fn write_llm(ctx: &mut WalkContext, writer: &mut dyn Write) -> io::Result<()> {
+    let config = ctx.config;
+    let mut tree = Vec::new();
+    write_tree(ctx, &mut tree)?;
+    let tree = String::from_utf8_lossy(&tree).into_owned();
+
+    let mut blocks = Vec::new();
+    let mut binary = Vec::new();
+    for path in std::mem::take(&mut ctx.bundle) {
+        let rel = relative_path(config, &path, false);
+        match file_block(&path, &rel, ctx) {
+            Ok(Some(text)) => blocks.push(Block {
+                depth: rel.matches('/').count(),
+                path: rel,
+                text,
+            }),
+            Ok(None) => binary.push(rel),
+            Err(err) => warn(ctx, "read_file", &path, &err)?,
+        }
+    }
+
+    let mut budget = config
+        .max_tokens
+        .map(|max| max.saturating_sub(estimate_tokens(&tree)));
+    let mut keep = vec![true; blocks.len()];
+    if let Some(left) = &mut budget {
+        let mut order: Vec<usize> = (0..blocks.len()).collect();
+        order.sort_by(|&a, &b| {
+            let (a, b) = (&blocks[a], &blocks[b]);
+            (a.depth, a.text.len(), &a.path).cmp(&(b.depth, b.text.len(), &b.path))
+        });
+        for idx in order {
+            let cost = estimate_tokens(&blocks[idx].text);
+            if cost <= *left {
+                *left -= cost;
+            } else {
+                keep[idx] = false;
+            }
+        }
+    }
+
+    write!(writer, "{tree}")?;
+    let mut tokens = estimate_tokens(&tree);
+    for (block, _) in blocks.iter().zip(&keep).filter(|(_, keep)| **keep) {
+        write!(writer, "\n{}", block.text)?;
+        tokens += estimate_tokens(&block.text);
+    }
+    let dropped: Vec<&str> = blocks
+        .iter()
+        .zip(&keep)
+        .filter(|(_, keep)| !**keep)
+        .map(|(block, _)| block.path.as_str())
+        .collect();
+    if !dropped.is_empty() {
+        writeln!(writer, "\nLeft out to fit --max-tokens:")?;
+        for path in &dropped {
+            writeln!(writer, "- {path}")?;
+        }
+    }
+    if !binary.is_empty() {
+        writeln!(writer, "\nBinary files, not included:")?;
+        for path in &binary {
+            writeln!(writer, "- {path}")?;
+        }
+    }
+    let files = blocks.len() - dropped.len();
+    let noun = if files == 1 { "file" } else { "files" };
+    writeln!(writer, "\n~{tokens} tokens, {files} {noun}")
+}
+
+/// The `## path` heading and fenced content of one file, or `None` for a
+/// binary file (unless `--include-binary`).
+fn file_block(path: &Path, rel: &str, ctx: &WalkContext) -> io::Result<Option<String>> {
+    let config = ctx.config;
+    let (bytes, more) = read_capped(path, config.max_content_bytes)?;
+    let encoding = encoding::detect(&bytes[..bytes.len().min(encoding::SNIFF_BYTES)]);
+    if encoding == encoding::Encoding::Binary && !config.include_binary {
+        return Ok(None);
+    }
+    let text = decoded_text(config, encoding, &bytes);
+    let fence = code_fence(&text);
+    let language = path
+        .extension()
+        .map(|ext| markdown_language(&ext.to_string_lossy().to_lowercase()))
+        .unwrap_or("");
+    let mut block = format!("## {rel}\n\n{fence}{language}\n{text}");
+    if !text.is_empty() && !text.ends_with('\n') {
+        block.push('\n');
+    }
+    block.push_str(&fence);
+    block.push('\n');
+    if more > 0 {
+        block.push_str(&format!("… truncated, {more} more bytes\n"));
+    }
+    Ok(Some(block))
+}
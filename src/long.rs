@@ -0,0 +1,147 @@
+//! Columns for `--long`: permissions, owner, group and modification time,
+//! printed in front of each tree line.
+//!
+//! Owner and group names come from `/etc/passwd` and `/etc/group`; ids
+//! without an entry there are shown as numbers. Windows has neither, so it
+//! shows the file's attribute flags instead and leaves owner and group
+//! empty. Every column has a fixed width, so the tree glyphs after them
+//! stay aligned; longer names are cut short with `…`.
+
+use std::collections::HashMap;
+use std::fs;
+
+use crate::format_timestamp;
+
+const NAME_WIDTH: usize = 8;
+
+/// Width of [`Owners::columns`], including the space before the tree.
+pub const WIDTH: usize = 10 + 1 + NAME_WIDTH + 1 + NAME_WIDTH + 1 + 16 + 1;
+
+pub struct Owners {
+    users: HashMap<u32, String>,
+    groups: HashMap<u32, String>,
+}
+
+impl Owners {
+    pub fn load() -> Self {
+        Owners {
+            users: read_id_file("/etc/passwd"),
+            groups: read_id_file("/etc/group"),
+        }
+    }
+
+    /// `drwxr-xr-x alice    staff    2024-05-01 12:00 `, or as many spaces
+    /// when the entry can't be stat-ed.
+    pub fn columns(&self, meta: Option<&fs::Metadata>) -> String {
+        let Some(meta) = meta else {
+            return " ".repeat(WIDTH);
+        };
+        let (owner, group) = self.names(meta);
+        let modified = meta
+            .modified()
+            .map(|t| format_timestamp(t)[..16].to_string())
+            .unwrap_or_else(|_| "?".to_string());
+        format!(
+            "{:<10} {} {} {modified:<16} ",
+            mode_string(meta),
+            fit(&owner),
+            fit(&group)
+        )
+    }
+
+    #[cfg(unix)]
+    fn names(&self, meta: &fs::Metadata) -> (String, String) {
+        use std::os::unix::fs::MetadataExt;
+        let lookup = |table: &HashMap<u32, String>, id: u32| {
+            table.get(&id).cloned().unwrap_or_else(|| id.to_string())
+        };
+        (
+            lookup(&self.users, meta.uid()),
+            lookup(&self.groups, meta.gid()),
+        )
+    }
+
+    #[cfg(not(unix))]
+    fn names(&self, _meta: &fs::Metadata) -> (String, String) {
+        (String::new(), String::new())
+    }
+}
+
+/// `name:x:id:...` lines, as in `/etc/passwd` and `/etc/group`.
+fn read_id_file(path: &str) -> HashMap<u32, String> {
+    let Ok(text) = fs::read_to_string(path) else {
+        return HashMap::new();
+    };
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse().ok()?;
+            Some((id, name.to_string()))
+        })
+        .collect()
+}
+
+/// Pads or cuts a name to exactly [`NAME_WIDTH`] columns.
+fn fit(name: &str) -> String {
+    if name.chars().count() > NAME_WIDTH {
+        let cut: String = name.chars().take(NAME_WIDTH - 1).collect();
+        format!("{cut}…")
+    } else {
+        format!("{name:<NAME_WIDTH$}")
+    }
+}
+
+/// `ls -l` style: the type, then read/write/execute for owner, group and
+/// others, with setuid, setgid and sticky bits folded into the `x` slots.
+#[cfg(unix)]
+fn mode_string(meta: &fs::Metadata) -> String {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = meta.permissions().mode();
+    let ft = meta.file_type();
+    let mut out = String::with_capacity(10);
+    out.push(if ft.is_symlink() {
+        'l'
+    } else if ft.is_dir() {
+        'd'
+    } else {
+        '-'
+    });
+    let specials = [(0o4000, 's'), (0o2000, 's'), (0o1000, 't')];
+    for (idx, (special, letter)) in specials.iter().enumerate() {
+        let shift = 6 - 3 * idx;
+        let bits = (mode >> shift) & 0o7;
+        out.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        out.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        out.push(match (bits & 0o1 != 0, mode & special != 0) {
+            (true, true) => *letter,
+            (false, true) => letter.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    out
+}
+
+/// Windows attribute flags: directory, read-only, hidden, system, archive.
+#[cfg(windows)]
+fn mode_string(meta: &fs::Metadata) -> String {
+    use std::os::windows::fs::MetadataExt;
+    let attrs = meta.file_attributes();
+    [(0x10, 'd'), (0x1, 'r'), (0x2, 'h'), (0x4, 's'), (0x20, 'a')]
+        .iter()
+        .map(|&(bit, letter)| if attrs & bit != 0 { letter } else { '-' })
+        .collect()
+}
+
+#[cfg(not(any(unix, windows)))]
+fn mode_string(meta: &fs::Metadata) -> String {
+    let kind = if meta.is_dir() { 'd' } else { '-' };
+    let write = if meta.permissions().readonly() {
+        '-'
+    } else {
+        'w'
+    };
+    format!("{kind}r{write}")
+}
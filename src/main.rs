@@ -1,337 +1,1135 @@
 use std::env;
-use std::fs::{self, File, ReadDir};
-use std::io::{self, BufWriter, Write};
-use std::path::{Path, PathBuf};
-
-struct Config {
-    start_dir: PathBuf,
-    max_depth: Option<usize>,
-    ascii: bool,
-    show_content: bool,
-    to_stdout: bool,
-}
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use folderwalk::{
+    ColorMode, Config, DEFAULT_EXCLUDES, ErrorFormat, ExcludeGlob, IconStyle, OutputFormat,
+    SortKey, config_file_args, config_files, error_json, glob_regex, interactive, run,
+    split_command, watch,
+};
+
+mod cli;
+mod completions;
+
+use cli::Value;
+
+const DEFAULT_RARE_EXTENSION_THRESHOLD: usize = 2;
+/// Cells in a `--bar` given no width.
+const DEFAULT_BAR_WIDTH: usize = 8;
+/// The header before each root's tree when several share one output.
+const DEFAULT_ROOT_SEPARATOR: &str = "=== {} ===";
 
 fn main() {
-    let config = match parse_args() {
-        Ok(c) => c,
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "completions") {
+        match args.get(1).and_then(|shell| completions::script(shell)) {
+            Some(script) => print!("{script}"),
+            None => {
+                eprintln!(
+                    "Error: completions requires a shell: {}",
+                    cli::SHELLS.join(", ")
+                );
+                std::process::exit(2);
+            }
+        }
+        return;
+    }
+
+    let (config, roots) = match parse_args() {
+        Ok(parsed) => parsed,
         Err(e) => {
-            eprintln!("Error: {e}");
-            print_usage();
+            if requested_error_format() == ErrorFormat::Json {
+                eprintln!("{}", error_json("usage", None, &e));
+            } else {
+                eprintln!("Error: {e}");
+                print_usage();
+            }
             std::process::exit(2);
         }
     };
 
-    if let Err(e) = run(&config) {
-        eprintln!("Failed: {e}");
-        std::process::exit(1);
-    }
-}
-
-fn parse_args() -> Result<Config, String> {
-    let mut args = env::args().skip(1);
-
-    let mut start_dir: Option<PathBuf> = None;
-    let mut max_depth: Option<usize> = None;
-    let mut ascii = false;
-    let mut show_content = false;
-    let mut to_stdout = false;
-
-    while let Some(arg) = args.next() {
-        match arg.as_str() {
-            "--max-depth" => {
-                let v = args
-                    .next()
-                    .ok_or_else(|| "--max-depth requires a value".to_string())?;
-                let d: usize = v
-                    .parse()
-                    .map_err(|_| "Invalid --max-depth value".to_string())?;
-                max_depth = Some(d);
-            }
-            "--ascii" => {
-                ascii = true;
-            }
-            "--content" | "-c" => {
-                show_content = true;
-            }
-            "--stdout" | "-o" => {
-                to_stdout = true;
-            }
-            "--help" | "-h" => {
-                print_usage();
-                std::process::exit(0);
+    let mut failed = false;
+    let mut partial = false;
+    let mut printed_any = false;
+    let several = roots.len() > 1;
+    for root in roots {
+        // Trees that end up in one output (stdout, or one --output) are
+        // told apart by a header naming their root.
+        let shared = several && (config.to_stdout || config.output.is_some());
+        let root_header = config
+            .root_header
+            .as_deref()
+            .filter(|template| shared && !template.is_empty())
+            .map(|template| template.replace("{}", &root.to_string_lossy()));
+        let config = Config {
+            start_dir: root,
+            // Several roots with one --output share the file.
+            append_output: printed_any && config.output.is_some(),
+            root_header,
+            ..config.clone()
+        };
+        // Checked before the header goes out, so a root that doesn't exist
+        // gets no tree heading.
+        let stat = match config.tree_from_json {
+            Some(_) => None,
+            None => Some(fs::metadata(&config.start_dir)),
+        };
+        match stat {
+            Some(Err(e)) => {
+                report_failure(&config, &e);
+                failed = true;
+                continue;
             }
-            _ if arg.starts_with('-') => {
-                return Err(format!("Unknown flag: {arg}"));
+            Some(Ok(meta)) if !meta.is_dir() => {
+                // A file among several roots is skipped.
+                let message = "not a directory, skipped";
+                match config.error_format {
+                    ErrorFormat::Json => eprintln!(
+                        "{}",
+                        error_json("not_a_directory", Some(&config.start_dir), message)
+                    ),
+                    ErrorFormat::Text => {
+                        eprintln!("Warning: {}: {message}", config.start_dir.to_string_lossy())
+                    }
+                }
+                continue;
             }
-            _ => {
-                if start_dir.is_none() {
-                    start_dir = Some(PathBuf::from(arg));
-                } else {
-                    return Err("Only one path argument is allowed".to_string());
+            _ => {}
+        }
+        if config.to_stdout && printed_any {
+            println!();
+        }
+        if config.to_stdout
+            && let Some(header) = &config.root_header
+        {
+            println!("{header}");
+        }
+        printed_any = true;
+        if config.interactive {
+            if let Err(e) = interactive(&config) {
+                report_failure(&config, &e);
+                failed = true;
+            }
+            continue;
+        }
+        if config.watch {
+            if config.error_format == ErrorFormat::Text {
+                eprintln!(
+                    "Watching {} for changes (Ctrl-C to stop)",
+                    config.start_dir.to_string_lossy()
+                );
+            }
+            let watched = watch(&config, |result| {
+                if let Err(e) = result {
+                    report_failure(&config, &e);
                 }
+            });
+            if let Err(e) = watched {
+                report_failure(&config, &e);
+                failed = true;
+            }
+            continue;
+        }
+        match run(&config) {
+            Ok(problems) => partial |= problems > 0,
+            Err(e) => {
+                report_failure(&config, &e);
+                failed = true;
             }
         }
     }
-
-    let start_dir = start_dir.unwrap_or_else(|| env::current_dir().unwrap_or_else(|_| ".".into()));
-    Ok(Config {
-        start_dir,
-        max_depth,
-        ascii,
-        show_content,
-        to_stdout,
-    })
-}
-
-fn print_usage() {
-    eprintln!(
-        "Usage: folderwalk [path] [--max-depth N] [--ascii] [--content] [--stdout]
-  - path:         directory to scan (default: current directory)
-  - --max-depth N: limit recursion depth
-  - --ascii:      use ASCII tree characters instead of Unicode
-  - --content, -c: include file contents
-  - --stdout, -o: output to stdout instead of files.txt
-Output: files.txt is created in the target directory unless --stdout is used."
-    );
+    if failed {
+        std::process::exit(1);
+    }
+    if partial {
+        // The output is there, but some entries couldn't be read.
+        std::process::exit(3);
+    }
 }
 
-fn run(config: &Config) -> io::Result<()> {
-    let start_meta = fs::metadata(&config.start_dir)?;
-    if !start_meta.is_dir() {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            format!(
-                "Path is not a directory: {}",
-                config.start_dir.to_string_lossy()
-            ),
-        ));
+fn report_failure(config: &Config, e: &std::io::Error) {
+    match config.error_format {
+        ErrorFormat::Json => eprintln!(
+            "{}",
+            error_json("fatal", Some(&config.start_dir), &e.to_string())
+        ),
+        ErrorFormat::Text => eprintln!("Failed: {e}"),
     }
+}
 
-    let output_path = config.start_dir.join("files.txt");
-
-    let mut writer: Box<dyn Write> = if config.to_stdout {
-        Box::new(io::stdout())
+/// Parses `START:END`, `START:` or `:END` (1-based, inclusive).
+fn parse_line_range(v: &str) -> Option<(usize, Option<usize>)> {
+    let (start, end) = v.split_once(':')?;
+    let start = if start.is_empty() {
+        1
     } else {
-        let outfile = File::create(&output_path)?;
-        Box::new(BufWriter::with_capacity(128 * 1024, outfile))
+        start.parse().ok().filter(|&n| n > 0)?
     };
-
-    writeln!(
-        writer,
-        "{}",
-        display_root_name(&config.start_dir).unwrap_or_else(|| ".".to_string())
-    )?;
-
-    walk_dir(
-        &config.start_dir,
-        if config.to_stdout { None } else { Some(&output_path) },
-        &mut *writer,
-        "",
-        0,
-        config.max_depth,
-        config.ascii,
-        config.show_content,
-        true,
-    )?;
-
-    writer.flush()?;
-    Ok(())
+    let end = if end.is_empty() {
+        None
+    } else {
+        Some(end.parse().ok().filter(|&n| n >= start)?)
+    };
+    Some((start, end))
 }
 
-fn display_root_name(p: &Path) -> Option<String> {
-    p.file_name()
-        .map(|s| s.to_string_lossy().to_string())
-        .or_else(|| Some(p.to_string_lossy().to_string()))
+/// Parses a byte count with an optional binary suffix: `512`, `10K`,
+/// `5M`, `1.5G` (also `KB`/`KiB` and lowercase forms).
+fn parse_size(v: &str) -> Option<u64> {
+    let v = v.trim();
+    let split = v
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(v.len());
+    let (number, suffix) = v.split_at(split);
+    let shift = match suffix.to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 10,
+        "m" | "mb" | "mib" => 20,
+        "g" | "gb" | "gib" => 30,
+        "t" | "tb" | "tib" => 40,
+        _ => return None,
+    };
+    if let Ok(n) = number.parse::<u64>() {
+        return n.checked_mul(1 << shift);
+    }
+    let n: f64 = number.parse().ok()?;
+    (n.is_finite() && n >= 0.0).then(|| (n * (1u64 << shift) as f64) as u64)
 }
 
-fn walk_dir(
-    dir: &Path,
-    output_path: Option<&Path>,
-    writer: &mut dyn Write,
-    prefix: &str,
-    depth: usize,
-    max_depth: Option<usize>,
-    ascii: bool,
-    show_content: bool,
-    is_root: bool,
-) -> io::Result<()> {
-    if let Some(maxd) = max_depth {
-        if depth >= maxd {
-            return Ok(());
-        }
+/// Parses `--newer-than`/`--older-than`: a duration before now (`90s`,
+/// `15m`, `2h`, `2d`, `3w`) or a UTC date, `YYYY-MM-DD` optionally followed
+/// by `HH:MM[:SS]` (after a space or `T`).
+fn parse_time_point(v: &str, now: SystemTime) -> Option<SystemTime> {
+    let v = v.trim();
+    if let Some(unit) = v.chars().last().filter(|c| c.is_ascii_alphabetic())
+        && let Ok(n) = v[..v.len() - 1].parse::<u64>()
+    {
+        let secs = match unit {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86_400,
+            'w' => 7 * 86_400,
+            _ => return None,
+        };
+        return now.checked_sub(Duration::from_secs(n.checked_mul(secs)?));
     }
 
-    let mut entries = read_dir_entries(dir)?;
-    if let Some(out_path) = output_path {
-        entries.retain(|e| e.path != out_path);
+    let (date, time) = match v.split_once([' ', 'T']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (v, None),
+    };
+    let mut parts = date.splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
     }
-
-    entries.sort_by(|a, b| {
-        let ad = a.file_type.is_dir();
-        let bd = b.file_type.is_dir();
-        match ad.cmp(&bd).reverse() {
-            std::cmp::Ordering::Equal => {
-                let an = a.file_name.to_string_lossy().to_lowercase();
-                let bn = b.file_name.to_string_lossy().to_lowercase();
-                an.cmp(&bn)
-            }
-            other => other,
+    let mut secs_of_day = 0;
+    if let Some(time) = time {
+        let fields: Vec<i64> = time
+            .split(':')
+            .map(|f| f.parse().ok())
+            .collect::<Option<_>>()?;
+        let (h, m, sec) = match fields[..] {
+            [h, m] => (h, m, 0),
+            [h, m, sec] => (h, m, sec),
+            _ => return None,
+        };
+        if !(0..24).contains(&h) || !(0..60).contains(&m) || !(0..60).contains(&sec) {
+            return None;
         }
-    });
+        secs_of_day = h * 3600 + m * 60 + sec;
+    }
+    // Days-from-civil (Howard Hinnant's algorithm).
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    let secs = days * 86_400 + secs_of_day;
+    if secs >= 0 {
+        SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(secs as u64))
+    } else {
+        SystemTime::UNIX_EPOCH.checked_sub(Duration::from_secs(secs.unsigned_abs()))
+    }
+}
 
-    let (tee, elbow, pipe, space) = if ascii {
-        ("|-- ", "`-- ", "|   ", "    ")
+/// Looks for `--error-format json` in the raw arguments, so that errors
+/// raised while parsing the rest of the command line honour it too.
+fn requested_error_format() -> ErrorFormat {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let json = args
+        .windows(2)
+        .any(|w| w[0] == "--error-format" && w[1] == "json");
+    if json {
+        ErrorFormat::Json
     } else {
-        ("├── ", "└── ", "│   ", "    ")
-    };
+        ErrorFormat::Text
+    }
+}
 
-    for (idx, entry) in entries.iter().enumerate() {
-        let is_last = idx == entries.len().saturating_sub(1);
-        let branch = if is_last { elbow } else { tee };
+/// Returns the options shared by every root, and the roots to scan in order.
+/// The `folderwalk.toml` files for the first root supply defaults that the
+/// command line overrides; see `config_file`.
+fn parse_args() -> Result<(Config, Vec<PathBuf>), String> {
+    let cli = cli::expand_subcommand(env::args().skip(1).collect())?;
+    let (config, mut roots) = parse_arg_list(cli.clone())?;
+    if roots.is_empty() {
+        roots.push(env::current_dir().unwrap_or_else(|_| ".".into()));
+    }
+    if cli.iter().any(|arg| arg == "--no-config") || config.tree_from_json.is_some() {
+        return Ok((config, roots));
+    }
 
-        let mut name = entry.file_name.to_string_lossy().to_string();
-        if entry.file_type.is_dir() {
-            name.push('/');
+    let mut args = Vec::new();
+    for path in config_files(&roots[0]) {
+        let in_file = |e: String| format!("{}: {e}", path.to_string_lossy());
+        let file_args = config_file_args(&path).map_err(|e| in_file(e.to_string()))?;
+        let (_, stray) = parse_arg_list(file_args.clone()).map_err(in_file)?;
+        if let Some(value) = stray.first() {
+            let key = file_args
+                .iter()
+                .position(|arg| value.as_os_str() == arg.as_str())
+                .and_then(|pos| pos.checked_sub(1))
+                .map(|pos| file_args[pos].trim_start_matches('-').replace('-', "_"))
+                .unwrap_or_default();
+            return Err(in_file(format!(
+                "`{key}` is a switch; set it to true or false"
+            )));
         }
+        args.extend(file_args);
+    }
+    if args.is_empty() {
+        return Ok((config, roots));
+    }
+    args.extend(cli);
+    let (config, _) = parse_arg_list(args)?;
+    Ok((config, roots))
+}
 
-        let display_name = if entry.file_type.is_symlink() {
-            match fs::read_link(&entry.path) {
-                Ok(target) => format!("{name} -> {}", target.to_string_lossy()),
-                Err(_) => format!("{name} -> <unreadable>"),
+/// Parses one argument list. The roots are left empty when none are given.
+fn parse_arg_list(args: Vec<String>) -> Result<(Config, Vec<PathBuf>), String> {
+    let mut args = args.into_iter().peekable();
+    let mut parsed = Parsed::new();
+    while let Some(arg) = args.next() {
+        if arg == "--help" || arg == "-h" {
+            print_usage();
+            std::process::exit(0);
+        }
+        let Some(flag) = cli::flag(&arg) else {
+            if arg.starts_with('-') {
+                return Err(format!("Unknown flag: {arg}"));
             }
-        } else {
-            name
+            parsed.roots.push(PathBuf::from(arg));
+            continue;
         };
+        let value = match flag.value {
+            Value::None => None,
+            Value::Any(placeholder) | Value::File(placeholder) | Value::OneOf(placeholder, _) => {
+                Some(
+                    args.next()
+                        .ok_or_else(|| format!("{arg} requires {placeholder}"))?,
+                )
+            }
+            // The value is optional; a following argument is only taken
+            // when it is one.
+            Value::OptionalNumber(_) => args.next_if(|v| v.parse::<usize>().is_ok()),
+            Value::OptionalOneOf(_, words) => args.next_if(|v| words.contains(&v.as_str())),
+        };
+        parsed.set(flag.name, value.as_deref())?;
+    }
+    validate(parsed)
+}
+
+/// One argument list as given, before its options are checked against each
+/// other: the [`Config`] fields each flag sets, and what can only be
+/// compiled once every flag is known.
+struct Parsed {
+    config: Config,
+    roots: Vec<PathBuf>,
+    snapshot: Option<PathBuf>,
+    path_regex: Option<String>,
+    grep: Option<String>,
+    match_globs: Vec<String>,
+    ignore_globs: Vec<String>,
+    excludes: Vec<String>,
+    default_excludes: bool,
+}
 
-        if !is_root {
-            writeln!(writer, "{prefix}{branch}{display_name}")?;
-        } else {
-            writeln!(writer, "{branch}{display_name}")?;
+impl Parsed {
+    fn new() -> Self {
+        Parsed {
+            config: Config {
+                exec_parallel: 1,
+                root_header: Some(DEFAULT_ROOT_SEPARATOR.to_string()),
+                ..Config::default()
+            },
+            roots: Vec::new(),
+            snapshot: None,
+            path_regex: None,
+            grep: None,
+            match_globs: Vec::new(),
+            ignore_globs: Vec::new(),
+            excludes: Vec::new(),
+            default_excludes: true,
         }
+    }
 
-        if entry.file_type.is_file() && show_content {
-            match fs::read_to_string(&entry.path) {
-                Ok(content) => {
-                    writeln!(writer, "{prefix}    --- FILE CONTENT START ---")?;
-                    for line in content.lines() {
-                        writeln!(writer, "{prefix}    {}", line)?;
-                    }
-                    writeln!(writer, "{prefix}    --- FILE CONTENT END ---")?;
+    /// Applies the flag named `name` in [`cli::FLAGS`], with the value that
+    /// followed it if it takes one.
+    fn set(&mut self, name: &str, value: Option<&str>) -> Result<(), String> {
+        let c = &mut self.config;
+        let v = value.unwrap_or_default();
+        match name {
+            "--max-depth" => c.walk.max_depth = Some(number(name, v)?),
+            "--no-depth-marker" => c.silent_max_depth = true,
+            "--jobs" => c.walk.jobs = positive(name, v)?,
+            "--ascii" => c.ascii = true,
+            "--content" => c.show_content = true,
+            "--stdout" => c.to_stdout = true,
+            "--output" => c.output = Some(PathBuf::from(v)),
+            "--gzip" => c.gzip = true,
+            "--split-size" => {
+                let n = parse_size(v).filter(|&n| n > 0).ok_or_else(|| {
+                    format!("Invalid --split-size value: {v} (e.g. 512K, 10M, 1G)")
+                })?;
+                c.split_size = Some(n);
+            }
+            "--root-separator" => c.root_header = Some(v.to_string()),
+            "--watch" => c.watch = true,
+            "--interactive" => c.interactive = true,
+            "--exec" => c.exec = Some(split_command(v)?),
+            "--exec-parallel" => c.exec_parallel = positive(name, v)?,
+            "--dirs-last" => c.walk.dirs_last = true,
+            "--color" => {
+                c.color = match v {
+                    "auto" => ColorMode::Auto,
+                    "always" => ColorMode::Always,
+                    "never" => ColorMode::Never,
+                    _ => return Err(format!("Invalid --color value: {v}")),
                 }
-                Err(err) => {
-                    writeln!(writer, "{prefix}    [Could not read file: {}]", err)?;
+            }
+            "--error-format" => {
+                c.error_format = match v {
+                    "text" => ErrorFormat::Text,
+                    "json" => ErrorFormat::Json,
+                    _ => return Err(format!("Invalid --error-format value: {v}")),
                 }
             }
-        }
-
-        if entry.file_type.is_dir() && !entry.is_symlink_dir {
-            let new_prefix = if is_last {
-                if is_root {
-                    space.to_string()
+            "--count-hidden-separately" => c.count_hidden = true,
+            "--summary" => c.summary = true,
+            "--stats" => c.stats = true,
+            "--flatten-below" => c.flatten_below = Some(number(name, v)?),
+            "--json" => c.format = OutputFormat::Json,
+            "--markdown" => c.format = OutputFormat::Markdown,
+            "--relative-names" => c.relative_names = true,
+            "--format" => {
+                c.format = match v {
+                    "tree" => OutputFormat::Tree,
+                    "shasum" => OutputFormat::Shasum,
+                    "json-tree" => OutputFormat::JsonTree,
+                    "json" => OutputFormat::Json,
+                    "markdown" => OutputFormat::Markdown,
+                    "html" => OutputFormat::Html,
+                    "llm" => OutputFormat::Llm,
+                    "csv" => OutputFormat::Csv,
+                    "tsv" => OutputFormat::Tsv,
+                    "dot" => OutputFormat::Dot,
+                    "yaml" | "yml" => OutputFormat::Yaml,
+                    "delete-list" => OutputFormat::DeleteList,
+                    _ => return Err(format!("Invalid --format value: {v}")),
+                }
+            }
+            "--bundle" => c.format = OutputFormat::Llm,
+            "--max-tokens" => c.max_tokens = Some(number(name, v)?),
+            "--progress" => c.progress = true,
+            "--progress-file" => c.progress_file = Some(PathBuf::from(v)),
+            "--symlinks-as-files" => c.walk.symlinks_as_files = true,
+            "--follow-symlinks" => c.walk.follow_symlinks = true,
+            "--content-max-line-length" => c.content_max_line_length = Some(number(name, v)?),
+            "--max-content-bytes" => c.max_content_bytes = Some(number(name, v)?),
+            "--content-lines" => {
+                let range = parse_line_range(v)
+                    .ok_or_else(|| format!("Invalid --content-lines range: {v}"))?;
+                c.content_lines = Some(range);
+            }
+            "--content-depth" => {
+                c.show_content = true;
+                c.content_depth = Some(number(name, v)?);
+            }
+            "--line-numbers" => c.line_numbers = true,
+            "--size" => c.show_size = true,
+            "--long" => c.long = true,
+            "--du" => c.du = true,
+            "--bar" => {
+                let n = value.map_or(Ok(DEFAULT_BAR_WIDTH), |v| number(name, v))?;
+                if n == 0 {
+                    return Err("--bar must be at least 1 cell wide".to_string());
+                }
+                c.bar = Some(n);
+            }
+            "--bytes" => c.size_bytes = true,
+            "--include-binary" => c.include_binary = true,
+            "--clipboard" => c.clipboard = true,
+            "--age-histogram" => c.age_histogram = true,
+            "--canonical" => c.canonical = true,
+            "--show-fs" => c.show_fs = true,
+            "--fast" => c.walk.fast = true,
+            "--path-regex" => self.path_regex = Some(v.to_string()),
+            "--prune" => c.walk.prune = true,
+            "--collapse" => c.collapse = true,
+            "--bfs" => c.bfs = true,
+            "--sort" => {
+                c.walk.sort = match v {
+                    "name" => SortKey::Name,
+                    "hash" => SortKey::Hash,
+                    "created" => SortKey::Created,
+                    "size" => SortKey::Size,
+                    "mtime" => SortKey::Mtime,
+                    "ext" => SortKey::Ext,
+                    "none" => SortKey::None,
+                    _ => return Err(format!("Invalid --sort value: {v}")),
+                }
+            }
+            "--reverse" => c.walk.reverse = true,
+            "--natural-sort" => c.walk.natural_sort = true,
+            "--locale-sort" => c.walk.locale_sort = true,
+            "--no-group-dirs" => c.walk.group_dirs = false,
+            "--defer-errors" => c.defer_errors = true,
+            "--errors-section" => c.errors_section = true,
+            "--strict" => c.strict = true,
+            "--quiet" => c.quiet = true,
+            "--content-changed-only" => {
+                c.show_content = true;
+                c.content_changed_only = true;
+            }
+            "--depth-markers" => c.depth_markers = true,
+            "--detect-encoding" => c.detect_encoding = true,
+            "--verify" => c.verify = true,
+            "--context-depth" => c.walk.context_depth = Some(number(name, v)?),
+            "--ignore-case" => c.walk.ignore_case = true,
+            "--relative-time" => c.relative_time = true,
+            "--strip-components" => c.strip_components = number(name, v)?,
+            "--created" => c.show_created = true,
+            "--content-base64" => {
+                c.show_content = true;
+                c.content_base64 = true;
+            }
+            "--skip-loops" => c.walk.skip_loops = true,
+            "--one-file-system" => c.walk.one_file_system = true,
+            "--tree-from-json" => c.tree_from_json = Some(PathBuf::from(v)),
+            "--snapshot" => self.snapshot = Some(PathBuf::from(v)),
+            // SHA-256 is the only algorithm; naming it is optional.
+            "--hash" => c.hash_files = true,
+            "--find-duplicates" => c.find_duplicates = true,
+            "--archives" => c.archives = true,
+            "--git-status" => c.git_status = true,
+            "--diff" => c.diff = Some(PathBuf::from(v)),
+            "--count-extensions-threshold" => {
+                let n = value.map_or(Ok(DEFAULT_RARE_EXTENSION_THRESHOLD), |v| number(name, v))?;
+                if n == 0 {
+                    return Err("--count-extensions-threshold must be at least 1".to_string());
+                }
+                c.rare_extension_threshold = Some(n);
+            }
+            "--width" => c.render_width = Some(number(name, v)?),
+            "--executables" => {
+                c.walk.executables = true;
+                c.walk.prune = true;
+            }
+            "--dirs-only" => c.walk.dirs_only = true,
+            "--files-only" => {
+                c.walk.files_only = true;
+                c.walk.prune = true;
+            }
+            "--min-size" | "--max-size" => {
+                let n = parse_size(v)
+                    .ok_or_else(|| format!("Invalid {name} value: {v} (e.g. 512, 10K, 5M, 1G)"))?;
+                if name == "--min-size" {
+                    c.walk.min_size = Some(n);
                 } else {
-                    format!("{prefix}{space}")
+                    c.walk.max_size = Some(n);
                 }
-            } else {
-                if is_root {
-                    pipe.to_string()
+                c.walk.prune = true;
+            }
+            "--newer-than" | "--older-than" => {
+                let t = parse_time_point(v, SystemTime::now()).ok_or_else(|| {
+                    format!("Invalid {name} value: {v} (e.g. 2d, 12h, 2024-05-01)")
+                })?;
+                if name == "--newer-than" {
+                    c.walk.newer_than = Some(t);
                 } else {
-                    format!("{prefix}{pipe}")
+                    c.walk.older_than = Some(t);
                 }
-            };
-            walk_dir(
-                &entry.path,
-                output_path,
-                writer,
-                &new_prefix,
-                depth + 1,
-                max_depth,
-                ascii,
-                show_content,
-                false,
-            )?;
+                c.walk.prune = true;
+            }
+            "--only-empty" => {
+                c.walk.only_empty = true;
+                c.walk.prune = true;
+            }
+            "--grep" => self.grep = Some(v.to_string()),
+            "--grep-context" => c.grep_context = Some(number(name, v)?),
+            "--stats-json" => c.stats_json = Some(PathBuf::from(v)),
+            "--exclude" => self.excludes.extend(
+                v.split(',')
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string),
+            ),
+            "--exclude-from" => {
+                let text = std::fs::read_to_string(v)
+                    .map_err(|e| format!("Cannot read --exclude-from {v}: {e}"))?;
+                self.excludes.extend(
+                    text.lines()
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                        .map(str::to_string),
+                );
+            }
+            "--no-default-excludes" => self.default_excludes = false,
+            "--gitignore" => c.walk.gitignore = true,
+            "--match" => self.match_globs.push(v.to_string()),
+            "--include" => {
+                self.match_globs.push(v.to_string());
+                c.walk.prune = true;
+            }
+            "--ext" => {
+                self.match_globs.extend(
+                    v.split(',')
+                        .map(|ext| ext.trim_start_matches('.'))
+                        .filter(|ext| !ext.is_empty())
+                        .map(|ext| format!("*.{ext}")),
+                );
+                c.walk.prune = true;
+            }
+            "--ignore" => self.ignore_globs.push(v.to_string()),
+            "--all" => c.walk.all = true,
+            "--no-hidden" => c.walk.all = false,
+            // Read in `parse_args`, before the config files.
+            "--no-config" => {}
+            "--icons" => {
+                c.icons = Some(match value {
+                    Some("nerd") => IconStyle::Nerd,
+                    _ => IconStyle::Emoji,
+                })
+            }
+            "--kind" => {
+                let (ext, kind) = key_value(v).ok_or_else(|| {
+                    format!("Invalid --kind value: {v} (expected EXT=KIND, e.g. proto=code)")
+                })?;
+                let ext = ext.trim_start_matches('.').to_lowercase();
+                c.file_kinds.push((ext, kind.to_string()));
+            }
+            "--icon" => {
+                let (kind, icon) = key_value(v)
+                    .ok_or_else(|| format!("Invalid --icon value: {v} (expected KIND=ICON)"))?;
+                c.kind_icons.push((kind.to_string(), icon.to_string()));
+            }
+            "--cache" => c.cache = Some(PathBuf::from(v)),
+            "--repeat" => c.repeat = Some(positive(name, v)?),
+            _ => unreachable!("{name} is in cli::FLAGS but not handled"),
         }
+        Ok(())
     }
+}
+
+/// `v` as the number `flag` takes.
+fn number<T: FromStr>(flag: &str, v: &str) -> Result<T, String> {
+    v.parse().map_err(|_| format!("Invalid {flag} value: {v}"))
+}
 
-    Ok(())
+/// `v` as the count `flag` takes, which can't be 0.
+fn positive(flag: &str, v: &str) -> Result<usize, String> {
+    number(flag, v)
+        .ok()
+        .filter(|&n| n > 0)
+        .ok_or_else(|| format!("Invalid {flag} value: {v}"))
 }
 
-struct DirEntryInfo {
-    path: PathBuf,
-    file_name: std::ffi::OsString,
-    file_type: fs::FileType,
-    is_symlink_dir: bool,
+/// `KEY=VALUE`, neither side empty.
+fn key_value(v: &str) -> Option<(&str, &str)> {
+    v.split_once('=')
+        .filter(|(key, value)| !key.is_empty() && !value.is_empty())
 }
 
-fn read_dir_entries(dir: &Path) -> io::Result<Vec<DirEntryInfo>> {
-    let rd: ReadDir = match fs::read_dir(dir) {
-        Ok(rd) => rd,
-        Err(err) => {
-            eprintln!(
-                "Warning: cannot read directory {}: {}",
-                dir.to_string_lossy(),
-                err
+/// Settles what the flags imply for each other, refuses combinations that
+/// can't work together, and compiles the patterns, giving the options and
+/// the roots to scan.
+fn validate(parsed: Parsed) -> Result<(Config, Vec<PathBuf>), String> {
+    let Parsed {
+        config: mut c,
+        roots,
+        snapshot,
+        path_regex,
+        grep,
+        match_globs,
+        ignore_globs,
+        excludes,
+        default_excludes,
+    } = parsed;
+
+    // The bar stands next to the totals it is drawn from.
+    c.du |= c.bar.is_some();
+    // `--bytes` alone shows every entry's size; with `--du`, only totals.
+    c.show_size |= c.size_bytes && !c.du;
+
+    // `--snapshot PATH` is `--format json-tree --output PATH`.
+    if let Some(path) = snapshot {
+        if c.output.is_some() || c.to_stdout || c.clipboard || c.gzip || c.split_size.is_some() {
+            return Err(
+                "--snapshot writes to its own path; it cannot be combined with --output, \
+                 --stdout, --clipboard, --gzip or --split-size"
+                    .to_string(),
             );
-            return Ok(Vec::new());
         }
-    };
-
-    let mut out = Vec::with_capacity(64);
-    for res in rd {
-        match res {
-            Ok(de) => {
-                let sy_meta = match fs::symlink_metadata(de.path()) {
-                    Ok(m) => m,
-                    Err(err) => {
-                        eprintln!(
-                            "Warning: cannot stat {}: {}",
-                            de.path().to_string_lossy(),
-                            err
-                        );
-                        continue;
-                    }
-                };
-                let ft = sy_meta.file_type();
-                let is_symlink = ft.is_symlink();
+        if !matches!(c.format, OutputFormat::Tree | OutputFormat::JsonTree) {
+            return Err("--snapshot always writes --format json-tree".to_string());
+        }
+        c.format = OutputFormat::JsonTree;
+        c.output = Some(path);
+    }
+    if c.hash_files && !matches!(c.format, OutputFormat::Tree | OutputFormat::JsonTree) {
+        return Err("--hash only applies to tree and json-tree output".to_string());
+    }
+    if c.find_duplicates && c.format != OutputFormat::Tree {
+        return Err("--find-duplicates only applies to tree output".to_string());
+    }
+    if c.git_status && (c.format != OutputFormat::Tree || c.tree_from_json.is_some()) {
+        return Err("--git-status only applies to tree output of a walk".to_string());
+    }
+    if c.archives && (c.format != OutputFormat::Tree || c.tree_from_json.is_some()) {
+        return Err("--archives only applies to tree output of a walk".to_string());
+    }
+    if c.bfs
+        && (!matches!(c.format, OutputFormat::Tree | OutputFormat::Llm)
+            || c.tree_from_json.is_some())
+    {
+        return Err("--bfs only applies to tree output of a walk".to_string());
+    }
+    if c.bfs && (c.collapse || c.flatten_below.is_some() || c.archives || c.interactive) {
+        return Err(
+            "--bfs lists each level on its own; it cannot be combined with --collapse, \
+             --flatten-below, --archives or --interactive"
+                .to_string(),
+        );
+    }
+    if c.diff.is_some()
+        && (c.format != OutputFormat::Tree
+            || c.show_content
+            || c.output.is_some()
+            || c.clipboard
+            || c.tree_from_json.is_some()
+            || c.repeat.is_some()
+            || c.watch)
+    {
+        return Err(
+            "--diff prints its report to stdout; it cannot be combined with --format, \
+             --content, --output, --snapshot, --clipboard, --tree-from-json, --repeat or \
+             --watch"
+                .to_string(),
+        );
+    }
 
-                let file_type = if is_symlink {
-                    ft
-                } else {
-                    match fs::metadata(de.path()) {
-                        Ok(m) => m.file_type(),
-                        Err(_) => ft,
-                    }
-                };
+    // Patterns are compiled once every flag is known, so --ignore-case
+    // applies regardless of where it appears on the command line.
+    let ignore_case = c.walk.ignore_case;
+    let compile_regex = |src: Option<String>, flag: &str| match src {
+        Some(src) => regex::RegexBuilder::new(&src)
+            .case_insensitive(ignore_case)
+            .build()
+            .map(Some)
+            .map_err(|e| format!("Invalid {flag} pattern: {e}")),
+        None => Ok(None),
+    };
+    c.walk.path_regex = compile_regex(path_regex, "--path-regex")?;
+    c.walk.grep = compile_regex(grep, "--grep")?;
+    let compile_globs = |globs: Vec<String>, flag: &str| {
+        globs
+            .iter()
+            .map(|g| {
+                glob_regex(g, ignore_case).map_err(|e| format!("Invalid {flag} glob {g}: {e}"))
+            })
+            .collect::<Result<Vec<_>, _>>()
+    };
+    c.walk.name_matches = compile_globs(match_globs, "--match")?;
+    c.walk.name_ignores = compile_globs(ignore_globs, "--ignore")?;
 
-                let is_symlink_dir =
-                    is_symlink && fs::metadata(de.path()).map(|m| m.is_dir()).unwrap_or(false);
+    let w = &c.walk;
+    if w.context_depth.is_some() && !w.filters_files() {
+        return Err("--context-depth needs a filter such as --path-regex".to_string());
+    }
+    if w.dirs_only && w.filters_files() {
+        return Err(
+            "--dirs-only lists no files; it cannot be combined with --files-only or file \
+             filters (--path-regex, --grep, --match, --include, --ext, --executables, \
+             --min-size, --max-size, --newer-than, --older-than, --only-empty)"
+                .to_string(),
+        );
+    }
+    if w.fast
+        && (c.age_histogram
+            || c.show_fs
+            || c.relative_time
+            || c.show_created
+            || matches!(w.sort, SortKey::Created | SortKey::Mtime | SortKey::Size)
+            || w.executables
+            || c.show_size
+            || c.du
+            || c.long
+            || c.stats
+            || w.follow_symlinks
+            || w.min_size.is_some()
+            || w.max_size.is_some()
+            || w.newer_than.is_some()
+            || w.older_than.is_some()
+            || w.only_empty)
+    {
+        return Err(
+            "--fast cannot be combined with options that need file metadata \
+             (--age-histogram, --show-fs, --relative-time, --created, --sort created|mtime|size, \
+             --executables, --size, --du, --bar, --long, --stats, --follow-symlinks, \
+             --min-size, --max-size, --newer-than, --older-than, --only-empty)"
+                .to_string(),
+        );
+    }
+    if c.format == OutputFormat::DeleteList && !w.filters_files() {
+        return Err(
+            "--format delete-list needs a filter to pick what to delete, such as --only-empty \
+             or --older-than; it won't list a whole tree"
+                .to_string(),
+        );
+    }
+    if c.format == OutputFormat::DeleteList
+        && (w.follow_symlinks
+            || w.context_depth.is_some()
+            || c.show_content
+            || c.grep_context.is_some())
+    {
+        return Err(
+            "--format delete-list lists only what the filters picked, below the root; it \
+             cannot be combined with --follow-symlinks, --context-depth, --content or \
+             --grep-context"
+                .to_string(),
+        );
+    }
+    if c.grep_context.is_some() && w.grep.is_none() {
+        return Err("--grep-context needs --grep".to_string());
+    }
+    // Matching lines are shown under each file, whether or not --content
+    // was asked for.
+    c.show_content |= c.grep_context.is_some();
+    let rewrites_lines =
+        c.content_base64 || c.grep_context.is_some() || c.content_max_line_length.is_some();
+    if (c.content_lines.is_some() || c.line_numbers) && rewrites_lines {
+        return Err(
+            "--content-lines and --line-numbers cannot be combined with --content-base64, \
+             --grep-context or --content-max-line-length"
+                .to_string(),
+        );
+    }
+    if c.format == OutputFormat::Markdown && rewrites_lines {
+        return Err(
+            "--markdown shows file contents as fenced code blocks; it cannot be combined with \
+             --content-base64, --grep-context or --content-max-line-length"
+                .to_string(),
+        );
+    }
+    if c.format == OutputFormat::Html && rewrites_lines {
+        return Err(
+            "--format html cannot be combined with --content-base64, --grep-context or \
+             --content-max-line-length"
+                .to_string(),
+        );
+    }
+    if matches!(
+        c.format,
+        OutputFormat::Csv | OutputFormat::Tsv | OutputFormat::Dot
+    ) && (c.show_content || c.tree_from_json.is_some())
+    {
+        return Err(
+            "--format csv, tsv and dot list entries only; they cannot be combined with \
+             --content or --tree-from-json"
+                .to_string(),
+        );
+    }
+    if c.icons.is_some()
+        && (matches!(
+            c.format,
+            OutputFormat::Shasum
+                | OutputFormat::Html
+                | OutputFormat::Dot
+                | OutputFormat::DeleteList
+        ) || c.tree_from_json.is_some())
+    {
+        return Err(
+            "--icons applies to tree, markdown, json, json-tree, yaml, csv, tsv and llm output of \
+             a walk"
+                .to_string(),
+        );
+    }
+    if c.errors_section
+        && (matches!(
+            c.format,
+            OutputFormat::Shasum
+                | OutputFormat::Html
+                | OutputFormat::Csv
+                | OutputFormat::Tsv
+                | OutputFormat::Dot
+                | OutputFormat::DeleteList
+        ) || c.tree_from_json.is_some())
+    {
+        return Err(
+            "--errors-section applies to tree, markdown, json, json-tree, yaml and llm output of \
+             a walk"
+                .to_string(),
+        );
+    }
+    if c.cache.is_some() && (w.jobs > 1 || c.tree_from_json.is_some() || c.repeat.is_some()) {
+        return Err(
+            "--cache cannot be combined with --jobs, --tree-from-json or --repeat".to_string(),
+        );
+    }
+    if c.max_tokens.is_some() && c.format != OutputFormat::Llm {
+        return Err("--max-tokens only applies to --format llm".to_string());
+    }
+    if c.format == OutputFormat::Llm
+        && (rewrites_lines
+            || c.content_lines.is_some()
+            || c.line_numbers
+            || c.tree_from_json.is_some())
+    {
+        return Err(
+            "--format llm includes whole files; it cannot be combined with --content-base64, \
+             --grep-context, --content-max-line-length, --content-lines, --line-numbers or \
+             --tree-from-json"
+                .to_string(),
+        );
+    }
+    // The bundle is the tree's files, so every listed file is read.
+    c.show_content |= c.format == OutputFormat::Llm;
+    if c.tree_from_json.is_some()
+        && !matches!(
+            c.format,
+            OutputFormat::Tree
+                | OutputFormat::JsonTree
+                | OutputFormat::Json
+                | OutputFormat::Yaml
+                | OutputFormat::Markdown
+                | OutputFormat::Shasum
+        )
+    {
+        return Err(
+            "--tree-from-json renders as tree, json-tree, json, yaml or markdown".to_string(),
+        );
+    }
+    if c.tree_from_json.is_some()
+        && (c.show_content
+            || c.format == OutputFormat::Shasum
+            || c.repeat.is_some()
+            || c.detect_encoding
+            || w.path_regex.is_some()
+            || w.grep.is_some()
+            || !w.name_matches.is_empty()
+            || c.stats_json.is_some())
+    {
+        return Err(
+            "--tree-from-json only re-renders a saved tree; it cannot be combined with \
+             options that read files (--content, --format shasum, --repeat, \
+             --detect-encoding, --path-regex, --grep, --match, --stats-json)"
+                .to_string(),
+        );
+    }
+    // A snapshot records names, kinds, sizes, times and symlink targets; it
+    // honours the filters and annotations that need nothing else, and these
+    // would be silently ignored.
+    if c.tree_from_json.is_some() {
+        let unhonoured = [
+            (w.files_only, "--files-only"),
+            (w.executables, "--executables"),
+            (
+                w.min_size.is_some() || w.max_size.is_some(),
+                "--min-size/--max-size",
+            ),
+            (
+                w.newer_than.is_some() || w.older_than.is_some(),
+                "--newer-than/--older-than",
+            ),
+            (w.only_empty, "--only-empty"),
+            (w.prune, "--prune"),
+            (
+                w.sort != SortKey::Name || w.reverse || w.natural_sort || w.locale_sort,
+                "--sort, --reverse, --natural-sort or --locale-sort",
+            ),
+            (
+                !w.group_dirs || w.dirs_last,
+                "--no-group-dirs or --dirs-last",
+            ),
+            (c.collapse, "--collapse"),
+            (c.bfs, "--bfs"),
+            (c.flatten_below.is_some(), "--flatten-below"),
+            (c.long, "--long"),
+            (c.relative_time, "--relative-time"),
+            (c.show_created, "--created"),
+            (
+                c.hash_files || c.find_duplicates,
+                "--hash or --find-duplicates",
+            ),
+            (c.stats || c.age_histogram, "--stats or --age-histogram"),
+            (
+                c.rare_extension_threshold.is_some(),
+                "--count-extensions-threshold",
+            ),
+            (c.show_fs, "--show-fs"),
+            (c.icons.is_some(), "--icons"),
+            (c.exec.is_some(), "--exec"),
+            (c.errors_section, "--errors-section"),
+            (w.gitignore, "--gitignore"),
+            (
+                w.follow_symlinks || w.symlinks_as_files || w.skip_loops || w.one_file_system,
+                "symlink and filesystem options",
+            ),
+            (c.color == ColorMode::Always, "--color always"),
+        ];
+        if let Some((_, option)) = unhonoured.iter().find(|(set, _)| *set) {
+            return Err(format!(
+                "--tree-from-json can't apply {option} to a saved tree"
+            ));
+        }
+    }
+    if c.clipboard && !cfg!(feature = "clipboard") {
+        return Err(
+            "--clipboard requires folderwalk to be built with `--features clipboard`".to_string(),
+        );
+    }
 
-                use std::collections::HashSet;
+    // Plain names stay exact matches; anything with glob syntax or a `/`
+    // becomes a glob, as does every name with --ignore-case.
+    let (glob_excludes, name_excludes): (Vec<String>, Vec<String>) = excludes
+        .into_iter()
+        .partition(|e| ignore_case || e.contains(['*', '?', '[', '/']));
+    c.walk.exclude_globs = glob_excludes
+        .iter()
+        .map(|g| {
+            ExcludeGlob::new(g, ignore_case).map_err(|e| format!("Invalid --exclude glob {g}: {e}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    c.walk.excludes = name_excludes.into_iter().collect();
+    if default_excludes {
+        c.walk
+            .excludes
+            .extend(DEFAULT_EXCLUDES.iter().map(|name| name.to_string()));
+    }
 
-                let excluded_dirs: HashSet<&str> =
-                    ["node_modules", ".git", "target"].iter().cloned().collect();
+    if c.walk.follow_symlinks && c.walk.symlinks_as_files {
+        return Err("--follow-symlinks cannot be combined with --symlinks-as-files".to_string());
+    }
+    if c.output.is_some() && (c.to_stdout || c.clipboard) {
+        return Err("--output cannot be combined with --stdout or --clipboard".to_string());
+    }
+    if (c.gzip || c.split_size.is_some()) && (c.to_stdout || c.clipboard || c.diff.is_some()) {
+        return Err(
+            "--gzip and --split-size only apply to the output file; they cannot be combined \
+             with --stdout, --clipboard or --diff"
+                .to_string(),
+        );
+    }
+    if roots.len() > 1 && c.output.is_some() && c.split_size.is_some() {
+        return Err(
+            "--split-size can't split one --output shared by several paths; give each \
+             path its own output"
+                .to_string(),
+        );
+    }
+    if roots.len() > 1 && (c.clipboard || c.progress_file.is_some() || c.stats_json.is_some()) {
+        return Err(
+            "--clipboard, --progress-file and --stats-json can only be used with a single path"
+                .to_string(),
+        );
+    }
+    if roots.len() > 1
+        && c.output.is_some()
+        && !matches!(c.format, OutputFormat::Tree | OutputFormat::Markdown)
+    {
+        return Err(
+            "--output with several paths writes their trees one after another, which only \
+             works for tree and markdown output"
+                .to_string(),
+        );
+    }
+    if c.watch && (roots.len() > 1 || c.tree_from_json.is_some() || c.repeat.is_some()) {
+        return Err(
+            "--watch needs a single directory to scan; it cannot be combined with several \
+             paths, --tree-from-json or --repeat"
+                .to_string(),
+        );
+    }
+    if c.exec.is_some()
+        && (c.watch
+            || c.interactive
+            || c.diff.is_some()
+            || c.tree_from_json.is_some()
+            || c.repeat.is_some())
+    {
+        return Err(
+            "--exec runs its command on the files of a scan; it cannot be combined with \
+             --watch, --interactive, --diff, --tree-from-json or --repeat"
+                .to_string(),
+        );
+    }
+    if c.interactive
+        && (roots.len() > 1
+            || c.format != OutputFormat::Tree
+            || c.to_stdout
+            || c.clipboard
+            || c.watch
+            || c.diff.is_some()
+            || c.tree_from_json.is_some()
+            || c.repeat.is_some())
+    {
+        return Err(
+            "--interactive browses a single directory and exports it as a tree; it cannot be \
+             combined with several paths, --format, --stdout, --clipboard, --watch, --diff, \
+             --tree-from-json or --repeat"
+                .to_string(),
+        );
+    }
+    Ok((c, roots))
+}
 
-                let file_name_os = de.file_name();
-                let file_name_str = file_name_os.to_string_lossy();
+fn print_usage() {
+    eprintln!("{}", cli::usage());
+}
 
-                if excluded_dirs.contains(file_name_str.as_ref()) {
-                    continue;
-                }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                out.push(DirEntryInfo {
-                    path: de.path(),
-                    file_name: de.file_name(),
-                    file_type,
-                    is_symlink_dir,
-                });
-            }
-            Err(err) => {
-                eprintln!(
-                    "Warning: error while reading in {}: {}",
-                    dir.to_string_lossy(),
-                    err
-                );
-            }
+    #[test]
+    fn every_flag_is_parsed() {
+        for flag in cli::FLAGS {
+            let value = match flag.value {
+                Value::None | Value::OptionalNumber(_) | Value::OptionalOneOf(..) => None,
+                Value::OneOf(_, words) => Some(words[0]),
+                Value::Any(_) | Value::File(_) => Some("1"),
+            };
+            // Values of the wrong kind are refused, not missed.
+            let _ = Parsed::new().set(flag.name, value);
         }
     }
-    Ok(out)
+
+    #[test]
+    fn checks_wait_for_every_flag() {
+        let parse = |line: &str| parse_arg_list(line.split(' ').map(str::to_string).collect());
+        let (config, roots) = parse("src --bar --ignore-case --path-regex ^A").unwrap();
+        assert_eq!(roots, [PathBuf::from("src")]);
+        assert!(config.du);
+        assert!(config.walk.path_regex.unwrap().is_match("a"));
+        assert!(parse("--dirs-only --ext rs").is_err());
+        assert!(parse("--sort bogus").is_err());
+        assert!(parse("--max-depth").is_err());
+        assert!(parse("--bogus").is_err());
+    }
 }
@@ -0,0 +1,236 @@
+//! The output file, for `--gzip` and `--split-size`.
+//!
+//! With `--gzip` the text is compressed as it is written; with
+//! `--split-size` it goes into numbered parts (`files.001.txt`,
+//! `files.002.txt`, ...), each at most SIZE bytes of text. Parts only end
+//! at line breaks, so every tree line, with the full prefix that draws its
+//! branches, is in one part, the parts concatenated are the unsplit
+//! output, and each part reads on its own. A single line longer than SIZE
+//! gets a part to itself. Both together compress each part separately.
+
+use std::ffi::OsStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::Config;
+use crate::gzip::{self, GzipEncoder};
+
+enum Sink {
+    Plain(BufWriter<File>),
+    Gzip(GzipEncoder<BufWriter<File>>),
+}
+
+impl Sink {
+    fn open(path: &Path, gzip: bool, append: bool) -> io::Result<Sink> {
+        let file = if append {
+            OpenOptions::new().append(true).create(true).open(path)?
+        } else {
+            File::create(path)?
+        };
+        let file = BufWriter::with_capacity(128 * 1024, file);
+        Ok(if gzip {
+            Sink::Gzip(GzipEncoder::new(file))
+        } else {
+            Sink::Plain(file)
+        })
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Sink::Plain(file) => file,
+            Sink::Gzip(encoder) => encoder,
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Sink::Plain(mut file) => file.flush(),
+            Sink::Gzip(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+pub struct OutputFile {
+    base: PathBuf,
+    gzip: bool,
+    split_size: Option<u64>,
+    sink: Option<Sink>,
+    /// Bytes of text in the open part.
+    part_len: u64,
+    /// The end of the output not yet known to be a whole line.
+    line: Vec<u8>,
+    parts: Vec<PathBuf>,
+}
+
+impl OutputFile {
+    /// Creates the output file (or its first part) at `base`, from
+    /// [`crate::output_path`]. With `config.append_output` it is added to
+    /// after a blank line instead; a gzip file gets another member. The
+    /// root's `--root-separator` header, if any, comes first.
+    pub fn create(base: PathBuf, config: &Config) -> io::Result<OutputFile> {
+        let mut out = OutputFile {
+            base,
+            gzip: config.gzip,
+            split_size: config.split_size,
+            sink: None,
+            part_len: 0,
+            line: Vec::new(),
+            parts: Vec::new(),
+        };
+        out.open_part(config.append_output)?;
+        if config.append_output {
+            out.write_all(b"\n")?;
+        }
+        if let Some(header) = &config.root_header {
+            out.write_all(format!("{header}\n").as_bytes())?;
+        }
+        Ok(out)
+    }
+
+    /// Writes what is left and returns the files written, in order. Parts
+    /// left over from an earlier, longer output are removed.
+    pub fn finish(mut self) -> io::Result<Vec<PathBuf>> {
+        let rest = std::mem::take(&mut self.line);
+        self.write_line(&rest)?;
+        if let Some(sink) = self.sink.take() {
+            sink.finish()?;
+        }
+        if self.split_size.is_some() {
+            for n in self.parts.len() + 1.. {
+                let stale = part_path(&self.base, n);
+                if !stale.exists() {
+                    break;
+                }
+                fs::remove_file(stale)?;
+            }
+        }
+        Ok(self.parts)
+    }
+
+    fn open_part(&mut self, append: bool) -> io::Result<()> {
+        if let Some(sink) = self.sink.take() {
+            sink.finish()?;
+        }
+        let path = match self.split_size {
+            Some(_) => part_path(&self.base, self.parts.len() + 1),
+            None => self.base.clone(),
+        };
+        self.sink = Some(Sink::open(&path, self.gzip, append)?);
+        self.parts.push(path);
+        self.part_len = 0;
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        if let Some(limit) = self.split_size
+            && self.part_len > 0
+            && self.part_len + line.len() as u64 > limit
+        {
+            self.open_part(false)?;
+        }
+        self.part_len += line.len() as u64;
+        match &mut self.sink {
+            Some(sink) => sink.writer().write_all(line),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.split_size.is_none() {
+            self.write_line(buf)?;
+            return Ok(buf.len());
+        }
+        let mut rest = buf;
+        while let Some(end) = rest.iter().position(|&b| b == b'\n') {
+            let (line, after) = rest.split_at(end + 1);
+            if self.line.is_empty() {
+                self.write_line(line)?;
+            } else {
+                let mut whole = std::mem::take(&mut self.line);
+                whole.extend_from_slice(line);
+                self.write_line(&whole)?;
+            }
+            rest = after;
+        }
+        self.line.extend_from_slice(rest);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.sink {
+            Some(sink) => sink.writer().flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Part `n` of the output at `base`: the number goes before the
+/// extensions, so `files.txt.gz` has parts `files.001.txt.gz`, ...
+pub fn part_path(base: &Path, n: usize) -> PathBuf {
+    let name = base.file_name().unwrap_or_default().to_string_lossy();
+    let (stem, ext) = split_name(&name);
+    base.with_file_name(format!("{stem}.{n:03}{ext}"))
+}
+
+/// Whether `name` is one of the parts of the output at `base`.
+pub fn is_part_name(base: &Path, name: &OsStr) -> bool {
+    let (Some(base), Some(name)) = (base.file_name().and_then(OsStr::to_str), name.to_str()) else {
+        return false;
+    };
+    let (stem, ext) = split_name(base);
+    name.strip_prefix(stem)
+        .and_then(|rest| rest.strip_prefix('.'))
+        .and_then(|rest| rest.strip_suffix(ext))
+        .is_some_and(|n| n.len() >= 3 && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// `files.txt.gz` as `("files", ".txt.gz")`; a leading dot is part of the
+/// stem.
+fn split_name(name: &str) -> (&str, &str) {
+    match name[1.min(name.len())..].find('.') {
+        Some(i) => name.split_at(i + 1),
+        None => (name, ""),
+    }
+}
+
+/// The files of the output at `base` as they are on disk: the parts, with
+/// `--split-size`, or the file itself.
+pub fn existing(base: &Path, config: &Config) -> Vec<PathBuf> {
+    if config.split_size.is_none() {
+        return vec![base.to_path_buf()];
+    }
+    (1..)
+        .map(|n| part_path(base, n))
+        .take_while(|part| part.exists())
+        .collect()
+}
+
+/// The canonical path of `base`, resolved through its directory when the
+/// file itself doesn't exist, as a split output's parts have other names.
+pub fn canonical(base: &Path) -> Option<PathBuf> {
+    if let Ok(path) = fs::canonicalize(base) {
+        return Some(path);
+    }
+    let dir = match base.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    Some(fs::canonicalize(dir).ok()?.join(base.file_name()?))
+}
+
+/// The text of the files `paths`, uncompressed where they are gzip.
+pub fn read_text(paths: &[PathBuf]) -> io::Result<Vec<u8>> {
+    let mut text = Vec::new();
+    for path in paths {
+        let bytes = fs::read(path)?;
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            text.extend(gzip::decompress(&bytes)?);
+        } else {
+            text.extend(bytes);
+        }
+    }
+    Ok(text)
+}
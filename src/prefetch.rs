@@ -0,0 +1,285 @@
+//! Reading the tree ahead of the walk on worker threads, for `--jobs`.
+//!
+//! Workers take directories from a shared stack, list each one with
+//! [`crate::scan_dir`] and push the subdirectories the walk will descend
+//! into: those within `--max-depth`, not hidden by a `.gitignore` or
+//! `.ignore`, and with `--skip-loops` not reached before. The newest
+//! directory is read first, so reading runs depth first, roughly in step
+//! with the walk. At most [`READ_AHEAD`] listings per thread wait for the
+//! walk to take them; when that many do, the workers pause, so a walk that
+//! stops early leaves the rest of the tree unread.
+//!
+//! The walk runs as usual, taking each listing as it gets there instead of
+//! reading the disk: it waits for one a worker is reading, and reads one
+//! still on the stack itself. Ordering and warnings come out exactly as
+//! without `--jobs`.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::gitignore::Rules;
+use crate::{DirKey, Listing, WalkOptions, is_walkable_dir, scan_dir};
+
+/// Listings each thread may have read that the walk hasn't taken yet.
+const READ_AHEAD: usize = 64;
+
+/// A directory waiting to be read.
+struct Job {
+    depth: usize,
+    /// `.gitignore` rules in effect in its parent.
+    rules: Option<Arc<Rules>>,
+}
+
+struct Queue {
+    /// Directories to read, the next one last. One no longer in `jobs` was
+    /// taken by the walk meanwhile.
+    stack: Vec<PathBuf>,
+    jobs: HashMap<PathBuf, Job>,
+    /// Directories a worker is reading.
+    reading: HashSet<PathBuf>,
+    /// Listings read and not yet taken.
+    ready: HashMap<PathBuf, Listing>,
+    /// Real directories queued so far, with `skip_loops`.
+    seen: HashSet<DirKey>,
+    /// Workers waiting for `work`.
+    idle: usize,
+    /// Whether the walk is waiting for `done`.
+    walk_waiting: bool,
+    stop: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    /// Wakes workers: a directory was queued, a listing taken, or it's
+    /// time to stop.
+    work: Condvar,
+    /// Wakes the walk: a listing is ready.
+    done: Condvar,
+    root: PathBuf,
+    opts: WalkOptions,
+}
+
+pub struct Prefetch {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Prefetch {
+    /// Starts `opts.jobs` threads reading `root` and what the walk will
+    /// visit below it. They stop when this is dropped.
+    pub fn start(root: &Path, opts: &WalkOptions) -> Prefetch {
+        let mut queue = Queue {
+            stack: Vec::new(),
+            jobs: HashMap::new(),
+            reading: HashSet::new(),
+            ready: HashMap::new(),
+            seen: HashSet::new(),
+            idle: 0,
+            walk_waiting: false,
+            stop: false,
+        };
+        if opts.max_depth != Some(0) {
+            if opts.skip_loops
+                && let Some(key) = DirKey::of(root)
+            {
+                queue.seen.insert(key);
+            }
+            queue.stack.push(root.to_path_buf());
+            queue.jobs.insert(
+                root.to_path_buf(),
+                Job {
+                    depth: 0,
+                    rules: None,
+                },
+            );
+        }
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(queue),
+            work: Condvar::new(),
+            done: Condvar::new(),
+            root: root.to_path_buf(),
+            opts: opts.clone(),
+        });
+        let workers = (0..opts.jobs)
+            .map(|_| {
+                let shared = Arc::clone(&shared);
+                thread::spawn(move || worker(&shared))
+            })
+            .collect();
+        Prefetch { shared, workers }
+    }
+
+    /// The listing of `dir`, once it has been read. `None` for a directory
+    /// the workers weren't going to read, or have handed out already.
+    pub fn take(&self, dir: &Path) -> Option<Listing> {
+        let shared = &*self.shared;
+        let mut q = shared.queue.lock().unwrap();
+        loop {
+            if let Some(listing) = q.ready.remove(dir) {
+                let idle = q.idle > 0;
+                drop(q);
+                if idle {
+                    shared.work.notify_one();
+                }
+                return Some(listing);
+            }
+            if let Some(job) = q.jobs.remove(dir) {
+                // Not started yet: reading it here beats waiting.
+                drop(q);
+                return Some(read(shared, dir, job));
+            }
+            if !q.reading.contains(dir) {
+                return None;
+            }
+            q.walk_waiting = true;
+            q = shared.done.wait(q).unwrap();
+            q.walk_waiting = false;
+        }
+    }
+}
+
+impl Drop for Prefetch {
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().stop = true;
+        self.shared.work.notify_all();
+        for worker in self.workers.drain(..) {
+            worker.join().expect("prefetch worker panicked");
+        }
+    }
+}
+
+fn worker(shared: &Shared) {
+    let limit = READ_AHEAD * shared.opts.jobs;
+    loop {
+        let (dir, job) = {
+            let mut q = shared.queue.lock().unwrap();
+            loop {
+                if q.stop {
+                    return;
+                }
+                if q.ready.len() + q.reading.len() < limit
+                    && let Some(dir) = q.stack.pop()
+                {
+                    if let Some(job) = q.jobs.remove(&dir) {
+                        q.reading.insert(dir.clone());
+                        break (dir, job);
+                    }
+                    continue;
+                }
+                q.idle += 1;
+                q = shared.work.wait(q).unwrap();
+                q.idle -= 1;
+            }
+        };
+
+        let listing = read(shared, &dir, job);
+        let mut q = shared.queue.lock().unwrap();
+        q.reading.remove(&dir);
+        q.ready.insert(dir, listing);
+        let walk_waiting = q.walk_waiting;
+        drop(q);
+        if walk_waiting {
+            shared.done.notify_one();
+        }
+    }
+}
+
+/// Lists `dir` and queues the subdirectories the walk will go into.
+fn read(shared: &Shared, dir: &Path, job: Job) -> Listing {
+    let opts = &shared.opts;
+    let listing = scan_dir(dir, &shared.root, opts);
+    if opts.max_depth.is_some_and(|maxd| job.depth + 1 >= maxd) {
+        return listing;
+    }
+    let rules = if opts.gitignore {
+        Rules::for_dir(dir, job.rules, opts.ignore_case)
+    } else {
+        None
+    };
+
+    let mut q = shared.queue.lock().unwrap();
+    // Pushed last to first, so the first is read first.
+    for entry in listing.entries.iter().rev().filter(|e| is_walkable_dir(e)) {
+        if rules
+            .as_ref()
+            .is_some_and(|r| r.is_ignored(&entry.path, true))
+        {
+            continue;
+        }
+        if opts.skip_loops
+            && let Some(key) = DirKey::of(&entry.path)
+            && !q.seen.insert(key)
+        {
+            continue;
+        }
+        q.stack.push(entry.path.clone());
+        q.jobs.insert(
+            entry.path.clone(),
+            Job {
+                depth: job.depth + 1,
+                rules: rules.clone(),
+            },
+        );
+    }
+    let idle = q.idle > 0;
+    drop(q);
+    if idle {
+        shared.work.notify_all();
+    }
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    struct TempDir(PathBuf);
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn names(listing: &Listing) -> Vec<String> {
+        let mut names: Vec<String> = listing
+            .entries
+            .iter()
+            .map(|e| e.file_name.to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn reads_only_what_the_walk_visits() {
+        let tmp = TempDir(
+            std::env::temp_dir().join(format!("folderwalk-prefetch-{}", std::process::id())),
+        );
+        let root = tmp.0.clone();
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("a/b/c")).unwrap();
+        fs::create_dir_all(root.join("ignored/x")).unwrap();
+        fs::write(root.join(".gitignore"), "ignored/\n").unwrap();
+        let opts = WalkOptions {
+            all: true,
+            gitignore: true,
+            max_depth: Some(2),
+            jobs: 2,
+            ..WalkOptions::default()
+        };
+
+        let prefetch = Prefetch::start(&root, &opts);
+        let listing = prefetch.take(&root).unwrap();
+        assert_eq!(names(&listing), [".gitignore", "a", "ignored"]);
+        assert_eq!(names(&prefetch.take(&root.join("a")).unwrap()), ["b"]);
+        // Handed out once; a second pass reads for itself.
+        assert!(prefetch.take(&root).is_none());
+        // Below --max-depth, and ignored.
+        assert!(prefetch.take(&root.join("a/b")).is_none());
+        assert!(prefetch.take(&root.join("ignored")).is_none());
+    }
+}
@@ -0,0 +1,567 @@
+//! Loading trees saved with `--format json-tree` (or `--format json`), for
+//! `--tree-from-json` and `--diff`.
+//!
+//! The document is parsed with a small JSON reader and then checked against
+//! the shape folderwalk writes: every json-tree node is an object with
+//! string `id`, `label` and `icon` fields and a `meta` object, and folders
+//! (and only folders) carry a `children` array. A `--format json` document,
+//! told apart by its root's `name` field, has `name`, `path` and `type`
+//! instead; it is read into the same nodes, with its symlink targets and
+//! kinds as `meta`, but records no sizes or times.
+
+use std::fs;
+use std::path::Path;
+
+use crate::json_escape;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Folder,
+    File,
+    Symlink,
+}
+
+impl NodeKind {
+    pub fn icon(self) -> &'static str {
+        match self {
+            NodeKind::Folder => "folder",
+            NodeKind::File => "file",
+            NodeKind::Symlink => "symlink",
+        }
+    }
+}
+
+pub struct Node {
+    pub id: String,
+    pub label: String,
+    pub kind: NodeKind,
+    /// The `meta` object, kept as-is so it can be written back out.
+    pub meta: Vec<(String, Value)>,
+    pub children: Vec<Node>,
+    /// Why the entry couldn't be read, when the walk that saved it said.
+    pub error: Option<String>,
+}
+
+impl Node {
+    /// Symlink target recorded in `meta`, if any.
+    pub fn target(&self) -> Option<&str> {
+        self.meta_str("target")
+    }
+
+    pub fn meta_str(&self, key: &str) -> Option<&str> {
+        self.meta.iter().find_map(|(k, v)| match v {
+            Value::String(s) if k == key => Some(s.as_str()),
+            _ => None,
+        })
+    }
+
+    /// A whole, non-negative number in `meta`, such as `size`.
+    pub fn meta_u64(&self, key: &str) -> Option<u64> {
+        self.meta.iter().find_map(|(k, v)| match v {
+            Value::Number(n) if k == key => n.parse().ok(),
+            _ => None,
+        })
+    }
+}
+
+pub enum Value {
+    Null,
+    Bool(bool),
+    /// Numbers keep their source text so they round-trip unchanged.
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn write_json(&self, out: &mut String) {
+        match self {
+            Value::Null => out.push_str("null"),
+            Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Value::Number(n) => out.push_str(n),
+            Value::String(s) => write_json_string(s, out),
+            Value::Array(items) => {
+                out.push('[');
+                for (idx, item) in items.iter().enumerate() {
+                    if idx > 0 {
+                        out.push(',');
+                    }
+                    item.write_json(out);
+                }
+                out.push(']');
+            }
+            Value::Object(fields) => write_json_object(fields, out),
+        }
+    }
+}
+
+pub fn write_json_object(fields: &[(String, Value)], out: &mut String) {
+    out.push('{');
+    for (idx, (key, value)) in fields.iter().enumerate() {
+        if idx > 0 {
+            out.push(',');
+        }
+        write_json_string(key, out);
+        out.push(':');
+        value.write_json(out);
+    }
+    out.push('}');
+}
+
+fn write_json_string(s: &str, out: &mut String) {
+    out.push('"');
+    out.push_str(&json_escape(s));
+    out.push('"');
+}
+
+/// Reads and validates a snapshot file.
+pub fn load(path: &Path) -> Result<Node, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    parse(&text)
+}
+
+fn parse(text: &str) -> Result<Node, String> {
+    let mut parser = Parser {
+        src: text.as_bytes(),
+        pos: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_ws();
+    if parser.pos != parser.src.len() {
+        return Err(parser.error("trailing data after the document"));
+    }
+    match &value {
+        Value::Object(fields) if fields.iter().any(|(key, _)| key == "name") => {
+            json_node_from(value, "$")
+        }
+        _ => node_from(value, "$"),
+    }
+}
+
+fn node_from(value: Value, at: &str) -> Result<Node, String> {
+    let Value::Object(fields) = value else {
+        return Err(format!("{at}: expected a node object"));
+    };
+    let mut id = None;
+    let mut label = None;
+    let mut icon = None;
+    let mut meta = None;
+    let mut children = None;
+    let mut error = None;
+    for (key, value) in fields {
+        match (key.as_str(), value) {
+            ("id", Value::String(s)) => id = Some(s),
+            ("label", Value::String(s)) => label = Some(s),
+            ("icon", Value::String(s)) => icon = Some(s),
+            ("error", Value::String(s)) => error = Some(s),
+            ("meta", Value::Object(m)) => meta = Some(m),
+            ("children", Value::Array(c)) => children = Some(c),
+            ("id" | "label" | "icon" | "error", _) => {
+                return Err(format!("{at}.{key}: expected a string"));
+            }
+            ("meta", _) => return Err(format!("{at}.meta: expected an object")),
+            ("children", _) => return Err(format!("{at}.children: expected an array")),
+            // `--errors-section` adds the problems to the root.
+            ("errors", Value::Array(_)) if at == "$" => {}
+            _ => return Err(format!("{at}: unknown field \"{key}\"")),
+        }
+    }
+    let id = id.ok_or_else(|| format!("{at}: missing \"id\""))?;
+    let label = label.ok_or_else(|| format!("{at}: missing \"label\""))?;
+    let meta = meta.ok_or_else(|| format!("{at}: missing \"meta\""))?;
+    let kind = match icon.as_deref() {
+        Some("folder") => NodeKind::Folder,
+        Some("file") => NodeKind::File,
+        Some("symlink") => NodeKind::Symlink,
+        Some(other) => return Err(format!("{at}.icon: unknown icon \"{other}\"")),
+        None => return Err(format!("{at}: missing \"icon\"")),
+    };
+    let children = match (kind, children) {
+        (NodeKind::Folder, Some(children)) => children
+            .into_iter()
+            .enumerate()
+            .map(|(idx, child)| node_from(child, &format!("{at}.children[{idx}]")))
+            .collect::<Result<_, _>>()?,
+        (NodeKind::Folder, None) => return Err(format!("{at}: folder without \"children\"")),
+        (_, Some(_)) => return Err(format!("{at}: only folders may have \"children\"")),
+        (_, None) => Vec::new(),
+    };
+    Ok(Node {
+        id,
+        label,
+        kind,
+        meta,
+        children,
+        error,
+    })
+}
+
+/// A `--format json` node: `{"name", "path", "type", ...}`. A symlink
+/// followed with `--follow-symlinks` keeps only its target, as the nodes
+/// have nowhere to put what was listed through it.
+fn json_node_from(value: Value, at: &str) -> Result<Node, String> {
+    let Value::Object(fields) = value else {
+        return Err(format!("{at}: expected a node object"));
+    };
+    let mut name = None;
+    let mut path = None;
+    let mut kind = None;
+    let mut meta = Vec::new();
+    let mut children = None;
+    let mut error = None;
+    for (key, value) in fields {
+        match (key.as_str(), value) {
+            ("name", Value::String(s)) => name = Some(s),
+            ("error", Value::String(s)) => error = Some(s),
+            ("path", Value::String(s)) => path = Some(s),
+            ("type", Value::String(s)) => kind = Some(s),
+            (field @ ("target" | "kind"), Value::String(s)) => {
+                meta.push((field.to_string(), Value::String(s)));
+            }
+            ("content", Value::String(_)) => {}
+            ("children", Value::Array(c)) => children = Some(c),
+            ("name" | "path" | "type" | "target" | "kind" | "content" | "error", _) => {
+                return Err(format!("{at}.{key}: expected a string"));
+            }
+            ("children", _) => return Err(format!("{at}.children: expected an array")),
+            ("errors", Value::Array(_)) if at == "$" => {}
+            _ => return Err(format!("{at}: unknown field \"{key}\"")),
+        }
+    }
+    let label = name.ok_or_else(|| format!("{at}: missing \"name\""))?;
+    let id = path.ok_or_else(|| format!("{at}: missing \"path\""))?;
+    let kind = match kind.as_deref() {
+        Some("dir") => NodeKind::Folder,
+        // An entry that couldn't be statted, as json-tree saves it.
+        Some("file" | "unknown") => NodeKind::File,
+        Some("symlink") => NodeKind::Symlink,
+        Some(other) => return Err(format!("{at}.type: unknown type \"{other}\"")),
+        None => return Err(format!("{at}: missing \"type\"")),
+    };
+    let children = match (kind, children) {
+        (NodeKind::Folder, Some(children)) => children
+            .into_iter()
+            .enumerate()
+            .map(|(idx, child)| json_node_from(child, &format!("{at}.children[{idx}]")))
+            .collect::<Result<_, _>>()?,
+        (NodeKind::Folder, None) => return Err(format!("{at}: dir without \"children\"")),
+        (NodeKind::Symlink, _) | (_, None) => Vec::new(),
+        (NodeKind::File, Some(_)) => return Err(format!("{at}: only dirs may have \"children\"")),
+    };
+    Ok(Node {
+        id,
+        label,
+        kind,
+        meta,
+        children,
+        error,
+    })
+}
+
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, what: &str) -> String {
+        format!("invalid JSON at byte {}: {what}", self.pos)
+    }
+
+    fn skip_ws(&mut self) {
+        while self
+            .src
+            .get(self.pos)
+            .is_some_and(|b| matches!(b, b' ' | b'\t' | b'\n' | b'\r'))
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_ws();
+        if self.src.get(self.pos) == Some(&byte) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        if self.eat(byte) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", byte as char)))
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        if self.src[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("unexpected token"))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_ws();
+        match self.src.get(self.pos) {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                if self.eat(b'}') {
+                    return Ok(Value::Object(fields));
+                }
+                loop {
+                    self.skip_ws();
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    fields.push((key, self.value()?));
+                    if self.eat(b'}') {
+                        return Ok(Value::Object(fields));
+                    }
+                    self.expect(b',')?;
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if self.eat(b']') {
+                    return Ok(Value::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    if self.eat(b']') {
+                        return Ok(Value::Array(items));
+                    }
+                    self.expect(b',')?;
+                }
+            }
+            Some(b'"') => self.string().map(Value::String),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'n') => self.literal("null", Value::Null),
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.pos;
+                while self
+                    .src
+                    .get(self.pos)
+                    .is_some_and(|b| matches!(b, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'))
+                {
+                    self.pos += 1;
+                }
+                let text = std::str::from_utf8(&self.src[start..self.pos]).unwrap_or_default();
+                if text.parse::<f64>().is_err() {
+                    return Err(self.error("malformed number"));
+                }
+                Ok(Value::Number(text.to_string()))
+            }
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.src.get(self.pos) != Some(&b'"') {
+            return Err(self.error("expected a string"));
+        }
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            let Some(&b) = self.src.get(self.pos) else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&esc) = self.src.get(self.pos) else {
+                        return Err(self.error("unterminated string"));
+                    };
+                    self.pos += 1;
+                    let c = match esc {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    let mut buf = [0; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                _ => out.push(b),
+            }
+        }
+        String::from_utf8(out).map_err(|_| self.error("string is not valid UTF-8"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .src
+            .get(self.pos..self.pos + 4)
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or_else(|| self.error("invalid \\u escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        if (0xD800..0xDC00).contains(&high) && self.src[self.pos..].starts_with(b"\\u") {
+            self.pos += 2;
+            let low = self.hex4()?;
+            let code = 0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+            return Ok(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+        }
+        Ok(char::from_u32(high).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> String {
+        match parse(text) {
+            Ok(_) => panic!("loaded {text}"),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn json_tree_documents_load() {
+        let root = parse(
+            r#"{"id":"src","label":"src","icon":"folder","meta":{"size":1.5e3},"children":[
+                {"id":"src/a.rs","label":"a.rs","icon":"file","meta":{"size":12,"modified":null}},
+                {"id":"src/l","label":"l","icon":"symlink","meta":{"target":"a.rs"}},
+                {"id":"src/x","label":"x","icon":"folder","meta":{},"children":[],
+                 "error":"permission denied"}
+            ],"errors":[]}"#,
+        )
+        .unwrap();
+        assert!(root.kind == NodeKind::Folder && root.error.is_none());
+        let [file, link, locked] = &root.children[..] else {
+            panic!("expected three children");
+        };
+        assert_eq!(
+            (file.id.as_str(), file.meta_u64("size")),
+            ("src/a.rs", Some(12))
+        );
+        assert!(link.kind == NodeKind::Symlink && link.target() == Some("a.rs"));
+        assert_eq!(locked.error.as_deref(), Some("permission denied"));
+        // `meta` is written back as it was read.
+        let mut out = String::new();
+        write_json_object(&file.meta, &mut out);
+        assert_eq!(out, r#"{"size":12,"modified":null}"#);
+        assert_eq!(root.meta_u64("size"), None);
+    }
+
+    #[test]
+    fn json_documents_load() {
+        let root = parse(
+            r#"{"name":"src","path":"src","type":"dir","children":[
+                {"name":"l","path":"src/l","type":"symlink","target":"d","children":[
+                    {"name":"inner","path":"src/l/inner","type":"file"}]},
+                {"name":"?","path":"src/?","type":"unknown","error":"gone"},
+                {"name":"a.rs","path":"src/a.rs","type":"file","content":"fn main() {}"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(root.label, "src");
+        let [link, gone, file] = &root.children[..] else {
+            panic!("expected three children");
+        };
+        assert!(link.kind == NodeKind::Symlink && link.children.is_empty());
+        assert_eq!(link.target(), Some("d"));
+        assert!(gone.kind == NodeKind::File && gone.error.as_deref() == Some("gone"));
+        assert_eq!(file.id, "src/a.rs");
+    }
+
+    #[test]
+    fn strings_unescape() {
+        let root = parse(r#"{"id":"a\"b\\c\/\né😀","label":"","icon":"file","meta":{}}"#).unwrap();
+        assert_eq!(root.id, "a\"b\\c/\né😀");
+    }
+
+    #[test]
+    fn malformed_json_is_rejected() {
+        assert_eq!(error(""), "invalid JSON at byte 0: unexpected end of input");
+        assert_eq!(
+            error("{} {}"),
+            "invalid JSON at byte 3: trailing data after the document"
+        );
+        assert_eq!(
+            error(r#"{"id":"a"#),
+            "invalid JSON at byte 8: unterminated string"
+        );
+        assert_eq!(
+            error(r#"{"id":"\q"}"#),
+            "invalid JSON at byte 9: invalid escape"
+        );
+        assert_eq!(
+            error(r#"{"id":"\u12"}"#),
+            "invalid JSON at byte 9: invalid \\u escape"
+        );
+        assert_eq!(
+            error(r#"{"size":1-2}"#),
+            "invalid JSON at byte 11: malformed number"
+        );
+        assert_eq!(
+            error(r#"{"id" "a"}"#),
+            "invalid JSON at byte 6: expected ':'"
+        );
+        assert_eq!(error("[1 2]"), "invalid JSON at byte 3: expected ','");
+        assert_eq!(error("{id:1}"), "invalid JSON at byte 1: expected a string");
+        assert_eq!(error("tru"), "invalid JSON at byte 0: unexpected token");
+    }
+
+    #[test]
+    fn other_documents_are_refused() {
+        assert_eq!(error("[]"), "$: expected a node object");
+        assert_eq!(
+            error(r#"{"id":"a","label":"a","icon":"file"}"#),
+            r#"$: missing "meta""#
+        );
+        assert_eq!(
+            error(r#"{"id":"a","label":"a","icon":"file","meta":{},"children":[]}"#),
+            r#"$: only folders may have "children""#
+        );
+        assert_eq!(
+            error(r#"{"id":"a","label":"a","icon":"folder","meta":{},"children":[1]}"#),
+            "$.children[0]: expected a node object"
+        );
+        assert_eq!(
+            error(r#"{"id":"a","label":"a","icon":"pipe","meta":{}}"#),
+            r#"$.icon: unknown icon "pipe""#
+        );
+        assert_eq!(
+            error(r#"{"id":1,"label":"a","icon":"file","meta":{}}"#),
+            "$.id: expected a string"
+        );
+        assert_eq!(
+            error(r#"{"id":"a","label":"a","icon":"file","meta":{},"size":1}"#),
+            r#"$: unknown field "size""#
+        );
+        assert_eq!(
+            error(r#"{"name":"a","path":"a","type":"dir"}"#),
+            r#"$: dir without "children""#
+        );
+        assert_eq!(
+            error(r#"{"name":"a","path":"a","type":"dir","children":[{"name":"b","path":"a/b"}]}"#),
+            r#"$.children[0]: missing "type""#
+        );
+        assert_eq!(
+            error(r#"{"name":"a","path":"a","type":"fifo"}"#),
+            r#"$.type: unknown type "fifo""#
+        );
+    }
+}
@@ -0,0 +1,809 @@
+//! `--interactive`: browse the tree in the terminal.
+//!
+//! The browser starts with the root's entries listed; a directory is read
+//! when it is first opened or previewed, through the same listing as the
+//! output (excludes, filters, sort), so it shows what `files.txt` would.
+//! Hidden entries are always read and only left off the screen, so `.`
+//! shows or hides them without reading anything again. The right-hand pane
+//! previews the selected file's first lines or a directory's entries, and
+//! `e` writes the rows currently shown, drawn as a tree, to the output file.
+//!
+//! The terminal is put into raw mode with `stty` on Unix and through the
+//! console API on Windows, and drawn on with ANSI escapes on the alternate
+//! screen, which is left again however the browser exits.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    Config, DirEntryInfo, WalkContext, WalkOptions, display_root_name, encoding, format_timestamp,
+    human_size, is_walkable_dir, list_dir, name_color, output, output_path, prepare_filters,
+    tree_glyphs, width, winfs,
+};
+
+/// Bytes of a file read for its preview.
+const PREVIEW_BYTES: u64 = 64 * 1024;
+/// Narrower terminals get the tree alone.
+const MIN_PREVIEW_COLUMNS: usize = 60;
+const HELP: &str = "↑↓ move  → open  ← close  . hidden  e export  q quit";
+
+struct Node {
+    entry: DirEntryInfo,
+    /// The directory's entries, once it has been read.
+    children: Option<Vec<Node>>,
+    /// Why the directory's entries couldn't all be read.
+    error: Option<String>,
+    expanded: bool,
+}
+
+impl Node {
+    fn new(entry: DirEntryInfo) -> Node {
+        Node {
+            entry,
+            children: None,
+            error: None,
+            expanded: false,
+        }
+    }
+}
+
+/// A line of the tree as shown.
+struct Row {
+    /// Child indices from the root's entries down to the node.
+    at: Vec<usize>,
+    /// The branch glyphs drawn before the name.
+    lead: String,
+}
+
+struct Browser<'a> {
+    ctx: WalkContext<'a>,
+    nodes: Vec<Node>,
+    show_hidden: bool,
+    rows: Vec<Row>,
+    cursor: usize,
+    scroll: usize,
+    /// Tree rows on screen, as of the last draw.
+    page_rows: usize,
+    /// The previewed path and its lines, kept while it stays selected.
+    preview: Option<(PathBuf, Vec<String>)>,
+    /// Shown in the status line until the next key.
+    message: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Quit,
+    Char(char),
+    Other,
+}
+
+/// Runs the browser on `config.start_dir` until it is quit.
+pub fn interactive(config: &Config) -> io::Result<()> {
+    let start_meta = fs::metadata(&config.start_dir)?;
+    if !start_meta.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Path is not a directory: {}",
+                config.start_dir.to_string_lossy()
+            ),
+        ));
+    }
+    // Problems are collected for the preview instead of printed over the
+    // screen, and prefetching would read directories that are never opened.
+    let browse = Config {
+        walk: WalkOptions {
+            all: true,
+            jobs: 1,
+            ..config.walk.clone()
+        },
+        quiet: true,
+        strict: false,
+        errors_section: true,
+        depth_markers: false,
+        ..config.clone()
+    };
+    let mut ctx = WalkContext::new(&browse, &start_meta);
+    ctx.output_path = output::canonical(&output_path(&browse));
+    prepare_filters(&mut ctx)?;
+
+    let mut browser = Browser {
+        ctx,
+        nodes: Vec::new(),
+        show_hidden: config.walk.all,
+        rows: Vec::new(),
+        cursor: 0,
+        scroll: 0,
+        page_rows: 1,
+        preview: None,
+        message: None,
+    };
+    let (nodes, error) = browser.read(&browse.start_dir, 0)?;
+    browser.nodes = nodes;
+    browser.message = error;
+    browser.layout();
+
+    let mut term = Terminal::enter()?;
+    loop {
+        browser.draw(&mut term)?;
+        for key in term.keys()? {
+            browser.message = None;
+            if !browser.handle(key)? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl Browser<'_> {
+    /// Lists `dir`, whose entries are at `depth`, and the first problem
+    /// reading it.
+    fn read(&mut self, dir: &Path, depth: usize) -> io::Result<(Vec<Node>, Option<String>)> {
+        let before = self.ctx.section_errors.len();
+        let entries = list_dir(dir, &mut self.ctx, depth)?;
+        let error = self.ctx.section_errors[before..]
+            .first()
+            .map(|e| e.describe());
+        Ok((entries.into_iter().map(Node::new).collect(), error))
+    }
+
+    fn node(&self, at: &[usize]) -> &Node {
+        let mut node = &self.nodes[at[0]];
+        for &i in &at[1..] {
+            node = &node
+                .children
+                .as_ref()
+                .expect("a shown node's parent was read")[i];
+        }
+        node
+    }
+
+    fn node_mut(&mut self, at: &[usize]) -> &mut Node {
+        let mut node = &mut self.nodes[at[0]];
+        for &i in &at[1..] {
+            node = &mut node
+                .children
+                .as_mut()
+                .expect("a shown node's parent was read")[i];
+        }
+        node
+    }
+
+    fn selected(&self) -> Option<&Row> {
+        self.rows.get(self.cursor)
+    }
+
+    /// Reads the directory at `at` if that hasn't happened yet. Returns
+    /// false when `--max-depth` keeps it unread.
+    fn load(&mut self, at: &[usize]) -> io::Result<bool> {
+        if self.node(at).children.is_some() {
+            return Ok(true);
+        }
+        // The root's entries are at depth 0, so these are at `at.len()`.
+        if self
+            .ctx
+            .config
+            .walk
+            .max_depth
+            .is_some_and(|max| at.len() >= max)
+        {
+            return Ok(false);
+        }
+        let dir = self.node(at).entry.path.clone();
+        let (children, error) = self.read(&dir, at.len())?;
+        let node = self.node_mut(at);
+        node.children = Some(children);
+        node.error = error;
+        Ok(true)
+    }
+
+    /// Rebuilds the rows, keeping the selection on the same entry, or on
+    /// its nearest shown parent if it was hidden.
+    fn layout(&mut self) {
+        let selected = self.selected().map(|row| row.at.clone());
+        let glyphs = tree_glyphs(self.ctx.config);
+        let mut rows = Vec::new();
+        push_rows(
+            &self.nodes,
+            self.show_hidden,
+            &mut Vec::new(),
+            "",
+            glyphs,
+            &mut rows,
+        );
+        self.rows = rows;
+        if let Some(mut at) = selected {
+            while !at.is_empty() {
+                if let Some(i) = self.rows.iter().position(|row| row.at == at) {
+                    self.cursor = i;
+                    return;
+                }
+                at.pop();
+            }
+        }
+        self.cursor = self.cursor.min(self.rows.len().saturating_sub(1));
+    }
+
+    /// Acts on `key`; false once the browser should close.
+    fn handle(&mut self, key: Key) -> io::Result<bool> {
+        let last = self.rows.len().saturating_sub(1);
+        let page = self.page_rows;
+        match key {
+            Key::Quit | Key::Char('q') => return Ok(false),
+            Key::Up | Key::Char('k') => self.cursor = self.cursor.saturating_sub(1),
+            Key::Down | Key::Char('j') => self.cursor = (self.cursor + 1).min(last),
+            Key::PageUp => self.cursor = self.cursor.saturating_sub(page),
+            Key::PageDown => self.cursor = (self.cursor + page).min(last),
+            Key::Home | Key::Char('g') => self.cursor = 0,
+            Key::End | Key::Char('G') => self.cursor = last,
+            Key::Right | Key::Char('l') => {
+                if let Some(at) = self.selected().map(|row| row.at.clone()) {
+                    if self.node(&at).expanded {
+                        if self
+                            .rows
+                            .get(self.cursor + 1)
+                            .is_some_and(|r| r.at.len() > at.len())
+                        {
+                            self.cursor += 1;
+                        }
+                    } else {
+                        self.open(&at)?;
+                    }
+                }
+            }
+            Key::Left | Key::Char('h') => {
+                if let Some(at) = self.selected().map(|row| row.at.clone()) {
+                    if self.node(&at).expanded {
+                        self.node_mut(&at).expanded = false;
+                        self.layout();
+                    } else if at.len() > 1
+                        && let Some(i) = self.rows.iter().position(|r| r.at == at[..at.len() - 1])
+                    {
+                        self.cursor = i;
+                    }
+                }
+            }
+            Key::Enter | Key::Char(' ') => {
+                if let Some(at) = self.selected().map(|row| row.at.clone()) {
+                    if self.node(&at).expanded {
+                        self.node_mut(&at).expanded = false;
+                        self.layout();
+                    } else {
+                        self.open(&at)?;
+                    }
+                }
+            }
+            Key::Char('.') => {
+                self.show_hidden = !self.show_hidden;
+                self.layout();
+                self.message = Some(if self.show_hidden {
+                    "Showing hidden entries".to_string()
+                } else {
+                    "Hiding hidden entries".to_string()
+                });
+            }
+            Key::Char('e') => {
+                self.message = Some(match self.export() {
+                    Ok(path) => format!(
+                        "Wrote {} rows to {}",
+                        self.rows.len(),
+                        path.to_string_lossy()
+                    ),
+                    Err(e) => format!("Export failed: {e}"),
+                });
+            }
+            Key::Char(_) | Key::Other => {}
+        }
+        Ok(true)
+    }
+
+    /// Expands the directory at `at`, reading it first if needed.
+    fn open(&mut self, at: &[usize]) -> io::Result<()> {
+        if !is_walkable_dir(&self.node(at).entry) {
+            return Ok(());
+        }
+        if !self.load(at)? {
+            self.message = Some("Below --max-depth".to_string());
+            return Ok(());
+        }
+        let node = self.node_mut(at);
+        node.expanded = true;
+        if let Some(error) = node.error.clone() {
+            self.message = Some(error);
+        }
+        self.layout();
+        Ok(())
+    }
+
+    /// Writes the shown rows to the output file, as the tree is drawn in
+    /// `files.txt`.
+    fn export(&self) -> io::Result<PathBuf> {
+        let config = self.ctx.config;
+        let path = output_path(config);
+        let mut out = output::OutputFile::create(path, config)?;
+        let root = display_root_name(&config.start_dir).unwrap_or_else(|| ".".to_string());
+        writeln!(out, "{root}")?;
+        for row in &self.rows {
+            writeln!(out, "{}{}", row.lead, label(&self.node(&row.at).entry))?;
+        }
+        let parts = out.finish()?;
+        Ok(parts.into_iter().next().unwrap_or_default())
+    }
+
+    fn draw(&mut self, term: &mut Terminal) -> io::Result<()> {
+        let (height, cols) = term.size();
+        // The header and the status line take one row each.
+        let body = height.saturating_sub(2).max(1);
+        self.page_rows = body;
+        if self.cursor < self.scroll {
+            self.scroll = self.cursor;
+        } else if self.cursor >= self.scroll + body {
+            self.scroll = self.cursor + 1 - body;
+        }
+        let tree_cols = if cols >= MIN_PREVIEW_COLUMNS {
+            cols / 2
+        } else {
+            cols
+        };
+        let preview_cols = cols.saturating_sub(tree_cols + 1);
+        let preview = if preview_cols > 0 {
+            self.preview_lines()?
+        } else {
+            Vec::new()
+        };
+
+        let root = self.ctx.config.start_dir.to_string_lossy();
+        let mut frame = format!(
+            "\x1b[H\x1b[1m{}\x1b[0m\x1b[K\r\n",
+            width::truncate(&root, cols)
+        );
+        for i in 0..body {
+            let mut used = 0;
+            if let Some(row) = self.rows.get(self.scroll + i) {
+                let entry = &self.node(&row.at).entry;
+                let lead = width::truncate(&row.lead, tree_cols);
+                used = width::display_width(&lead);
+                let name = width::truncate(&label(entry), tree_cols - used);
+                used += width::display_width(&name);
+                if self.scroll + i == self.cursor {
+                    frame.push_str(&format!("{lead}\x1b[7m{name}\x1b[0m"));
+                } else if let Some(color) = name_color(entry) {
+                    frame.push_str(&format!("{lead}{color}{name}\x1b[0m"));
+                } else {
+                    frame.push_str(&format!("{lead}{name}"));
+                }
+            } else if i == 0 && self.rows.is_empty() {
+                frame.push_str("(empty)");
+                used = "(empty)".len();
+            }
+            if preview_cols > 0 {
+                frame.push_str(&" ".repeat(tree_cols.saturating_sub(used)));
+                frame.push('│');
+                if let Some(line) = preview.get(i) {
+                    frame.push_str(&width::truncate(line, preview_cols));
+                }
+            }
+            frame.push_str("\x1b[K\r\n");
+        }
+        let status = match (&self.message, self.selected()) {
+            (Some(message), _) => message.clone(),
+            (None, Some(row)) => {
+                let path = &self.node(&row.at).entry.path;
+                let rel = path
+                    .strip_prefix(&self.ctx.config.start_dir)
+                    .unwrap_or(path);
+                format!("{}  {HELP}", rel.to_string_lossy())
+            }
+            (None, None) => HELP.to_string(),
+        };
+        let status = width::truncate(&status, cols);
+        let pad = cols.saturating_sub(width::display_width(&status));
+        frame.push_str(&format!("\x1b[7m{status}{}\x1b[0m", " ".repeat(pad)));
+        term.write(&frame)
+    }
+
+    /// The preview of the selected entry, from the cache while it stays
+    /// selected.
+    fn preview_lines(&mut self) -> io::Result<Vec<String>> {
+        let Some(at) = self.selected().map(|row| row.at.clone()) else {
+            return Ok(Vec::new());
+        };
+        let path = self.node(&at).entry.path.clone();
+        if let Some((previewed, lines)) = &self.preview
+            && *previewed == path
+        {
+            return Ok(lines.clone());
+        }
+        let lines = self.describe(&at)?;
+        self.preview = Some((path, lines.clone()));
+        Ok(lines)
+    }
+
+    fn describe(&mut self, at: &[usize]) -> io::Result<Vec<String>> {
+        let entry = self.node(at).entry.clone();
+        let mut lines = Vec::new();
+        if entry.file_type.is_symlink() {
+            let target = winfs::read_link(&entry.path)
+                .map(|t| t.to_string_lossy().into_owned())
+                .unwrap_or_else(|_| "<unreadable>".to_string());
+            lines.push(format!("-> {target}"));
+        }
+        if is_walkable_dir(&entry) {
+            if !self.load(at)? {
+                lines.push("(below --max-depth)".to_string());
+                return Ok(lines);
+            }
+            let node = self.node(at);
+            let children: Vec<&Node> = node
+                .children
+                .iter()
+                .flatten()
+                .filter(|child| self.show_hidden || !child.entry.hidden)
+                .collect();
+            let noun = if children.len() == 1 {
+                "entry"
+            } else {
+                "entries"
+            };
+            lines.push(format!("{} {noun}", children.len()));
+            if let Some(error) = &node.error {
+                lines.push(error.clone());
+            }
+            lines.push(String::new());
+            lines.extend(children.iter().map(|child| label(&child.entry)));
+            return Ok(lines);
+        }
+        if !entry.file_type.is_file() {
+            return Ok(lines);
+        }
+
+        let mut about = human_size(entry.len);
+        if let Some(mtime) = entry.modified {
+            about.push_str(&format!(", modified {}", format_timestamp(mtime)));
+        }
+        lines.push(about);
+        lines.push(String::new());
+        let mut head = Vec::new();
+        match File::open(&entry.path).and_then(|f| f.take(PREVIEW_BYTES).read_to_end(&mut head)) {
+            Ok(_) => match encoding::detect(&head).decode(&head) {
+                Some(text) => lines.extend(text.lines().map(|line| {
+                    line.replace('\t', "    ")
+                        .chars()
+                        .filter(|c| !c.is_control())
+                        .collect()
+                })),
+                None => lines.push("(binary)".to_string()),
+            },
+            Err(e) => lines.push(format!("cannot read file: {e}")),
+        }
+        Ok(lines)
+    }
+}
+
+/// Adds the shown rows below `nodes`, whose position is `at` and whose
+/// lines start with `prefix`.
+fn push_rows(
+    nodes: &[Node],
+    show_hidden: bool,
+    at: &mut Vec<usize>,
+    prefix: &str,
+    (tee, elbow, pipe, space): (&str, &str, &str, &str),
+    rows: &mut Vec<Row>,
+) {
+    let shown: Vec<usize> = (0..nodes.len())
+        .filter(|&i| show_hidden || !nodes[i].entry.hidden)
+        .collect();
+    for (n, &i) in shown.iter().enumerate() {
+        let is_last = n + 1 == shown.len();
+        at.push(i);
+        let branch = if is_last { elbow } else { tee };
+        rows.push(Row {
+            at: at.clone(),
+            lead: format!("{prefix}{branch}"),
+        });
+        if nodes[i].expanded
+            && let Some(children) = &nodes[i].children
+        {
+            let prefix = format!("{prefix}{}", if is_last { space } else { pipe });
+            push_rows(
+                children,
+                show_hidden,
+                at,
+                &prefix,
+                (tee, elbow, pipe, space),
+                rows,
+            );
+        }
+        at.pop();
+    }
+}
+
+/// An entry's name as the tree shows it: `/` after directories, and where
+/// a symlink points.
+fn label(entry: &DirEntryInfo) -> String {
+    let mut name = entry.file_name.to_string_lossy().into_owned();
+    if entry.file_type.is_dir() {
+        name.push('/');
+    }
+    if entry.file_type.is_symlink() {
+        match winfs::read_link(&entry.path) {
+            Ok(target) => name.push_str(&format!(" -> {}", target.to_string_lossy())),
+            Err(_) => name.push_str(" -> <unreadable>"),
+        }
+    }
+    name
+}
+
+/// The terminal in raw mode on the alternate screen, restored on drop.
+struct Terminal {
+    input: File,
+    output: File,
+    /// `stty -g`'s settings to go back to.
+    #[cfg(not(windows))]
+    saved: String,
+    /// The input and output console modes to go back to.
+    #[cfg(windows)]
+    saved: (u32, u32),
+}
+
+impl Terminal {
+    /// Reads the keys pressed since the last call; several can arrive at
+    /// once when typed quickly or held down.
+    fn keys(&mut self) -> io::Result<Vec<Key>> {
+        const SEQUENCES: [(&[u8], Key); 20] = [
+            (b"\x1b[A", Key::Up),
+            (b"\x1bOA", Key::Up),
+            (b"\x1b[B", Key::Down),
+            (b"\x1bOB", Key::Down),
+            (b"\x1b[C", Key::Right),
+            (b"\x1bOC", Key::Right),
+            (b"\x1b[D", Key::Left),
+            (b"\x1bOD", Key::Left),
+            (b"\x1b[5~", Key::PageUp),
+            (b"\x1b[6~", Key::PageDown),
+            (b"\x1b[H", Key::Home),
+            (b"\x1bOH", Key::Home),
+            (b"\x1b[1~", Key::Home),
+            (b"\x1b[7~", Key::Home),
+            (b"\x1b[F", Key::End),
+            (b"\x1bOF", Key::End),
+            (b"\x1b[4~", Key::End),
+            (b"\x1b[8~", Key::End),
+            (b"\r", Key::Enter),
+            (b"\n", Key::Enter),
+        ];
+        let mut buf = [0u8; 256];
+        let n = self.input.read(&mut buf)?;
+        if n == 0 {
+            return Ok(vec![Key::Quit]);
+        }
+        let mut keys = Vec::new();
+        let mut rest = &buf[..n];
+        while !rest.is_empty() {
+            if let Some((seq, key)) = SEQUENCES.iter().find(|(seq, _)| rest.starts_with(seq)) {
+                keys.push(*key);
+                rest = &rest[seq.len()..];
+                continue;
+            }
+            let len = match rest {
+                // Ctrl-C, Ctrl-D, or Esc on its own.
+                [3 | 4, ..] | [0x1b] => {
+                    keys.push(Key::Quit);
+                    1
+                }
+                // Any other escape sequence ends with a byte in `@`..`~`.
+                [0x1b, b'[', tail @ ..] => {
+                    keys.push(Key::Other);
+                    2 + tail
+                        .iter()
+                        .position(|b| (0x40..=0x7e).contains(b))
+                        .map_or(tail.len(), |i| i + 1)
+                }
+                [c, ..] if c.is_ascii_graphic() || *c == b' ' => {
+                    keys.push(Key::Char(char::from(*c)));
+                    1
+                }
+                _ => {
+                    keys.push(Key::Other);
+                    1
+                }
+            };
+            rest = &rest[len..];
+        }
+        Ok(keys)
+    }
+
+    fn write(&mut self, frame: &str) -> io::Result<()> {
+        self.output.write_all(frame.as_bytes())?;
+        self.output.flush()
+    }
+
+    /// Switches to the alternate screen with the cursor hidden.
+    fn start(mut self) -> io::Result<Terminal> {
+        self.write("\x1b[?1049h\x1b[?25l\x1b[2J")?;
+        Ok(self)
+    }
+
+    #[cfg(not(windows))]
+    fn enter() -> io::Result<Terminal> {
+        let tty = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .map_err(|e| io::Error::other(format!("--interactive needs a terminal: {e}")))?;
+        let saved = stty(&tty, &["-g"])?;
+        stty(&tty, &["raw", "-echo"])?;
+        Terminal {
+            input: tty.try_clone()?,
+            output: tty,
+            saved: saved.trim().to_string(),
+        }
+        .start()
+    }
+
+    /// Rows and columns, `stty size` permitting.
+    #[cfg(not(windows))]
+    fn size(&self) -> (usize, usize) {
+        stty(&self.input, &["size"])
+            .ok()
+            .and_then(|size| {
+                let (rows, cols) = size.trim().split_once(' ')?;
+                Some((rows.parse().ok()?, cols.parse().ok()?))
+            })
+            .filter(|&(rows, cols)| rows > 0 && cols > 0)
+            .unwrap_or((24, 80))
+    }
+
+    #[cfg(not(windows))]
+    fn restore(&mut self) {
+        let _ = stty(&self.input, &[self.saved.as_str()]);
+    }
+
+    #[cfg(windows)]
+    fn enter() -> io::Result<Terminal> {
+        const ENABLE_PROCESSED_INPUT: u32 = 0x1;
+        const ENABLE_LINE_INPUT: u32 = 0x2;
+        const ENABLE_ECHO_INPUT: u32 = 0x4;
+        const ENABLE_VIRTUAL_TERMINAL_INPUT: u32 = 0x200;
+        const ENABLE_PROCESSED_OUTPUT: u32 = 0x1;
+        const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x4;
+
+        let open = |name: &str| {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(name)
+                .map_err(|e| io::Error::other(format!("--interactive needs a console: {e}")))
+        };
+        let input = open("CONIN$")?;
+        let output = open("CONOUT$")?;
+        let saved = (console::mode(&input)?, console::mode(&output)?);
+        console::set_mode(
+            &input,
+            (saved.0 & !(ENABLE_PROCESSED_INPUT | ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT))
+                | ENABLE_VIRTUAL_TERMINAL_INPUT,
+        )?;
+        console::set_mode(
+            &output,
+            saved.1 | ENABLE_PROCESSED_OUTPUT | ENABLE_VIRTUAL_TERMINAL_PROCESSING,
+        )?;
+        Terminal {
+            input,
+            output,
+            saved,
+        }
+        .start()
+    }
+
+    #[cfg(windows)]
+    fn size(&self) -> (usize, usize) {
+        console::size(&self.output).unwrap_or((24, 80))
+    }
+
+    #[cfg(windows)]
+    fn restore(&mut self) {
+        let _ = console::set_mode(&self.input, self.saved.0);
+        let _ = console::set_mode(&self.output, self.saved.1);
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.write("\x1b[?25h\x1b[?1049l");
+        self.restore();
+    }
+}
+
+/// Runs `stty` on the terminal `tty` and returns what it printed.
+#[cfg(not(windows))]
+fn stty(tty: &File, args: &[&str]) -> io::Result<String> {
+    let out = std::process::Command::new("stty")
+        .args(args)
+        .stdin(tty.try_clone()?)
+        .output()?;
+    if !out.status.success() {
+        return Err(io::Error::other(format!(
+            "stty {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&out.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+#[cfg(windows)]
+mod console {
+    use std::fs::File;
+    use std::io;
+    use std::os::windows::io::AsRawHandle;
+
+    #[repr(C)]
+    struct Coord {
+        x: i16,
+        y: i16,
+    }
+
+    #[allow(dead_code)]
+    #[repr(C)]
+    struct ScreenBufferInfo {
+        size: Coord,
+        cursor_position: Coord,
+        attributes: u16,
+        window_left: i16,
+        window_top: i16,
+        window_right: i16,
+        window_bottom: i16,
+        maximum_window_size: Coord,
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetConsoleMode(console: isize, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: isize, mode: u32) -> i32;
+        fn GetConsoleScreenBufferInfo(console: isize, info: *mut ScreenBufferInfo) -> i32;
+    }
+
+    pub fn mode(console: &File) -> io::Result<u32> {
+        let mut mode = 0;
+        // SAFETY: the handle is open for as long as `console` is.
+        if unsafe { GetConsoleMode(console.as_raw_handle() as isize, &mut mode) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(mode)
+    }
+
+    pub fn set_mode(console: &File, mode: u32) -> io::Result<()> {
+        // SAFETY: as above.
+        if unsafe { SetConsoleMode(console.as_raw_handle() as isize, mode) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Rows and columns of the visible window.
+    pub fn size(console: &File) -> io::Result<(usize, usize)> {
+        let mut info = std::mem::MaybeUninit::<ScreenBufferInfo>::uninit();
+        // SAFETY: `info` is only read after the call succeeded and filled it
+        // in.
+        let info = unsafe {
+            if GetConsoleScreenBufferInfo(console.as_raw_handle() as isize, info.as_mut_ptr()) == 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            info.assume_init()
+        };
+        let rows = info.window_bottom - info.window_top + 1;
+        let cols = info.window_right - info.window_left + 1;
+        Ok((rows.max(1) as usize, cols.max(1) as usize))
+    }
+}
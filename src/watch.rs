@@ -0,0 +1,87 @@
+//! `--watch`: run again whenever the tree changes.
+//!
+//! There is no change notification here, only polling: every
+//! [`POLL_INTERVAL`] the tree is listed (with the same excludes and
+//! filters as the output) and every entry's path, size and modification
+//! time hashed. A change only triggers a run once two polls
+//! [`SETTLE_DELAY`] apart agree, so a burst of saves leads to one run, not
+//! one per file. The files folderwalk writes itself are left out of the
+//! hash, or every run would trigger the next.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use crate::{Config, output, output_path, run, walk_with};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const SETTLE_DELAY: Duration = Duration::from_millis(200);
+
+/// Runs `config` now and again after every change below its root, handing
+/// each result to `report`. Only returns if the root itself can't be read.
+pub fn watch<F>(config: &Config, mut report: F) -> io::Result<()>
+where
+    F: FnMut(io::Result<usize>),
+{
+    let mut first = true;
+    loop {
+        if config.to_stdout && !first {
+            println!();
+        }
+        first = false;
+        report(run(config));
+
+        let own = own_outputs(config);
+        let mut seen = fingerprint(config, &own)?;
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let now = fingerprint(config, &own)?;
+            if now == seen {
+                continue;
+            }
+            seen = now;
+            thread::sleep(SETTLE_DELAY);
+            let settled = fingerprint(config, &own)?;
+            if settled == seen {
+                break;
+            }
+            seen = settled;
+        }
+    }
+}
+
+/// Canonical paths of the files a run writes, as they exist after it.
+fn own_outputs(config: &Config) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if !config.to_stdout && !config.clipboard {
+        paths.extend(output::existing(&output_path(config), config));
+    }
+    paths.extend(config.progress_file.clone());
+    paths.extend(config.stats_json.clone());
+    paths
+        .iter()
+        .filter_map(|p| fs::canonicalize(p).ok())
+        .collect()
+}
+
+fn fingerprint(config: &Config, own: &[PathBuf]) -> io::Result<u64> {
+    let mut hasher = DefaultHasher::new();
+    walk_with(&config.start_dir, &config.walk, |entry, depth| {
+        let is_own = own.iter().any(|p| {
+            p.file_name() == Some(entry.file_name.as_os_str())
+                && fs::canonicalize(&entry.path).is_ok_and(|c| c == *p)
+        });
+        if is_own {
+            return;
+        }
+        entry.path.hash(&mut hasher);
+        depth.hash(&mut hasher);
+        entry.len.hash(&mut hasher);
+        entry.modified.hash(&mut hasher);
+    })?;
+    Ok(hasher.finish())
+}
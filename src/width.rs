@@ -0,0 +1,99 @@
+//! Terminal column widths for `--width`.
+//!
+//! A compact approximation of Unicode East Asian Width: combining marks and
+//! other zero-width characters take no columns, wide CJK and emoji ranges
+//! take two, everything else one.
+
+pub fn char_width(c: char) -> usize {
+    let cp = c as u32;
+    if cp < 0x20 || (0x7F..0xA0).contains(&cp) {
+        return 0;
+    }
+    if matches!(
+        cp,
+        0x0300..=0x036F
+            | 0x0483..=0x0489
+            | 0x0591..=0x05BD
+            | 0x0610..=0x061A
+            | 0x064B..=0x065F
+            | 0x200B..=0x200F
+            | 0x20D0..=0x20FF
+            | 0xFE00..=0xFE0F
+            | 0xFE20..=0xFE2F
+            | 0xE0100..=0xE01EF
+    ) {
+        return 0;
+    }
+    if matches!(
+        cp,
+        0x1100..=0x115F
+            | 0x2E80..=0x303E
+            | 0x3041..=0x33FF
+            | 0x3400..=0x4DBF
+            | 0x4E00..=0x9FFF
+            | 0xA000..=0xA4CF
+            | 0xAC00..=0xD7A3
+            | 0xF900..=0xFAFF
+            | 0xFE30..=0xFE4F
+            | 0xFF00..=0xFF60
+            | 0xFFE0..=0xFFE6
+            | 0x1F300..=0x1F64F
+            | 0x1F900..=0x1F9FF
+            | 0x20000..=0x3FFFD
+    ) {
+        return 2;
+    }
+    1
+}
+
+pub fn display_width(s: &str) -> usize {
+    s.chars().map(char_width).sum()
+}
+
+/// Cuts `s` to at most `max` columns, ending it with `…` when anything was
+/// dropped. A trailing `/` is kept so directories stay recognisable.
+/// Combining marks stay with the character they follow.
+pub fn truncate(s: &str, max: usize) -> String {
+    if display_width(s) <= max {
+        return s.to_string();
+    }
+    let suffix = if s.ends_with('/') { "…/" } else { "…" };
+    let budget = max.saturating_sub(display_width(suffix));
+    let mut out = String::new();
+    let mut used = 0;
+    for c in s.chars() {
+        let w = char_width(c);
+        if used + w > budget {
+            break;
+        }
+        used += w;
+        out.push(c);
+    }
+    out.push_str(suffix);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widths_follow_east_asian_width() {
+        assert_eq!(display_width("abc"), 3);
+        assert_eq!(display_width("日本語"), 6);
+        assert_eq!(display_width("e\u{301}"), 1);
+        assert_eq!(display_width("🦀 rs"), 5);
+        assert_eq!(display_width("a\u{200B}\tb"), 2);
+    }
+
+    #[test]
+    fn truncation_fits_the_columns() {
+        assert_eq!(truncate("short", 5), "short");
+        assert_eq!(truncate("a-long-name.txt", 8), "a-long-…");
+        assert_eq!(truncate("directory/", 6), "dire…/");
+        // A wide character that doesn't fit is left out whole.
+        assert_eq!(truncate("日本語のファイル", 6), "日本…");
+        assert_eq!(display_width(&truncate("日本語のファイル", 6)), 5);
+        assert_eq!(truncate("cafe\u{301}-menu", 5), "cafe\u{301}…");
+    }
+}
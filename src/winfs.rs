@@ -0,0 +1,120 @@
+//! Windows path handling: junctions and extended-length paths.
+//!
+//! Junctions (directory mount points) are reparse points that behave like
+//! symlinks to a directory; std already reports them as symlinks, so they
+//! are listed with their target and only descended into with
+//! `--follow-symlinks`. Their reparse tag is read here so the tree can say
+//! which of the two an entry is. Directories are listed through
+//! extended-length `\\?\` paths, which are not limited to `MAX_PATH` (260)
+//! characters, and the prefix is taken off link targets again before they
+//! are shown. Elsewhere, everything here is a no-op.
+
+use std::borrow::Cow;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// `path` in extended-length form, e.g. `\\?\C:\src\deep` or
+/// `\\?\UNC\server\share`. Paths that can't be made absolute, or already
+/// use the prefix, are returned as they are.
+#[cfg(windows)]
+pub fn extended_length(path: &Path) -> Cow<'_, Path> {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use std::path::{Component, Prefix};
+
+    let Ok(abs) = std::path::absolute(path) else {
+        return Cow::Borrowed(path);
+    };
+    // `\\server\share` becomes `\\?\UNC\server\share`, keeping one of its
+    // leading backslashes.
+    let (mut extended, skip) = match abs.components().next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(_) => (OsString::from(r"\\?\"), 0),
+            Prefix::UNC(..) => (OsString::from(r"\\?\UNC"), 1),
+            _ => return Cow::Borrowed(path),
+        },
+        _ => return Cow::Borrowed(path),
+    };
+    let wide: Vec<u16> = abs.as_os_str().encode_wide().collect();
+    extended.push(OsString::from_wide(&wide[skip..]));
+    Cow::Owned(PathBuf::from(extended))
+}
+
+#[cfg(not(windows))]
+pub fn extended_length(path: &Path) -> Cow<'_, Path> {
+    Cow::Borrowed(path)
+}
+
+/// The target of the symlink or junction at `path`, without the `\\?\`
+/// prefix Windows reports absolute targets with.
+pub fn read_link(path: &Path) -> io::Result<PathBuf> {
+    let target = std::fs::read_link(path)?;
+    if cfg!(windows) {
+        let s = target.to_string_lossy();
+        if let Some(unc) = s.strip_prefix(r"\\?\UNC\") {
+            return Ok(PathBuf::from(format!(r"\\{unc}")));
+        }
+        if let Some(rest) = s.strip_prefix(r"\\?\") {
+            return Ok(PathBuf::from(rest));
+        }
+    }
+    Ok(target)
+}
+
+/// Whether the reparse point at `path` is a junction rather than a
+/// symlink.
+#[cfg(windows)]
+pub fn is_junction(path: &Path) -> bool {
+    use std::os::windows::ffi::OsStrExt;
+
+    const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+    const IO_REPARSE_TAG_MOUNT_POINT: u32 = 0xA000_0003;
+    const INVALID_HANDLE_VALUE: isize = -1;
+
+    // Only the attributes and the tag are read; the layout has to match.
+    #[allow(dead_code)]
+    #[repr(C)]
+    struct Win32FindDataW {
+        file_attributes: u32,
+        creation_time: [u32; 2],
+        last_access_time: [u32; 2],
+        last_write_time: [u32; 2],
+        file_size_high: u32,
+        file_size_low: u32,
+        /// The reparse tag, when `FILE_ATTRIBUTE_REPARSE_POINT` is set.
+        reserved0: u32,
+        reserved1: u32,
+        file_name: [u16; 260],
+        alternate_file_name: [u16; 14],
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn FindFirstFileW(file_name: *const u16, find_data: *mut Win32FindDataW) -> isize;
+        fn FindClose(find_file: isize) -> i32;
+    }
+
+    let wide: Vec<u16> = extended_length(path)
+        .as_os_str()
+        .encode_wide()
+        .chain(Some(0))
+        .collect();
+    let mut data = std::mem::MaybeUninit::<Win32FindDataW>::uninit();
+    // SAFETY: `wide` is NUL-terminated and `data` is only read after the
+    // call succeeded and filled it in.
+    unsafe {
+        let handle = FindFirstFileW(wide.as_ptr(), data.as_mut_ptr());
+        if handle == INVALID_HANDLE_VALUE {
+            return false;
+        }
+        FindClose(handle);
+        let data = data.assume_init();
+        data.file_attributes & FILE_ATTRIBUTE_REPARSE_POINT != 0
+            && data.reserved0 == IO_REPARSE_TAG_MOUNT_POINT
+    }
+}
+
+#[cfg(not(windows))]
+pub fn is_junction(_path: &Path) -> bool {
+    false
+}
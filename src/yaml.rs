@@ -0,0 +1,308 @@
+//! `--format yaml`: the `--format json` document as YAML, for manifests
+//! checked into a repository and reviewed as diffs.
+//!
+//! Keys come in the same order as in the JSON, one per line, and every
+//! child is a `- ` item of its directory's `children`, so adding or
+//! removing a file shows up as a few lines of diff. Names and paths are
+//! written plain where YAML reads them back as the same string and
+//! double-quoted otherwise (`"true"`, `"1.0"`, `"- x"`); `--content` is
+//! written as a literal block (`content: |`) unless the text holds
+//! characters a block can't, such as `\r`.
+
+use std::io::{self, Write};
+
+use crate::{Node, WalkContext, collect_nodes, display_root_name};
+
+pub fn write_yaml(ctx: &mut WalkContext, writer: &mut dyn Write) -> io::Result<()> {
+    let config = ctx.config;
+    let children = collect_nodes(&config.start_dir, ctx, 0)?;
+    let root = Node::Dir {
+        name: display_root_name(&config.start_dir).unwrap_or_else(|| ".".to_string()),
+        children,
+        error: ctx.take_error(&config.start_dir),
+    };
+    let mut out = String::new();
+    write_node(&root, ".", "", "", &mut out);
+    if config.errors_section {
+        if ctx.section_errors.is_empty() {
+            out.push_str("errors: []\n");
+        } else {
+            out.push_str("errors:\n");
+            for error in &ctx.section_errors {
+                out.push_str(&format!("  - kind: {}\n", scalar(error.kind)));
+                out.push_str(&format!(
+                    "    path: {}\n",
+                    scalar(&error.path.to_string_lossy())
+                ));
+                out.push_str(&format!("    message: {}\n", scalar(&error.message)));
+            }
+        }
+    }
+    writer.write_all(out.as_bytes())
+}
+
+/// Appends `node`, found at `path`, as a mapping: the first key after
+/// `lead`, the rest after `indent`.
+pub fn write_node(node: &Node, path: &str, lead: &str, indent: &str, out: &mut String) {
+    let (name, kind, error) = match node {
+        Node::File { name, error, .. } => (name, "file", error.as_deref()),
+        Node::Dir { name, error, .. } => (name, "dir", error.as_deref()),
+        Node::Symlink { name, error, .. } => (name, "symlink", error.as_deref()),
+        Node::Unreadable { name, error } => (name, "unknown", Some(error.as_str())),
+    };
+    out.push_str(&format!("{lead}name: {}\n", scalar(name)));
+    out.push_str(&format!("{indent}path: {}\n", scalar(path)));
+    out.push_str(&format!("{indent}type: {kind}\n"));
+    if let Some(error) = error {
+        out.push_str(&format!("{indent}error: {}\n", scalar(error)));
+    }
+    match node {
+        Node::File { kind, content, .. } => {
+            if let Some(kind) = kind {
+                out.push_str(&format!("{indent}kind: {}\n", scalar(kind)));
+            }
+            if let Some(content) = content {
+                write_content(content, indent, out);
+            }
+        }
+        Node::Dir { children, .. } => write_children(children, path, indent, out),
+        Node::Symlink {
+            target, children, ..
+        } => {
+            out.push_str(&format!("{indent}target: {}\n", scalar(target)));
+            if let Some(children) = children {
+                write_children(children, path, indent, out);
+            }
+        }
+        Node::Unreadable { .. } => {}
+    }
+}
+
+fn write_children(children: &[Node], parent: &str, indent: &str, out: &mut String) {
+    if children.is_empty() {
+        out.push_str(&format!("{indent}children: []\n"));
+        return;
+    }
+    out.push_str(&format!("{indent}children:\n"));
+    let lead = format!("{indent}  - ");
+    let inner = format!("{indent}    ");
+    for child in children {
+        let path = if parent == "." {
+            child.name().to_string()
+        } else {
+            format!("{parent}/{}", child.name())
+        };
+        write_node(child, &path, &lead, &inner, out);
+    }
+}
+
+/// `content: |` with the text indented below it. The chomping indicator
+/// keeps the text's trailing newlines exactly, and an indentation
+/// indicator is added when the text itself starts indented.
+fn write_content(text: &str, indent: &str, out: &mut String) {
+    let blockable = !text.is_empty()
+        && !text
+            .chars()
+            .any(|c| (c.is_control() && c != '\n' && c != '\t') || c == '\u{feff}');
+    if !blockable {
+        out.push_str(&format!("{indent}content: {}\n", quoted(text)));
+        return;
+    }
+    let body = text.trim_end_matches('\n');
+    let chomp = match text.len() - body.len() {
+        0 => "-",
+        1 => "",
+        _ => "+",
+    };
+    let starts_indented = text
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .is_some_and(|line| line.starts_with([' ', '\t']))
+        || text
+            .lines()
+            .take_while(|line| line.trim().is_empty())
+            .any(|line| !line.is_empty());
+    let indentation = if starts_indented { "2" } else { "" };
+    out.push_str(&format!("{indent}content: |{indentation}{chomp}\n"));
+    for line in text.split_inclusive('\n') {
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        if line.is_empty() {
+            out.push('\n');
+        } else {
+            out.push_str(&format!("{indent}  {line}\n"));
+        }
+    }
+}
+
+/// `s` as a plain scalar when YAML would read that back as the same
+/// string, double-quoted otherwise.
+fn scalar(s: &str) -> String {
+    if is_plain(s) {
+        s.to_string()
+    } else {
+        quoted(s)
+    }
+}
+
+/// A deliberately narrow test: names starting with a letter, `_`, `.` or
+/// `/`, made of common filename characters, that no YAML 1.1 or 1.2 schema
+/// reads as a boolean, null or number.
+fn is_plain(s: &str) -> bool {
+    const RESERVED: [&str; 11] = [
+        "true", "false", "yes", "no", "on", "off", "y", "n", "null", ".inf", ".nan",
+    ];
+    let Some(first) = s.chars().next() else {
+        return false;
+    };
+    // `.5` is a float.
+    let second_is_digit = s.chars().nth(1).is_some_and(|c| c.is_ascii_digit());
+    (first.is_alphabetic() || matches!(first, '_' | '/') || first == '.' && !second_is_digit)
+        && s.chars().all(|c| {
+            c.is_alphanumeric() || matches!(c, '_' | '.' | '/' | '-' | '+' | '@' | '(' | ')')
+        })
+        && !RESERVED.iter().any(|r| s.eq_ignore_ascii_case(r))
+}
+
+/// A double-quoted scalar, escaping what YAML can't take literally.
+fn quoted(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() || matches!(c, '\u{feff}' | '\u{fffe}' | '\u{ffff}') => {
+                out.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, content: Option<&str>) -> Node {
+        Node::File {
+            name: name.to_string(),
+            kind: None,
+            content: content.map(str::to_string),
+            error: None,
+        }
+    }
+
+    fn content(text: &str) -> String {
+        let mut out = String::new();
+        write_content(text, "", &mut out);
+        out
+    }
+
+    #[test]
+    fn scalars_are_plain_only_when_they_read_back_unchanged() {
+        for plain in [
+            "src",
+            "main.rs",
+            ".gitignore",
+            "_build",
+            "/tmp/x",
+            "a-b+c@(1)",
+        ] {
+            assert_eq!(scalar(plain), plain);
+        }
+        for (value, written) in [
+            ("", r#""""#),
+            ("true", r#""true""#),
+            ("No", r#""No""#),
+            ("null", r#""null""#),
+            ("1.0", r#""1.0""#),
+            (".5", r#"".5""#),
+            (".inf", r#"".inf""#),
+            ("- x", r#""- x""#),
+            ("a: b", r#""a: b""#),
+            ("#x", r##""#x""##),
+            ("say \"hi\"\\", r#""say \"hi\"\\""#),
+            ("tab\there\n", r#""tab\there\n""#),
+            ("bell\u{7}bom\u{feff}", r#""bell\u0007bom\ufeff""#),
+        ] {
+            assert_eq!(scalar(value), written, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn content_keeps_trailing_newlines_and_indentation() {
+        assert_eq!(content("a\nb\n"), "content: |\n  a\n  b\n");
+        assert_eq!(content("a"), "content: |-\n  a\n");
+        assert_eq!(content("a\n\n\n"), "content: |+\n  a\n\n\n");
+        assert_eq!(content("  indented\n"), "content: |2\n    indented\n");
+        assert_eq!(content("\n  x\n"), "content: |2\n\n    x\n");
+        assert_eq!(content(" \nx\n"), "content: |2\n   \n  x\n");
+        // What a block can't hold is quoted instead.
+        assert_eq!(content(""), "content: \"\"\n");
+        assert_eq!(content("a\r\nb"), "content: \"a\\r\\nb\"\n");
+    }
+
+    #[test]
+    fn trees_nest_as_items() {
+        let root = Node::Dir {
+            name: "root".to_string(),
+            children: vec![
+                Node::Dir {
+                    name: "empty".to_string(),
+                    children: Vec::new(),
+                    error: Some("permission denied".to_string()),
+                },
+                Node::Symlink {
+                    name: "link".to_string(),
+                    target: "yes".to_string(),
+                    children: Some(vec![file("inner", None)]),
+                    error: None,
+                },
+                file("notes", Some("hi\n")),
+                Node::Unreadable {
+                    name: "gone".to_string(),
+                    error: "no such file".to_string(),
+                },
+            ],
+            error: None,
+        };
+        let mut out = String::new();
+        write_node(&root, ".", "", "", &mut out);
+        assert_eq!(
+            out,
+            "\
+name: root
+path: .
+type: dir
+children:
+  - name: empty
+    path: empty
+    type: dir
+    error: \"permission denied\"
+    children: []
+  - name: link
+    path: link
+    type: symlink
+    target: \"yes\"
+    children:
+      - name: inner
+        path: link/inner
+        type: file
+  - name: notes
+    path: notes
+    type: file
+    content: |
+      hi
+  - name: gone
+    path: gone
+    type: unknown
+    error: \"no such file\"
+"
+        );
+    }
+}
@@ -0,0 +1,627 @@
+//! Golden-output tests for the tree glyphs: each builds a small tree in a
+//! temporary directory, runs the binary on it and compares stdout with the
+//! exact expected rendering.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A fresh directory named after the test, removed again on drop.
+struct Fixture {
+    root: PathBuf,
+}
+
+impl Fixture {
+    fn new(name: &str, files: &[(&str, &str)]) -> Fixture {
+        let root =
+            std::env::temp_dir().join(format!("folderwalk-golden-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (path, content) in files {
+            let path = root.join(path);
+            if let Some(stripped) = path.to_str().and_then(|p| p.strip_suffix('/')) {
+                fs::create_dir_all(stripped).unwrap();
+            } else {
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, content).unwrap();
+            }
+        }
+        Fixture { root }
+    }
+
+    fn name(&self) -> String {
+        self.root
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn render(&self, args: &[&str]) -> String {
+        render(&self.root, args)
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+fn render(root: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_folderwalk"))
+        .arg(root)
+        .arg("--stdout")
+        .arg("--no-config")
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "folderwalk failed: {output:?}");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn nested() -> Fixture {
+    Fixture::new(
+        "nested",
+        &[
+            ("a/b/c.txt", ""),
+            ("a/d.txt", ""),
+            ("e/f/g.txt", ""),
+            ("e/h.txt", ""),
+            ("i.txt", ""),
+        ],
+    )
+}
+
+#[test]
+fn unicode_glyphs_follow_last_elements() {
+    let fixture = nested();
+    let expected = format!(
+        "{}
+├── a/
+│   ├── b/
+│   │   └── c.txt
+│   └── d.txt
+├── e/
+│   ├── f/
+│   │   └── g.txt
+│   └── h.txt
+└── i.txt
+",
+        fixture.name()
+    );
+    assert_eq!(fixture.render(&[]), expected);
+}
+
+#[test]
+fn ascii_glyphs_follow_last_elements() {
+    let fixture = nested();
+    let expected = format!(
+        "{}
+|-- a/
+|   |-- b/
+|   |   `-- c.txt
+|   `-- d.txt
+|-- e/
+|   |-- f/
+|   |   `-- g.txt
+|   `-- h.txt
+`-- i.txt
+",
+        fixture.name()
+    );
+    assert_eq!(fixture.render(&["--ascii"]), expected);
+}
+
+#[test]
+fn last_directory_children_are_indented_with_spaces() {
+    let fixture = Fixture::new(
+        "last-dir",
+        &[("a.txt", ""), ("z/y/x.txt", ""), ("z/w.txt", "")],
+    );
+    let expected = format!(
+        "{}
+├── z/
+│   ├── y/
+│   │   └── x.txt
+│   └── w.txt
+└── a.txt
+",
+        fixture.name()
+    );
+    assert_eq!(fixture.render(&[]), expected);
+
+    let expected = format!(
+        "{}
+├── a.txt
+└── z/
+    ├── w.txt
+    └── y/
+        └── x.txt
+",
+        fixture.name()
+    );
+    assert_eq!(fixture.render(&["--dirs-last"]), expected);
+}
+
+#[test]
+fn content_aligns_under_its_file_at_every_depth() {
+    let fixture = Fixture::new(
+        "content",
+        &[("top.txt", "one\n"), ("sub/deep/low.txt", "two\n")],
+    );
+    let expected = format!(
+        "{}
+├── sub/
+│   └── deep/
+│       └── low.txt
+│           --- FILE CONTENT START ---
+│           two
+│           --- FILE CONTENT END ---
+└── top.txt
+    --- FILE CONTENT START ---
+    one
+    --- FILE CONTENT END ---
+",
+        fixture.name()
+    );
+    assert_eq!(fixture.render(&["--content"]), expected);
+}
+
+#[test]
+fn content_keeps_the_branches_of_later_siblings() {
+    let fixture = Fixture::new(
+        "content-siblings",
+        &[
+            ("d/bin.dat", "\0\u{1}\u{2}\u{3}\u{4}"),
+            ("d/dup1", "x\n"),
+            ("z.txt", "last\n"),
+        ],
+    );
+    let expected = format!(
+        "{}
+├── d/
+│   ├── bin.dat
+│   │   [binary file, 5 bytes]
+│   └── dup1
+│       --- FILE CONTENT START ---
+│       x
+│       --- FILE CONTENT END ---
+└── z.txt
+    --- FILE CONTENT START ---
+    last
+    --- FILE CONTENT END ---
+",
+        fixture.name()
+    );
+    assert_eq!(fixture.render(&["--content"]), expected);
+}
+
+#[test]
+fn empty_directories_get_no_children() {
+    let fixture = Fixture::new("empty", &[("empty/", ""), ("full/f.txt", "")]);
+    let expected = format!(
+        "{}
+├── empty/
+└── full/
+    └── f.txt
+",
+        fixture.name()
+    );
+    assert_eq!(fixture.render(&[]), expected);
+}
+
+#[test]
+fn bfs_lists_each_level_under_a_heading() {
+    let fixture = nested();
+    let expected = format!(
+        "{}
+
+Depth 1 (3 entries):
+├── a/
+├── e/
+└── i.txt
+
+Depth 2 (4 entries):
+├── a/b/
+├── a/d.txt
+├── e/f/
+└── e/h.txt
+
+Depth 3 (2 entries):
+├── a/b/c.txt
+└── e/f/g.txt
+",
+        fixture.name()
+    );
+    assert_eq!(fixture.render(&["--bfs"]), expected);
+
+    let expected = format!(
+        "{}
+
+Depth 1 (3 entries):
+├── a/
+├── e/
+└── i.txt
+
+... (4 more entries below depth 1)
+",
+        fixture.name()
+    );
+    assert_eq!(fixture.render(&["--bfs", "--max-depth", "1"]), expected);
+}
+
+#[test]
+fn walker_renders_like_the_binary() {
+    let fixture = nested();
+    let mut out = Vec::new();
+    let errors = folderwalk::Walker::new(&fixture.root)
+        .max_depth(2)
+        .ascii(true)
+        .run(&mut out)
+        .unwrap();
+    assert!(errors.is_empty());
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        fixture.render(&["--max-depth", "2", "--ascii"])
+    );
+}
+
+#[test]
+fn entries_iterate_like_walk() {
+    let fixture = nested();
+    let walker = folderwalk::Walker::new(&fixture.root);
+    let mut visited = Vec::new();
+    walker
+        .walk(|entry, depth| visited.push((depth, entry.path.clone())))
+        .unwrap();
+    let iterated: Vec<_> = walker
+        .entries()
+        .unwrap()
+        .map(|item| (item.depth, item.entry.path))
+        .collect();
+    assert_eq!(iterated, visited);
+}
+
+#[cfg(unix)]
+#[test]
+fn followed_links_stop_at_cycles() {
+    let fixture = Fixture::new("cycle", &[("a/b/", ""), ("a/f.txt", "")]);
+    std::os::unix::fs::symlink("../..", fixture.root.join("a/b/up")).unwrap();
+    std::os::unix::fs::symlink("a", fixture.root.join("link")).unwrap();
+    let expected = format!(
+        "{}
+├── a/
+│   ├── b/
+│   │   └── up -> <cycle>
+│   └── f.txt
+└── link -> a
+    ├── b/
+    │   └── up -> <cycle>
+    └── f.txt
+",
+        fixture.name()
+    );
+    assert_eq!(fixture.render(&["--follow-symlinks"]), expected);
+}
+
+#[test]
+fn saved_trees_render_like_the_walk() {
+    let fixture = Fixture::new(
+        "saved",
+        &[
+            ("a/b.txt", "12345"),
+            ("a/skip/c.txt", "x"),
+            ("d/e/f.txt", "123"),
+            (".hidden", "1"),
+            ("g.txt", "1234567890"),
+        ],
+    );
+    let saved = std::env::temp_dir().join(format!("{}.json", fixture.name()));
+    fs::write(&saved, fixture.render(&["--format", "json-tree", "--all"])).unwrap();
+    let saved_arg = saved.to_str().unwrap();
+    for args in [
+        &["--size", "--du", "--summary"][..],
+        &[
+            "--exclude",
+            "skip",
+            "--summary",
+            "--count-hidden-separately",
+        ],
+        &["--max-depth", "1", "--du", "--bytes"],
+        &["--dirs-only", "--all", "--summary"],
+    ] {
+        let walked = fixture.render(args);
+        let rendered = fixture.render(&[&["--tree-from-json", saved_arg][..], args].concat());
+        assert_eq!(rendered, walked, "with {args:?}");
+    }
+    let _ = fs::remove_file(&saved);
+}
+
+#[test]
+fn several_roots_get_headers() {
+    let first = Fixture::new("roots-a", &[("x/", "")]);
+    let second = Fixture::new("roots-b", &[("y", "")]);
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_folderwalk"))
+            .arg(&first.root)
+            .arg(&second.root)
+            .args(["--stdout", "--no-config"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "folderwalk failed: {output:?}");
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let (a, b) = (first.root.display(), second.root.display());
+    let (an, bn) = (first.name(), second.name());
+    assert_eq!(
+        run(&[]),
+        format!("=== {a} ===\n{an}\n└── x/\n\n=== {b} ===\n{bn}\n└── y\n")
+    );
+    assert_eq!(
+        run(&["--root-separator", "# {}"]),
+        format!("# {a}\n{an}\n└── x/\n\n# {b}\n{bn}\n└── y\n")
+    );
+    assert_eq!(
+        run(&["--root-separator", ""]),
+        format!("{an}\n└── x/\n\n{bn}\n└── y\n")
+    );
+    // A single root gets no header.
+    assert_eq!(first.render(&[]), format!("{an}\n└── x/\n"));
+}
+
+#[test]
+fn missing_roots_get_no_header() {
+    let fixture = Fixture::new("roots-missing", &[("y", "")]);
+    let missing = fixture.root.with_extension("absent");
+    let output = Command::new(env!("CARGO_BIN_EXE_folderwalk"))
+        .arg(&missing)
+        .arg(&fixture.root)
+        .args(["--stdout", "--no-config"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "=== {} ===\n{}\n└── y\n",
+            fixture.root.display(),
+            fixture.name()
+        )
+    );
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .starts_with("Failed: ")
+    );
+}
+
+#[test]
+fn bars_show_each_directory_share_of_its_parent() {
+    let fixture = Fixture::new(
+        "bar",
+        &[
+            ("big/sub/f", &"x".repeat(3000)),
+            ("big/g", &"x".repeat(1000)),
+            ("empty/", ""),
+            ("small/h", &"x".repeat(1000)),
+        ],
+    );
+    let expected = format!(
+        "{} (5000)
+|-- big/ (4000) ###.
+|   |-- sub/ (3000) ###.
+|   |   `-- f
+|   `-- g
+|-- empty/ (0) ....
+`-- small/ (1000) #...
+    `-- h
+",
+        fixture.name()
+    );
+    assert_eq!(
+        fixture.render(&["--bar", "4", "--bytes", "--ascii"]),
+        expected
+    );
+}
+
+#[test]
+fn delete_lists_are_nul_terminated_and_spare_home() {
+    let fixture = Fixture::new(
+        "delete-list",
+        &[
+            ("a/empty/", ""),
+            ("a/full", "x"),
+            ("a/zero", ""),
+            ("b/.hidden", ""),
+            ("c/new\nline", ""),
+            ("home/", ""),
+        ],
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_folderwalk"))
+        .arg(&fixture.root)
+        .args(["--stdout", "--no-config", "--format", "delete-list"])
+        .arg("--only-empty")
+        .env("HOME", fixture.root.join("home"))
+        .output()
+        .unwrap();
+    let root = fixture.root.to_str().unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{root}/a/empty\0{root}/a/zero\0{root}/c/new\nline\0")
+    );
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is $HOME"));
+}
+
+#[test]
+fn pruning_keeps_only_paths_to_matches() {
+    let fixture = Fixture::new(
+        "prune",
+        &[
+            ("a/b/c/hit.rs", ""),
+            ("a/b/c/miss.txt", ""),
+            ("a/b/d/e/miss.txt", ""),
+            ("a/f/", ""),
+            ("g/h/i/j/miss.txt", ""),
+        ],
+    );
+    let expected = format!(
+        "{}
+└── a/
+    └── b/
+        └── c/
+            └── hit.rs
+",
+        fixture.name()
+    );
+    assert_eq!(fixture.render(&["--include", "*.rs"]), expected);
+    // Cut off at the limit, `c/` has nothing listed, so neither has `a/`.
+    assert_eq!(
+        fixture.render(&["--include", "*.rs", "--max-depth", "3"]),
+        format!("{}\n", fixture.name())
+    );
+}
+
+#[test]
+fn ignore_case_covers_plain_excludes_and_gitignore() {
+    let fixture = Fixture::new(
+        "ignore-case",
+        &[
+            ("docs/a.md", ""),
+            ("Build/out.o", ""),
+            ("src/main.rs", ""),
+            (".gitignore", "build/\n"),
+        ],
+    );
+    let expected = format!(
+        "{}
+└── src/
+    └── main.rs
+",
+        fixture.name()
+    );
+    let args = ["--gitignore", "--exclude", "DOCS", "-i"];
+    assert_eq!(fixture.render(&args), expected);
+    // Without -i, names and patterns keep their case.
+    assert!(fixture.render(&args[..3]).contains("docs/"));
+    assert!(fixture.render(&args[..3]).contains("Build/"));
+}
+
+#[test]
+fn utf16_content_is_decoded_without_detect_encoding() {
+    let utf16: String = "hello, world\n".chars().flat_map(|c| [c, '\0']).collect();
+    let fixture = Fixture::new("utf16", &[("le.txt", &utf16), ("nul.bin", "\0\0\0\0x")]);
+    let expected = format!(
+        "{}
+├── le.txt
+│   --- FILE CONTENT START ---
+│   hello, world
+│   --- FILE CONTENT END ---
+└── nul.bin
+    [binary file, 5 bytes]
+",
+        fixture.name()
+    );
+    assert_eq!(fixture.render(&["--content"]), expected);
+}
+
+#[test]
+fn html_pages_escape_names_and_content() {
+    let fixture = Fixture::new(
+        "html",
+        &[("<b>&\"x\".txt", "</pre><script>alert('x')</script>\n")],
+    );
+    let page = fixture.render(&["--format", "html", "--content"]);
+    assert!(!page.contains("<b>") && !page.contains("alert('x')"));
+    assert!(page.contains(
+        r#"<li class="file" data-name="&lt;b&gt;&amp;&quot;x&quot;.txt"><details><summary>&lt;b&gt;&amp;&quot;x&quot;.txt</summary>"#
+    ));
+    assert!(
+        page.contains("<pre>&lt;/pre&gt;&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt;\n</pre>")
+    );
+}
+
+#[test]
+fn diffs_list_what_changed_since_a_snapshot() {
+    let fixture = Fixture::new(
+        "diff",
+        &[
+            ("same.txt", "same"),
+            ("grown.txt", "a"),
+            ("sub/gone.txt", ""),
+        ],
+    );
+    let snapshot = std::env::temp_dir().join(format!("{}.snapshot.json", fixture.name()));
+    let status = Command::new(env!("CARGO_BIN_EXE_folderwalk"))
+        .arg(&fixture.root)
+        .args(["--no-config", "--snapshot"])
+        .arg(&snapshot)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    fs::write(fixture.root.join("grown.txt"), "abc").unwrap();
+    fs::remove_file(fixture.root.join("sub/gone.txt")).unwrap();
+    fs::create_dir(fixture.root.join("new")).unwrap();
+
+    let diff = fixture.render(&["--diff", snapshot.to_str().unwrap()]);
+    let _ = fs::remove_file(&snapshot);
+    assert_eq!(
+        diff,
+        "~ grown.txt\n+ new/\n- sub/gone.txt\n1 added, 1 removed, 1 modified\n"
+    );
+}
+
+#[test]
+fn jobs_do_not_change_the_output() {
+    let fixture = Fixture::new(
+        "jobs",
+        &[
+            (".gitignore", "target/\n"),
+            ("src/main.rs", "fn main() {}\n"),
+            ("src/cli/args.rs", ""),
+            ("src/cli/deep/er/x.rs", ""),
+            ("target/debug/out", ""),
+            ("docs/", ""),
+        ],
+    );
+    for args in [
+        &["--gitignore"][..],
+        &["--gitignore", "--max-depth", "2"],
+        &["--prune", "--match", "*.rs"],
+        &["--format", "json"],
+    ] {
+        let with_jobs: Vec<&str> = args.iter().copied().chain(["--jobs", "4"]).collect();
+        assert_eq!(fixture.render(&with_jobs), fixture.render(args), "{args:?}");
+    }
+}
+
+#[test]
+fn cutoff_markers_count_what_the_filters_leave() {
+    let fixture = Fixture::new(
+        "cutoff",
+        &[
+            ("empty/other.rs", ""),
+            ("empty/.hidden", ""),
+            ("nested/inner/other.rs", ""),
+            ("some/long.txt", ""),
+            ("some/other.rs", ""),
+            ("long.txt", ""),
+        ],
+    );
+    // The marker counts what the next level down would list.
+    assert_eq!(
+        fixture.render(&["--match", "long.txt", "--max-depth", "1"]),
+        format!(
+            "{}\n├── empty/\n├── nested/\n│   └── ... (1 more entry)\n├── some/\n│   └── ... (1 more entry)\n└── long.txt\n",
+            fixture.name()
+        )
+    );
+    assert_eq!(
+        fixture.render(&["--match", "long.txt", "--max-depth", "2"]),
+        format!(
+            "{}\n├── empty/\n├── nested/\n│   └── inner/\n├── some/\n│   └── long.txt\n└── long.txt\n",
+            fixture.name()
+        )
+    );
+}